@@ -0,0 +1,86 @@
+// FilePath: rust/rust-cli/grss/benches/aho_corasick.rs
+
+//! Many fixed patterns, as one Aho-Corasick automaton and as a regex
+//! alternation (see matcher.rs)
+//!
+//! Run with `cargo bench --bench aho_corasick`. 500 patterns, like an -f
+//! file of identifiers, over a few MB of generated code-like lines, one in
+//! fifty of which has one of them in it. Each pattern is wrapped in a group
+//! to keep it a regex for the comparison, which doesn't change what it
+//! matches.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use grss::{Matcher, MatcherOptions, SearchOptions, Searcher};
+use std::hint::black_box;
+
+const PATTERNS: usize = 500;
+
+fn patterns() -> Vec<String> {
+    (0..PATTERNS).map(|n| format!("handler_{n}_x")).collect()
+}
+
+fn fixture() -> String {
+    let mut text = String::new();
+    for n in 0..100_000 {
+        match n % 50 {
+            0 => text.push_str(&format!("    register(handler_{}_x, {n});\n", n % PATTERNS)),
+            _ => text.push_str(&format!(
+                "    let value_{n} = compute(input, {n}).unwrap_or_default();\n"
+            )),
+        }
+    }
+    text
+}
+
+/// The matcher for the patterns as plain strings, or as regexes
+fn matcher(automaton: bool) -> Matcher {
+    let (patterns, options) = match automaton {
+        true => {
+            let options = MatcherOptions {
+                fixed_strings: true,
+                ..MatcherOptions::default()
+            };
+            (patterns(), options)
+        }
+        false => {
+            let grouped: Vec<String> = patterns()
+                .iter()
+                .map(|pattern| format!("({pattern})"))
+                .collect();
+            (grouped, MatcherOptions::default())
+        }
+    };
+    Matcher::new(&patterns, options).unwrap()
+}
+
+fn aho_corasick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("many_patterns");
+    for (name, automaton) in [("automaton", true), ("regex", false)] {
+        group.bench_function(format!("build/{name}"), |b| {
+            b.iter(|| matcher(black_box(automaton)))
+        });
+    }
+
+    let text = fixture();
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    for (name, automaton) in [("automaton", true), ("regex", false)] {
+        let searcher = Searcher::new(matcher(automaton), SearchOptions::default());
+        group.bench_function(format!("search/{name}"), |b| {
+            b.iter(|| {
+                let mut highlighted = 0;
+                let outcome = searcher
+                    .search_slice(black_box(text.as_bytes()), &mut |found| {
+                        // What printing with colors or -o needs
+                        highlighted += found.ranges().len();
+                        Ok(None)
+                    })
+                    .unwrap();
+                assert_eq!((outcome.count, highlighted), (2000, 2000));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, aho_corasick);
+criterion_main!(benches);
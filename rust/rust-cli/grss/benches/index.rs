@@ -0,0 +1,94 @@
+// FilePath: rust/rust-cli/grss/benches/index.rs
+
+//! Searching a tree with and without its index (see index.rs)
+//!
+//! Run with `cargo bench --bench index`. The fixture is a generated tree of
+//! a thousand code-like files, one in a hundred of which calls what the
+//! pattern is after. Both searches stat every file; the indexed one only
+//! reads the ten the index can't rule out.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use grss::filter::PathFilter;
+use grss::index::{self, IndexedSearch};
+use grss::walk::{WalkOptions, relative_path, walk_files};
+use grss::{Matcher, MatcherOptions, SearchOptions, Searcher};
+use std::fs::{self, File};
+use std::hint::black_box;
+use std::io::BufReader;
+use std::ops::ControlFlow;
+use std::path::Path;
+
+fn fixture() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for file in 0..1000 {
+        let mut text = String::new();
+        for n in 0..200 {
+            match (file % 100, n) {
+                (0, 100) => text.push_str(&format!("    let shape = Rectangle::new({n}, {n});\n")),
+                _ => text.push_str(&format!(
+                    "    let value_{n} = compute(input, {file}).unwrap_or_default();\n"
+                )),
+            }
+        }
+        fs::write(dir.path().join(format!("file_{file}.rs")), text).unwrap();
+    }
+    dir
+}
+
+/// Searches every file under `root` the index doesn't rule out
+///
+/// # Returns
+/// How many lines matched
+fn search(root: &Path, searcher: &Searcher, indexed: Option<&IndexedSearch>) -> usize {
+    let filter = PathFilter::new(&[], &[]).unwrap();
+    let mut matched = 0;
+    walk_files(
+        root,
+        &filter,
+        WalkOptions::default(),
+        |_| {},
+        |path| {
+            let metadata = fs::metadata(&path).unwrap();
+            if indexed
+                .is_some_and(|indexed| indexed.can_skip(relative_path(root, &path), &metadata))
+            {
+                return ControlFlow::Continue(());
+            }
+            let reader = BufReader::new(File::open(&path).unwrap());
+            matched += searcher
+                .search_reader(reader, &mut |_| Ok(None))
+                .unwrap()
+                .count;
+            ControlFlow::Continue(())
+        },
+    );
+    matched
+}
+
+fn indexed(c: &mut Criterion) {
+    let dir = fixture();
+    index::build(dir.path()).unwrap();
+    let patterns = [r"Rectangle::new\(\d+".to_string()];
+    let options = MatcherOptions::default();
+    let texts = grss::matcher::required_texts(&patterns, options).unwrap();
+    let searcher = Searcher::new(
+        Matcher::new(&patterns, options).unwrap(),
+        SearchOptions::default(),
+    );
+
+    let mut group = c.benchmark_group("index");
+    group.bench_function("full_scan", |b| {
+        b.iter(|| assert_eq!(search(black_box(dir.path()), &searcher, None), 10))
+    });
+    // Reading the index is part of every search that uses it
+    group.bench_function("indexed", |b| {
+        b.iter(|| {
+            let indexed = IndexedSearch::new(black_box(dir.path()), &texts).unwrap();
+            assert_eq!(search(dir.path(), &searcher, Some(&indexed)), 10)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, indexed);
+criterion_main!(benches);
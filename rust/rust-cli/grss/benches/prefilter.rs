@@ -0,0 +1,66 @@
+// FilePath: rust/rust-cli/grss/benches/prefilter.rs
+
+//! Searching with and without the literal prefilter (see matcher.rs)
+//!
+//! Run with `cargo bench --bench prefilter`. The fixture is generated: a
+//! few MB of code-like lines, one in a thousand of which has the text the
+//! pattern is after, which is about what searching a real source tree for
+//! one call looks like.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use grss::{Matcher, MatcherOptions, SearchOptions, Searcher};
+use std::hint::black_box;
+use std::io::BufReader;
+
+fn fixture() -> String {
+    let mut text = String::new();
+    for n in 0..100_000 {
+        match n % 1000 {
+            0 => text.push_str(&format!("    let shape = Rectangle::new({n}, {n});\n")),
+            _ => text.push_str(&format!(
+                "    let value_{n} = compute(input, {n}).unwrap_or_default();\n"
+            )),
+        }
+    }
+    text
+}
+
+fn searcher(prefiltered: bool) -> Searcher {
+    let patterns = [r"Rectangle::new\(\d+".to_string()];
+    let matcher = Matcher::new(&patterns, MatcherOptions::default()).unwrap();
+    let matcher = match prefiltered {
+        true => matcher,
+        false => matcher.without_prefilter(),
+    };
+    Searcher::new(matcher, SearchOptions::default())
+}
+
+fn prefilter(c: &mut Criterion) {
+    let text = fixture();
+    let mut group = c.benchmark_group("prefilter");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    for (name, prefiltered) in [("filtered", true), ("unfiltered", false)] {
+        let searcher = searcher(prefiltered);
+        // In memory, as with --mmap
+        group.bench_function(format!("slice/{name}"), |b| {
+            b.iter(|| {
+                let outcome = searcher
+                    .search_slice(black_box(text.as_bytes()), &mut |_| Ok(None))
+                    .unwrap();
+                assert_eq!(outcome.count, 100);
+            })
+        });
+        // Through a buffer the size grss reads files with
+        group.bench_function(format!("reader/{name}"), |b| {
+            b.iter(|| {
+                let reader = BufReader::new(black_box(text.as_bytes()));
+                let outcome = searcher.search_reader(reader, &mut |_| Ok(None)).unwrap();
+                assert_eq!(outcome.count, 100);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, prefilter);
+criterion_main!(benches);
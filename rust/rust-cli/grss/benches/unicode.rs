@@ -0,0 +1,83 @@
+// FilePath: rust/rust-cli/grss/benches/unicode.rs
+
+//! Matching with and without Unicode (--no-unicode, see matcher.rs)
+//!
+//! Run with `cargo bench --bench unicode`. The fixture is generated: a few
+//! MB of ASCII log lines, the kind of file --no-unicode is for. The
+//! patterns lean on what Unicode makes bigger: `\w` repeated a bounded
+//! number of times, `\b` and -i.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use grss::{Matcher, MatcherOptions, SearchOptions, Searcher};
+use std::hint::black_box;
+
+fn fixture() -> String {
+    let mut text = String::new();
+    for n in 0..100_000 {
+        let level = ["INFO", "DEBUG", "WARN", "ERROR"][n % 4];
+        text.push_str(&format!(
+            "2024-05-{:02}T12:{:02}:{:02}Z {level} worker_{} request_id={n} took {}ms\n",
+            n % 28 + 1,
+            n % 60,
+            n % 59,
+            n % 16,
+            n % 997
+        ));
+    }
+    text
+}
+
+fn options(ignore_case: bool, no_unicode: bool) -> MatcherOptions {
+    MatcherOptions {
+        ignore_case,
+        no_unicode,
+        ..MatcherOptions::default()
+    }
+}
+
+/// The patterns, with whether they're -i and how many lines they match
+const PATTERNS: [(&str, &str, bool, usize); 2] = [
+    ("classes", r"\b\w{4,12}_\w{2,8}=\d{4,}7\b", false, 9_000),
+    ("ignore_case", r"\w{3,12}_ID=\d{3,}7\b", true, 9_900),
+];
+
+/// Most of the difference is here: Unicode's \w is hundreds of ranges,
+/// repeated for every {n}
+fn compile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("unicode/compile");
+    for (name, pattern, ignore_case, _) in PATTERNS {
+        let patterns = [pattern.to_string()];
+        for (mode, no_unicode) in [("unicode", false), ("ascii", true)] {
+            let options = options(ignore_case, no_unicode);
+            group.bench_function(format!("{name}/{mode}"), |b| {
+                b.iter(|| Matcher::new(black_box(&patterns), options).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    let text = fixture();
+    let mut group = c.benchmark_group("unicode/search");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    for (name, pattern, ignore_case, expected) in PATTERNS {
+        let patterns = [pattern.to_string()];
+        for (mode, no_unicode) in [("unicode", false), ("ascii", true)] {
+            let matcher = Matcher::new(&patterns, options(ignore_case, no_unicode)).unwrap();
+            let searcher = Searcher::new(matcher, SearchOptions::default());
+            group.bench_function(format!("{name}/{mode}"), |b| {
+                b.iter(|| {
+                    let outcome = searcher
+                        .search_slice(black_box(text.as_bytes()), &mut |_| Ok(None))
+                        .unwrap();
+                    assert_eq!(outcome.count, expected);
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compile, search);
+criterion_main!(benches);
@@ -0,0 +1,160 @@
+// FilePath: rust/rust-cli/grss/src/byte_range.rs
+
+//! --byte-range START..END: searching only part of a file
+//!
+//! Meant for a huge log where you know roughly where to look. The search
+//! starts at the first line that starts at or after START (the rest of a
+//! line START lands in the middle of is left out) and ends with the line
+//! byte END-1 is in, so a line is searched whole or not at all. Finding
+//! both only takes a seek and a read of the line around each.
+//!
+//! Line numbers and byte offsets still count from the start of the file,
+//! so they point at the same lines a search of the whole file would. That
+//! takes counting the newlines before START, which means reading that part
+//! of the file, but with memchr rather than the patterns: many times faster
+//! than searching it.
+
+use memchr::{memchr, memchr_iter};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// The part of each file to search, as given to --byte-range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    /// None to go to the end of the file
+    pub end: Option<u64>,
+}
+
+/// Where a --byte-range search goes in one file
+#[derive(Debug, PartialEq)]
+pub struct Bounds {
+    /// Where the first line searched starts
+    pub start: u64,
+    /// Just past the last line searched
+    pub end: u64,
+    /// How many lines come before `start`
+    pub lines_before: usize,
+}
+
+/// Parses START..END, either of which can be left out (`..1M`, `500..`);
+/// both are a number of bytes, optionally ending in K, M or G like
+/// --max-filesize's
+///
+/// # Returns
+/// The range, or a message for clap to show when it isn't one or it's empty
+pub fn parse(text: &str) -> Result<ByteRange, String> {
+    let Some((start, end)) = text.split_once("..") else {
+        return Err("expected START..END, e.g. `1000..`, `..5M` or `10K..20K`".to_string());
+    };
+    let bound = |text: &str| match text {
+        "" => Ok(None),
+        text => crate::parse_size(text).map(Some),
+    };
+    let range = ByteRange {
+        start: bound(start)?.unwrap_or(0),
+        end: bound(end)?,
+    };
+    if range.end.is_some_and(|end| end <= range.start) {
+        return Err("the range is empty, END has to come after START".to_string());
+    }
+    Ok(range)
+}
+
+impl ByteRange {
+    /// Works out which lines of `file` are in the range, lines ending in
+    /// `terminator`, and leaves `file` at the first of them
+    pub fn bounds(&self, file: &mut File, terminator: u8) -> io::Result<Bounds> {
+        let len = file.metadata()?.len();
+        let start = match self.start.min(len) {
+            0 => 0,
+            start => line_end(file, start - 1, len, terminator)?,
+        };
+        let end = match self.end {
+            Some(end) if end <= start => start,
+            Some(end) => line_end(file, end.min(len) - 1, len, terminator)?,
+            None => len,
+        };
+        file.seek(SeekFrom::Start(0))?;
+        let mut lines_before = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        let mut before = Read::by_ref(file).take(start);
+        loop {
+            let read = match before.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            lines_before += memchr_iter(terminator, &buffer[..read]).count();
+        }
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Bounds {
+            start,
+            end,
+            lines_before,
+        })
+    }
+}
+
+/// Where the line byte `at` of `file` is in ends: just past its
+/// terminator, or at `len` for a last line without one
+fn line_end(file: &mut File, at: u64, len: u64, terminator: u8) -> io::Result<u64> {
+    file.seek(SeekFrom::Start(at))?;
+    let mut buffer = [0; 8 * 1024];
+    let mut offset = at;
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => return Ok(len),
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        if let Some(found) = memchr(terminator, &buffer[..read]) {
+            return Ok(offset + found as u64 + 1);
+        }
+        offset += read as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn ranges_parse_with_either_bound_left_out() {
+        let range = |start, end| Ok(ByteRange { start, end });
+        assert_eq!(parse("..1000"), range(0, Some(1000)));
+        assert_eq!(parse("500.."), range(500, None));
+        assert_eq!(parse("10..20"), range(10, Some(20)));
+        assert_eq!(parse("1K..2M"), range(1024, Some(2 << 20)));
+        assert_eq!(parse(".."), range(0, None));
+        for invalid in ["20..10", "10..10", "10", "10-20", "a..b", "..-1", "1...2"] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn bounds_take_whole_lines() {
+        // Lines start at 0, 4, 9 and 15
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"one\ntwo!\nthree\nfour").unwrap();
+        let mut bounds = |start, end| {
+            let range = ByteRange { start, end };
+            let bounds = range.bounds(&mut file, b'\n').unwrap();
+            (bounds.start, bounds.end, bounds.lines_before)
+        };
+        assert_eq!(bounds(0, None), (0, 19, 0));
+        // Starting mid-line skips to the next one, ending mid-line finishes
+        // the line
+        assert_eq!(bounds(1, Some(5)), (4, 9, 1));
+        assert_eq!(bounds(4, Some(9)), (4, 9, 1));
+        assert_eq!(bounds(5, Some(10)), (9, 15, 2));
+        assert_eq!(bounds(10, None), (15, 19, 3));
+        // Nothing left after START, or before END
+        assert_eq!(bounds(16, None), (19, 19, 3));
+        assert_eq!(bounds(100, Some(200)), (19, 19, 3));
+        assert_eq!(bounds(1, Some(3)), (4, 4, 1));
+    }
+}
@@ -1,15 +1,51 @@
 use clap::Parser;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process::ExitCode;
 
+/// Search for a pattern in a file and print the lines that contain it
 #[derive(Parser)]
 struct Cli {
-    /// The patterhn to loonk for
+    /// The text to look for in each line of the file
     pattern: String,
-    /// The path to the file to read
+    /// The path to the file to search
     path: std::path::PathBuf,
 }
 
-fn main() {
+fn main() -> ExitCode {
+    // Cli::parse() reads std::env::args() for us, and prints usage/help and
+    // exits by itself when arguments are missing or --help is passed
     let args = Cli::parse();
 
-    println!(" Pattern is {:?}, path is {:?}");
+    // PathBuf doesn't implement Display (paths aren't always valid UTF-8),
+    // so we use .display() whenever we want to print one
+    let file = match File::open(&args.path) {
+        Ok(file) => file,
+        Err(error) => {
+            eprintln!("clap_parser: {}: {}", args.path.display(), error);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut found_match = false;
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                eprintln!("clap_parser: {}: {}", args.path.display(), error);
+                return ExitCode::from(2);
+            }
+        };
+
+        if line.contains(&args.pattern) {
+            println!("{}", line);
+            found_match = true;
+        }
+    }
+
+    if found_match {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    }
 }
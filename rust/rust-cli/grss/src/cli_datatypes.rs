@@ -1,20 +1,38 @@
 // FilePath: rust/rust-cli/grss/src/cli_datatypes.rs
 
+use std::process::ExitCode;
+
 struct Cli {
     pattern: String,
     path: std::path::PathBuf,
 }
 
-fn main() {
-    let pattern = std::env::args().nth(1).expect("no pattern given");
-    let path = std::env::args().nth(2).expect("no path given");
+impl Cli {
+    /// Builds a Cli from the command line arguments
+    ///
+    /// Returns a message instead of panicking when an argument is missing,
+    /// a missing argument is the user's mistake, not a bug in the program
+    fn from_args() -> Result<Cli, String> {
+        let pattern = std::env::args().nth(1).ok_or("no pattern given")?;
+        let path = std::env::args().nth(2).ok_or("no path given")?;
+
+        Ok(Cli {
+            pattern,
+            path: std::path::PathBuf::from(path),
+        })
+    }
+}
 
-    let args = Cli {
-        pattern,
-        path: std::path::PathBuf::from(path),
+fn main() -> ExitCode {
+    let args = match Cli::from_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("cli_datatypes: {}", message);
+            eprintln!("usage: cli_datatypes <pattern> <path>");
+            return ExitCode::from(2);
+        }
     };
 
     println!("pattern: {:?}, path: {:?}", args.pattern, args.path);
+    ExitCode::SUCCESS
 }
-
-
@@ -0,0 +1,226 @@
+// FilePath: rust/rust-cli/grss/src/colors.rs
+
+//! The colors used for matches, line numbers and paths, and the little spec
+//! language for changing them through GRSS_COLORS or --colors
+//!
+//! A spec is a comma separated list of `what:attribute:value` entries:
+//!
+//! ```text
+//! match:fg:yellow,match:style:bold,line:fg:green,path:fg:magenta
+//! ```
+//!
+//! - `what` is `match` (the matched text), `line` (line numbers) or `path`
+//! - `attribute` is `fg` or `bg` with one of the eight basic terminal colors
+//!   (`black`, `red`, `green`, `yellow`, `blue`, `magenta`, `cyan`,
+//!   `white`), or `style` with `bold`, `italic` or `underline`
+//! - `none` as the value clears that attribute
+//!
+//! Entries only change what they name, everything else keeps grep's
+//! defaults, and when the same `what:attribute` shows up twice the last one
+//! wins.
+
+/// The basic terminal colors, in ANSI order: `red` is foreground code 31 and
+/// background code 41
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// The escape code that switches all colors and styles off again
+pub const RESET: &str = "\x1b[0m";
+
+/// Escape codes to start each colored part of the output with
+#[derive(Debug, PartialEq, Eq)]
+pub struct Colors {
+    pub matched: String,
+    pub line_number: String,
+    pub path: String,
+}
+
+impl Default for Colors {
+    /// The same defaults grep uses: bold red matches, green line numbers and
+    /// magenta file names
+    fn default() -> Self {
+        Styles::default().colors()
+    }
+}
+
+/// One colored part of the output, as SGR codes (e.g. 31 for a red
+/// foreground)
+#[derive(Clone, Copy, Default)]
+struct Style {
+    fg: Option<u8>,
+    bg: Option<u8>,
+    style: Option<u8>,
+}
+
+impl Style {
+    /// The escape sequence that turns this style on, e.g. `\x1b[1;31m`
+    fn escape(&self) -> String {
+        let codes: Vec<String> = [self.style, self.fg, self.bg]
+            .into_iter()
+            .flatten()
+            .map(|code| code.to_string())
+            .collect();
+        if codes.is_empty() {
+            return String::new();
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+struct Styles {
+    matched: Style,
+    line_number: Style,
+    path: Style,
+}
+
+impl Default for Styles {
+    fn default() -> Self {
+        Styles {
+            matched: Style {
+                fg: Some(31),
+                bg: None,
+                style: Some(1),
+            },
+            line_number: Style {
+                fg: Some(32),
+                ..Style::default()
+            },
+            path: Style {
+                fg: Some(35),
+                ..Style::default()
+            },
+        }
+    }
+}
+
+impl Styles {
+    fn colors(&self) -> Colors {
+        Colors {
+            matched: self.matched.escape(),
+            line_number: self.line_number.escape(),
+            path: self.path.escape(),
+        }
+    }
+}
+
+/// Applies `spec` on top of the default colors
+///
+/// # Returns
+/// The colors, or a message saying which entry is wrong. Nothing of a
+/// spec with a mistake in it is used, the caller falls back to the defaults.
+pub fn parse(spec: &str) -> Result<Colors, String> {
+    let mut styles = Styles::default();
+
+    for entry in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let parts: Vec<&str> = entry.split(':').collect();
+        let [what, attribute, value] = parts[..] else {
+            return Err(format!(
+                "'{}' isn't of the form what:attribute:value",
+                entry
+            ));
+        };
+
+        let style = match what {
+            "match" => &mut styles.matched,
+            "line" => &mut styles.line_number,
+            "path" => &mut styles.path,
+            _ => {
+                return Err(format!(
+                    "unknown '{}' in '{}', expected match, line or path",
+                    what, entry
+                ));
+            }
+        };
+
+        let code = if value == "none" {
+            None
+        } else {
+            Some(attribute_code(attribute, value).ok_or_else(|| {
+                format!("unknown value '{}' for {} in '{}'", value, attribute, entry)
+            })?)
+        };
+
+        match attribute {
+            "fg" => style.fg = code,
+            "bg" => style.bg = code,
+            "style" => style.style = code,
+            _ => {
+                return Err(format!(
+                    "unknown attribute '{}' in '{}', expected fg, bg or style",
+                    attribute, entry
+                ));
+            }
+        }
+    }
+
+    Ok(styles.colors())
+}
+
+/// The SGR code for `value` of `attribute`, None when it isn't one
+fn attribute_code(attribute: &str, value: &str) -> Option<u8> {
+    let color = || COLOR_NAMES.iter().position(|name| *name == value);
+    match attribute {
+        "fg" => color().map(|index| 30 + index as u8),
+        "bg" => color().map(|index| 40 + index as u8),
+        "style" => match value {
+            "bold" => Some(1),
+            "italic" => Some(3),
+            "underline" => Some(4),
+            _ => None,
+        },
+        // parse() reports the unknown attribute itself
+        _ => Some(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_spec_gives_the_defaults() {
+        let colors = parse("").unwrap();
+        assert_eq!(colors.matched, "\x1b[1;31m");
+        assert_eq!(colors.line_number, "\x1b[32m");
+        assert_eq!(colors.path, "\x1b[35m");
+        assert_eq!(colors, Colors::default());
+    }
+
+    #[test]
+    fn entries_only_change_what_they_name() {
+        let colors = parse("match:fg:yellow, line:bg:blue,path:style:underline").unwrap();
+        assert_eq!(colors.matched, "\x1b[1;33m");
+        assert_eq!(colors.line_number, "\x1b[32;44m");
+        assert_eq!(colors.path, "\x1b[4;35m");
+    }
+
+    #[test]
+    fn none_clears_an_attribute() {
+        let colors = parse("match:style:none,path:fg:none").unwrap();
+        assert_eq!(colors.matched, "\x1b[31m");
+        assert_eq!(colors.path, "");
+    }
+
+    #[test]
+    fn last_duplicate_wins() {
+        let colors = parse("match:fg:green,match:fg:cyan").unwrap();
+        assert_eq!(colors.matched, "\x1b[1;36m");
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        assert!(parse("match:fg:purple").unwrap_err().contains("'purple'"));
+        assert!(parse("matches:fg:red").unwrap_err().contains("'matches'"));
+        assert!(parse("match:blink:red").unwrap_err().contains("'blink'"));
+        assert!(
+            parse("match:fg")
+                .unwrap_err()
+                .contains("what:attribute:value")
+        );
+    }
+}
@@ -0,0 +1,76 @@
+// FilePath: rust/rust-cli/grss/src/completions.rs
+
+//! `grss completions <SHELL>`: printing a tab completion script
+//!
+//! The script is generated by clap_complete from the same clap definition
+//! that parses the command line, so every flag grss has is in it without
+//! anyone keeping a list up to date by hand. Install it with e.g.
+//! `grss completions zsh > ~/.zfunc/_grss`.
+
+use clap::Parser;
+use clap_complete::Shell;
+use grss::GrssError;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Print a tab completion script for SHELL to standard output
+#[derive(Parser)]
+#[command(name = "grss completions", bin_name = "grss completions")]
+pub struct CompletionsCli {
+    /// The shell to complete in
+    shell: Shell,
+}
+
+/// Parses the arguments after `grss` (starting with `completions` itself)
+/// and prints the script
+///
+/// # Returns
+/// Always true, so grss exits with 0
+pub fn run(argv: impl IntoIterator<Item = OsString>) -> Result<bool, GrssError> {
+    let args = CompletionsCli::parse_from(argv);
+    write_script(args.shell, &mut io::stdout().lock())
+        .map_err(|error| GrssError::io(Path::new("(standard output)"), error))?;
+    Ok(true)
+}
+
+/// Writes the completion script for `shell` to `out`
+fn write_script<W: Write>(shell: Shell, out: &mut W) -> io::Result<()> {
+    // clap_complete panics on a write error instead of returning it, so the
+    // script goes into memory first and is written out from there
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut crate::command(), "grss", &mut script);
+    out.write_all(&script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(shell: Shell) -> String {
+        let mut out = Vec::new();
+        write_script(shell, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn every_shell_gets_its_own_script() {
+        for (shell, marker) in [
+            (Shell::Bash, "complete -F _grss"),
+            (Shell::Zsh, "#compdef grss"),
+            (Shell::Fish, "complete -c grss"),
+            (Shell::PowerShell, "Register-ArgumentCompleter"),
+        ] {
+            let script = script(shell);
+            assert!(script.contains(marker), "{shell}: no `{marker}`");
+            assert!(script.contains("ignore-case"), "{shell}: no flags");
+        }
+    }
+
+    #[test]
+    fn paths_complete_as_file_names() {
+        let zsh = script(Shell::Zsh);
+        assert!(zsh.contains("--pattern-file=[") && zsh.contains(":FILE:_files"));
+        assert!(zsh.contains("*::paths"));
+    }
+}
@@ -0,0 +1,216 @@
+// FilePath: rust/rust-cli/grss/src/config.rs
+
+//! Default options from a config file and GRSS_OPTS
+//!
+//! The file lives at `$XDG_CONFIG_HOME/grss/config`, or
+//! `~/.config/grss/config` when XDG_CONFIG_HOME isn't set. Each line holds
+//! one or more command line arguments, split the way a shell would split
+//! them, so a value with spaces in it can be quoted:
+//!
+//! ```text
+//! # Always color when printing to a terminal
+//! --color auto
+//! --exclude "build output/**"
+//! ```
+//!
+//! Blank lines and lines starting with `#` are skipped. The arguments are
+//! put in front of the ones actually typed, so anything on the command line
+//! wins over the config (the same trick as ripgrep's RIPGREP_CONFIG_PATH),
+//! and `--no-config` skips the file altogether.
+//!
+//! The GRSS_OPTS environment variable is the lighter way of doing the same,
+//! for one shell or one script: its value is split the same way, and its
+//! arguments go between the config file's and the typed ones, so they win
+//! over the file and lose to the command line. `--no-config` skips it too,
+//! unless it's in GRSS_OPTS itself, which skips only the file.
+
+use grss::GrssError;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The environment variable with default arguments
+const OPTIONS_VAR: &str = "GRSS_OPTS";
+
+/// Puts the arguments from the config file in front of `argv` (which still
+/// starts with the program name), unless `argv` has --no-config
+///
+/// A missing config file is fine and leaves `argv` as it is, any other
+/// problem reading or parsing it is an error
+pub fn with_config(mut argv: Vec<OsString>) -> Result<Vec<OsString>, GrssError> {
+    if argv.iter().skip(1).any(|arg| arg == "--no-config") {
+        return Ok(argv);
+    }
+    let Some(path) = config_path(env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME")) else {
+        return Ok(argv);
+    };
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(argv),
+        Err(error) => return Err(GrssError::io(&path, error)),
+    };
+
+    let defaults = parse(&contents, &path)?;
+    // Straight after the program name, before anything the user typed
+    argv.splice(1..1, defaults.into_iter().map(OsString::from));
+    Ok(argv)
+}
+
+/// Puts the arguments from GRSS_OPTS in front of the ones in `argv` (which
+/// still starts with the program name), unless `argv` has --no-config
+///
+/// An unset or empty GRSS_OPTS leaves `argv` as it is; one that can't be
+/// split into arguments is an error.
+pub fn with_env_options(mut argv: Vec<OsString>) -> Result<Vec<OsString>, GrssError> {
+    if argv.iter().skip(1).any(|arg| arg == "--no-config") {
+        return Ok(argv);
+    }
+    let Some(value) = env::var_os(OPTIONS_VAR) else {
+        return Ok(argv);
+    };
+    let value = value.into_string().map_err(|_| GrssError::Environment {
+        name: OPTIONS_VAR.to_string(),
+        message: "not valid UTF-8".to_string(),
+    })?;
+    let options = split_options(&value)?;
+    argv.splice(1..1, options.into_iter().map(OsString::from));
+    Ok(argv)
+}
+
+/// Splits the value of GRSS_OPTS into arguments, the way a shell would:
+/// quotes keep spaces in, and a backslash escapes the next character
+/// (except within single quotes)
+fn split_options(value: &str) -> Result<Vec<String>, GrssError> {
+    shell_words::split(value).map_err(|error| GrssError::Environment {
+        name: OPTIONS_VAR.to_string(),
+        message: error.to_string(),
+    })
+}
+
+/// Where the config file is, given the values of XDG_CONFIG_HOME and HOME
+///
+/// # Returns
+/// None when neither variable is set, so there's nowhere to look
+fn config_path(xdg_config_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    // An empty XDG_CONFIG_HOME counts as unset, as the XDG spec says
+    let config_dir = match xdg_config_home.filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(home?).join(".config"),
+    };
+    Some(config_dir.join("grss").join("config"))
+}
+
+/// Turns the contents of the config file at `path` into a list of arguments
+fn parse(contents: &str, path: &Path) -> Result<Vec<String>, GrssError> {
+    let mut args = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words = shell_words::split(line).map_err(|error| GrssError::Config {
+            path: path.to_path_buf(),
+            line: index + 1,
+            message: error.to_string(),
+        })?;
+        args.extend(words);
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cli;
+    use clap::Parser;
+
+    fn parse_ok(contents: &str) -> Vec<String> {
+        parse(contents, Path::new("config")).unwrap()
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let contents = "# my defaults\n\n--color auto\n   # indented comment\n-i\n";
+        assert_eq!(parse_ok(contents), ["--color", "auto", "-i"]);
+    }
+
+    #[test]
+    fn quoted_values_keep_their_spaces() {
+        let contents = "--exclude \"build output/**\"\n--include='my file.rs'\n";
+        assert_eq!(
+            parse_ok(contents),
+            ["--exclude", "build output/**", "--include=my file.rs"]
+        );
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = parse("-i\n--exclude \"oops\n", Path::new("/home/me/config")).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("/home/me/config:2: invalid config line")
+        );
+    }
+
+    #[test]
+    fn options_split_like_a_shell_would() {
+        let split = |value| split_options(value).unwrap();
+        assert!(split("").is_empty());
+        assert_eq!(split("  -n   --color always "), ["-n", "--color", "always"]);
+        assert_eq!(
+            split(r#"--exclude "build output/**" -g'*.rs'"#),
+            ["--exclude", "build output/**", "-g*.rs"]
+        );
+        assert_eq!(
+            split(r"--exclude my\ dir a\\b"),
+            ["--exclude", "my dir", r"a\b"]
+        );
+        // Nothing is special within single quotes, and a backslash only
+        // escapes \ and " within double ones
+        assert_eq!(split(r#"'a\"b' "c\"d\e""#), [r#"a\"b"#, r#"c"d\e"#]);
+        assert_eq!(split(r#"-e """#), ["-e", ""]);
+    }
+
+    #[test]
+    fn an_unterminated_quote_names_the_variable() {
+        for value in ["--exclude 'oops", "-e \"oops", "'a' \"b"] {
+            let error = split_options(value).unwrap_err();
+            assert!(
+                error.to_string().starts_with("invalid GRSS_OPTS: "),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn config_path_prefers_xdg() {
+        assert_eq!(
+            config_path(Some("/xdg".into()), Some("/home/me".into())),
+            Some(PathBuf::from("/xdg/grss/config"))
+        );
+        assert_eq!(
+            config_path(Some("".into()), Some("/home/me".into())),
+            Some(PathBuf::from("/home/me/.config/grss/config"))
+        );
+        assert_eq!(config_path(None, None), None);
+    }
+
+    #[test]
+    fn command_line_flags_win_over_config() {
+        let mut argv = vec!["grss".to_string()];
+        argv.extend(parse_ok("--color always\n-m 5\n-i\n--exclude target/**\n"));
+        argv.extend(["--color", "never", "-m", "1", "-i", "--exclude", "*.lock"].map(String::from));
+        argv.extend(["foo", "src"].map(String::from));
+
+        let args = Cli::parse_from(argv);
+        assert!(args.color == crate::ColorChoice::Never);
+        assert_eq!(args.max_count, Some(1));
+        assert!(args.patterns.ignore_case);
+        // Lists add up instead
+        assert_eq!(args.walk.exclude, ["target/**", "*.lock"]);
+    }
+}
@@ -0,0 +1,142 @@
+// FilePath: rust/rust-cli/grss/src/dedupe.rs
+
+//! --dedupe: printing each distinct selected line once
+//!
+//! Lines are told apart by their bytes as printed (after --replace), without
+//! the path and line number in front. Only a 64-bit hash of each is kept,
+//! so going through a log with millions of copies of a few lines takes
+//! memory for the few; two different lines with the same hash would count
+//! as one, which in practice never happens.
+//!
+//! --dedupe-global shares the hashes between all the files searched, so a
+//! line is only printed for the first file it's found in. Which file that
+//! is depends on which is searched first, which, like the order files are
+//! printed in, only --sort pins down.
+//!
+//! --count-duplicates holds the lines of a file back until all of it is
+//! searched, to know how many times each came, and then prints them in the
+//! order they first came, followed by `(xN)` when there was more than one.
+//! That takes memory for the text of every distinct line of the file.
+
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::Mutex;
+
+/// A selected line held back by --count-duplicates, as it's to be printed
+pub struct Held {
+    pub number: usize,
+    pub offset: usize,
+    pub line: Vec<u8>,
+    pub ranges: Vec<Range<usize>>,
+    /// How many times the line came in the file
+    pub times: usize,
+}
+
+/// Which selected lines of one file were printed (or held) already
+pub struct Dedupe<'a> {
+    /// The lines of this file, by hash, with where each is in `held` when
+    /// counting
+    seen: HashMap<u64, usize>,
+    /// --dedupe-global: the lines of every file, instead of `seen`
+    global: Option<&'a Mutex<HashSet<u64>>>,
+    /// --count-duplicates: the lines held back, None when not counting
+    held: Option<Vec<Held>>,
+}
+
+impl<'a> Dedupe<'a> {
+    /// Starts on a file, sharing what's been printed with the other files
+    /// through `global` for --dedupe-global, and holding lines back to count
+    /// them with `count`
+    pub fn new(global: Option<&'a Mutex<HashSet<u64>>>, count: bool) -> Self {
+        Dedupe {
+            seen: HashMap::new(),
+            global,
+            held: count.then(Vec::new),
+        }
+    }
+
+    /// Goes through a selected line, `line` being its bytes as they would
+    /// be printed
+    ///
+    /// # Returns
+    /// Whether to print it now: only if it's the first time it came, and
+    /// not counting. When counting, the first time `hold` gives what to
+    /// print at the end (see held()), every time after adds one to it.
+    pub fn first(&mut self, line: &[u8], hold: impl FnOnce() -> Held) -> bool {
+        let hash = hash(line);
+        if let Some(global) = self.global {
+            return global.lock().unwrap().insert(hash);
+        }
+        let Some(held) = &mut self.held else {
+            return self.seen.insert(hash, 0).is_none();
+        };
+        match self.seen.entry(hash) {
+            Entry::Occupied(index) => held[*index.get()].times += 1,
+            Entry::Vacant(index) => {
+                index.insert(held.len());
+                held.push(hold());
+            }
+        }
+        false
+    }
+
+    /// The lines held back to count them, in the order they first came
+    pub fn held(self) -> Vec<Held> {
+        self.held.unwrap_or_default()
+    }
+}
+
+fn hash(line: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    line.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn held(line: &[u8]) -> impl FnOnce() -> Held + '_ {
+        move || Held {
+            number: 1,
+            offset: 0,
+            line: line.to_vec(),
+            ranges: Vec::new(),
+            times: 1,
+        }
+    }
+
+    #[test]
+    fn each_line_once_per_file_or_overall() {
+        let mut dedupe = Dedupe::new(None, false);
+        let firsts: Vec<bool> = [b"a", b"b", b"a", b"a", b"c", b"b"]
+            .into_iter()
+            .map(|line| dedupe.first(line, held(line)))
+            .collect();
+        assert_eq!(firsts, [true, true, false, false, true, false]);
+        assert!(dedupe.held().is_empty());
+
+        let global = Mutex::new(HashSet::new());
+        let mut one = Dedupe::new(Some(&global), false);
+        let mut other = Dedupe::new(Some(&global), false);
+        assert!(one.first(b"a", held(b"a")));
+        assert!(!other.first(b"a", held(b"a")));
+        assert!(other.first(b"b", held(b"b")));
+    }
+
+    #[test]
+    fn counting_holds_every_line_back() {
+        let mut dedupe = Dedupe::new(None, true);
+        for line in [b"x", b"y", b"x", b"x", b"x", b"x"] {
+            assert!(!dedupe.first(line, held(line)));
+        }
+        let counts: Vec<(Vec<u8>, usize)> = dedupe
+            .held()
+            .into_iter()
+            .map(|held| (held.line, held.times))
+            .collect();
+        assert_eq!(counts, [(b"x".to_vec(), 5), (b"y".to_vec(), 1)]);
+    }
+}
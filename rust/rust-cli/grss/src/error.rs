@@ -0,0 +1,106 @@
+// FilePath: rust/rust-cli/grss/src/error.rs
+
+//! Everything that can make grss give up
+//!
+//! Each variant carries enough context to print a single readable line.
+//! main() prints them as `grss: <error>` and exits with code 2, so a panic
+//! only ever means a bug in grss itself.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum GrssError {
+    /// The command line doesn't make sense, e.g. no pattern or no path
+    Usage(String),
+    /// A pattern that doesn't compile as a regex
+    InvalidPattern { pattern: String, message: String },
+    /// An --include or --exclude glob that doesn't compile
+    InvalidGlob(String),
+    /// A line in the config file that can't be split into arguments
+    Config {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// An environment variable with a value that can't be used
+    Environment { name: String, message: String },
+    /// A directory given as the path without -r
+    IsADirectory(PathBuf),
+    /// Opening or reading a file failed, including halfway through it, or
+    /// writing the results failed
+    Io { path: PathBuf, source: io::Error },
+    /// Searching some of several paths failed. Each failure was already
+    /// printed as it happened (the other paths were still searched), all
+    /// that's left is exiting with code 2.
+    PathsFailed,
+    /// Ctrl-C stopped the search. What was found before it has been
+    /// printed, all that's left is exiting with code 130.
+    Interrupted,
+}
+
+impl GrssError {
+    /// Wraps an I/O error together with the path it happened on
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        GrssError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// Whether this is a write to a pipe nobody reads anymore, like the
+    /// results going into `head` once it has all it wants
+    ///
+    /// Reading files doesn't fail that way, so it's the output that closed.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, GrssError::Io { source, .. } if source.kind() == io::ErrorKind::BrokenPipe)
+    }
+}
+
+impl fmt::Display for GrssError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrssError::Usage(message) => write!(f, "{}", message),
+            // The regex crate's message spans several lines (it draws a
+            // little pointer under the bad part), so it goes after the colon
+            GrssError::InvalidPattern { pattern, message } => {
+                write!(f, "invalid pattern '{}': {}", pattern, message)
+            }
+            GrssError::InvalidGlob(message) => write!(f, "invalid glob: {}", message),
+            GrssError::Config {
+                path,
+                line,
+                message,
+            } => write!(
+                f,
+                "{}:{}: invalid config line: {}",
+                path.display(),
+                line,
+                message
+            ),
+            GrssError::Environment { name, message } => {
+                write!(f, "invalid {}: {}", name, message)
+            }
+            GrssError::IsADirectory(path) => {
+                write!(
+                    f,
+                    "{}: is a directory (use -r to search it)",
+                    path.display()
+                )
+            }
+            GrssError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            GrssError::PathsFailed => write!(f, "some paths couldn't be searched"),
+            GrssError::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+impl std::error::Error for GrssError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GrssError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
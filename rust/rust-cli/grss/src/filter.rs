@@ -0,0 +1,374 @@
+// FilePath: rust/rust-cli/grss/src/filter.rs
+
+//! Deciding which files a recursive search looks at, from --include,
+//! --exclude and --iglob globs and the --type and --type-not file types
+//!
+//! Globs are matched against the path relative to the directory being
+//! searched, so `grss -r --include '*.rs' foo ./project` sees `src/main.rs`
+//! rather than `./project/src/main.rs`. A glob without any `/` in it (like
+//! `*.rs` or `target`) only has to match the file or directory name, one with
+//! a `/` (like `**/src/*.rs`) has to match the whole relative path, and then
+//! `*` never crosses a `/`.
+//!
+//! --iglob globs are the same, only matched ignoring case: a plain one adds
+//! to the --include globs and one starting with `!` to the --exclude ones,
+//! so `--iglob '*.rs'` picks `Foo.RS` too and `--iglob '!*.md'` leaves out
+//! `README.MD`.
+//!
+//! Exclusions always win: a file matching --exclude, a `!` --iglob or
+//! --type-not is never searched. Past that, a file has to match one of the
+//! --include or plain --iglob globs (if there are any) AND be one of the
+//! --type types (if there are any), so `--type rust --include 'src/**'`
+//! means Rust files under src/.
+//!
+//! All of this only narrows down what the walk finds. Hidden files and
+//! directories, and whatever an ignore file lists, are left out before any
+//! glob is looked at, so no --include or --iglob brings them back; that
+//! takes --hidden or --no-ignore.
+//!
+//! --exclude-dir globs only ever match directories, and the walk doesn't go
+//! into one that matches at all.
+
+use crate::error::GrssError;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+/// The compiled --include and --exclude globs
+pub struct PathFilter {
+    include_names: GlobSet,
+    include_paths: GlobSet,
+    exclude_names: GlobSet,
+    exclude_paths: GlobSet,
+    /// Directories whose whole contents are excluded, built from exclude
+    /// globs ending in `/**` (e.g. `target/**` gives `target`). The walk can
+    /// skip these entirely instead of looking at every file inside.
+    exclude_dirs: GlobSet,
+    /// The globs of every --type, split the same way as include_*
+    type_names: GlobSet,
+    type_paths: GlobSet,
+    /// The globs of every --type-not
+    type_not_names: GlobSet,
+    type_not_paths: GlobSet,
+    /// --exclude-dir globs, matched against directories only
+    exclude_dir_names: GlobSet,
+    exclude_dir_paths: GlobSet,
+    /// The plain --iglob globs, which count as more include_* globs but
+    /// ignore case
+    iinclude_names: GlobSet,
+    iinclude_paths: GlobSet,
+    /// The `!` --iglob globs, the same for exclude_* and exclude_dirs
+    iexclude_names: GlobSet,
+    iexclude_paths: GlobSet,
+    iexclude_dirs: GlobSet,
+}
+
+impl PathFilter {
+    /// Compiles the globs, failing with a readable message on the first one
+    /// that isn't valid glob syntax
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, GrssError> {
+        let (include_names, include_paths) = build_sets(include.iter(), false)?;
+        let (exclude_names, exclude_paths) = build_sets(exclude.iter(), false)?;
+
+        Ok(PathFilter {
+            include_names,
+            include_paths,
+            exclude_names,
+            exclude_paths,
+            exclude_dirs: build_dir_set(exclude.iter(), false)?,
+            type_names: GlobSet::empty(),
+            type_paths: GlobSet::empty(),
+            type_not_names: GlobSet::empty(),
+            type_not_paths: GlobSet::empty(),
+            exclude_dir_names: GlobSet::empty(),
+            exclude_dir_paths: GlobSet::empty(),
+            iinclude_names: GlobSet::empty(),
+            iinclude_paths: GlobSet::empty(),
+            iexclude_names: GlobSet::empty(),
+            iexclude_paths: GlobSet::empty(),
+            iexclude_dirs: GlobSet::empty(),
+        })
+    }
+
+    /// Adds the --iglob globs, matched ignoring case: ones starting with `!`
+    /// exclude like --exclude, the others include like --include
+    pub fn with_iglobs(self, globs: &[String]) -> Result<Self, GrssError> {
+        let (excludes, includes): (Vec<_>, Vec<_>) =
+            globs.iter().partition(|glob| glob.starts_with('!'));
+        let excludes: Vec<String> = excludes.iter().map(|glob| glob[1..].to_string()).collect();
+        let (iinclude_names, iinclude_paths) = build_sets(includes.into_iter(), true)?;
+        let (iexclude_names, iexclude_paths) = build_sets(excludes.iter(), true)?;
+        Ok(PathFilter {
+            iinclude_names,
+            iinclude_paths,
+            iexclude_names,
+            iexclude_paths,
+            iexclude_dirs: build_dir_set(excludes.iter(), true)?,
+            ..self
+        })
+    }
+
+    /// Adds the --exclude-dir globs: bare names like `node_modules` (or name
+    /// globs like `*.egg-info`) match a directory at any depth, ones with a
+    /// `/` the directory's whole relative path
+    pub fn with_excluded_dirs(self, globs: &[String]) -> Result<Self, GrssError> {
+        let (exclude_dir_names, exclude_dir_paths) = build_sets(globs.iter(), false)?;
+        Ok(PathFilter {
+            exclude_dir_names,
+            exclude_dir_paths,
+            ..self
+        })
+    }
+
+    /// Adds the globs of the --type types (`select`) and --type-not types
+    /// (`reject`), as looked up in FileTypes
+    ///
+    /// Unlike --exclude these only ever apply to files: a directory called
+    /// `notes.md` is still searched with `--type-not md`
+    pub fn with_types(self, select: &[String], reject: &[String]) -> Result<Self, GrssError> {
+        let (type_names, type_paths) = build_sets(select.iter(), false)?;
+        let (type_not_names, type_not_paths) = build_sets(reject.iter(), false)?;
+        Ok(PathFilter {
+            type_names,
+            type_paths,
+            type_not_names,
+            type_not_paths,
+            ..self
+        })
+    }
+
+    /// Whether the file at `relative` (relative to the search root) should
+    /// be searched
+    ///
+    /// --exclude, `!` --iglob and --type-not win over --include, --iglob
+    /// and --type when both match. With no --include, plain --iglob or
+    /// --type at all, everything that isn't excluded is searched.
+    pub fn is_included(&self, relative: &Path) -> bool {
+        if self.is_excluded(relative)
+            || matches(&self.type_not_names, &self.type_not_paths, relative)
+        {
+            return false;
+        }
+        let none = |sets: &[&GlobSet]| sets.iter().all(|set| set.is_empty());
+        let includes = [
+            &self.include_names,
+            &self.include_paths,
+            &self.iinclude_names,
+            &self.iinclude_paths,
+        ];
+        let included = none(&includes)
+            || matches(&self.include_names, &self.include_paths, relative)
+            || matches(&self.iinclude_names, &self.iinclude_paths, relative);
+        let typed = none(&[&self.type_names, &self.type_paths])
+            || matches(&self.type_names, &self.type_paths, relative);
+        included && typed
+    }
+
+    /// Whether the walk should go into the directory at `relative` at all
+    ///
+    /// --include isn't checked here: `--include '*.rs'` has to look inside
+    /// `src/` even though the name `src` doesn't end in `.rs`
+    pub fn should_descend(&self, relative: &Path) -> bool {
+        !self.is_excluded(relative)
+            && !self.exclude_dirs.is_match(relative)
+            && !self.iexclude_dirs.is_match(relative)
+            && !matches(&self.exclude_dir_names, &self.exclude_dir_paths, relative)
+    }
+
+    fn is_excluded(&self, relative: &Path) -> bool {
+        matches(&self.exclude_names, &self.exclude_paths, relative)
+            || matches(&self.iexclude_names, &self.iexclude_paths, relative)
+    }
+}
+
+/// Splits `globs` into the ones matched against just the file name and the
+/// ones matched against the whole relative path, ignoring case if
+/// `case_insensitive`
+fn build_sets<'a>(
+    globs: impl Iterator<Item = &'a String>,
+    case_insensitive: bool,
+) -> Result<(GlobSet, GlobSet), GrssError> {
+    let mut names = GlobSetBuilder::new();
+    let mut paths = GlobSetBuilder::new();
+    for glob in globs {
+        if glob.contains('/') {
+            paths.add(path_glob(glob, case_insensitive)?);
+        } else {
+            let name = GlobBuilder::new(glob)
+                .case_insensitive(case_insensitive)
+                .build();
+            names.add(name.map_err(glob_error)?);
+        }
+    }
+    let names = names.build().map_err(glob_error)?;
+    let paths = paths.build().map_err(glob_error)?;
+    Ok((names, paths))
+}
+
+/// The directories whose whole contents the exclude `globs` ending in `/**`
+/// leave out (e.g. `target/**` gives `target`)
+fn build_dir_set<'a>(
+    globs: impl Iterator<Item = &'a String>,
+    case_insensitive: bool,
+) -> Result<GlobSet, GrssError> {
+    let mut dirs = GlobSetBuilder::new();
+    for glob in globs {
+        if let Some(dir) = glob.strip_suffix("/**") {
+            dirs.add(path_glob(dir, case_insensitive)?);
+        }
+    }
+    dirs.build().map_err(glob_error)
+}
+
+/// Compiles a glob that's matched against a whole relative path, where `*`
+/// stays within one directory and only `**` crosses `/`
+fn path_glob(glob: &str, case_insensitive: bool) -> Result<Glob, GrssError> {
+    GlobBuilder::new(glob)
+        .literal_separator(true)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(glob_error)
+}
+
+fn glob_error(error: globset::Error) -> GrssError {
+    GrssError::InvalidGlob(error.to_string())
+}
+
+/// Whether `relative` matches one of the name globs by its file name, or
+/// one of the path globs as a whole
+fn matches(names: &GlobSet, paths: &GlobSet, relative: &Path) -> bool {
+    let name_matches = relative
+        .file_name()
+        .is_some_and(|name| names.is_match(Path::new(name)));
+    name_matches || paths.is_match(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+        PathFilter::new(&strings(include), &strings(exclude)).unwrap()
+    }
+
+    /// `globs` the way they come from the command line
+    fn strings(globs: &[&str]) -> Vec<String> {
+        globs.iter().map(|glob| glob.to_string()).collect()
+    }
+
+    #[test]
+    fn no_globs_includes_everything() {
+        let filter = filter(&[], &[]);
+        assert!(filter.is_included(Path::new("src/main.rs")));
+        assert!(filter.should_descend(Path::new("target")));
+    }
+
+    #[test]
+    fn name_globs_match_in_any_directory() {
+        let filter = filter(&["*.rs"], &[]);
+        assert!(filter.is_included(Path::new("main.rs")));
+        assert!(filter.is_included(Path::new("src/deep/lib.rs")));
+        assert!(!filter.is_included(Path::new("Cargo.toml")));
+    }
+
+    #[test]
+    fn path_globs_keep_star_within_one_directory() {
+        let filter = filter(&["**/src/*.rs"], &[]);
+        assert!(filter.is_included(Path::new("src/main.rs")));
+        assert!(filter.is_included(Path::new("crates/grss/src/lib.rs")));
+        assert!(!filter.is_included(Path::new("src/bin/extra.rs")));
+        assert!(!filter.is_included(Path::new("tests/cli.rs")));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter = filter(&["*.rs"], &["build.rs"]);
+        assert!(filter.is_included(Path::new("src/main.rs")));
+        assert!(!filter.is_included(Path::new("build.rs")));
+    }
+
+    #[test]
+    fn directory_globs_prune_the_walk() {
+        let filter = filter(&["*.rs"], &["target/**"]);
+        assert!(!filter.should_descend(Path::new("target")));
+        assert!(!filter.is_included(Path::new("target/debug/build.rs")));
+        assert!(filter.should_descend(Path::new("src")));
+        // Only `target` at the top is excluded, not every directory named so
+        assert!(filter.should_descend(Path::new("src/target")));
+    }
+
+    #[test]
+    fn excluded_directory_names_prune_the_walk() {
+        let filter = filter(&[], &["node_modules"]);
+        assert!(!filter.should_descend(Path::new("web/node_modules")));
+    }
+
+    #[test]
+    fn types_narrow_down_include_and_lose_to_exclusions() {
+        let rust = strings(&["*.rs"]);
+
+        let rust_only = filter(&[], &[]).with_types(&rust, &[]).unwrap();
+        assert!(rust_only.is_included(Path::new("src/main.rs")));
+        assert!(!rust_only.is_included(Path::new("README.md")));
+
+        // Both --include and --type have to match
+        let rust_in_src = filter(&["src/**"], &[]).with_types(&rust, &[]).unwrap();
+        assert!(rust_in_src.is_included(Path::new("src/main.rs")));
+        assert!(!rust_in_src.is_included(Path::new("build.rs")));
+        assert!(!rust_in_src.is_included(Path::new("src/notes.md")));
+
+        // --exclude and --type-not win over both
+        let filter = filter(&["*.rs"], &["build.rs"])
+            .with_types(&rust, &strings(&["*_test.rs"]))
+            .unwrap();
+        assert!(filter.is_included(Path::new("src/main.rs")));
+        assert!(!filter.is_included(Path::new("build.rs")));
+        assert!(!filter.is_included(Path::new("src/parse_test.rs")));
+        // --type-not never prunes directories
+        assert!(filter.should_descend(Path::new("x_test.rs")));
+    }
+
+    #[test]
+    fn exclude_dir_takes_names_and_globs_and_leaves_files_alone() {
+        let pruned = filter(&[], &[])
+            .with_excluded_dirs(&strings(&["node_modules", "*.egg-info", "web/dist"]))
+            .unwrap();
+        assert!(!pruned.should_descend(Path::new("node_modules")));
+        assert!(!pruned.should_descend(Path::new("web/node_modules")));
+        assert!(!pruned.should_descend(Path::new("lib/grss.egg-info")));
+        assert!(!pruned.should_descend(Path::new("web/dist")));
+        assert!(pruned.should_descend(Path::new("dist")));
+        assert!(pruned.should_descend(Path::new("src")));
+        // A file with the same name is still searched
+        assert!(pruned.is_included(Path::new("docs/node_modules")));
+    }
+
+    #[test]
+    fn iglob_ignores_case_and_adds_to_include_and_exclude() {
+        let iglob = filter(&[], &[])
+            .with_iglobs(&strings(&["*.rs", "!Target/**", "!*_TEST.rs"]))
+            .unwrap();
+        assert!(iglob.is_included(Path::new("src/Foo.RS")));
+        assert!(iglob.is_included(Path::new("bar.rs")));
+        assert!(!iglob.is_included(Path::new("README.md")));
+        assert!(!iglob.is_included(Path::new("parse_test.rs")));
+        assert!(!iglob.should_descend(Path::new("target")));
+
+        // A file matching either --include or --iglob is in, and the
+        // exclusions of both win over the includes of both
+        let both = filter(&["*.md"], &["NOTES.md"])
+            .with_iglobs(&strings(&["*.rs", "!skip.*"]))
+            .unwrap();
+        assert!(both.is_included(Path::new("README.md")));
+        assert!(!both.is_included(Path::new("readme.MD")));
+        assert!(both.is_included(Path::new("main.RS")));
+        assert!(!both.is_included(Path::new("NOTES.md")));
+        assert!(!both.is_included(Path::new("SKIP.rs")));
+        assert!(!both.is_included(Path::new("Skip.md")));
+    }
+
+    #[test]
+    fn invalid_glob_is_an_error() {
+        let include = vec!["[abc".to_string()];
+        assert!(PathFilter::new(&include, &[]).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+// FilePath: rust/rust-cli/grss/src/format.rs
+
+//! --format: output lines laid out by a template
+//!
+//! The template is the text of every output line, with placeholders in
+//! braces for what was found: `--format '{path}\t{line}\t{match}'`. It's
+//! parsed once, before the search, into a list of Parts (see Format::parse()),
+//! so printing a line only goes through that list, and a mistake in it stops
+//! grss before anything is searched.
+//!
+//! `{{` and `}}` are literal braces, and the same backslash escapes as
+//! --context-separator takes (`\t`, `\n`, ...) work outside the braces.
+
+use grss::GrssError;
+
+/// What a placeholder stands for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// The path of the file, `(standard input)` for stdin
+    Path,
+    /// The line number
+    Line,
+    /// The column of the match, counted in characters from 1
+    Column,
+    /// The whole line
+    Text,
+    /// The text of the match
+    Match,
+    /// The byte offset of the match (of the line, when the template has
+    /// no match in it)
+    Offset,
+}
+
+/// Every placeholder, by name, in the order the error message lists them
+const FIELDS: [(&str, Field); 6] = [
+    ("path", Field::Path),
+    ("line", Field::Line),
+    ("column", Field::Column),
+    ("text", Field::Text),
+    ("match", Field::Match),
+    ("offset", Field::Offset),
+];
+
+/// One piece of a template
+#[derive(Debug, PartialEq, Eq)]
+pub enum Part {
+    /// Written as it is, escapes already turned into what they stand for
+    Text(Vec<u8>),
+    Field(Field),
+}
+
+/// A parsed --format template
+#[derive(Debug)]
+pub struct Format {
+    parts: Vec<Part>,
+}
+
+impl Format {
+    /// Parses `template`, the argument to --format
+    ///
+    /// An unknown placeholder, a `{` without its `}` and a `}` on its own
+    /// are errors, the first one saying what the placeholders are.
+    pub fn parse(template: &str) -> Result<Self, GrssError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(at) = rest.find(['{', '}']) {
+            text.push_str(&rest[..at]);
+            let (brace, after) = rest[at..].split_at(1);
+            if let Some(after) = after.strip_prefix(brace) {
+                text.push_str(brace);
+                rest = after;
+                continue;
+            }
+            if brace == "}" {
+                return Err(usage("a `}` has to be written `}}`"));
+            }
+            let Some(end) = after.find('}') else {
+                return Err(usage(
+                    "a `{` without its `}` (a `{` on its own is written `{{`)",
+                ));
+            };
+            let name = &after[..end];
+            let Some(&(_, field)) = FIELDS.iter().find(|(known, _)| *known == name) else {
+                let known: Vec<String> = FIELDS
+                    .iter()
+                    .map(|(name, _)| format!("{{{}}}", name))
+                    .collect();
+                return Err(usage(&format!(
+                    "unknown placeholder {{{}}}, the ones there are: {}",
+                    name,
+                    known.join(", ")
+                )));
+            };
+            if !text.is_empty() {
+                parts.push(Part::Text(crate::unescape(&std::mem::take(&mut text))));
+            }
+            parts.push(Part::Field(field));
+            rest = &after[end + 1..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(crate::unescape(&text)));
+        }
+        Ok(Format { parts })
+    }
+
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// Whether a line with several matches gives one output line per match,
+    /// which is when the template has {match} or {column} in it; otherwise
+    /// every selected line gives one
+    pub fn per_match(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Field(Field::Match) | Part::Field(Field::Column)))
+    }
+}
+
+fn usage(message: &str) -> GrssError {
+    GrssError::Usage(format!("invalid --format: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(template: &str) -> Vec<Part> {
+        Format::parse(template).unwrap().parts
+    }
+
+    fn text(text: &str) -> Part {
+        Part::Text(text.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn placeholders_and_text_in_between() {
+        assert_eq!(
+            parts("{path}:{line}: {text}"),
+            [
+                Part::Field(Field::Path),
+                text(":"),
+                Part::Field(Field::Line),
+                text(": "),
+                Part::Field(Field::Text),
+            ]
+        );
+        assert!(parts("").is_empty());
+        assert!(!Format::parse("{path}:{line}:{offset}").unwrap().per_match());
+        assert!(Format::parse("{path}:{column}").unwrap().per_match());
+    }
+
+    #[test]
+    fn braces_and_backslashes_escape() {
+        assert_eq!(
+            parts(r"{{{match}}}\t{{line}}\n"),
+            [text("{"), Part::Field(Field::Match), text("}\t{line}\n")]
+        );
+        // A `\{` is just a backslash before a placeholder
+        assert_eq!(parts(r"\{line}"), [text("\\"), Part::Field(Field::Line)]);
+    }
+
+    #[test]
+    fn unknown_placeholders_and_lone_braces_are_errors() {
+        let error = |template| Format::parse(template).unwrap_err().to_string();
+        assert_eq!(
+            error("{path}:{lines}"),
+            "invalid --format: unknown placeholder {lines}, the ones there are: \
+             {path}, {line}, {column}, {text}, {match}, {offset}"
+        );
+        assert!(error("{path").contains("a `{` without its `}`"));
+        assert!(error("path}").contains("a `}` has to be written `}}`"));
+        assert!(error("{}").contains("unknown placeholder {}"));
+    }
+}
@@ -0,0 +1,301 @@
+// FilePath: rust/rust-cli/grss/src/ignore_files.rs
+
+//! Leaving out what ignore files say to during a recursive search
+//!
+//! Every directory the walk goes into can have a .gitignore and a
+//! .grssignore (the same syntax, for trees that aren't git repositories, or
+//! for what git should track but searches should skip). Their patterns apply
+//! to everything below that directory, and the ones in deeper directories
+//! win over the ones above them (a `!keep.log` in `logs/` brings back a file
+//! the top-level `*.log` left out). Within one directory .grssignore wins
+//! over .gitignore.
+//!
+//! Ignore files above the search root count too, as far up as the top of
+//! the git repository the root is in, so searching `src/` of a project still
+//! skips what the project's own .gitignore lists. Above a root that isn't in
+//! a git repository at all nothing is looked at: a stray .gitignore in the
+//! home directory shouldn't hide files from every search.
+//!
+//! Files given with --ignore-file have their patterns rooted at the search
+//! root, wherever the file itself is, and lose to every ignore file found
+//! in the tree.
+//!
+//! Patterns are matched against paths made absolute from the search root,
+//! which is also what each file's patterns are rooted at, so a `/build` in
+//! the top-level .gitignore means the build directory there and nowhere
+//! else. A pattern that isn't valid is warned about with the file and line
+//! it's on, and the rest of the file still counts.
+
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+
+/// The ignore files looked for in every directory, in order of precedence:
+/// a later one wins over an earlier one
+const VCS_IGNORE: &str = ".gitignore";
+const GRSS_IGNORE: &str = ".grssignore";
+
+/// Which ignore files a walk honors, from --no-ignore and friends
+///
+/// The default honors none at all, which is what the library's own users
+/// get unless they ask; the grss binary turns them on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IgnoreOptions {
+    /// Honor .gitignore files (off with --no-ignore or --no-ignore-vcs)
+    pub vcs: bool,
+    /// Honor .grssignore files (off with --no-ignore)
+    pub grss: bool,
+    /// Also honor the ones in directories above the search root (off with
+    /// --no-ignore-parent)
+    pub parents: bool,
+    /// More ignore files, from --ignore-file, rooted at the search root
+    pub files: Vec<PathBuf>,
+}
+
+impl IgnoreOptions {
+    /// The names of the ignore files looked for in every directory, the
+    /// one that wins last
+    fn file_names(&self) -> impl Iterator<Item = &'static str> {
+        [(self.vcs, VCS_IGNORE), (self.grss, GRSS_IGNORE)]
+            .into_iter()
+            .filter_map(|(honored, name)| honored.then_some(name))
+    }
+}
+
+/// The ignore files in force at some point of a walk: one set of patterns
+/// per directory between the root and there, plus the ones above the root
+/// and the --ignore-file ones
+pub struct IgnoreStack {
+    /// The names of the ignore files looked for in every directory
+    names: Vec<&'static str>,
+    /// The depth of each directory and its patterns, deepest last
+    dirs: Vec<(usize, Gitignore)>,
+    /// The patterns from above the root, nearest first, then the
+    /// --ignore-file ones
+    outer: Vec<Gitignore>,
+}
+
+impl IgnoreStack {
+    /// Starts a walk of `root`, which has to be absolute, loading the
+    /// ignore files above it if `options` says so, and the --ignore-file
+    /// ones
+    pub fn new(root: &Path, options: &IgnoreOptions) -> Self {
+        let mut stack = IgnoreStack {
+            names: options.file_names().collect(),
+            dirs: Vec::new(),
+            outer: Vec::new(),
+        };
+        if options.parents && !is_repository_top(root) {
+            let mut parents = Vec::new();
+            for dir in root.ancestors().skip(1) {
+                parents.extend(stack.load(dir));
+                if is_repository_top(dir) {
+                    stack.outer = parents;
+                    break;
+                }
+            }
+        }
+        let files = options.files.iter().map(PathBuf::as_path);
+        stack.outer.extend(build(root, files));
+        stack
+    }
+
+    /// Loads the ignore files in `dir`, which is at `depth` below the root,
+    /// for everything underneath it
+    pub fn enter(&mut self, depth: usize, dir: &Path) {
+        self.leave(depth);
+        if let Some(patterns) = self.load(dir) {
+            self.dirs.push((depth, patterns));
+        }
+    }
+
+    /// Whether `path` (absolute, at `depth` below the root) is ignored
+    ///
+    /// Entries have to be asked about in the order the walk finds them:
+    /// asking about one drops the patterns of directories it isn't in.
+    pub fn is_ignored(&mut self, depth: usize, path: &Path, is_dir: bool) -> bool {
+        self.leave(depth);
+        let nearest_first = self
+            .dirs
+            .iter()
+            .rev()
+            .map(|(_, patterns)| patterns)
+            .chain(&self.outer);
+        for patterns in nearest_first {
+            match patterns.matched(path, is_dir) {
+                Match::None => continue,
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+        false
+    }
+
+    /// Drops the patterns of directories at `depth` or deeper, which the
+    /// walk has left by the time it's at an entry that deep
+    fn leave(&mut self, depth: usize) {
+        while self.dirs.last().is_some_and(|(dir, _)| *dir >= depth) {
+            self.dirs.pop();
+        }
+    }
+
+    /// The patterns of the ignore files in `dir`, if it has any
+    fn load(&self, dir: &Path) -> Option<Gitignore> {
+        let files: Vec<PathBuf> = self
+            .names
+            .iter()
+            .map(|name| dir.join(name))
+            .filter(|file| file.is_file())
+            .collect();
+        build(dir, files.iter().map(PathBuf::as_path))
+    }
+}
+
+/// The patterns of `files`, rooted at `root`, with the later files winning
+///
+/// # Returns
+/// None when there are no files, or nothing in them could be used
+fn build<'f>(root: &Path, files: impl Iterator<Item = &'f Path>) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    let mut any = false;
+    for file in files {
+        any = true;
+        if let Some(error) = builder.add(file) {
+            warn(error);
+        }
+    }
+    if !any {
+        return None;
+    }
+    match builder.build() {
+        Ok(patterns) => Some(patterns),
+        Err(error) => {
+            warn(error);
+            None
+        }
+    }
+}
+
+/// Warns about every problem in `error`, each on a line of its own
+fn warn(error: ignore::Error) {
+    match error {
+        ignore::Error::Partial(errors) => errors.into_iter().for_each(warn),
+        error => log::warn!("{}", error),
+    }
+}
+
+/// Whether `dir` is the top of a git repository (or a worktree of one, where
+/// .git is a file)
+fn is_repository_top(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn all() -> IgnoreOptions {
+        IgnoreOptions {
+            vcs: true,
+            grss: true,
+            parents: true,
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn deeper_files_win_and_are_dropped_on_the_way_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n/build\n").unwrap();
+        fs::write(root.join("logs/.gitignore"), "!keep.log\n").unwrap();
+
+        let mut stack = IgnoreStack::new(root, &all());
+        stack.enter(0, root);
+        assert!(stack.is_ignored(1, &root.join("app.log"), false));
+        assert!(stack.is_ignored(1, &root.join("build"), true));
+        assert!(!stack.is_ignored(1, &root.join("main.rs"), false));
+
+        stack.enter(1, &root.join("logs"));
+        assert!(!stack.is_ignored(2, &root.join("logs/keep.log"), false));
+        assert!(stack.is_ignored(2, &root.join("logs/other.log"), false));
+        // `/build` only means the one at the top
+        assert!(!stack.is_ignored(2, &root.join("logs/build"), true));
+
+        // Back at depth 1, logs/.gitignore no longer applies
+        assert!(stack.is_ignored(1, &root.join("keep.log"), false));
+    }
+
+    #[test]
+    fn parents_count_up_to_the_repository_top_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let top = dir.path().join("repo");
+        fs::create_dir_all(top.join(".git")).unwrap();
+        fs::create_dir_all(top.join("src")).unwrap();
+        fs::write(top.join(".gitignore"), "*.log\n").unwrap();
+        let src = top.join("src");
+
+        let mut stack = IgnoreStack::new(&src, &all());
+        stack.enter(0, &src);
+        assert!(stack.is_ignored(1, &src.join("debug.log"), false));
+
+        let no_parents = IgnoreOptions {
+            parents: false,
+            ..all()
+        };
+        let mut stack = IgnoreStack::new(&src, &no_parents);
+        stack.enter(0, &src);
+        assert!(!stack.is_ignored(1, &src.join("debug.log"), false));
+
+        // Without a repository above, a .gitignore up there doesn't count
+        let outside = dir.path().join("outside/src");
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(dir.path().join("outside/.gitignore"), "*.log\n").unwrap();
+        let mut stack = IgnoreStack::new(&outside, &all());
+        stack.enter(0, &outside);
+        assert!(!stack.is_ignored(1, &outside.join("debug.log"), false));
+    }
+
+    #[test]
+    fn nothing_is_ignored_without_vcs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        let mut stack = IgnoreStack::new(root, &IgnoreOptions::default());
+        stack.enter(0, root);
+        assert!(!stack.is_ignored(1, &root.join("app.log"), false));
+    }
+
+    #[test]
+    fn grssignore_wins_over_gitignore_and_ignore_files_lose_to_both() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join(".grssignore"), "!keep.log\n*.tmp\n").unwrap();
+        let extra = root.join("extra-ignore");
+        fs::write(&extra, "!wanted.tmp\n/notes.md\n").unwrap();
+        let options = IgnoreOptions {
+            files: vec![extra],
+            ..all()
+        };
+
+        let mut stack = IgnoreStack::new(root, &options);
+        stack.enter(0, root);
+        assert!(stack.is_ignored(1, &root.join("app.log"), false));
+        assert!(!stack.is_ignored(1, &root.join("keep.log"), false));
+        assert!(stack.is_ignored(1, &root.join("wanted.tmp"), false));
+        assert!(stack.is_ignored(1, &root.join("notes.md"), false));
+
+        // --no-ignore-vcs keeps .grssignore
+        let grss_only = IgnoreOptions {
+            vcs: false,
+            ..all()
+        };
+        let mut stack = IgnoreStack::new(root, &grss_only);
+        stack.enter(0, root);
+        assert!(!stack.is_ignored(1, &root.join("app.log"), false));
+        assert!(stack.is_ignored(1, &root.join("scratch.tmp"), false));
+    }
+}
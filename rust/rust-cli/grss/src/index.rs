@@ -0,0 +1,689 @@
+// FilePath: rust/rust-cli/grss/src/index.rs
+
+//! `grss index`: a trigram index of a directory tree, so that searching it
+//! again doesn't have to read every file in it
+//!
+//! For every three bytes in a row (a "trigram") found anywhere in the tree,
+//! the index knows which files have them, and for every file its size and
+//! modification time when it was indexed. All the matches of a pattern like
+//! `Rectangle::new\(\d+` contain `Rectangle::new(` (see
+//! matcher::required_texts()), so a file without one of that text's
+//! trigrams can't match, and as long as it's still the file that was
+//! indexed (same size, same modification time, the test make uses) it can
+//! be skipped without opening it. Every other file is searched as usual:
+//! one changed or added since, one the index left out, and all of them for
+//! a pattern without three bytes of text every match has. So a stale index
+//! only makes a search slower, never different.
+//!
+//! The index is one file, `.grss-index/trigrams` inside the directory
+//! indexed, all its numbers little endian:
+//!
+//! - the bytes `grssidx1`, which change whenever the layout does
+//! - the number of files (u32), then for each its path relative to the
+//!   directory (u32 length, UTF-8), its modification time (u64 seconds and
+//!   u32 nanoseconds since the Unix epoch) and its size (u64); the files
+//!   are numbered by this order, from 0
+//! - the number of trigrams (u32), then for each, by ascending trigram, the
+//!   trigram (u32, its bytes in the low 24 bits), and where its list of
+//!   files starts after the table (u64) and how many bytes it takes (u32)
+//! - the lists: the numbers of the files with the trigram, ascending, each
+//!   as a LEB128 varint of how much it's bigger than the one before
+//!
+//! A search memory-maps it and binary searches the table where it is, so
+//! it only ever reads the lists of its own trigrams.
+
+use crate::filter::PathFilter;
+use crate::ignore_files::IgnoreOptions;
+use crate::input;
+use crate::walk::{WalkOptions, relative_path, walk_files};
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::{ControlFlow, Range};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The directory the index goes in, inside the directory indexed
+pub const INDEX_DIR: &str = ".grss-index";
+
+/// The index file's name in INDEX_DIR
+const INDEX_FILE: &str = "trigrams";
+
+const MAGIC: &[u8; 8] = b"grssidx1";
+
+/// The bytes of one entry of the trigram table: trigram, offset, length
+const ENTRY_LEN: usize = 16;
+
+/// Files bigger than this aren't indexed but always searched: they hold
+/// most trigrams there are anyway, and would put their number in nearly
+/// every list
+pub const MAX_INDEXED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// What the index knows about one file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IndexedFile {
+    /// Its number in the lists of files
+    pub id: u32,
+    pub modified: SystemTime,
+    pub size: u64,
+}
+
+impl IndexedFile {
+    /// Whether the file, now that it has `metadata`, is still the one that
+    /// was indexed
+    pub fn is_current(&self, metadata: &fs::Metadata) -> bool {
+        metadata.len() == self.size
+            && metadata
+                .modified()
+                .is_ok_and(|modified| modified == self.modified)
+    }
+}
+
+/// How building an index went, for `grss index` to report
+#[derive(Debug)]
+pub struct Built {
+    pub files: usize,
+    pub trigrams: usize,
+    /// The size of the index file
+    pub bytes: u64,
+}
+
+/// How an index compares to the files under its directory now
+#[derive(Debug, Default, PartialEq)]
+pub struct Status {
+    /// Files in the index that haven't changed since
+    pub current: usize,
+    /// Files in the index that have
+    pub changed: usize,
+    /// Files in the index that aren't there any more
+    pub gone: usize,
+    /// Files that aren't in the index: added since, or left out of it
+    pub unindexed: usize,
+}
+
+/// An index read back from disk
+pub struct Index {
+    /// The directory indexed, canonicalized
+    root: PathBuf,
+    files: HashMap<PathBuf, IndexedFile>,
+    map: Mmap,
+    /// Where in `map` the trigram table is, the lists come right after it
+    table: Range<usize>,
+}
+
+/// What a search of one directory takes from an index: which files in it
+/// don't need searching
+pub struct IndexedSearch {
+    index: Index,
+    /// The directory searched, relative to the one indexed
+    base: PathBuf,
+    /// The files that could match, ascending
+    candidates: Vec<u32>,
+}
+
+/// Indexes every file under `root` that a search goes through by default
+/// (so no hidden or ignored ones), writing `.grss-index/trigrams` in it
+///
+/// Files that can't be read are left out with a warning, and so are files
+/// bigger than MAX_INDEXED_SIZE, ones in UTF-16 (which a search decodes
+/// before matching) and ones whose path isn't UTF-8. A search just reads
+/// them every time. The index is written to a temporary file first and
+/// renamed over the old one, so a search running at the same time sees
+/// either index whole.
+pub fn build(root: &Path) -> io::Result<Built> {
+    let filter = PathFilter::new(&[], &[]).expect("no globs to get wrong");
+    let options = WalkOptions {
+        ignore: IgnoreOptions {
+            vcs: true,
+            grss: true,
+            parents: true,
+            files: Vec::new(),
+        },
+        ..WalkOptions::default()
+    };
+    let mut files = Vec::new();
+    let mut lists: HashMap<u32, List> = HashMap::new();
+    let mut trigrams = Trigrams::new();
+    let on_error = |error| log::warn!("{}", error);
+    walk_files(root, &filter, options, on_error, |path| {
+        let Some(name) = relative_path(root, &path).to_str() else {
+            log::info!("{}: not indexed, its path isn't UTF-8", path.display());
+            return ControlFlow::Continue(());
+        };
+        match read_file(&path, &mut trigrams) {
+            Ok(Some((modified, size))) => {
+                let id = files.len() as u32;
+                for trigram in trigrams.take() {
+                    lists.entry(trigram).or_default().push(id);
+                }
+                files.push((name.to_string(), modified, size));
+            }
+            Ok(None) => {}
+            Err(error) => log::warn!("{}: {}", path.display(), error),
+        }
+        ControlFlow::Continue(())
+    });
+
+    let dir = root.join(INDEX_DIR);
+    fs::create_dir_all(&dir)?;
+    let mut temporary = tempfile::NamedTempFile::new_in(&dir)?;
+    let mut out = BufWriter::new(temporary.as_file_mut());
+    out.write_all(MAGIC)?;
+    out.write_all(&(files.len() as u32).to_le_bytes())?;
+    for (name, modified, size) in &files {
+        out.write_all(&(name.len() as u32).to_le_bytes())?;
+        out.write_all(name.as_bytes())?;
+        out.write_all(&modified.as_secs().to_le_bytes())?;
+        out.write_all(&modified.subsec_nanos().to_le_bytes())?;
+        out.write_all(&size.to_le_bytes())?;
+    }
+    let mut lists: Vec<(u32, List)> = lists.into_iter().collect();
+    lists.sort_unstable_by_key(|(trigram, _)| *trigram);
+    out.write_all(&(lists.len() as u32).to_le_bytes())?;
+    let mut offset = 0u64;
+    for (trigram, list) in &lists {
+        out.write_all(&trigram.to_le_bytes())?;
+        out.write_all(&offset.to_le_bytes())?;
+        out.write_all(&(list.bytes.len() as u32).to_le_bytes())?;
+        offset += list.bytes.len() as u64;
+    }
+    for (_, list) in &lists {
+        out.write_all(&list.bytes)?;
+    }
+    out.flush()?;
+    drop(out);
+    let path = dir.join(INDEX_FILE);
+    temporary.persist(&path).map_err(|error| error.error)?;
+    Ok(Built {
+        files: files.len(),
+        trigrams: lists.len(),
+        bytes: fs::metadata(&path)?.len(),
+    })
+}
+
+/// Reads the file at `path` into `trigrams`
+///
+/// # Returns
+/// Its modification time (since the Unix epoch) and size from before it was
+/// read, or None when it's not to be indexed
+fn read_file(path: &Path, trigrams: &mut Trigrams) -> io::Result<Option<(Duration, u64)>> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if metadata.len() > MAX_INDEXED_SIZE {
+        log::info!("{}: not indexed, it's too big", path.display());
+        return Ok(None);
+    }
+    // Files from before 1970 are rare enough to just always search
+    let Ok(modified) = metadata.modified()?.duration_since(UNIX_EPOCH) else {
+        return Ok(None);
+    };
+    let mut buffer = vec![0; input::BINARY_CHECK_LEN];
+    let mut start = true;
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => {
+                trigrams.take();
+                return Err(error);
+            }
+        };
+        if start && input::has_utf16_bom(&buffer[..read]) {
+            log::info!("{}: not indexed, it's UTF-16", path.display());
+            trigrams.take();
+            return Ok(None);
+        }
+        start = false;
+        trigrams.add(&buffer[..read]);
+    }
+    Ok(Some((modified, metadata.len())))
+}
+
+/// The different trigrams in one file, collected as it's read
+struct Trigrams {
+    /// A bit for every trigram there is, set once it's been found
+    seen: Vec<u64>,
+    found: Vec<u32>,
+    /// The last three bytes, in the low 24 bits
+    window: u32,
+    /// How many bytes went into `window` so far
+    bytes: usize,
+}
+
+impl Trigrams {
+    fn new() -> Self {
+        Trigrams {
+            seen: vec![0; (1 << 24) / 64],
+            found: Vec::new(),
+            window: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Adds the trigrams in `bytes`, including the ones that start in the
+    /// bytes added before
+    fn add(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.window = (self.window << 8 | u32::from(byte)) & 0xFF_FFFF;
+            self.bytes += 1;
+            let (word, bit) = (self.window as usize / 64, self.window % 64);
+            if self.bytes >= 3 && self.seen[word] & 1 << bit == 0 {
+                self.seen[word] |= 1 << bit;
+                self.found.push(self.window);
+            }
+        }
+    }
+
+    /// The trigrams found since the last take(), starting over for the next
+    /// file
+    fn take(&mut self) -> Vec<u32> {
+        for &trigram in &self.found {
+            self.seen[trigram as usize / 64] = 0;
+        }
+        self.window = 0;
+        self.bytes = 0;
+        std::mem::take(&mut self.found)
+    }
+}
+
+/// One trigram's list of files while it's built, already encoded
+#[derive(Default)]
+struct List {
+    last: u32,
+    bytes: Vec<u8>,
+}
+
+impl List {
+    /// Adds file `id`, which comes after every one added before
+    fn push(&mut self, id: u32) {
+        let mut delta = id - self.last;
+        self.last = id;
+        loop {
+            let byte = (delta & 0x7F) as u8;
+            delta >>= 7;
+            if delta == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+}
+
+/// The file numbers in an encoded list, or None when it's cut off
+fn decode_list(mut bytes: &[u8]) -> Option<Vec<u32>> {
+    let mut ids = Vec::new();
+    let mut id = 0u32;
+    while !bytes.is_empty() {
+        let mut delta = 0u32;
+        for shift in (0..32).step_by(7) {
+            let (&byte, rest) = bytes.split_first()?;
+            bytes = rest;
+            delta |= u32::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        id = id.checked_add(delta)?;
+        ids.push(id);
+    }
+    Some(ids)
+}
+
+/// The numbers in both `a` and `b`, which are ascending
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut both = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                both.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    both
+}
+
+/// Goes through the bytes of an index file front to back
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .at
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| invalid("it's cut off"))?;
+        let taken = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+fn invalid(why: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("not a grss index this version can read, {}", why),
+    )
+}
+
+impl Index {
+    /// Reads the index built in `root`
+    ///
+    /// # Returns
+    /// None when there's none, an InvalidData error when there's one that
+    /// isn't readable (e.g. built by a grss with another layout)
+    pub fn open(root: &Path) -> io::Result<Option<Index>> {
+        let root = fs::canonicalize(root)?;
+        let file = match File::open(root.join(INDEX_DIR).join(INDEX_FILE)) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        // build() only ever renames a new index over the old one, so the
+        // file mapped here is never truncated under us
+        let map = input::map_file(&file).ok_or_else(|| invalid("it's empty"))?;
+        let mut reader = Reader { bytes: &map, at: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("it starts with the wrong bytes"));
+        }
+        let mut files = HashMap::new();
+        for id in 0..reader.u32()? {
+            let len = reader.u32()? as usize;
+            let name = std::str::from_utf8(reader.take(len)?)
+                .map_err(|_| invalid("a path isn't UTF-8"))?;
+            let (seconds, nanos) = (reader.u64()?, reader.u32()?);
+            let modified = (nanos < 1_000_000_000)
+                .then(|| UNIX_EPOCH.checked_add(Duration::new(seconds, nanos)))
+                .flatten()
+                .ok_or_else(|| invalid("a modification time is out of range"))?;
+            let size = reader.u64()?;
+            let file = IndexedFile { id, modified, size };
+            files.insert(PathBuf::from(name), file);
+        }
+        let trigrams = reader.u32()? as usize;
+        let start = reader.at;
+        reader.take(trigrams * ENTRY_LEN)?;
+        let table = start..reader.at;
+        Ok(Some(Index {
+            root,
+            files,
+            map,
+            table,
+        }))
+    }
+
+    /// Finds the index for `dir`: one built in it or in a directory above
+    ///
+    /// # Returns
+    /// The index and `dir` relative to the directory it was built in, None
+    /// when there's none
+    pub fn find(dir: &Path) -> io::Result<Option<(Index, PathBuf)>> {
+        let dir = fs::canonicalize(dir)?;
+        for root in dir.ancestors() {
+            if root.join(INDEX_DIR).join(INDEX_FILE).is_file()
+                && let Some(index) = Index::open(root)?
+            {
+                let base = dir.strip_prefix(root).unwrap_or(&dir).to_path_buf();
+                return Ok(Some((index, base)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The directory indexed, canonicalized
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// What the index knows about the file at `path`, relative to root()
+    pub fn file(&self, path: &Path) -> Option<&IndexedFile> {
+        self.files.get(path)
+    }
+
+    /// How many files are in the index
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The files that could have a match of patterns whose matches each
+    /// contain one of `texts` (see matcher::required_texts()): the ones
+    /// with every trigram of at least one of them
+    ///
+    /// # Returns
+    /// Their numbers, ascending, or None when the index can't tell: one of
+    /// `texts` is shorter than a trigram, or the index is damaged
+    pub fn candidates(&self, texts: &[Vec<u8>]) -> Option<Vec<u32>> {
+        let mut candidates = Vec::new();
+        for text in texts {
+            if text.len() < 3 {
+                return None;
+            }
+            let mut with_text: Option<Vec<u32>> = None;
+            for window in text.windows(3) {
+                let files =
+                    self.files_with(u32::from_be_bytes([0, window[0], window[1], window[2]]))?;
+                let files = match with_text {
+                    Some(before) => intersect(&before, &files),
+                    None => files,
+                };
+                let done = files.is_empty();
+                with_text = Some(files);
+                if done {
+                    break;
+                }
+            }
+            candidates.extend(with_text.unwrap_or_default());
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        Some(candidates)
+    }
+
+    /// The files that have `trigram`, or None when its list can't be read
+    fn files_with(&self, trigram: u32) -> Option<Vec<u32>> {
+        let table = &self.map[self.table.clone()];
+        let entries = table.len() / ENTRY_LEN;
+        let entry = |at: usize| &table[at * ENTRY_LEN..(at + 1) * ENTRY_LEN];
+        let (mut low, mut high) = (0, entries);
+        while low < high {
+            let middle = (low + high) / 2;
+            let entry = entry(middle);
+            let found = u32::from_le_bytes(entry[..4].try_into().unwrap());
+            if found < trigram {
+                low = middle + 1;
+            } else if found > trigram {
+                high = middle;
+            } else {
+                let offset = u64::from_le_bytes(entry[4..12].try_into().unwrap());
+                let len = u32::from_le_bytes(entry[12..].try_into().unwrap());
+                let start = self.table.end.checked_add(usize::try_from(offset).ok()?)?;
+                let list = self.map.get(start..start.checked_add(len as usize)?)?;
+                return decode_list(list);
+            }
+        }
+        Some(Vec::new())
+    }
+
+    /// Compares the index to the files under root() now, going through
+    /// them like build() does
+    pub fn status(&self) -> Status {
+        let filter = PathFilter::new(&[], &[]).expect("no globs to get wrong");
+        let options = WalkOptions {
+            ignore: IgnoreOptions {
+                vcs: true,
+                grss: true,
+                parents: true,
+                files: Vec::new(),
+            },
+            ..WalkOptions::default()
+        };
+        let mut status = Status::default();
+        let on_error = |error| log::warn!("{}", error);
+        walk_files(&self.root, &filter, options, on_error, |path| {
+            let metadata = fs::metadata(&path).ok();
+            match self.file(relative_path(&self.root, &path)) {
+                Some(file) if metadata.is_some_and(|metadata| file.is_current(&metadata)) => {
+                    status.current += 1
+                }
+                Some(_) => status.changed += 1,
+                None => status.unindexed += 1,
+            }
+            ControlFlow::Continue(())
+        });
+        status.gone = self.len() - status.current - status.changed;
+        status
+    }
+}
+
+impl IndexedSearch {
+    /// Looks for an index of `dir` (see Index::find()) for a search for
+    /// patterns whose matches each contain one of `texts`
+    ///
+    /// # Returns
+    /// None when there's no index, or it's of no help with `texts`. An index
+    /// that can't be read is warned about, and the search goes on without.
+    pub fn new(dir: &Path, texts: &[Vec<u8>]) -> Option<Self> {
+        let (index, base) = match Index::find(dir) {
+            Ok(found) => found?,
+            Err(error) => {
+                log::warn!("{}: index not used, {}", dir.display(), error);
+                return None;
+            }
+        };
+        let candidates = index.candidates(texts)?;
+        log::info!(
+            "{}: using the index, {} of its {} files could match",
+            index.root().display(),
+            candidates.len(),
+            index.len()
+        );
+        Some(IndexedSearch {
+            index,
+            base,
+            candidates,
+        })
+    }
+
+    /// Whether the file at `path`, relative to the directory searched and
+    /// with `metadata`, can be left out: it's in the index as it is now,
+    /// without the text
+    pub fn can_skip(&self, path: &Path, metadata: &fs::Metadata) -> bool {
+        self.index.file(&self.base.join(path)).is_some_and(|file| {
+            file.is_current(metadata) && self.candidates.binary_search(&file.id).is_err()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(texts: &[&str]) -> Vec<Vec<u8>> {
+        texts.iter().map(|text| text.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn lists_round_trip_through_their_encoding() {
+        let mut list = List::default();
+        let ids = [0, 1, 5, 127, 128, 300, 70_000, u32::MAX];
+        for id in ids {
+            list.push(id);
+        }
+        assert_eq!(decode_list(&list.bytes).unwrap(), ids);
+        assert_eq!(decode_list(&[0x80]), None);
+        assert_eq!(intersect(&[1, 3, 5, 8], &[2, 3, 8, 9]), [3, 8]);
+    }
+
+    #[test]
+    fn only_files_with_every_trigram_are_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("a.rs"),
+            "let shape = Rectangle::new(1, 2);\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("b.rs"), "let shape = Circle::new(3);\n").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        // Every trigram of "Rectangle", but never all in a row
+        fs::write(dir.path().join("sub/c.rs"), "Rec ect cta tan ang ngl gle\n").unwrap();
+        let built = build(dir.path()).unwrap();
+        assert_eq!(built.files, 3);
+
+        let index = Index::open(dir.path()).unwrap().unwrap();
+        let id = |path: &str| index.file(Path::new(path)).unwrap().id;
+        let (a, b, c) = (id("a.rs"), id("b.rs"), id("sub/c.rs"));
+        assert_eq!(
+            index.candidates(&texts(&["Rectangle"])),
+            Some(vec![a.min(c), a.max(c)])
+        );
+        assert_eq!(index.candidates(&texts(&["Rectangle::"])), Some(vec![a]));
+        let mut either = vec![a, b];
+        either.sort();
+        assert_eq!(
+            index.candidates(&texts(&["Rectangle::", "Circle"])),
+            Some(either)
+        );
+        assert_eq!(index.candidates(&texts(&["Triangle"])), Some(vec![]));
+        assert_eq!(index.candidates(&texts(&["Rectangle", "::"])), None);
+    }
+
+    #[test]
+    fn changed_files_are_never_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sub/notes.txt");
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(&path, "nothing to see\n").unwrap();
+        build(dir.path()).unwrap();
+
+        let search = IndexedSearch::new(&dir.path().join("sub"), &texts(&["needle"])).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(search.can_skip(Path::new("notes.txt"), &metadata));
+        assert!(!search.can_skip(Path::new("other.txt"), &metadata));
+
+        fs::write(&path, "a needle, at last\n").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(!search.can_skip(Path::new("notes.txt"), &metadata));
+        let index = Index::open(dir.path()).unwrap().unwrap();
+        assert_eq!(
+            index.status(),
+            Status {
+                changed: 1,
+                ..Status::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unreadable_indexes_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Index::open(dir.path()).unwrap().is_none());
+        fs::create_dir(dir.path().join(INDEX_DIR)).unwrap();
+        let path = dir.path().join(INDEX_DIR).join(INDEX_FILE);
+        fs::write(&path, b"grssidx0").unwrap();
+        assert!(Index::open(dir.path()).is_err());
+        fs::write(&path, b"grssidx1\x05\0\0\0").unwrap();
+        assert!(Index::open(dir.path()).is_err());
+    }
+}
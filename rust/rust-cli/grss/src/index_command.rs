@@ -0,0 +1,80 @@
+// FilePath: rust/rust-cli/grss/src/index_command.rs
+
+//! `grss index [PATH]`: building the trigram index searches of PATH use
+//!
+//! The index goes in `PATH/.grss-index/` (see grss::index for what's in it
+//! and how a search uses it). Nothing keeps it up to date by itself: files
+//! changed since are just searched again every time, so running `grss index`
+//! again now and then, e.g. from a git hook, keeps it worth having. `grss
+//! index --status` says how far behind it is.
+
+use clap::{Parser, ValueHint};
+use grss::GrssError;
+use grss::index::{self, Index};
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Build an index of a directory, so searches of it can skip the files that
+/// can't match without reading them
+#[derive(Parser)]
+#[command(name = "grss index", bin_name = "grss index")]
+pub struct IndexCli {
+    /// The directory to index
+    #[arg(default_value = ".", value_hint = ValueHint::DirPath)]
+    path: PathBuf,
+    /// Instead of building the index, print how many of its files changed
+    /// since it was built
+    #[arg(long)]
+    status: bool,
+}
+
+/// Parses the arguments after `grss` (starting with `index` itself) and
+/// builds the index, or reports on it
+///
+/// # Returns
+/// True, except for --status when there's no index
+pub fn run(argv: impl IntoIterator<Item = OsString>) -> Result<bool, GrssError> {
+    let args = IndexCli::parse_from(argv);
+    crate::init_logging(0, false);
+    if !args.path.is_dir() {
+        return Err(GrssError::Usage(format!(
+            "{}: not a directory, only directories are indexed",
+            args.path.display()
+        )));
+    }
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let written = if args.status {
+        let Some(index) =
+            Index::open(&args.path).map_err(|error| GrssError::io(&args.path, error))?
+        else {
+            writeln!(out, "{}: not indexed", args.path.display())
+                .map_err(|error| GrssError::io(Path::new("(standard output)"), error))?;
+            return Ok(false);
+        };
+        let status = index.status();
+        writeln!(
+            out,
+            "{}: {} files indexed, {} unchanged, {} changed, {} gone, {} not indexed",
+            index.root().display(),
+            index.len(),
+            status.current,
+            status.changed,
+            status.gone,
+            status.unindexed
+        )
+    } else {
+        let built = index::build(&args.path).map_err(|error| GrssError::io(&args.path, error))?;
+        writeln!(
+            out,
+            "{}: indexed {} files, {} trigrams, {} bytes",
+            args.path.join(index::INDEX_DIR).display(),
+            built.files,
+            built.trigrams,
+            built.bytes
+        )
+    };
+    written.map_err(|error| GrssError::io(Path::new("(standard output)"), error))?;
+    Ok(true)
+}
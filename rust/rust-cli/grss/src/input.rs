@@ -0,0 +1,187 @@
+// FilePath: rust/rust-cli/grss/src/input.rs
+
+//! Getting at the text inside a file: telling binary files apart, undoing
+//! byte order marks and other encodings, decompressing gzip for
+//! --search-zip, capping zip entries for --search-archives, and
+//! memory-mapping for --mmap
+
+use encoding_rs::Encoding;
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+use flate2::read::MultiGzDecoder;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// How much of the start of a file is_binary() looks at - the same as a
+/// BufReader's default buffer, so the buffered path checks exactly one fill
+pub const BINARY_CHECK_LEN: usize = 8 * 1024;
+
+/// Guesses whether a file is binary the same way grep does: text files
+/// practically never contain a NUL byte, binary files almost always do
+///
+/// Only the start of the file (`head`) is checked, not the whole thing
+pub fn is_binary(head: &[u8]) -> bool {
+    head.contains(&0)
+}
+
+/// The byte order mark some editors put at the start of UTF-8 files
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Whether `data` starts with a UTF-16 (little or big endian) byte order mark
+pub fn has_utf16_bom(data: &[u8]) -> bool {
+    data.starts_with(b"\xFF\xFE") || data.starts_with(b"\xFE\xFF")
+}
+
+/// Whether the file at `path` is gzip-compressed, going by its `.gz`
+/// extension, so --search-zip should decompress it
+pub fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "gz")
+}
+
+/// Wraps `reader` so that reading from it gives the decompressed contents
+/// of the gzip data it holds
+///
+/// Decompression happens a buffer at a time as the search reads, so even a
+/// huge log never has to fit in memory. Rotated logs are sometimes several
+/// gzip streams one after the other (`cat a.gz b.gz`), which MultiGzDecoder
+/// reads as one. Data that isn't valid gzip gives an InvalidInput or
+/// InvalidData error from read(), which is reported like any other
+/// unreadable file.
+pub fn gunzip<R: Read>(reader: R) -> MultiGzDecoder<R> {
+    MultiGzDecoder::new(reader)
+}
+
+/// The most one entry of a zip archive may decompress to before
+/// --search-archives gives up on it
+///
+/// A few kilobytes of deflated zeros can stand for gigabytes (a "zip
+/// bomb"), and a search would happily go through all of them.
+pub const MAX_ENTRY_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Whether the file at `path` is a zip archive, going by its `.zip`
+/// extension, so --search-archives should look inside it
+pub fn is_zip(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "zip")
+}
+
+/// A reader that fails with InvalidData once more than `cap` bytes came out
+/// of it
+///
+/// A zip entry's header says how big it is, but nothing stops the header
+/// from lying, so what's decompressed is counted as it's read.
+pub struct Capped<R> {
+    inner: R,
+    cap: u64,
+    left: u64,
+}
+
+impl<R> Capped<R> {
+    pub fn new(inner: R, cap: u64) -> Self {
+        Capped {
+            inner,
+            cap,
+            left: cap,
+        }
+    }
+}
+
+impl<R: Read> Read for Capped<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.left = self.left.checked_sub(read as u64).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("decompresses to more than {} bytes", self.cap),
+            )
+        })?;
+        Ok(read)
+    }
+}
+
+/// Memory-maps `file` for --mmap
+///
+/// # Returns
+/// None when the file is empty (mapping zero bytes is an error on most
+/// systems) or mapping fails (e.g. on filesystems that don't support it), in
+/// which case the caller reads the file normally instead
+pub fn map_file(file: &File) -> Option<Mmap> {
+    if file.metadata().ok()?.len() == 0 {
+        return None;
+    }
+
+    // SAFETY: Mmap::map is unsafe because Rust assumes the bytes behind a
+    // &[u8] never change, but another process could write to or truncate the
+    // file while it's mapped. We only ever read the mapping, and only for as
+    // long as this one search takes. Writes from elsewhere mean we may see a
+    // mix of old and new contents, the same as a racing read() would give.
+    // Truncation can't be turned into a Rust error at all: touching a page
+    // past the new end of the file makes the OS send SIGBUS, which kills the
+    // process instead of reading garbage. That's why --mmap is opt-in and
+    // documented as unsuitable for files that may shrink under us.
+    unsafe { Mmap::map(file).ok() }
+}
+
+/// Wraps `reader` so that whatever it reads comes out as UTF-8
+///
+/// A BOM at the start picks the encoding (and is itself dropped), then
+/// `encoding`, if given, and otherwise the bytes pass through untouched. The
+/// transcoding happens a buffer at a time as the search reads, so a big
+/// UTF-16 file is never decoded into memory in one go.
+pub fn decode<R: Read>(
+    reader: R,
+    encoding: Option<&'static Encoding>,
+) -> BufReader<DecodeReaderBytes<R, Vec<u8>>> {
+    BufReader::new(
+        DecodeReaderBytesBuilder::new()
+            .encoding(encoding)
+            .bom_override(true)
+            .build(reader),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capped_fails_past_the_cap() {
+        let mut data = Vec::new();
+        Capped::new(&b"12345"[..], 5)
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, b"12345");
+
+        let error = Capped::new(&b"123456"[..], 5)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "decompresses to more than 5 bytes");
+    }
+
+    /// Hands out one byte per read, so every character in more than one
+    /// byte is split between reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn decode_keeps_characters_split_between_reads() {
+        let encoding = Encoding::for_label(b"shift_jis");
+        let (bytes, _, _) = encoding.unwrap().encode("接続できません\nok\n");
+        let mut text = String::new();
+        decode(Trickle(&bytes), encoding)
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "接続できません\nok\n");
+    }
+}
@@ -0,0 +1,90 @@
+// FilePath: rust/rust-cli/grss/src/interrupt.rs
+
+//! Ctrl-C: stop searching, but leave the output in one piece
+//!
+//! Being killed by SIGINT halfway through writing a line leaves a broken
+//! last line behind, and whatever was still buffered never comes out. So the
+//! handler only raises a flag. The walker stops handing out files, workers
+//! stop taking them, and the search of a file stops at its next line (or the
+//! next buffer it reads), always between lines. What was found so far is
+//! flushed, --stats is printed for it, and grss exits with 130 like a shell
+//! does for a command killed by SIGINT.
+//!
+//! A second Ctrl-C doesn't wait for any of that and exits right away.
+
+use std::io::{self, BufRead, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The exit code after Ctrl-C: 128 + SIGINT's number
+pub const EXIT_CODE: u8 = 130;
+
+/// Set by the first Ctrl-C
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the Ctrl-C handler
+///
+/// # Returns
+/// An error when the handler can't be installed, in which case Ctrl-C just
+/// kills grss as it would without one
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            std::process::exit(EXIT_CODE.into());
+        }
+    })
+}
+
+/// Whether Ctrl-C was pressed
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Fails once Ctrl-C was pressed, to get out of a search through the error
+/// path from anywhere that can return an io::Error
+pub fn check() -> io::Result<()> {
+    match is_interrupted() {
+        // Not ErrorKind::Interrupted: read_until() and friends retry those
+        true => Err(io::Error::other("interrupted")),
+        false => Ok(()),
+    }
+}
+
+/// A reader that stops reading once Ctrl-C was pressed, or once `stopped`
+/// is set because the search as a whole is done (-q found its match), so a
+/// long file with few matches doesn't have to be read to the end first
+pub struct Interruptible<'s, R> {
+    inner: R,
+    stopped: &'s AtomicBool,
+}
+
+impl<'s, R> Interruptible<'s, R> {
+    pub fn new(inner: R, stopped: &'s AtomicBool) -> Self {
+        Interruptible { inner, stopped }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        check()?;
+        match self.stopped.load(Ordering::Relaxed) {
+            true => Err(io::Error::other("stopped")),
+            false => Ok(()),
+        }
+    }
+}
+
+impl<R: Read> Read for Interruptible<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        self.inner.read(buf)
+    }
+}
+
+impl<R: BufRead> BufRead for Interruptible<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check()?;
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.consume(amount);
+    }
+}
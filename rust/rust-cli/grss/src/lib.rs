@@ -0,0 +1,48 @@
+// FilePath: rust/rust-cli/grss/src/lib.rs
+
+//! grss as a library: the matching and searching behind the grss binary,
+//! usable from other programs
+//!
+//! A [`Matcher`] decides which lines are selected and where they match, a
+//! [`Searcher`] runs it over some input and hands every line to show to a
+//! sink of your choosing:
+//!
+//! ```
+//! use grss::{Matcher, MatcherOptions, SearchOptions, Searcher};
+//!
+//! let options = MatcherOptions {
+//!     ignore_case: true,
+//!     ..MatcherOptions::default()
+//! };
+//! let matcher = Matcher::new(&["todo".to_string()], options)?;
+//! let searcher = Searcher::new(matcher, SearchOptions::default());
+//!
+//! let mut found = Vec::new();
+//! let input = "fn main() {}\n// TODO: tests\n";
+//! searcher.search_reader(input.as_bytes(), &mut |line| {
+//!     found.push((line.number, line.ranges()));
+//!     Ok(None)
+//! })?;
+//! assert_eq!(found, [(2, vec![3..7])]);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The binary adds everything about files on top: walking directories
+//! ([`walk`], honoring [`ignore_files`]), skipping binary files and decoding other encodings
+//! ([`input`]), skipping the files an [`index`] rules out, and printing.
+
+pub mod error;
+pub mod filter;
+pub mod ignore_files;
+pub mod index;
+pub mod input;
+pub mod matcher;
+pub mod pattern_error;
+pub mod searcher;
+pub mod template;
+pub mod types;
+pub mod walk;
+
+pub use error::GrssError;
+pub use matcher::{Matcher, MatcherOptions};
+pub use searcher::{LineKind, Match, Outcome, SearchOptions, Searcher, Stop};
@@ -1,35 +1,3023 @@
-fn main() {
-    // Print a sarcastic greeting message to the console
-    println!("Oh look, another 'Hello, world!' program. How original.");
-
-    // Get the program name (the first argument in std::env::args())
-    // std::env::args() returns an iterator over the command line arguments
-    // .next() gets the first item from this iterator (which is the program name)
-    // .expect() unwraps the Option, returning the value if Some, or panicking with the message if None
-    let program_name = std::env::args()
-        .next()
-        .expect("Program name can't find I don't know how this will happen");
-
-    // Print the program name with a debug format specifier {:?}
-    // Note: There's a missing space between "is" and the format specifier
-    println!("Program name is{:?}", program_name);
-
-    // Get the pattern argument (the second argument in std::env::args())
-    // std::env::args().nth(1) gets the second item from the arguments iterator
-    // .expect() unwraps the Option, returning the value if Some, or panicking with the message if None
-    let pattern = std::env::args()
-        .nth(1)
-        .expect("No pattern given, please give some pattern brother.");
-
-    // Print the pattern with a debug format specifier {:?}
-    println!("Pattern name is {:?}", pattern);
-
-    // This is trying to get the path argument, but has the same bug
-    // Using .next() a third time on the same iterator still returns the first item
-    let path = std::env::args()
-        .nth(2)
-        .expect("No path given, please give some path brother, what to do?");
-
-    // Print both pattern and path (both are actually the program name due to the bug)
-    println!("path is {:?}", path);
+//! The grss command line tool: parses the arguments and prints what the
+//! grss library finds
+//!
+//! Everything about matching lines lives in the library (lib.rs), this file
+//! only deals with turning flags into options, opening files, searching
+//! directories in parallel and formatting the output.
+
+mod byte_range;
+mod colors;
+mod completions;
+mod config;
+mod dedupe;
+mod format;
+mod index_command;
+mod interrupt;
+mod man;
+mod pager;
+mod preprocess;
+mod printer;
+mod replace;
+mod sarif;
+mod stats;
+mod subcommands;
+#[cfg(feature = "tui")]
+mod tui;
+mod watch;
+
+use clap::builder::RangedU64ValueParser;
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, ValueEnum, ValueHint};
+use colors::Colors;
+use dedupe::{Dedupe, Held};
+use encoding_rs::Encoding;
+use format::Format;
+use grss::filter::PathFilter;
+use grss::ignore_files::IgnoreOptions;
+use grss::index::IndexedSearch;
+use grss::input::{self, BINARY_CHECK_LEN, Capped, MAX_ENTRY_SIZE, UTF8_BOM};
+use grss::matcher::{CaseFlags, Engine, required_texts, resolve_ignore_case};
+use grss::searcher::Sink;
+use grss::template::Template;
+use grss::types::FileTypes;
+use grss::walk::{WalkOptions, relative_path, walk_files};
+use grss::{GrssError, LineKind, Matcher, MatcherOptions, Outcome, SearchOptions, Searcher, Stop};
+use interrupt::Interruptible;
+use pager::Pager;
+use preprocess::Preprocessor;
+use printer::{PrintOptions, Printer, WithFilename};
+use stats::{Counted, Metrics, Noted, Phase, Skip, Stats, Timed};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::ops::{ControlFlow, Range};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, mpsc};
+use std::thread;
+use std::time::{Instant, SystemTime};
+use zip::ZipArchive;
+
+/// Search files for lines matching a pattern, and print them
+///
+/// grss reads each file (or, with -r, each file under a directory) line by
+/// line and prints the lines that match the regular expression PATTERN.
+/// Several patterns can be given with -e or read from a file with -f; a line
+/// matches when any of them does. --and and --not add patterns that also
+/// have to match, or mustn't. Files that look binary are skipped, and
+/// files starting with a UTF-16 byte order mark are decoded first.
+///
+/// Default options can be kept in a config file, see FILES in the man page.
+/// `grss count` and `grss files` are -c and --files with only the options
+/// that go with them, and `grss search` is grss itself: a pattern that's
+/// one of those names goes after `--`. `grss replace` rewrites matches in
+/// place, see `grss replace --help`.
+/// `grss index` builds an index that makes searching a big tree again
+/// faster, see `grss index --help`.
+#[derive(Parser, Debug, PartialEq)]
+// Options from the config file come first, and the same option given again
+// on the command line replaces them instead of being an error
+// -h is --no-filename like in grep, so help is only --help
+#[command(args_override_self = true, disable_help_flag = true)]
+struct Cli {
+    #[command(flatten)]
+    patterns: PatternArgs,
+    #[command(flatten)]
+    walk: WalkArgs,
+    /// Prefix each line with its 1-based line number
+    #[arg(short = 'n', long)]
+    line_number: bool,
+    /// Print the column of the first match after the line number (or with
+    /// -o, of each match), for editors to jump to: `file:line:column:text`.
+    /// Implies -n.
+    ///
+    /// Columns count characters from 1, so multi-byte characters before the
+    /// match count once, and so does a tab.
+    #[arg(long)]
+    column: bool,
+    /// Prefix each line with the byte offset of its start, counted from 0 at
+    /// the start of the file
+    ///
+    /// Offsets count the text as searched: after a byte order mark, and in
+    /// UTF-8 for files decoded from another encoding.
+    #[arg(short = 'b', long)]
+    byte_offset: bool,
+    /// Print only the matched parts of each line, one match per output line
+    ///
+    /// -n repeats the line number for every match on a line, and -b gives
+    /// the offset of the match itself. Empty matches (say of `a*` where
+    /// there's no `a`) are left out.
+    #[arg(short = 'o', long)]
+    only_matching: bool,
+    /// Print only the line number of each selected line, one per line, for
+    /// `sed -n` or an editor's jump list: `grss --only-line-numbers 'fn '
+    /// src/main.rs` gives `3`, `17`, `42`
+    ///
+    /// With several files each number comes after its path, as
+    /// `path:line`, unless -h leaves the path out. -v and -m select the
+    /// lines as usual, and there are no context lines.
+    #[arg(
+        long,
+        conflicts_with_all = ["only_matching", "count", "count_matches", "json", "format", "csv", "sarif", "files_with_matches", "files_without_match", "files", "dedupe", "dedupe_global", "tui"]
+    )]
+    only_line_numbers: bool,
+    /// Print each selected line laid out by TEMPLATE, e.g.
+    /// `--format '{path}\t{line}\t{match}'`
+    ///
+    /// The placeholders are {path}, {line}, {column}, {text} (the whole
+    /// line), {match} and {offset} (in bytes). With {match} or {column} in
+    /// it, a line with several matches gives one output line per match, and
+    /// {offset} is the match's; otherwise it gives one, and {offset} is the
+    /// line's. `{{` and `}}` are literal braces, and `\t`, `\n` and the other
+    /// --context-separator escapes work. No context lines are printed.
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with_all = ["only_matching", "count", "count_matches", "files_with_matches", "files_without_match", "files", "tui"]
+    )]
+    format: Option<String>,
+    /// Print the results as CSV: a `path,line,column,text` header, then a
+    /// record for every match, with the whole line as its text
+    ///
+    /// Fields with a comma, a quote or a line break in them are quoted, the
+    /// way spreadsheets read them back. A line selected by -v has no match
+    /// in it and gets one record, with column 1. The output is never
+    /// colored, so --color can't be given with it, and there are no
+    /// context lines.
+    #[arg(
+        long,
+        conflicts_with_all = ["format", "color", "only_matching", "count", "count_matches", "files_with_matches", "files_without_match", "files", "tui"]
+    )]
+    csv: bool,
+    /// Print the results as a SARIF 2.1.0 log, the JSON that GitHub code
+    /// scanning takes, for failing a CI job on a banned pattern
+    ///
+    /// There's a rule for each pattern (with its text as the ID) and a
+    /// result for each match, with the path, line and column it's at. It's
+    /// one JSON document, written once the whole search is done, sorted by
+    /// path. -U and -v aren't supported, since a finding needs a match that
+    /// says which pattern it was.
+    #[arg(
+        long,
+        conflicts_with_all = ["format", "csv", "only_matching", "count", "count_matches", "files_with_matches", "files_without_match", "files", "quiet", "stats", "watch", "tui", "multiline", "invert_match", "count_duplicates"]
+    )]
+    sarif: bool,
+    /// Print the results as JSON Lines: an object for every selected line,
+    /// with its path, line number, byte offset, text and where the matches
+    /// in it are
+    ///
+    /// Each is `{"type":"match",...}`, and with --stats the summary comes
+    /// last as `{"type":"summary",...}`, so a script can tell them apart.
+    /// Text that isn't UTF-8 has U+FFFD in place of the bytes that aren't.
+    /// The output is never colored, and there are no context lines.
+    #[arg(
+        long,
+        conflicts_with_all = ["format", "csv", "sarif", "only_matching", "count", "count_matches", "files_with_matches", "files_without_match", "files", "tui", "count_duplicates"]
+    )]
+    json: bool,
+    /// Print each distinct selected line of a file once, leaving out the
+    /// ones with the same text as one printed before (after --replace, and
+    /// not counting the path and line number)
+    ///
+    /// Only the printing is affected: -m still counts every selected line,
+    /// and there are no context lines.
+    #[arg(long, conflicts_with_all = ["watch", "tui"])]
+    dedupe: bool,
+    /// Like --dedupe, for the lines of all the files together: a line is
+    /// only printed for the first file it's found in, which without --sort
+    /// can change from run to run
+    #[arg(long, conflicts_with_all = ["watch", "tui"])]
+    dedupe_global: bool,
+    /// With --dedupe, put ` (xN)` after a line that came N times in the
+    /// file. A file's lines are held back until all of it is searched, to
+    /// know how many times each came.
+    #[arg(
+        long,
+        requires = "dedupe",
+        conflicts_with_all = ["dedupe_global", "only_matching", "format", "csv"]
+    )]
+    count_duplicates: bool,
+    /// Print selected lines with every match replaced by TEMPLATE (files are
+    /// left alone, see `grss replace` for that)
+    ///
+    /// `$0` is the whole match, `$1` or `$name` a capture group, `${1}` or
+    /// `${name}` the same when letters follow, and `$$` a literal `$`. With
+    /// -o only the replaced text is printed, so `--replace '$1' -o` pulls a
+    /// field out of each line.
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "multiline")]
+    replace: Option<String>,
+    /// After the results, print how many files were searched, skipped and
+    /// matched, how many lines matched, how many bytes were read and how
+    /// long it all took
+    ///
+    /// With -q, -l, -L or -m, where grss stops reading early, the numbers
+    /// cover what was actually read. With --json they're a JSON object
+    /// instead, which also says why each skipped file was skipped.
+    #[arg(long)]
+    stats: bool,
+    /// After the results, print a table of the files with the most
+    /// matches, most first: each one's path, matched lines and matches
+    ///
+    /// Shows where the matches of an audit with -r are concentrated. With
+    /// -q only the table is printed, and -q then reads every file to the
+    /// end to count it. -l, -L and -m stop reading a file early, and the
+    /// numbers then cover what was read.
+    #[arg(long, conflicts_with_all = ["json", "sarif", "watch", "tui"])]
+    summary: bool,
+    /// How many files --summary lists at most
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 20,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    summary_limit: usize,
+    /// After the search, print to standard error where its time went:
+    /// walking directories, reading files, matching and printing
+    ///
+    /// Reading, matching and printing are added up over all the threads
+    /// searching, so together they can be more than the wall time.
+    #[arg(long, conflicts_with = "watch")]
+    debug_timing: bool,
+    /// Which engine matches the patterns: `regex`, `literal` (a substring
+    /// search for each pattern), `aho-corasick` (one automaton for all of
+    /// them) or `auto`, which picks one from the patterns
+    ///
+    /// For finding out why a search is slow: the results are the same with
+    /// any of them (except with -w, for a pattern that starts or ends with
+    /// punctuation). `literal` and `aho-corasick` only take plain text, -F
+    /// patterns or regexes without anything special in them, and give an
+    /// error for anything else; their -i only folds ASCII letters. -vv and
+    /// --debug-timing say which engine matched.
+    #[arg(long, value_enum, value_name = "ENGINE", default_value_t = Engine::Auto, hide_possible_values = true)]
+    engine: Engine,
+    /// Decompress gzip files (the ones ending in `.gz`) and search what's
+    /// inside, like zgrep. Decompression streams, it never holds a whole
+    /// file in memory.
+    #[arg(short = 'z', long)]
+    search_zip: bool,
+    /// Search every file inside zip archives (the ones ending in `.zip`),
+    /// printing them as `bundle.zip!path/inside.txt`
+    ///
+    /// Binary entries are skipped, and so are archives inside the archive.
+    /// An entry that decompresses to more than 256 MiB is given up on, so a
+    /// zip bomb can't keep the search busy forever.
+    #[arg(long, conflicts_with = "watch")]
+    search_archives: bool,
+    /// Search what COMMAND prints for each file instead of the file itself,
+    /// e.g. `pdftotext {} -` to search inside PDFs
+    ///
+    /// `{}` is replaced by the file's path, or the path is added at the end
+    /// when there's no `{}`. Line numbers count the command's output. A file
+    /// the command fails on is skipped with a warning. Running a command per
+    /// file is slow, so limit it to the files that need it with --pre-glob.
+    #[arg(long, value_name = "COMMAND", conflicts_with = "watch")]
+    pre: Option<String>,
+    /// Only run --pre on files matching this glob, e.g. `*.pdf` (can be
+    /// repeated); other files are searched as they are
+    #[arg(long, value_name = "GLOB", requires = "pre")]
+    pre_glob: Vec<String>,
+    /// Keep running after searching the files, and search whatever gets
+    /// appended to them as they grow, like `tail -f` piped into grep
+    ///
+    /// A file that shrinks (truncated, or replaced by log rotation) is
+    /// searched again from the start. Stop with Ctrl-C.
+    #[arg(
+        long,
+        conflicts_with_all = ["count", "count_matches", "quiet", "files_with_matches", "files_without_match", "stats"]
+    )]
+    watch: bool,
+    /// Browse the matches in an interactive list instead of printing them
+    ///
+    /// Up/Down (or j/k) move through the matches as they come in, `/`
+    /// changes the pattern and searches again as you type, Enter prints the
+    /// selected match's file and exits (for `$EDITOR $(grss --tui ...)`),
+    /// q or Esc exit without. Only there when grss is built with the `tui`
+    /// feature.
+    #[arg(
+        long,
+        conflicts_with_all = ["watch", "files", "count", "count_matches", "quiet", "files_with_matches", "files_without_match", "replace", "stats", "output"]
+    )]
+    tui: bool,
+    /// Write out every line as soon as it's printed, so matches show up
+    /// right away even when the output is piped into another program
+    ///
+    /// This is the default on a terminal and with --watch. With -r, the
+    /// lines of a file come out together once its search is done.
+    #[arg(long, overrides_with = "block_buffered")]
+    line_buffered: bool,
+    /// Collect the output and write it in big blocks, the fastest way for a
+    /// batch run. The default when the output isn't a terminal.
+    #[arg(long, overrides_with = "line_buffered")]
+    block_buffered: bool,
+    /// When to highlight matches with color
+    ///
+    /// `auto` only colors when stdout is a terminal, so piping into another
+    /// program or a file never gets escape codes mixed in. It also stays
+    /// plain when NO_COLOR is set (to anything but an empty string).
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Only print how many lines matched (`path:count` per file when there
+    /// are several)
+    #[arg(short = 'c', long)]
+    count: bool,
+    /// Only print how many times the pattern occurs, in the same format as
+    /// -c
+    ///
+    /// Where -c counts `foo foo foo` as one matching line, this counts it as
+    /// three. With several patterns, every pattern's matches count. With -v
+    /// it counts lines, like -c.
+    #[arg(long, conflicts_with = "count")]
+    count_matches: bool,
+    /// Print NUM lines of trailing context after each match
+    #[arg(short = 'A', long, value_name = "NUM")]
+    after_context: Option<usize>,
+    /// Print NUM lines of leading context before each match
+    #[arg(short = 'B', long, value_name = "NUM")]
+    before_context: Option<usize>,
+    /// Print NUM lines of context both before and after each match (-A and
+    /// -B take precedence when also given)
+    #[arg(short = 'C', long, value_name = "NUM")]
+    context: Option<usize>,
+    /// The line to print between groups of lines that don't follow on from
+    /// each other with -A, -B or -C, and between files (default `--`)
+    ///
+    /// Escapes like `\t`, `\n`, `\0` and `\x7F` are turned into the
+    /// characters they stand for, and `\\` into a backslash.
+    #[arg(
+        long,
+        value_name = "STRING",
+        default_value = "--",
+        overrides_with = "no_context_separator"
+    )]
+    context_separator: String,
+    /// Don't print anything between groups of context lines, they just
+    /// follow on from each other
+    #[arg(long, overrides_with = "context_separator")]
+    no_context_separator: bool,
+    /// Print every file type --type knows with its globs, and exit
+    #[arg(long)]
+    type_list: bool,
+    /// Memory-map files instead of reading them through a buffer, which can
+    /// be faster on very large files
+    ///
+    /// Falls back to normal reading for standard input, empty files, and
+    /// anywhere mapping doesn't work. Don't use it on files that other
+    /// programs may truncate while grss is reading them: the OS kills a
+    /// process that touches a mapped page that no longer exists (SIGBUS).
+    #[arg(long)]
+    mmap: bool,
+    /// Print nothing, just exit with 0 if anything matched and 1 if not
+    ///
+    /// Stops at the first match, and also silences warnings like skipped
+    /// binary or unreadable files (errors that stop grss still show)
+    #[arg(short = 'q', long)]
+    quiet: bool,
+    /// Explain what's going on, on standard error: once for which files are
+    /// searched and why others are skipped, twice for how the patterns were
+    /// compiled and every --include/--exclude decision too
+    ///
+    /// There's no -v for this, that's --invert-match like in grep. RUST_LOG
+    /// (e.g. `RUST_LOG=grss=debug`) takes precedence over --verbose.
+    #[arg(long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Stop reading a file after NUM selected lines (with -v, NUM
+    /// non-matching lines). Trailing context after the last one is still
+    /// printed.
+    #[arg(short = 'm', long, value_name = "NUM")]
+    max_count: Option<usize>,
+    /// Stop the whole search after printing NUM selected lines, from all
+    /// the files together
+    ///
+    /// Exactly NUM are printed (unless there are fewer), but which ones
+    /// depends on which files are searched first, unless -j1 searches them
+    /// one after the other. No context is printed after the last one.
+    #[arg(
+        long,
+        value_name = "NUM",
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with_all = ["count", "count_matches", "files_with_matches", "files_without_match", "count_duplicates", "watch", "tui"]
+    )]
+    max_total: Option<usize>,
+    /// Let patterns match across lines, like `fn main\(\)[\s\S]*?unwrap`,
+    /// and print every line a match touches
+    ///
+    /// `^` and `$` match at the start and end of every line. `.` still stops
+    /// at a newline unless the pattern starts with `(?s)`. Each file is read
+    /// into memory whole for this (with --mmap it's only mapped), so memory
+    /// use grows with the size of the largest file.
+    #[arg(
+        short = 'U',
+        long,
+        conflicts_with_all = ["fixed_strings", "and_patterns", "not_patterns"]
+    )]
+    multiline: bool,
+    /// With -U, treat `\r\n` as a line ending too: `$` matches before the
+    /// `\r`, so `error$` finds `error` at the end of a line of a Windows
+    /// file, and `.` doesn't match it
+    ///
+    /// Without -U this is always the case, since every line is split off at
+    /// its `\n` and loses a `\r` before it, in files that mix the two
+    /// endings too. Either way byte offsets (-b) count the `\r`.
+    #[arg(long)]
+    crlf: bool,
+    /// Match ASCII only: `\w`, `\d`, `\s`, `\b` (and -w) and -i only know
+    /// ASCII letters and digits, and `.` matches any one byte
+    ///
+    /// For files that are ASCII anyway, like most logs: a pattern with
+    /// classes like `\w{4,12}` in it compiles many times faster without
+    /// all of Unicode's letters, which adds up with many patterns (-f).
+    /// Other text in a pattern still matches itself byte for byte, `é`
+    /// included, but a Unicode class like `\p{Greek}` is an error, and so is
+    /// `(?u)`.
+    #[arg(long)]
+    no_unicode: bool,
+    /// Only search the lines between byte START and byte END of each file,
+    /// e.g. `1000000..` or `10M..20M`; either can be left out
+    ///
+    /// The line START falls in the middle of is left out and the one END
+    /// does is searched to its end, so lines are always whole. Line numbers
+    /// and byte offsets still count from the start of the file, which
+    /// takes a quick count of the newlines before START.
+    #[arg(
+        long,
+        value_name = "START..END",
+        value_parser = byte_range::parse,
+        conflicts_with_all = ["pre", "search_zip", "search_archives", "watch", "tui"]
+    )]
+    byte_range: Option<byte_range::ByteRange>,
+    /// Don't print lines longer than NUM characters, only a note that one
+    /// was left out, so a match in minified or generated code doesn't fill
+    /// the terminal
+    #[arg(
+        short = 'M',
+        long,
+        value_name = "NUM",
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_columns: Option<usize>,
+    /// With --max-columns, print the first NUM characters of a line that's
+    /// too long, followed by `[... omitted]`, instead of leaving it out
+    #[arg(long, requires = "max_columns")]
+    max_columns_preview: bool,
+    /// Leave out the spaces and tabs at the start of every line printed, so
+    /// matches in deeply indented code line up
+    ///
+    /// Only the text shown changes: --column and -b still count from the
+    /// start of the line as it is in the file.
+    #[arg(long)]
+    trim: bool,
+    /// Write the results to FILE (created, or emptied if it exists) instead
+    /// of standard output
+    ///
+    /// Warnings still go to standard error, and --stats to standard output.
+    /// Only --color always colors the file. A search never reads FILE
+    /// itself, even when it's inside a directory searched with -r.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+    /// Print the path of every file a search would look at, without
+    /// searching any of them; every argument is a path
+    ///
+    /// Directories are gone through whether or not -r is given, honoring
+    /// --include, --type, --hidden, ignore files and so on, which makes this
+    /// the way to check what those leave out.
+    #[arg(long, conflicts_with_all = ["regexps", "pattern_files", "watch"])]
+    files: bool,
+    /// Only print the names of files with at least one selected line (with
+    /// -v, at least one non-matching line). Each file is only read up to its
+    /// first one.
+    #[arg(short = 'l', long, conflicts_with = "files_without_match")]
+    files_with_matches: bool,
+    /// Only print the names of files without a single selected line, e.g.
+    /// `grss -rL '^// FilePath:' src/` for the files missing that header
+    ///
+    /// Each file is read up to its first selected line, which rules it out,
+    /// or else to the end. Files left out of the search (binary, excluded,
+    /// not of the --type) aren't listed. Exits with 0 when any file was
+    /// listed.
+    #[arg(short = 'L', long)]
+    files_without_match: bool,
+    /// Follow every file name with a NUL byte instead of `:` (or, with -l,
+    /// -L and --files, instead of a newline), so names with spaces or
+    /// newlines in them survive `xargs -0` or `fzf --read0`
+    ///
+    /// The names are written as the bytes they are, even the ones that
+    /// aren't UTF-8. -Z does the same, as in GNU grep.
+    #[arg(short = '0', short_alias = 'Z', long)]
+    null: bool,
+    /// Read the input as records ending in a NUL byte instead of lines, like
+    /// what `find -print0` writes, and end the records printed with a NUL
+    /// too. -n then numbers records.
+    ///
+    /// There's no -z for this as in GNU grep: that's --search-zip. NUL bytes
+    /// don't make a file count as binary with this.
+    #[arg(long, conflicts_with = "watch")]
+    null_data: bool,
+    /// Search binary files as if they were text, instead of skipping them
+    ///
+    /// A file is binary when there's a NUL byte near its start. The lines
+    /// printed from one show each NUL byte as `␀`, so they can't mess up the
+    /// terminal, and are cut off after 500 characters (or --max-columns)
+    /// followed by `[... omitted]`, since a "line" in a binary file can be
+    /// the whole file.
+    #[arg(short = 'a', long)]
+    text: bool,
+    /// Print each file's path once, on a line of its own above its lines,
+    /// instead of in front of every line, with a blank line between files
+    ///
+    /// The default on a terminal when searching more than one file (or with
+    /// -r). Has no effect on -c, -l and -L.
+    #[arg(long, overrides_with = "no_heading")]
+    heading: bool,
+    /// Put the path in front of every line even on a terminal, like when
+    /// the output is piped
+    #[arg(long, overrides_with = "heading")]
+    no_heading: bool,
+    /// Show the results in a pager when they go to a terminal: GRSS_PAGER,
+    /// else PAGER, else `less -R`
+    ///
+    /// Colors still work (`-R` is added for less). Quitting the pager early
+    /// stops the search. Does nothing when the output is piped or with
+    /// --watch.
+    #[arg(long, overrides_with = "no_pager")]
+    pager: bool,
+    /// Don't use a pager, e.g. to undo --pager from the config file
+    #[arg(long, overrides_with = "pager")]
+    no_pager: bool,
+    /// Read files (and standard input) without a byte order mark as LABEL
+    /// instead of UTF-8, for text a pattern can't match as it is, like a
+    /// Shift-JIS or Latin-1 log: any WHATWG label, e.g. `shift_jis`,
+    /// `windows-1252`, `latin1` or `utf-16le`
+    ///
+    /// Files starting with a BOM are always read in the encoding the BOM
+    /// names. The text is decoded a buffer at a time as it's searched, and
+    /// the lines printed are UTF-8.
+    #[arg(long, value_name = "LABEL")]
+    encoding: Option<String>,
+    /// Ignore the config file (`$XDG_CONFIG_HOME/grss/config` or
+    /// `~/.config/grss/config`) and GRSS_OPTS
+    #[arg(long)]
+    no_config: bool,
+    /// Start every line with the path of the file it's from, even when only
+    /// one file is searched
+    ///
+    /// By default the path is only shown when there's more than one file
+    /// the line could be from: several paths, or a directory with -r. -H
+    /// and -h make the output the same whatever was searched, for scripts.
+    #[arg(short = 'H', long, overrides_with = "no_filename")]
+    with_filename: bool,
+    /// Never show the path in front of lines (or -c counts), even when
+    /// several files are searched
+    #[arg(short = 'h', long, overrides_with = "with_filename")]
+    no_filename: bool,
+    /// Print help
+    #[arg(long, action = ArgAction::HelpLong)]
+    help: Option<bool>,
+    /// Change the colors used with --color, e.g. `match:fg:yellow,line:fg:cyan`.
+    /// Goes on top of the GRSS_COLORS environment variable, which takes the
+    /// same format.
+    #[arg(long, value_name = "SPEC")]
+    colors: Option<String>,
+    /// Print the man page (roff) and exit, for packaging
+    #[arg(long, hide = true)]
+    generate_man: bool,
+}
+
+/// What to look for, in every subcommand that searches (see subcommands.rs)
+#[derive(Args, Debug, PartialEq)]
+struct PatternArgs {
+    /// The regular expression to look for in each line
+    ///
+    /// The pattern is treated as a regex, so characters like `.`, `(` or `[`
+    /// have their regex meaning. Escape them with a backslash (e.g.
+    /// `vec!\[1\.0\]`) or use -F to match them literally.
+    ///
+    /// Leave it out when using -e, the first positional argument is then a
+    /// path instead.
+    pattern: Option<String>,
+    /// A pattern to search for, can be repeated: a line matches when any of
+    /// them matches
+    #[arg(short = 'e', long = "regexp", value_name = "PATTERN")]
+    regexps: Vec<String>,
+    /// Read patterns from FILE, one per line (empty lines are skipped), or
+    /// from standard input with `-`. Like -e, a line matches when any of
+    /// them matches. Can be repeated and combined with -e.
+    #[arg(
+        short = 'f',
+        long = "pattern-file",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath
+    )]
+    pattern_files: Vec<PathBuf>,
+    /// Only select the lines PATTERN matches too, besides the pattern (or
+    /// one of the -e patterns); can be repeated, and then every one of them
+    /// has to match
+    ///
+    /// What it matches is highlighted, and printed by -o, along with what
+    /// the pattern matched. -v selects the lines that don't pass --and and
+    /// --not together: `foo --and bar -v` gives the lines without both.
+    #[arg(long = "and", value_name = "PATTERN")]
+    and_patterns: Vec<String>,
+    /// Leave out the lines PATTERN matches, even when the pattern (and
+    /// --and) match them too; can be repeated, and then any one of them is
+    /// enough
+    #[arg(long = "not", value_name = "PATTERN")]
+    not_patterns: Vec<String>,
+    /// Select the lines that do NOT match the pattern
+    #[arg(short = 'v', long)]
+    invert_match: bool,
+    /// Match upper and lower case letters alike (`foo` also finds `FOO`)
+    #[arg(short = 'i', long, overrides_with = "case_sensitive")]
+    ignore_case: bool,
+    /// Match case exactly, even with -i or -S earlier on the command line or
+    /// in the config file (this is the default)
+    #[arg(short = 's', long, overrides_with = "ignore_case")]
+    case_sensitive: bool,
+    /// Ignore case unless a pattern has an upper case letter in it: `foo`
+    /// finds `FOO`, `Foo` only finds `Foo`. -i and -s win over it.
+    #[arg(short = 'S', long)]
+    smart_case: bool,
+    /// Only match whole words: the match must have a non-word character (or
+    /// the start/end of the line) on both sides
+    #[arg(short = 'w', long)]
+    word_regexp: bool,
+    /// Treat every pattern as a literal string instead of a regex, so
+    /// `vec![1.0]` matches exactly that text
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+}
+
+/// Which files to search: the paths and what leaves files under them out,
+/// and how many to search at once, in every subcommand
+#[derive(Args, Debug, PartialEq)]
+struct WalkArgs {
+    /// The files (or, with -r, directories) to search, or `-` to read from
+    /// standard input. With more than one, every output line starts with the
+    /// path it came from (see -H and -h).
+    #[arg(value_hint = ValueHint::AnyPath)]
+    paths: Vec<PathBuf>,
+    /// Search every file inside the directories among the paths
+    ///
+    /// Hidden files and what .gitignore and .grssignore files list are left
+    /// out (see --hidden and --no-ignore), as far up as the top of the git
+    /// repository.
+    #[arg(short = 'r', long)]
+    recursive: bool,
+    /// With -r, only search files matching this glob (can be repeated)
+    ///
+    /// Globs are matched against the path relative to the searched directory;
+    /// one without a `/` (like `*.rs`) just has to match the file name
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// With -r, skip files and directories matching this glob (can be
+    /// repeated, and wins over --include). `target/**` skips the whole
+    /// directory without looking inside it.
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// With -r, like --include but ignoring case, so `*.rs` matches `Foo.RS`
+    /// too; starting it with `!` makes it like --exclude (can be repeated)
+    ///
+    /// A file is searched when it matches --include or --iglob (if either is
+    /// given), and neither --exclude nor a `!` --iglob, which always win.
+    /// Hidden and ignored files stay left out whatever the globs say.
+    #[arg(long, value_name = "GLOB")]
+    iglob: Vec<String>,
+    /// With -r, don't go into directories with this name, like
+    /// `node_modules`, or matching this glob (can be repeated)
+    ///
+    /// Nothing inside them is even looked at, which is what makes skipping
+    /// big dependency or build directories fast. This holds with --hidden
+    /// too; a directory given as a path is still searched.
+    #[arg(long, value_name = "NAME|GLOB")]
+    exclude_dir: Vec<String>,
+    /// With -r, only search files of this type, e.g. `rust` for `*.rs` (can
+    /// be repeated; --type-list shows them all)
+    ///
+    /// A file has to match --include too when both are given, and --exclude
+    /// and --type-not always win
+    #[arg(short = 't', long = "type", value_name = "TYPE")]
+    types: Vec<String>,
+    /// With -r, skip files of this type (can be repeated)
+    #[arg(short = 'T', long, value_name = "TYPE")]
+    type_not: Vec<String>,
+    /// Define a file type for --type, or add a glob to an existing one, as
+    /// NAME:GLOB (e.g. `web:*.vue`, can be repeated)
+    #[arg(long, value_name = "NAME:GLOB")]
+    type_add: Vec<String>,
+    /// With -r, also search hidden files and directories (names starting
+    /// with `.`, like `.git/`), which are skipped otherwise. A hidden path
+    /// given on the command line is always searched.
+    #[arg(long)]
+    hidden: bool,
+    /// With -r, descend into symlinked directories and search symlinked
+    /// files instead of skipping them
+    ///
+    /// Every directory is searched once however many ways there are into
+    /// it: coming to one again, like through a link that points back up the
+    /// tree, is only warned about, so a cycle can't make the search go on
+    /// forever. So is a broken link.
+    #[arg(long)]
+    follow: bool,
+    /// With -r, don't descend more than NUM directories below each path
+    /// given: 1 only searches the files directly inside it
+    #[arg(long, value_name = "NUM")]
+    max_depth: Option<usize>,
+    /// With -r, skip files bigger than SIZE bytes, which can end in K, M or
+    /// G (e.g. `10M`, powers of 1024). Paths given on the command line are
+    /// always searched.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_filesize: Option<u64>,
+    /// With -r, don't go into directories on another file system (another
+    /// mount) than the path they're under, like /proc or a network share
+    /// mounted inside the tree
+    ///
+    /// The paths given are always searched. Does nothing on Windows.
+    #[arg(long)]
+    one_file_system: bool,
+    /// With -r, search what ignore files (.gitignore, .grssignore) would
+    /// leave out
+    #[arg(long)]
+    no_ignore: bool,
+    /// With -r, search what .gitignore files would leave out, but still
+    /// skip what .grssignore files list
+    #[arg(long)]
+    no_ignore_vcs: bool,
+    /// With -r, only go by the ignore files in and below the searched
+    /// directory, not by the ones above it in the same git repository
+    #[arg(long)]
+    no_ignore_parent: bool,
+    /// With -r, read every file even when the directory has an index (see
+    /// `grss index`) that says which ones can't match
+    #[arg(long)]
+    no_index: bool,
+    /// With -r, also leave out what this file lists, in .gitignore syntax
+    /// (can be repeated)
+    ///
+    /// Its patterns are rooted at each searched directory, and lose to the
+    /// ignore files found there. --no-ignore doesn't turn it off.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    ignore_file: Vec<PathBuf>,
+    /// With -r, print the files of each directory in this order
+    ///
+    /// `none` prints each file as soon as it's searched, so the order
+    /// changes from run to run. `path` compares names byte by byte, one
+    /// directory level at a time, and only holds on to a file's results
+    /// until every file before it is done. `modified`, `created`, `accessed`
+    /// (oldest first) and `size` (smallest first) hold on to the results of
+    /// a whole directory until all of it is searched, which takes memory for
+    /// as much output as the directory produces. Files the file system
+    /// doesn't keep that time for come after all the others, in path order.
+    #[arg(long, value_enum, value_name = "BY", default_value_t = SortBy::None)]
+    sort: SortBy,
+    /// Like --sort, in reverse order (this holds on to all results, by path
+    /// too): `--sortr modified` puts the files changed last first
+    #[arg(long, value_enum, value_name = "BY", conflicts_with = "sort")]
+    sortr: Option<SortBy>,
+    /// How many files to search at once with -r: 0 (the default) uses one
+    /// thread per CPU, 1 searches one file after the other, in the order
+    /// of --sort path
+    #[arg(
+        short = 'j',
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        value_parser = RangedU64ValueParser::<usize>::new().range(0..=MAX_THREADS as u64)
+    )]
+    threads: usize,
+}
+impl Cli {
+    /// Whether -l or -L asked for file names instead of lines
+    fn lists_files(&self) -> bool {
+        self.files_with_matches || self.files_without_match
+    }
+
+    /// Whether only a count per file is printed, of lines (-c) or of
+    /// matches (--count-matches)
+    fn counts(&self) -> bool {
+        self.count || self.count_matches
+    }
+
+    /// The pattern, when it's the first positional argument: not with -e,
+    /// -f or --files, which make that a path
+    fn positional_pattern(&self) -> Option<&str> {
+        let positional = !self.files
+            && self.patterns.regexps.is_empty()
+            && self.patterns.pattern_files.is_empty();
+        self.patterns.pattern.as_deref().filter(|_| positional)
+    }
+
+    /// Whether the search is over at the first selected line: with -q,
+    /// unless --summary still wants every file counted
+    fn stops_at_first_match(&self) -> bool {
+        self.quiet && !self.summary
+    }
+
+    /// Sorts out which positional argument is the pattern and which are
+    /// paths, and reads the pattern files from -f
+    ///
+    /// `grss foo a.txt b.txt` starts with the pattern, but in `grss -e foo
+    /// a.txt b.txt` (or with -f) every positional is a path. clap can't tell
+    /// these apart by itself, so the pattern positional is optional and this
+    /// does the check.
+    fn patterns_and_paths(&self) -> Result<(Vec<String>, Vec<PathBuf>), GrssError> {
+        let usage = |message: &str| GrssError::Usage(message.to_string());
+
+        let (mut patterns, paths) = if self.files {
+            // There's nothing to search for, what clap took for the pattern
+            // is the first path
+            let first = self.patterns.pattern.iter().map(PathBuf::from);
+            (Vec::new(), first.chain(self.walk.paths.clone()).collect())
+        } else if self.patterns.regexps.is_empty() && self.patterns.pattern_files.is_empty() {
+            let pattern = self
+                .patterns
+                .pattern
+                .clone()
+                .ok_or(usage("no pattern given"))?;
+            (vec![pattern], self.walk.paths.clone())
+        } else {
+            // What clap took for the pattern is really the first path
+            let first = self.patterns.pattern.iter().map(PathBuf::from);
+            (
+                self.patterns.regexps.clone(),
+                first.chain(self.walk.paths.clone()).collect(),
+            )
+        };
+        if paths.is_empty() {
+            return Err(usage("no path given"));
+        }
+
+        for file in &self.patterns.pattern_files {
+            if file.as_os_str() == "-" && paths.iter().any(|path| path.as_os_str() == "-") {
+                return Err(usage(
+                    "standard input can't hold both the patterns (-f -) and the text to search",
+                ));
+            }
+            patterns.extend(read_pattern_file(file)?);
+        }
+        Ok((patterns, paths))
+    }
+}
+
+/// Reads the patterns for -f from `path` (`-` for standard input): one per
+/// line, skipping empty lines, with `\n` or `\r\n` endings
+///
+/// A file without a single pattern in it is an error rather than a search
+/// that can never match anything, it's most likely the wrong file
+fn read_pattern_file(path: &Path) -> Result<Vec<String>, GrssError> {
+    let contents = if path.as_os_str() == "-" {
+        io::read_to_string(io::stdin().lock())
+    } else {
+        fs::read_to_string(path)
+    };
+    let contents = contents.map_err(|error| GrssError::io(path, error))?;
+
+    // str::lines() already drops a `\r` before the `\n`
+    let patterns: Vec<String> = contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+    if patterns.is_empty() {
+        let error = io::Error::new(io::ErrorKind::InvalidData, "no patterns in pattern file");
+        return Err(GrssError::io(path, error));
+    }
+    Ok(patterns)
+}
+
+/// The most threads -j takes, far more than any machine has cores for: more
+/// than that is surely a typo
+const MAX_THREADS: usize = 1024;
+
+/// How much output is collected before it's written to stdout, unless
+/// it's line buffered
+const OUTPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The values accepted by --color
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    Always,
+    Never,
+    Auto,
+}
+
+/// The values accepted by --sort and --sortr
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum SortBy {
+    Path,
+    Modified,
+    Created,
+    Accessed,
+    Size,
+    None,
+}
+
+impl SortBy {
+    /// Whether sorting this way needs each file's metadata
+    fn needs_metadata(self) -> bool {
+        !matches!(self, SortBy::Path | SortBy::None)
+    }
+}
+
+/// What a file is sorted on for --sort by a time or size. For path, the
+/// order the walk found the files in is all it takes.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortKey {
+    Time(SystemTime),
+    Size(u64),
+    /// For path and none, and for files whose metadata can't be read or
+    /// that don't have the time asked for, which sort last
+    None,
+}
+
+/// One file's worth of results, handed from a worker to the printer
+struct Searched {
+    /// Where the file came in the walk
+    index: usize,
+    output: Vec<u8>,
+    matched: bool,
+    key: SortKey,
+}
+
+fn main() -> ExitCode {
+    let argv: Vec<OsString> = env::args_os().collect();
+
+    // parse() prints usage and exits with code 2 by itself on unknown flags
+    // or --help. The defaults from GRSS_OPTS and the config file go in
+    // first. `grss search`, `count` and `files` are the same search, once
+    // their arguments are checked and turned back into the plain ones.
+    // `grss replace ...`, `grss completions ...` and `grss index ...` have
+    // arguments of their own and skip all of that.
+    let result = match argv.get(1).and_then(|arg| arg.to_str()) {
+        Some("replace") => replace::run(argv.into_iter().skip(1)),
+        Some("completions") => completions::run(argv.into_iter().skip(1)),
+        Some("index") => index_command::run(argv.into_iter().skip(1)),
+        Some("search" | "count" | "files") => {
+            search(subcommands::classic(argv).unwrap_or_else(|error| error.exit()))
+        }
+        _ => search(argv),
+    };
+
+    // Same exit codes as grep: 0 when something matched, 1 when nothing did,
+    // 2 when something went wrong
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        // Already reported path by path
+        Err(GrssError::PathsFailed) => ExitCode::from(2),
+        Err(GrssError::Interrupted) => ExitCode::from(interrupt::EXIT_CODE),
+        Err(error) => {
+            eprintln!("grss: {}", error);
+            if let GrssError::Usage(_) = error {
+                eprintln!(
+                    "usage: grss [OPTIONS] <PATTERN> <PATH>... or grss [OPTIONS] -e <PATTERN>... <PATH>..."
+                );
+            }
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Searches as `argv` (starting with the program name) says, after the
+/// options from GRSS_OPTS and the config file
+fn search(argv: Vec<OsString>) -> Result<bool, GrssError> {
+    config::with_env_options(argv)
+        .and_then(config::with_config)
+        .and_then(|argv| run(&parse(argv)))
+}
+
+/// The clap definition of grss's command line, for parsing it and for
+/// generating things from it like completion scripts
+fn command() -> clap::Command {
+    Cli::command().after_long_help(man::examples_help())
+}
+
+/// Parses `argv` (starting with the program name) with command(), exiting
+/// with usage (code 2) if it doesn't parse, or with the help text
+fn parse(argv: Vec<OsString>) -> Cli {
+    command()
+        .try_get_matches_from(argv)
+        .and_then(|matches| Cli::from_arg_matches(&matches))
+        .unwrap_or_else(|error| error.exit())
+}
+
+/// Does the actual work of main(), with every failure coming back as a
+/// GrssError instead of being printed on the spot
+///
+/// # Returns
+/// Whether anything matched
+fn run(args: &Cli) -> Result<bool, GrssError> {
+    init_logging(args.verbose, args.quiet);
+    if args.generate_man {
+        man::write_man(&mut io::stdout().lock())
+            .map_err(|error| GrssError::io(Path::new("(standard output)"), error))?;
+        return Ok(true);
+    }
+    if args.type_list {
+        file_types(args)?
+            .write_list(&mut io::stdout().lock())
+            .map_err(|error| GrssError::io(Path::new("(standard output)"), error))?;
+        return Ok(true);
+    }
+    let start = Instant::now();
+    // Both positionals are optional as far as clap knows (see -e), so a
+    // missing pattern or path is caught here instead
+    let (patterns, paths) = args.patterns_and_paths()?;
+    if args.tui {
+        #[cfg(feature = "tui")]
+        return tui::run(args, &patterns, &paths);
+        #[cfg(not(feature = "tui"))]
+        return Err(GrssError::Usage(
+            "--tui needs grss built with the tui feature (cargo build --features tui)".to_string(),
+        ));
+    }
+
+    // Work out "auto" once here instead of asking the terminal on every line
+    let to_terminal = args.output.is_none() && io::stdout().is_terminal();
+    let color = resolve_color(
+        args.color,
+        to_terminal,
+        env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()),
+    );
+    // Escape codes in CSV or JSON would only end up in the values
+    let colors = (color && !args.csv && !args.json).then(|| resolve_colors(args));
+
+    // With --pager, "stdout" is the pager's stdin from here on. It's
+    // declared before the writer so it's dropped (and waited for) after the
+    // writer is, which is what closes the pager's stdin.
+    let mut pager = None;
+    if args.pager && to_terminal && !args.watch {
+        match Pager::spawn() {
+            Ok(spawned) => pager = Some(spawned),
+            Err(error) => log::warn!("can't start the pager: {}", error),
+        }
+    }
+    let (_pager, stdout): (Option<Pager>, Box<dyn Write>) = match pager {
+        Some((pager, stdin)) => (Some(pager), Box::new(stdin)),
+        None => (None, Box::new(io::stdout().lock())),
+    };
+    // Whatever reads the results closing early (`| head`, quitting the
+    // pager) only means the rest isn't wanted: grss stops without a word,
+    // before --stats, and exits by what it found so far, like it would have
+    // if that was all there was. Not with 141 like a shell reports a
+    // process killed by SIGPIPE, grss isn't killed, Rust ignores SIGPIPE.
+    let closed = |error: &GrssError| error.is_broken_pipe();
+
+    // Lock stdout once up front instead of on every println!, and buffer it
+    // ourselves: std only ever buffers stdout up to the end of the line,
+    // which is a write() call per line. With --line-buffered, the Printer
+    // flushes after every line instead. What's written is noted, for
+    // --summary to set its table apart from any results.
+    let mut stdout = Noted::new(BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, stdout));
+    let mut output_file = match &args.output {
+        Some(path) => Some(BufWriter::new(
+            File::create(path).map_err(|error| GrssError::io(path, error))?,
+        )),
+        None => None,
+    };
+    let mut out: &mut dyn Write = match &mut output_file {
+        Some(file) => file,
+        None => &mut stdout,
+    };
+
+    // Like grep, lines only say which file they're from when there's more
+    // than one they could be from, and an archive holds several
+    let several_files = paths.len() > 1
+        || (args.walk.recursive && paths.iter().any(|path| path.is_dir()))
+        || (args.search_archives && paths.iter().any(|path| input::is_zip(path)));
+    let mut app = App {
+        // Canonical now that it exists, to recognize it under any path
+        output: args
+            .output
+            .as_deref()
+            .and_then(|path| fs::canonicalize(path).ok()),
+        ..App::new(args, &patterns, colors)?
+    };
+    app.print.line_buffered =
+        args.line_buffered || (!args.block_buffered && (args.watch || to_terminal));
+    app.print.heading = resolve_heading(args, to_terminal, several_files);
+    app.print.with_filename = resolve_with_filename(args, several_files);
+
+    if let Err(error) = interrupt::install() {
+        log::warn!("can't handle Ctrl-C: {}", error);
+    }
+    if args.csv {
+        app.print
+            .write_csv_header(&mut out)
+            .map_err(|error| GrssError::io(Path::new("(standard output)"), error))?;
+    }
+    if args.watch {
+        return watch::run(&app, &paths, &mut out);
+    }
+    if args.files {
+        let listed = app.list_files(&paths, &mut out).and_then(|listed| {
+            out.flush()
+                .map_err(|error| GrssError::io(Path::new("(standard output)"), error))?;
+            Ok(listed)
+        });
+        return match listed {
+            Err(error) if closed(&error) => Ok(true),
+            listed => listed,
+        };
+    }
+
+    // One path failing doesn't stop the others from being searched, it's
+    // reported right away and only remembered for the exit code
+    let mut found_match = false;
+    let mut failed = false;
+    for path in &paths {
+        match app.search_path(path, &mut out) {
+            Ok(matched) => found_match |= matched,
+            Err(error) if closed(&error) => return Ok(app.matched.into_inner()),
+            // The search was cut short on purpose, that's not an error
+            Err(_) if app.is_stopped() => break,
+            Err(error) => {
+                log::error!("{}", error);
+                // `grss src/main.rs TODO` is the first path not existing
+                if let GrssError::Io {
+                    path: missing,
+                    source,
+                } = &error
+                    && source.kind() == io::ErrorKind::NotFound
+                    && missing == path
+                    && path == &paths[0]
+                    && let Some(pattern) = args.positional_pattern()
+                    && let Some(hint) = swapped_hint(pattern, &path.to_string_lossy(), |name| {
+                        Path::new(name).is_file()
+                    })
+                {
+                    log::error!("{}", hint);
+                }
+                app.stats.skipped(Skip::Failed, 1);
+                failed = true;
+            }
+        }
+        if (found_match && args.stops_at_first_match()) || app.is_stopped() {
+            break;
+        }
+    }
+
+    // --sarif's one document has every file's findings in it, so it can
+    // only be written now
+    if let Some(sarif) = app.sarif.take() {
+        let written = sarif
+            .write(&mut out)
+            .map_err(|error| GrssError::io(Path::new("(standard output)"), error));
+        match written {
+            Err(error) if closed(&error) => return Ok(found_match),
+            written => written?,
+        }
+    }
+    if let (Some(file), Some(path)) = (&mut output_file, &args.output) {
+        file.flush().map_err(|error| GrssError::io(path, error))?;
+    }
+    let metrics = app.metrics.into_inner().unwrap();
+    let summary = args.stats.then(|| app.stats.summary(start.elapsed()));
+    let table = args
+        .summary
+        .then(|| app.stats.top_files(args.summary_limit));
+    let written = match table {
+        Some(table) => stats::write_table(&table, stdout.written(), &mut stdout),
+        None => Ok(()),
+    }
+    .and_then(|()| match summary {
+        Some(summary) if args.json => summary.write_json(&mut stdout),
+        Some(summary) => summary.write(&mut stdout),
+        None => Ok(()),
+    })
+    .and_then(|()| metrics.time(Phase::Print, || stdout.flush()))
+    .map_err(|error| GrssError::io(Path::new("(standard output)"), error));
+    match written {
+        Err(error) if closed(&error) => return Ok(found_match),
+        written => written?,
+    }
+    if args.debug_timing {
+        // Standard error, so it doesn't get mixed up with the results
+        let engine = app.searcher.matcher().engine();
+        let _ = metrics.write(
+            &mut io::stderr().lock(),
+            &app.stats,
+            engine,
+            start.elapsed(),
+        );
+    }
+
+    if interrupt::is_interrupted() {
+        return Err(GrssError::Interrupted);
+    }
+    // Like grep, -q still exits with 0 when something matched, whatever
+    // went wrong elsewhere: the question it answers was answered
+    if failed && !(found_match && args.quiet) {
+        return Err(GrssError::PathsFailed);
+    }
+    Ok(found_match)
+}
+
+/// Sends warnings (and with --verbose, more) from the `log` macros to
+/// standard error, each line starting with `grss: ` like any other message
+///
+/// Other crates only get a say for warnings, so -vv is about grss and not
+/// about how globset builds its glob sets. RUST_LOG, when it's set, replaces
+/// all of that.
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Warn,
+        (false, 1) => log::LevelFilter::Info,
+        (false, _) => log::LevelFilter::Debug,
+    };
+    let mut builder = env_logger::Builder::new();
+    builder.format(|out, record| match record.level() {
+        log::Level::Error | log::Level::Warn => writeln!(out, "grss: {}", record.args()),
+        level => writeln!(
+            out,
+            "grss: {}: {}",
+            level.as_str().to_lowercase(),
+            record.args()
+        ),
+    });
+    match env::var("RUST_LOG") {
+        Ok(filters) => builder.parse_filters(&filters),
+        Err(_) => builder
+            .filter_level(log::LevelFilter::Warn)
+            .filter_module("grss", level),
+    };
+    // Fails only if a logger is already set, i.e. from a second call in tests
+    let _ = builder.try_init();
+}
+
+/// Parses a --max-filesize SIZE: a number of bytes, optionally followed by
+/// K, M or G (upper or lower case) for KiB, MiB or GiB
+///
+/// # Returns
+/// The size in bytes, or a message for clap to show when it isn't one
+fn parse_size(text: &str) -> Result<u64, String> {
+    let (digits, unit) = match text.char_indices().last() {
+        Some((at, 'k' | 'K')) => (&text[..at], 1 << 10),
+        Some((at, 'm' | 'M')) => (&text[..at], 1 << 20),
+        Some((at, 'g' | 'G')) => (&text[..at], 1 << 30),
+        _ => (text, 1),
+    };
+    // parse() would also take a leading `+`
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err("expected a number of bytes, optionally ending in K, M or G".to_string());
+    }
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or_else(|| "too big".to_string())
+}
+
+/// What to sort a file with `metadata` on, for `sort`
+///
+/// Which times there are depends on the platform and the file system (Linux
+/// only has creation times since 4.11, on some file systems), so not having
+/// one isn't an error.
+fn sort_key(metadata: Option<&fs::Metadata>, sort: SortBy) -> SortKey {
+    let Some(metadata) = metadata else {
+        return SortKey::None;
+    };
+    let time = match sort {
+        SortBy::Modified => metadata.modified(),
+        SortBy::Created => metadata.created(),
+        SortBy::Accessed => metadata.accessed(),
+        SortBy::Size => return SortKey::Size(metadata.len()),
+        SortBy::Path | SortBy::None => return SortKey::None,
+    };
+    time.map_or(SortKey::None, SortKey::Time)
+}
+
+/// Whether to color the output, given --color, whether the output goes to a
+/// terminal and whether NO_COLOR is set
+///
+/// `always` and `never` mean just that, NO_COLOR only turns off `auto`: see
+/// https://no-color.org
+fn resolve_color(choice: ColorChoice, is_tty: bool, no_color_env: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => is_tty && !no_color_env,
+    }
+}
+
+/// The note to follow the error for a `path` that doesn't exist, when
+/// `pattern` is a file: it looks like the two were given the wrong way
+/// round. Only a note, grss doesn't search the other way round by itself.
+///
+/// `is_file` says whether there's a file by that name, Path::is_file()
+/// except in the tests.
+///
+/// # Returns
+/// None when `pattern` isn't a file, or `path` is one after all
+fn swapped_hint(pattern: &str, path: &str, is_file: impl Fn(&str) -> bool) -> Option<String> {
+    (is_file(pattern) && !is_file(path)).then(|| {
+        format!(
+            "note: '{}' exists as a file — did you swap the pattern and path arguments?",
+            pattern
+        )
+    })
+}
+
+/// Whether to print --heading style: each file's path on a line of its own
+/// above its lines
+///
+/// On by default only on a terminal, when lines could come from more than
+/// one file. Either way it's only for printing lines, -c and -l keep their
+/// `path:count` and one name per line.
+fn resolve_heading(args: &Cli, to_terminal: bool, several_files: bool) -> bool {
+    let prints_lines = !(args.counts() || args.quiet || args.lists_files());
+    let default = to_terminal && several_files && !args.watch;
+    prints_lines && (args.heading || (default && !args.no_heading))
+}
+
+/// How -r goes through directories, from --follow, --max-depth, --hidden
+/// and the --no-ignore flags
+///
+/// --no-ignore turns off every ignore file found in the tree, the others
+/// only their own part of them, so whichever turns off more wins. Files
+/// given with --ignore-file were asked for by name and always count.
+fn walk_options(args: &Cli) -> WalkOptions {
+    let honor_ignores = !args.walk.no_ignore;
+    WalkOptions {
+        follow_links: args.walk.follow,
+        max_depth: args.walk.max_depth,
+        hidden: args.walk.hidden,
+        one_file_system: args.walk.one_file_system,
+        ignore: IgnoreOptions {
+            vcs: honor_ignores && !args.walk.no_ignore_vcs,
+            grss: honor_ignores,
+            parents: honor_ignores && !args.walk.no_ignore_parent,
+            files: args.walk.ignore_file.clone(),
+        },
+    }
+}
+
+/// Whether lines start with their path: by -H and -h if given, else only
+/// when there are `several_files`
+fn resolve_with_filename(args: &Cli, several_files: bool) -> WithFilename {
+    match (args.with_filename, args.no_filename) {
+        (true, _) => WithFilename::Always,
+        (_, true) => WithFilename::Never,
+        _ if several_files => WithFilename::Always,
+        _ => WithFilename::Never,
+    }
+}
+
+/// `text` with its backslash escapes turned into the bytes they stand for,
+/// for --context-separator
+///
+/// `\t`, `\n`, `\r`, `\0`, `\\` and `\xHH` (any byte, in hex) are
+/// understood. Anything else after a backslash, a trailing backslash
+/// included, is kept as it is.
+fn unescape(text: &str) -> Vec<u8> {
+    let hex = |digit: u8| (digit as char).to_digit(16).map(|value| value as u8);
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&first, after)) = rest.split_first() {
+        let (byte, after) = match (first, after) {
+            (b'\\', [b't', after @ ..]) => (b'\t', after),
+            (b'\\', [b'n', after @ ..]) => (b'\n', after),
+            (b'\\', [b'r', after @ ..]) => (b'\r', after),
+            (b'\\', [b'0', after @ ..]) => (b'\0', after),
+            (b'\\', [b'\\', after @ ..]) => (b'\\', after),
+            (b'\\', [b'x', high, low, rest @ ..])
+                if hex(*high).is_some() && hex(*low).is_some() =>
+            {
+                (hex(*high).unwrap() << 4 | hex(*low).unwrap(), rest)
+            }
+            _ => (first, after),
+        };
+        bytes.push(byte);
+        rest = after;
+    }
+    bytes
+}
+
+/// The colors to use, from GRSS_COLORS and then --colors
+///
+/// A bad spec isn't worth failing the search over, so it gets a warning and
+/// the default colors are used instead
+fn resolve_colors(args: &Cli) -> Colors {
+    let spec = [env::var("GRSS_COLORS").ok(), args.colors.clone()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(",");
+    colors::parse(&spec).unwrap_or_else(|message| {
+        log::warn!("invalid colors, using the defaults: {}", message);
+        Colors::default()
+    })
+}
+
+/// The built-in file types plus the ones from --type-add
+fn file_types(args: &Cli) -> Result<FileTypes, GrssError> {
+    let mut types = FileTypes::default();
+    for definition in &args.walk.type_add {
+        types.add(definition)?;
+    }
+    Ok(types)
+}
+
+/// Holds everything worked out from the command line before searching starts
+///
+/// The compiled patterns and the final colors (if any) are computed once in
+/// main() and then shared by every file we search
+struct App<'a> {
+    args: &'a Cli,
+    searcher: Searcher,
+    filter: PathFilter,
+    print: PrintOptions,
+    /// The encoding from --encoding, None to go by the BOM alone
+    encoding: Option<&'static Encoding>,
+    /// Counted always, only printed with --stats
+    stats: Stats,
+    /// The --output file, canonicalized, which is never searched
+    output: Option<PathBuf>,
+    /// --replace's template
+    template: Option<Template>,
+    /// --pre's command, for the files --pre-glob picks
+    preprocessor: Option<Preprocessor>,
+    /// --sarif: every file's findings, written at the end
+    sarif: Option<sarif::Collector>,
+    /// The text every match has, for skipping the files an index says
+    /// don't have it (see search_dir()); None when the search can't use an
+    /// index
+    index_texts: Option<Vec<Vec<u8>>>,
+    /// Whether any file's lines were written yet, the ones after get a
+    /// separator in front (see PrintOptions::write_file_separator())
+    files_written: AtomicBool,
+    /// Set once a search ended with Stop::All (-q found a match), so the
+    /// walk and every other file's search stop too
+    stopped: AtomicBool,
+    /// --dedupe-global: the selected lines printed so far, by hash
+    printed_lines: Mutex<HashSet<u64>>,
+    /// --max-total: how many selected lines were claimed for printing, by
+    /// all the files together
+    total_printed: AtomicUsize,
+    /// Set on the first selected line (or -L listing), so a search cut
+    /// short by the output closing still exits by whether it found anything
+    matched: AtomicBool,
+    /// Every thread's timings, merged in when it's done, for --debug-timing
+    metrics: Mutex<Metrics>,
+}
+
+impl<'a> App<'a> {
+    /// Compiles `patterns` - compiling is far more expensive than matching,
+    /// so it happens once here rather than once per line
+    fn new(args: &'a Cli, patterns: &[String], colors: Option<Colors>) -> Result<Self, GrssError> {
+        // -S goes by every pattern, --and and --not ones included
+        let all_patterns: Vec<String> = [
+            patterns,
+            &args.patterns.and_patterns,
+            &args.patterns.not_patterns,
+        ]
+        .concat();
+        let matcher_options = MatcherOptions {
+            ignore_case: resolve_ignore_case(
+                CaseFlags {
+                    ignore_case: args.patterns.ignore_case,
+                    case_sensitive: args.patterns.case_sensitive,
+                    smart_case: args.patterns.smart_case,
+                },
+                &all_patterns,
+                args.patterns.fixed_strings,
+            ),
+            word: args.patterns.word_regexp,
+            fixed_strings: args.patterns.fixed_strings,
+            invert: args.patterns.invert_match,
+            multiline: args.multiline,
+            crlf: args.crlf,
+            engine: args.engine,
+            no_unicode: args.no_unicode,
+        };
+        let matcher = Matcher::new(patterns, matcher_options)?.with_conditions(
+            &args.patterns.and_patterns,
+            &args.patterns.not_patterns,
+            matcher_options,
+        )?;
+
+        // -q, -l and -L only care whether there's a match at all, -c only
+        // how many, -o only about the matches, --only-line-numbers only
+        // where they are and --format, --csv, --sarif and --json only lay
+        // out selected lines, so none of them want context lines. Nor does
+        // --dedupe, which would print the context of lines it leaves out.
+        let lines_wanted = !(args.quiet
+            || args.lists_files()
+            || args.counts()
+            || args.only_matching
+            || args.only_line_numbers
+            || args.format.is_some()
+            || args.csv
+            || args.sarif
+            || args.json
+            || args.dedupe
+            || args.dedupe_global);
+        let context = |specific: Option<usize>| {
+            let lines = specific.or(args.context).unwrap_or(0);
+            if lines_wanted { lines } else { 0 }
+        };
+        let options = SearchOptions {
+            before_context: context(args.before_context),
+            after_context: context(args.after_context),
+            max_count: args.max_count,
+            null_data: args.null_data,
+        };
+
+        // A file without a match still shows up with -v, -L and -c, and
+        // what --pre, --search-zip, --search-archives and --encoding search
+        // isn't the bytes that were indexed
+        let index_usable = !(args.walk.no_index
+            || args.patterns.invert_match
+            || args.files_without_match
+            || args.counts()
+            || args.pre.is_some()
+            || args.search_zip
+            || args.search_archives
+            || args.encoding.is_some());
+        let index_texts = index_usable
+            .then(|| required_texts(patterns, matcher_options))
+            .flatten();
+
+        // A group that doesn't exist would otherwise quietly print nothing
+        let template = args.replace.as_deref().map(Template::parse);
+        if let Some(template) = &template {
+            template.check(patterns, args.patterns.fixed_strings)?;
+        }
+
+        Ok(App {
+            args,
+            searcher: Searcher::new(matcher, options),
+            template,
+            preprocessor: args
+                .pre
+                .as_deref()
+                .map(|command| Preprocessor::new(command, &args.pre_glob))
+                .transpose()?,
+            // The --and patterns' matches are numbered after the others'
+            sarif: args
+                .sarif
+                .then(|| sarif::Collector::new([patterns, &args.patterns.and_patterns].concat())),
+            index_texts,
+            filter: {
+                let types = file_types(args)?;
+                PathFilter::new(&args.walk.include, &args.walk.exclude)?
+                    .with_iglobs(&args.walk.iglob)?
+                    .with_types(
+                        &types.globs(&args.walk.types)?,
+                        &types.globs(&args.walk.type_not)?,
+                    )?
+                    .with_excluded_dirs(&args.walk.exclude_dir)?
+            },
+            print: PrintOptions {
+                line_number: args.line_number || args.column,
+                column: args.column,
+                byte_offset: args.byte_offset,
+                colors,
+                null: args.null,
+                context: options.before_context > 0 || options.after_context > 0,
+                line_buffered: args.line_buffered,
+                null_data: args.null_data,
+                heading: args.heading,
+                with_filename: resolve_with_filename(args, false),
+                context_separator: (!args.no_context_separator)
+                    .then(|| unescape(&args.context_separator)),
+                max_columns: args.max_columns,
+                max_columns_preview: args.max_columns_preview,
+                trim: args.trim,
+                format: args.format.as_deref().map(Format::parse).transpose()?,
+                csv: args.csv,
+                json: args.json,
+            },
+            encoding: args
+                .encoding
+                .as_deref()
+                .map(|label| {
+                    Encoding::for_label(label.as_bytes()).ok_or_else(|| {
+                        GrssError::Usage(format!(
+                            "unknown encoding '{}', it takes WHATWG labels like utf-8, \
+                             utf-16le, windows-1252, latin1, shift_jis or euc-kr",
+                            label
+                        ))
+                    })
+                })
+                .transpose()?,
+            stats: Stats::default(),
+            output: None,
+            files_written: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            printed_lines: Mutex::new(HashSet::new()),
+            total_printed: AtomicUsize::new(0),
+            matched: AtomicBool::new(false),
+            metrics: Mutex::new(Metrics::default()),
+        })
+    }
+
+    /// Searches one of the paths given on the command line: standard input
+    /// for `-`, a single file, or with -r a whole directory
+    ///
+    /// # Returns
+    /// Whether anything matched
+    fn search_path<W: Write>(&self, path: &Path, out: &mut W) -> Result<bool, GrssError> {
+        // `-` is the usual Unix spelling for "read standard input instead"
+        let metrics = Metrics::default();
+        if path.as_os_str() == "-" {
+            if self.args.byte_range.is_some() {
+                return Err(GrssError::Usage(
+                    "--byte-range only works on files, not standard input".to_string(),
+                ));
+            }
+            let stdin_path = Path::new("(standard input)");
+            let reader = self.decode(Timed::new(io::stdin().lock(), &metrics));
+            let result = metrics.time_rest(Phase::Match, || {
+                self.search(reader, Some(stdin_path), false, false, &metrics, out)
+            });
+            self.metrics.lock().unwrap().merge(&metrics);
+            return result.map_err(|error| GrssError::io(stdin_path, error));
+        }
+
+        // fs::metadata tells us whether the path exists and whether it's a
+        // directory, without opening it
+        let metadata = fs::metadata(path).map_err(|error| GrssError::io(path, error))?;
+
+        let result = if metadata.is_dir() {
+            if !self.args.walk.recursive {
+                return Err(GrssError::IsADirectory(path.to_path_buf()));
+            }
+            if self.args.walk.max_depth == Some(0) {
+                // Depth 0 is the directory itself, so there'd be nothing to
+                // search; most likely not what was meant
+                log::warn!(
+                    "{}: not searched, --max-depth 0 doesn't go inside directories",
+                    path.display()
+                );
+                return Ok(false);
+            }
+            self.search_dir(path, out)
+        } else {
+            let result = self.search_file(path, false, &metrics, out);
+            self.metrics.lock().unwrap().merge(&metrics);
+            result
+        };
+        result.map_err(|error| GrssError::io(path, error))
+    }
+
+    /// Walks `root` and searches every regular file underneath it
+    ///
+    /// The work is split over several threads: one walks the directory tree
+    /// and queues up file paths, a pool of workers takes paths off the queue
+    /// and searches them, and the calling thread prints. Each worker writes a
+    /// file's output into its own Vec<u8> and hands the whole thing over once
+    /// the file is done, so lines from two files can never get mixed up and
+    /// the lines of one file stay in order. Which file comes out first depends
+    /// on which worker finishes first, so without --sort the order of files
+    /// can change from run to run. When `root` has an index (see `grss
+    /// index`), the files it rules out never make it onto the queue.
+    ///
+    /// # Returns
+    /// Whether at least one line in any of the files matched
+    fn search_dir<W: Write>(&self, root: &Path, out: &mut W) -> io::Result<bool> {
+        let threads = match self.args.walk.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            threads => threads,
+        };
+        let (sort, reverse) = match self.args.walk.sortr {
+            Some(sort) => (sort, true),
+            None => (self.args.walk.sort, false),
+        };
+
+        // std's Receiver can only be used from one thread at a time, so the
+        // workers take turns through a Mutex. Holding the lock only for the
+        // recv() call keeps that turn-taking short.
+        let (path_sender, path_receiver) = mpsc::channel::<(usize, PathBuf, SortKey)>();
+        let path_receiver = Mutex::new(path_receiver);
+        let (output_sender, output_receiver) = mpsc::channel::<Searched>();
+
+        // thread::scope lets the threads borrow `self` and `root` directly,
+        // because every thread is guaranteed to be joined before it returns
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                // The walk goes through each directory sorted by name, so
+                // numbering the files as they're found numbers them in
+                // --sort path order
+                let mut index = 0;
+                let started = Instant::now();
+                let indexed = self
+                    .index_texts
+                    .as_deref()
+                    .and_then(|texts| IndexedSearch::new(root, texts));
+                self.walk(root, |path, metadata| {
+                    if let Some(indexed) = &indexed {
+                        let metadata = metadata.clone().or_else(|| fs::metadata(&path).ok());
+                        if metadata.is_some_and(|metadata| {
+                            indexed.can_skip(relative_path(root, &path), &metadata)
+                        }) {
+                            log::info!("{}: skipped, the index rules it out", path.display());
+                            self.stats.skipped(Skip::Indexed, 1);
+                            return;
+                        }
+                    }
+                    // Taken before the file is searched, so --sort accessed
+                    // goes by when it was read before grss read it
+                    let key = sort_key(metadata.as_ref(), sort);
+                    // Fails only once every worker has stopped, in which case
+                    // there's nobody left to search the rest anyway
+                    let _ = path_sender.send((index, path, key));
+                    index += 1;
+                });
+                let walked = started.elapsed();
+                self.metrics.lock().unwrap().add(Phase::Walk, walked);
+                // path_sender is dropped here, which is what tells the
+                // workers that no more paths are coming
+            });
+
+            for _ in 0..threads {
+                let output_sender = output_sender.clone();
+                let path_receiver = &path_receiver;
+                scope.spawn(move || {
+                    let metrics = Metrics::default();
+                    loop {
+                        let next = path_receiver.lock().unwrap().recv();
+                        let Ok((index, path, key)) = next else { break };
+                        if self.is_stopped() {
+                            break;
+                        }
+
+                        let mut output = Vec::new();
+                        let result = self.search_file(&path, true, &metrics, &mut output);
+                        let matched = match result {
+                            Ok(matched) => matched,
+                            // Stopped between two lines, so what's in
+                            // `output` so far is still worth printing
+                            Err(_) if self.is_stopped() => false,
+                            Err(error) => {
+                                log::warn!("{}", GrssError::io(&path, error));
+                                self.stats.skipped(Skip::Failed, 1);
+                                // Still handed over, with nothing in it, so
+                                // --sort path isn't left waiting for it
+                                output.clear();
+                                false
+                            }
+                        };
+                        let searched = Searched {
+                            index,
+                            output,
+                            matched,
+                            key,
+                        };
+                        if output_sender.send(searched).is_err() {
+                            // The printer gave up (e.g. stdout closed)
+                            break;
+                        }
+                    }
+                    self.metrics.lock().unwrap().merge(&metrics);
+                });
+            }
+            // Drop our own copy so print_searched()'s loop ends when the
+            // last worker finishes and drops its clone
+            drop(output_sender);
+
+            let printed = self.print_searched(output_receiver, (sort, reverse), out);
+            // Nothing more can be printed (say, stdout was closed), so the
+            // walk and the workers stop too instead of searching for nobody
+            if printed.is_err() {
+                self.stopped.store(true, Ordering::Relaxed);
+            }
+            printed
+        })
+    }
+
+    /// The printing thread of search_dir(): writes each file's output as
+    /// the workers hand it over, in the order `sort` asks for
+    ///
+    /// # Returns
+    /// Whether at least one line in any of the files matched
+    fn print_searched<W: Write>(
+        &self,
+        output_receiver: mpsc::Receiver<Searched>,
+        (sort, reverse): (SortBy, bool),
+        out: &mut W,
+    ) -> io::Result<bool> {
+        let metrics = Metrics::default();
+        let mut found_match = false;
+        // --sort path: files that finished before some file found ahead
+        // of them, by index, and the index of the next file to print
+        let mut waiting = BTreeMap::new();
+        let mut next = 0;
+        // Every other order: everything, to be sorted at the end
+        let mut all = Vec::new();
+        for searched in output_receiver {
+            found_match |= searched.matched;
+            match (sort, reverse) {
+                (SortBy::None, _) => self.write_searched(out, &searched.output, &metrics)?,
+                (SortBy::Path, false) => {
+                    waiting.insert(searched.index, searched.output);
+                    while let Some(output) = waiting.remove(&next) {
+                        self.write_searched(out, &output, &metrics)?;
+                        next += 1;
+                    }
+                }
+                _ => all.push(searched),
+            }
+            // The workers' Printers only flushed into their Vecs
+            if self.print.line_buffered {
+                metrics.time(Phase::Print, || out.flush())?;
+            }
+            // One match is all -q needs to know. Leaving the loop drops
+            // the receiver, so workers stop at their next send.
+            if found_match && self.args.stops_at_first_match() {
+                break;
+            }
+        }
+
+        // A stopped search leaves gaps, files nobody searched
+        for output in waiting.into_values() {
+            self.write_searched(out, &output, &metrics)?;
+        }
+        // Ties (and --sort path) go by the order of the walk. Files missing
+        // the time or size asked for go last, reversed or not.
+        let missing = |searched: &Searched| sort.needs_metadata() && searched.key == SortKey::None;
+        all.sort_by(|a, b| {
+            let order = a.key.cmp(&b.key).then(a.index.cmp(&b.index));
+            let order = if reverse { order.reverse() } else { order };
+            missing(a).cmp(&missing(b)).then(order)
+        });
+        for searched in all {
+            self.write_searched(out, &searched.output, &metrics)?;
+        }
+        self.metrics.lock().unwrap().merge(&metrics);
+        Ok(found_match)
+    }
+
+    /// Writes what search_dir() got out of one file, with a blank line in
+    /// front when it's another --heading group after the first
+    fn write_searched<W: Write>(
+        &self,
+        out: &mut W,
+        output: &[u8],
+        metrics: &Metrics,
+    ) -> io::Result<()> {
+        metrics.time(Phase::Print, || {
+            if !output.is_empty() && self.files_written.swap(true, Ordering::Relaxed) {
+                self.print.write_file_separator(out)?;
+            }
+            out.write_all(output)
+        })
+    }
+
+    /// --files: writes the path of every file a search of `paths` would look
+    /// at, walking the directories among them even without -r
+    ///
+    /// # Returns
+    /// Whether any file was listed, or PathsFailed when a path doesn't
+    /// exist (after listing the others)
+    fn list_files<W: Write>(&self, paths: &[PathBuf], out: &mut W) -> Result<bool, GrssError> {
+        let mut listed = false;
+        let mut failed = false;
+        for path in paths {
+            let mut printer = Printer::new(&mut *out, &self.print, None);
+            let mut written = Ok(());
+            let mut list = |file: &Path| {
+                if written.is_ok() {
+                    written = printer.file_name(file);
+                    listed = true;
+                }
+            };
+            match fs::metadata(path) {
+                Ok(metadata) if metadata.is_dir() => self.walk(path, |file, _| list(&file)),
+                _ if path.as_os_str() == "-" => list(path),
+                Ok(_) => list(path),
+                Err(error) => {
+                    log::error!("{}", GrssError::io(path, error));
+                    failed = true;
+                }
+            }
+            written.map_err(|error| GrssError::io(Path::new("(standard output)"), error))?;
+        }
+        match failed {
+            true => Err(GrssError::PathsFailed),
+            false => Ok(listed),
+        }
+    }
+
+    /// Whether the search should stop: Ctrl-C was pressed, or -q already
+    /// found what it was after
+    fn is_stopped(&self) -> bool {
+        interrupt::is_interrupted() || self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Walks `root` and calls `found` with every regular file underneath it
+    /// that passes --include/--exclude, warning about entries it can't read
+    ///
+    /// Each file's metadata comes along when --max-filesize or --sort needed
+    /// it, so the file is only looked up the once. The walk ends early once
+    /// the search is stopped (see is_stopped()).
+    fn walk(&self, root: &Path, mut found: impl FnMut(PathBuf, Option<fs::Metadata>)) {
+        let options = walk_options(self.args);
+        let on_error = |error| {
+            log::warn!("{}", error);
+            self.stats.skipped(Skip::Failed, 1);
+        };
+        // Only the size the file system reports is looked at, a huge file
+        // isn't even opened
+        let found = |path: PathBuf| {
+            if self.is_stopped() {
+                return ControlFlow::Break(());
+            }
+            let sort = self.args.walk.sortr.unwrap_or(self.args.walk.sort);
+            let metadata = (self.args.walk.max_filesize.is_some() || sort.needs_metadata())
+                .then(|| fs::metadata(&path).ok())
+                .flatten();
+            if let Some(max) = self.args.walk.max_filesize
+                && metadata
+                    .as_ref()
+                    .is_some_and(|metadata| metadata.len() > max)
+            {
+                log::warn!("{}: skipped, bigger than --max-filesize", path.display());
+                self.stats.skipped(Skip::TooBig, 1);
+                return ControlFlow::Continue(());
+            }
+            found(path, metadata);
+            ControlFlow::Continue(())
+        };
+        let filtered_out = walk_files(root, &self.filter, options, on_error, found);
+        self.stats.skipped(Skip::Filtered, filtered_out);
+    }
+
+    /// Opens the file at `path` and searches it
+    ///
+    /// `in_walk` is true for files found by walking a directory: binary files
+    /// among them are skipped quietly (a recursive search over a project
+    /// would otherwise complain about every file in target/ or .git/).
+    fn search_file<W: Write>(
+        &self,
+        path: &Path,
+        in_walk: bool,
+        metrics: &Metrics,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        // Only reading and printing are timed as such, the rest of the
+        // file's time is what matching took
+        metrics.time_rest(Phase::Match, || {
+            self.open_and_search(path, in_walk, metrics, out)
+        })
+    }
+
+    /// The part of search_file() that isn't about timing it
+    fn open_and_search<W: Write>(
+        &self,
+        path: &Path,
+        in_walk: bool,
+        metrics: &Metrics,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        if self.is_output(path) {
+            // Searching the results as they're written would find every
+            // match again, and write it again, and find it again...
+            log::info!("{}: skipped, it's the --output file", path.display());
+            self.stats.skipped(Skip::Other, 1);
+            return Ok(false);
+        }
+        if let Some(preprocessor) = &self.preprocessor
+            && preprocessor.applies_to(path)
+        {
+            return match metrics.time(Phase::Read, || preprocessor.run(path)) {
+                Ok(output) => {
+                    log::info!("{}: searching what --pre made of it", path.display());
+                    self.search_reader(output.as_slice(), path, in_walk, metrics, out)
+                }
+                Err(error) => {
+                    log::warn!("{}: skipped, --pre failed: {}", path.display(), error);
+                    self.stats.skipped(Skip::Failed, 1);
+                    Ok(false)
+                }
+            };
+        }
+        let file = metrics.time(Phase::Read, || File::open(path))?;
+        log::info!("{}: searching", path.display());
+
+        if let Some(range) = self.args.byte_range {
+            return self.search_byte_range(file, range, path, in_walk, metrics, out);
+        }
+
+        if self.args.search_zip && input::is_gzip(path) {
+            return self.search_reader(input::gunzip(file), path, in_walk, metrics, out);
+        }
+        if self.args.search_archives && input::is_zip(path) {
+            return self.search_archive(file, path, metrics, out);
+        }
+
+        // A mapped file is searched as raw UTF-8, so anything that needs
+        // transcoding first goes through the reader below instead
+        if self.args.mmap
+            && let Some(map) = metrics.time(Phase::Read, || input::map_file(&file))
+            && self.encoding.is_none()
+            && !input::has_utf16_bom(&map)
+        {
+            let data = map.strip_prefix(UTF8_BOM).unwrap_or(&map);
+            let binary = self.is_binary(&data[..data.len().min(BINARY_CHECK_LEN)]);
+            if binary && self.skip_binary(path, in_walk) {
+                return Ok(false);
+            }
+            self.stats.read(data.len());
+            return self.search_slice(data, Some(path), in_walk, binary, metrics, out);
+        }
+
+        self.search_reader(file, path, in_walk, metrics, out)
+    }
+
+    /// The part of search_file() for anything that isn't memory-mapped:
+    /// reads the contents of the file at `path` from `reader` (the file
+    /// itself, or a decompressor wrapped around it) through a buffer
+    fn search_reader<R: Read, W: Write>(
+        &self,
+        reader: R,
+        path: &Path,
+        in_walk: bool,
+        metrics: &Metrics,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        let mut reader = BufReader::new(Timed::new(reader, metrics));
+        // fill_buf() doesn't consume anything, so the search still starts
+        // from the very first byte. UTF-16 text is full of NUL bytes, so it
+        // can't be told apart from binary this way and isn't checked at all.
+        let head = reader.fill_buf()?;
+        let utf16 =
+            input::has_utf16_bom(head) || self.encoding.is_some_and(|e| !e.is_ascii_compatible());
+        let binary = !utf16 && self.is_binary(head);
+        if binary && self.skip_binary(path, in_walk) {
+            return Ok(false);
+        }
+        self.search(
+            self.decode(reader),
+            Some(path),
+            in_walk,
+            binary,
+            metrics,
+            out,
+        )
+    }
+
+    /// The part of search_file() for --byte-range: searches the lines of
+    /// `file` in `range`, numbered as in the whole file
+    ///
+    /// Always reads through a buffer, even with --mmap, which would gain
+    /// nothing on the part of a file that's read.
+    fn search_byte_range<W: Write>(
+        &self,
+        mut file: File,
+        range: byte_range::ByteRange,
+        path: &Path,
+        in_walk: bool,
+        metrics: &Metrics,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        let terminator = self.searcher.options().terminator();
+        let bounds = metrics.time(Phase::Read, || range.bounds(&mut file, terminator))?;
+        log::info!(
+            "{}: searching bytes {}..{}",
+            path.display(),
+            bounds.start,
+            bounds.end
+        );
+        let mut reader = BufReader::new(Timed::new(file.take(bounds.end - bounds.start), metrics));
+        // A BOM is only ever at the very start, --encoding still applies
+        let head = reader.fill_buf()?;
+        let utf16 =
+            input::has_utf16_bom(head) || self.encoding.is_some_and(|e| !e.is_ascii_compatible());
+        let binary = !utf16 && self.is_binary(head);
+        if binary && self.skip_binary(path, in_walk) {
+            return Ok(false);
+        }
+        let reader = Counted::new(
+            Interruptible::new(self.decode(reader), &self.stopped),
+            &self.stats,
+        );
+        let (lines_before, offset_before) = (bounds.lines_before, bounds.start as usize);
+        self.print_results(Some(path), in_walk, binary, metrics, out, |sink| {
+            // The Searcher numbers lines (and offsets) from the start of
+            // what it's given, like for --watch
+            self.searcher.search_reader(reader, &mut |mut found| {
+                found.number += lines_before;
+                found.offset += offset_before;
+                sink(found)
+            })
+        })
+    }
+
+    /// Searches every file in the zip archive `file` (at `path`) for
+    /// --search-archives, each as if it were a file of its own at
+    /// `path!name`
+    ///
+    /// Entries are decompressed a buffer at a time as they're searched, like
+    /// gzip files. Archives inside the archive aren't opened, and binary
+    /// entries are skipped quietly, the way they are in a directory.
+    ///
+    /// # Returns
+    /// Whether at least one line in any of the entries matched, or the first
+    /// error reading the archive, which ends the search of this archive only
+    fn search_archive<W: Write>(
+        &self,
+        file: File,
+        path: &Path,
+        metrics: &Metrics,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        let mut archive = metrics.time(Phase::Read, || ZipArchive::new(BufReader::new(file)))?;
+        let mut found = false;
+        for index in 0..archive.len() {
+            let entry = metrics.time(Phase::Read, || archive.by_index(index))?;
+            if entry.is_dir() {
+                continue;
+            }
+            let name = Path::new(entry.name());
+            let entry_path = PathBuf::from(format!("{}!{}", path.display(), name.display()));
+            if input::is_zip(name) || input::is_gzip(name) {
+                log::info!(
+                    "{}: skipped, archives inside archives aren't searched",
+                    entry_path.display()
+                );
+                self.stats.skipped(Skip::Other, 1);
+                continue;
+            }
+            if entry.size() > MAX_ENTRY_SIZE {
+                log::warn!(
+                    "{}: skipped, decompresses to more than {} bytes",
+                    entry_path.display(),
+                    MAX_ENTRY_SIZE
+                );
+                self.stats.skipped(Skip::TooBig, 1);
+                continue;
+            }
+            let entry = Capped::new(entry, MAX_ENTRY_SIZE);
+            found |= self.search_reader(entry, &entry_path, true, metrics, out)?;
+        }
+        Ok(found)
+    }
+
+    /// Wraps `reader` so that whatever it reads comes out as UTF-8, going by
+    /// its BOM or --encoding
+    fn decode<R: Read>(&self, reader: R) -> impl BufRead {
+        input::decode(reader, self.encoding)
+    }
+
+    /// Whether `path` is the --output file
+    fn is_output(&self, path: &Path) -> bool {
+        // Comparing the names first saves canonicalizing every file in the
+        // tree
+        self.output.as_ref().is_some_and(|output| {
+            output.file_name() == path.file_name()
+                && fs::canonicalize(path).is_ok_and(|path| &path == output)
+        })
+    }
+
+    /// Whether a file that starts with `head` is binary
+    fn is_binary(&self, head: &[u8]) -> bool {
+        // NUL bytes are what binary files are told apart by, and with
+        // --null-data they separate the records
+        !self.args.null_data && input::is_binary(head)
+    }
+
+    /// Whether the binary file at `path` should be skipped, which it is
+    /// unless --text says to search it anyway
+    ///
+    /// Printing lines out of a binary file just fills the terminal with junk
+    fn skip_binary(&self, path: &Path, in_walk: bool) -> bool {
+        if self.args.text {
+            log::info!("{}: binary file, searching it as text", path.display());
+            return false;
+        }
+        if in_walk {
+            log::info!("{}: skipped, binary file", path.display());
+        } else {
+            log::warn!("{}: binary file, skipping", path.display());
+        }
+        self.stats.skipped(Skip::Binary, 1);
+        true
+    }
+
+    /// Reads `reader` line by line and writes every selected line to `out`
+    ///
+    /// `path` is where the input came from, None when there's no path to
+    /// show. Each output line is prefixed with it (`path:text`, or
+    /// `path:line:text` with -n) when -H is on, or by default when several
+    /// files are searched, like grep does. `binary` is for a binary file
+    /// searched anyway with --text, whose lines are printed with care (see
+    /// Printer::binary()).
+    ///
+    /// Taking any `BufRead` and any `Write` (instead of a File and stdout) is
+    /// what lets the tests below feed in a string and capture the output in a
+    /// Vec<u8>
+    ///
+    /// With -c nothing is printed per line, just the number of matching lines
+    /// once the input is exhausted (with the same path prefix).
+    ///
+    /// # Returns
+    /// Whether at least one line matched
+    fn search<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        path: Option<&Path>,
+        in_walk: bool,
+        binary: bool,
+        metrics: &Metrics,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        let reader = Counted::new(Interruptible::new(reader, &self.stopped), &self.stats);
+        self.print_results(path, in_walk, binary, metrics, out, |sink| {
+            self.searcher.search_reader(reader, sink)
+        })
+    }
+
+    /// Like search(), over a file that's in memory already (memory-mapped)
+    fn search_slice<W: Write>(
+        &self,
+        data: &[u8],
+        path: Option<&Path>,
+        in_walk: bool,
+        binary: bool,
+        metrics: &Metrics,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        self.print_results(path, in_walk, binary, metrics, out, |sink| {
+            self.searcher.search_slice(data, sink)
+        })
+    }
+
+    /// Prints a line the Searcher handed over, as line `number` starting
+    /// `offset` bytes in (which --watch counts from the start of the file,
+    /// not of what the Searcher saw)
+    fn print_found<W: Write>(
+        &self,
+        printer: &mut Printer<W>,
+        number: usize,
+        offset: usize,
+        found: &grss::Match,
+    ) -> io::Result<()> {
+        let (line, ranges) = self.shown(found);
+        if let Some(format) = &self.print.format
+            && found.kind == LineKind::Selected
+        {
+            return printer.formatted(format, number, offset, &line, &ranges);
+        }
+        if self.print.json && found.kind == LineKind::Selected {
+            return printer.json_record(number, offset, &line, &ranges);
+        }
+        if self.print.csv && found.kind == LineKind::Selected {
+            return printer.csv_records(number, &line, &ranges);
+        }
+        if self.args.only_line_numbers {
+            return printer.line_number(number);
+        }
+        if self.args.only_matching {
+            return printer.matches(number, offset, &line, &ranges);
+        }
+        printer.line(number, offset, found.kind, &line, &ranges)
+    }
+
+    /// The bytes of a line the Searcher handed over as it's printed, which
+    /// is after --replace for a selected one, and where to highlight in it
+    fn shown<'f>(&self, found: &grss::Match<'f>) -> (Cow<'f, [u8]>, Vec<Range<usize>>) {
+        match &self.template {
+            Some(template) if found.kind == LineKind::Selected => {
+                let (replaced, ranges) = self.searcher.matcher().replace(found.line, template);
+                (Cow::Owned(replaced), ranges)
+            }
+            _ => (Cow::Borrowed(found.line), found.ranges()),
+        }
+    }
+
+    /// Does the printing for search() and search_slice(): `search` runs the
+    /// Searcher with a sink that prints each line it's handed, then comes
+    /// the file name for -l/-L or the count for -c
+    ///
+    /// `in_walk` is true when `out` is one file's buffer in search_dir(),
+    /// which separates --heading groups itself. The time spent printing is
+    /// counted into `metrics`.
+    fn print_results<W: Write>(
+        &self,
+        path: Option<&Path>,
+        in_walk: bool,
+        binary: bool,
+        metrics: &Metrics,
+        out: &mut W,
+        search: impl FnOnce(&mut Sink) -> io::Result<Outcome>,
+    ) -> io::Result<bool> {
+        // Counting and listing files don't print any lines
+        let print_lines = !(self.args.counts() || self.args.quiet || self.args.lists_files());
+        // -q and -l only care whether there's a match at all, so the first
+        // selected line is the last one needed. -q is done with every file
+        // then, unless it's -L asking about the files without one.
+        let stop = match (self.args.stops_at_first_match(), self.args.lists_files()) {
+            (true, _) if !self.args.files_without_match => Some(Stop::All),
+            (true, _) | (_, true) => Some(Stop::File),
+            _ => None,
+        };
+        let mut printer = Printer::new(out, &self.print, path);
+        if !in_walk {
+            printer = printer.with_file_separator(&self.files_written);
+        }
+        if binary {
+            printer = printer.binary();
+        }
+        let mut dedupe = (self.args.dedupe || self.args.dedupe_global).then(|| {
+            let global = self.args.dedupe_global.then_some(&self.printed_lines);
+            Dedupe::new(global, self.args.count_duplicates)
+        });
+
+        let mut occurrences = 0;
+        let outcome = search(&mut |found| {
+            // Checked before each line, so every line printed is whole
+            interrupt::check()?;
+            if found.kind == LineKind::Selected && !self.args.files_without_match {
+                self.matched.store(true, Ordering::Relaxed);
+            }
+            if self.args.count_matches || self.args.stats || self.args.summary {
+                occurrences += found.match_count();
+            }
+            if !print_lines {
+                return Ok(stop);
+            }
+            if let Some(dedupe) = &mut dedupe
+                && found.kind == LineKind::Selected
+            {
+                // Only the text is needed to tell whether it came before,
+                // not where the matches are
+                let text = match &self.template {
+                    Some(template) => {
+                        Cow::Owned(self.searcher.matcher().replace(found.line, template).0)
+                    }
+                    None => Cow::Borrowed(found.line),
+                };
+                let hold = || {
+                    let (line, ranges) = self.shown(&found);
+                    Held {
+                        number: found.number,
+                        offset: found.offset,
+                        line: line.into_owned(),
+                        ranges,
+                        times: 1,
+                    }
+                };
+                if !dedupe.first(&text, hold) {
+                    return Ok(None);
+                }
+            }
+            // Each line claims its place before it's printed, so exactly
+            // --max-total get printed however the files' searches race
+            let mut last = false;
+            if let Some(max) = self.args.max_total
+                && found.kind == LineKind::Selected
+            {
+                let claimed = self.total_printed.fetch_add(1, Ordering::Relaxed);
+                if claimed >= max {
+                    return Ok(Some(Stop::All));
+                }
+                last = claimed + 1 == max;
+            }
+            metrics.time(Phase::Print, || match &self.sarif {
+                // Which pattern matched is only known to the Matcher
+                Some(sarif) => {
+                    let matches = self.searcher.matcher().find_matches(found.line);
+                    sarif.add(path, found.number, found.line, &matches);
+                    Ok(())
+                }
+                None => self.print_found(&mut printer, found.number, found.offset, &found),
+            })?;
+            Ok(last.then_some(Stop::All))
+        })?;
+        if outcome.stopped == Some(Stop::All) {
+            self.stopped.store(true, Ordering::Relaxed);
+        }
+        // --count-duplicates held the lines back until now
+        for held in dedupe.map(Dedupe::held).unwrap_or_default() {
+            metrics.time(Phase::Print, || {
+                printer.repeated_line(
+                    held.number,
+                    held.offset,
+                    &held.line,
+                    &held.ranges,
+                    held.times,
+                )
+            })?;
+        }
+        let count = outcome.count;
+        let found_match = count > 0;
+        self.stats.searched(count, occurrences);
+        if self.args.summary {
+            let path = path.unwrap_or(Path::new("(standard input)"));
+            self.stats.file(path, count, occurrences);
+        }
+
+        if self.args.lists_files() {
+            // -l lists the files that had a selected line, -L the ones that
+            // didn't. Either way the answer is "did we print a name", so a
+            // -L that lists something exits with 0 like GNU grep does.
+            let listed = found_match == self.args.files_with_matches;
+            if listed && !self.args.quiet {
+                self.matched.store(true, Ordering::Relaxed);
+                let name = path.unwrap_or(Path::new("(standard input)"));
+                metrics.time(Phase::Print, || printer.file_name(name))?;
+            }
+            return Ok(listed);
+        }
+
+        if self.args.counts() && !self.args.quiet {
+            // Files with no matches still get `path:0`, like grep
+            let count = match self.args.count_matches {
+                true => occurrences,
+                false => count,
+            };
+            metrics.time(Phase::Print, || printer.count(count))?;
+        }
+
+        Ok(found_match)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str =
+        "let x = 5;\nlet y = 6;\nprintln!(\"{}\", x);\nlet z = x + y;\nfn main() {}";
+
+    /// Runs a search over FIXTURE with the given command line arguments
+    fn run(argv: &[&str]) -> String {
+        run_on(FIXTURE, argv)
+    }
+
+    /// Runs a search over `input` with the given command line arguments
+    fn run_on(input: &str, argv: &[&str]) -> String {
+        let args = Cli::parse_from(argv);
+        let (patterns, _) = args.patterns_and_paths().unwrap();
+        let colors = (args.color == ColorChoice::Always).then(Colors::default);
+        let app = App::new(&args, &patterns, colors).unwrap();
+        let mut out = Vec::new();
+        app.search(
+            input.as_bytes(),
+            None,
+            false,
+            false,
+            &Metrics::default(),
+            &mut out,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn prints_line_numbers_with_n() {
+        assert_eq!(
+            run(&["grss", "-n", "x", "fixture.txt"]),
+            "1:let x = 5;\n3:println!(\"{}\", x);\n4:let z = x + y;\n"
+        );
+    }
+
+    #[test]
+    fn omits_line_numbers_by_default() {
+        assert_eq!(run(&["grss", "main", "fixture.txt"]), "fn main() {}\n");
+    }
+
+    #[test]
+    fn anchors_match_start_and_end_of_line() {
+        assert_eq!(run(&["grss", "^let", "fixture.txt"]).lines().count(), 3);
+        assert_eq!(run(&["grss", "\\{\\}$", "fixture.txt"]), "fn main() {}\n");
+    }
+
+    #[test]
+    fn character_classes() {
+        assert_eq!(
+            run(&["grss", "let [xz] =", "fixture.txt"]),
+            "let x = 5;\nlet z = x + y;\n"
+        );
+        assert_eq!(run(&["grss", "= \\d;", "fixture.txt"]).lines().count(), 2);
+    }
+
+    #[test]
+    fn highlights_every_match_on_a_line() {
+        assert_eq!(
+            run(&["grss", "--color", "always", "x", "fixture.txt"])
+                .lines()
+                .nth(1),
+            Some("println!(\"{}\", \x1b[1;31mx\x1b[0m);")
+        );
+        assert_eq!(
+            run(&["grss", "--color", "always", "[xy]", "fixture.txt"])
+                .lines()
+                .nth(3),
+            Some("let z = \x1b[1;31mx\x1b[0m + \x1b[1;31my\x1b[0m;")
+        );
+    }
+
+    #[test]
+    fn color_never_matches_plain_output() {
+        assert_eq!(
+            run(&["grss", "--color", "never", "-n", "x", "fixture.txt"]),
+            run(&["grss", "-n", "x", "fixture.txt"])
+        );
+    }
+
+    #[test]
+    fn count_prints_number_of_matching_lines() {
+        assert_eq!(run(&["grss", "-c", "x", "fixture.txt"]), "3\n");
+        assert_eq!(run(&["grss", "-c", "nothing", "fixture.txt"]), "0\n");
+    }
+
+    #[test]
+    fn count_matches_counts_each_occurrence() {
+        let input = "foo foo foo\nbar\nFoo food\n";
+        assert_eq!(run_on(input, &["grss", "-c", "foo", "in.txt"]), "2\n");
+        assert_eq!(
+            run_on(input, &["grss", "--count-matches", "foo", "in.txt"]),
+            "4\n"
+        );
+        assert_eq!(
+            run_on(
+                input,
+                &["grss", "--count-matches", "-i", "-w", "foo", "in.txt"]
+            ),
+            "4\n"
+        );
+        assert_eq!(
+            run_on(
+                input,
+                &[
+                    "grss",
+                    "--count-matches",
+                    "-e",
+                    "foo",
+                    "-e",
+                    "bar",
+                    "in.txt"
+                ]
+            ),
+            "5\n"
+        );
+        // -v selects lines with no matches in them, so it counts lines
+        assert_eq!(
+            run_on(input, &["grss", "--count-matches", "-v", "foo", "in.txt"]),
+            "1\n"
+        );
+        assert_eq!(
+            run_on(input, &["grss", "--count-matches", "nothing", "in.txt"]),
+            "0\n"
+        );
+    }
+
+    #[test]
+    fn invert_prints_non_matching_lines() {
+        assert_eq!(
+            run(&["grss", "-v", "-n", "x", "fixture.txt"]),
+            "2:let y = 6;\n5:fn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn invert_count_is_total_minus_matches() {
+        let total = FIXTURE.lines().count();
+        let matches: usize = run(&["grss", "-c", "x", "fixture.txt"])
+            .trim()
+            .parse()
+            .unwrap();
+        let inverted: usize = run(&["grss", "-v", "-c", "x", "fixture.txt"])
+            .trim()
+            .parse()
+            .unwrap();
+        assert_eq!(inverted, total - matches);
+    }
+
+    #[test]
+    fn invert_disables_highlighting() {
+        assert_eq!(
+            run(&["grss", "-v", "--color", "always", "x", "fixture.txt"]),
+            "let y = 6;\nfn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn ignore_case_composes_with_invert() {
+        assert_eq!(run(&["grss", "-i", "-c", "LET", "fixture.txt"]), "3\n");
+        assert_eq!(
+            run(&["grss", "-i", "-v", "-c", "LET", "fixture.txt"]),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn smart_case_gives_way_to_i_and_s() {
+        assert_eq!(run(&["grss", "-S", "-c", "let", "fixture.txt"]), "3\n");
+        assert_eq!(run(&["grss", "-S", "-c", "LET", "fixture.txt"]), "0\n");
+        assert_eq!(
+            run(&["grss", "-S", "-i", "-c", "LET", "fixture.txt"]),
+            "3\n"
+        );
+        // Of -i and -s the last one wins
+        assert_eq!(
+            run(&["grss", "-i", "-s", "-c", "LET", "fixture.txt"]),
+            "0\n"
+        );
+        assert_eq!(
+            run(&["grss", "-s", "-i", "-c", "LET", "fixture.txt"]),
+            "3\n"
+        );
+    }
+
+    #[test]
+    fn repeated_e_matches_any_pattern() {
+        assert_eq!(
+            run(&["grss", "-e", "main", "-e", "y =", "fixture.txt"]),
+            "let y = 6;\nfn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn overlapping_patterns_print_a_line_once() {
+        assert_eq!(
+            run(&["grss", "-e", "let", "-e", "et x", "fixture.txt"]),
+            "let x = 5;\nlet y = 6;\nlet z = x + y;\n"
+        );
+    }
+
+    #[test]
+    fn e_combines_with_ignore_case_and_invert() {
+        assert_eq!(
+            run(&["grss", "-i", "-v", "-e", "LET", "-e", "MAIN", "fixture.txt"]),
+            "println!(\"{}\", x);\n"
+        );
+    }
+
+    #[test]
+    fn only_matching_prints_every_match() {
+        assert_eq!(
+            run(&["grss", "-on", "[xyz]", "fixture.txt"]),
+            "1:x\n2:y\n3:x\n4:z\n4:x\n4:y\n"
+        );
+        // `5*` matches (emptily) on every line, only the real 5 is printed
+        assert_eq!(run(&["grss", "-o", "5*", "fixture.txt"]), "5\n");
+        assert_eq!(run(&["grss", "-o", "-C1", "main", "fixture.txt"]), "main\n");
+    }
+
+    const MULTILINE: &str =
+        "fn main() {\n    let file = open()\n        .unwrap();\n}\nfn other() {}\n";
+
+    #[test]
+    fn multiline_prints_every_line_a_match_touches() {
+        let pattern = r"fn main\(\)[\s\S]*?unwrap";
+        assert_eq!(
+            run_on(MULTILINE, &["grss", "-U", "-n", pattern, "x.rs"]),
+            "1:fn main() {\n2:    let file = open()\n3:        .unwrap();\n"
+        );
+        // Without -U no single line has all of it
+        assert_eq!(run_on(MULTILINE, &["grss", pattern, "x.rs"]), "");
+        // A match that starts further down numbers from there
+        assert_eq!(
+            run_on(MULTILINE, &["grss", "-Un", r"open\(\)\s+\.unwrap", "x.rs"]),
+            "2:    let file = open()\n3:        .unwrap();\n"
+        );
+        // ^ and $ still work per line
+        assert_eq!(
+            run_on(MULTILINE, &["grss", "-Uc", r"^fn .*\{$", "x.rs"]),
+            "1\n"
+        );
+    }
+
+    #[test]
+    fn multiline_with_context_and_only_matching() {
+        let pattern = r"open\(\)\s+\.unwrap";
+        assert_eq!(
+            run_on(MULTILINE, &["grss", "-U", "-n", "-C1", pattern, "x.rs"]),
+            "1-fn main() {\n2:    let file = open()\n3:        .unwrap();\n4-}\n"
+        );
+        assert_eq!(
+            run_on(
+                MULTILINE,
+                &["grss", "-U", "-n", "-A1", "-B0", pattern, "x.rs"]
+            ),
+            "2:    let file = open()\n3:        .unwrap();\n4-}\n"
+        );
+        // -o prints the part of the match on each line it covers
+        assert_eq!(
+            run_on(MULTILINE, &["grss", "-U", "-on", pattern, "x.rs"]),
+            "2:open()\n3:        .unwrap\n"
+        );
+        assert_eq!(
+            run_on(MULTILINE, &["grss", "-U", "-v", pattern, "x.rs"]),
+            "fn main() {\n}\nfn other() {}\n"
+        );
+    }
+
+    #[test]
+    fn column_counts_an_emoji_as_one() {
+        let input = "🎉 hello world\nworld\n";
+        assert_eq!(
+            run_on(input, &["grss", "--column", "world", "x"]),
+            "1:9:🎉 hello world\n2:1:world\n"
+        );
+        assert_eq!(
+            run_on(input, &["grss", "--column", "-o", "[lw]o", "x"]),
+            "1:6:lo\n1:9:wo\n2:1:wo\n"
+        );
+    }
+
+    #[test]
+    fn replace_rewrites_the_printed_matches() {
+        let toml = "[package]\nname = \"grss\"\nversion = \"0.1.0\"\n";
+        let pattern = r#"(?P<key>\w+) = "(.*)""#;
+        assert_eq!(
+            run_on(
+                toml,
+                &["grss", "--replace", "$1", "-o", r#"name = "(.*)""#, "x"]
+            ),
+            "grss\n"
+        );
+        assert_eq!(
+            run_on(
+                toml,
+                &["grss", "-n", "--replace", "$key is $$$2", pattern, "x"]
+            ),
+            "2:name is $grss\n3:version is $0.1.0\n"
+        );
+        // Every match on the line, and context lines are left as they are
+        assert_eq!(
+            run(&["grss", "--replace", "[$0]", "-A1", "y", "fixture.txt"]),
+            "let [y] = 6;\nprintln!(\"{}\", x);\nlet z = x + [y];\nfn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn replace_with_a_missing_group_fails_up_front() {
+        let args = Cli::parse_from(["grss", "--replace", "$name", "(?P<nmae>a)", "x"]);
+        let (patterns, _) = args.patterns_and_paths().unwrap();
+        let error = App::new(&args, &patterns, None).err().unwrap();
+        assert!(error.to_string().contains("${name}"), "{error}");
+    }
+
+    #[test]
+    fn sizes_take_k_m_and_g_suffixes() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("1234"), Ok(1234));
+        assert_eq!(parse_size("500K"), Ok(500 * 1024));
+        assert_eq!(parse_size("500k"), Ok(500 * 1024));
+        assert_eq!(parse_size("10M"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
+        for invalid in [
+            "",
+            "K",
+            "10X",
+            "10KB",
+            "-5",
+            "+5",
+            "1.5M",
+            " 10",
+            "99999999999999999999G",
+        ] {
+            assert!(parse_size(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn pattern_and_path_positionals() {
+        let args = Cli::parse_from(["grss", "-e", "foo", "file.txt"]);
+        assert_eq!(
+            args.patterns_and_paths().unwrap(),
+            (vec!["foo".to_string()], vec![PathBuf::from("file.txt")])
+        );
+
+        // With -e every positional is a path
+        let args = Cli::parse_from(["grss", "-e", "foo", "a.txt", "b.txt"]);
+        let (_, paths) = args.patterns_and_paths().unwrap();
+        assert_eq!(paths, [PathBuf::from("a.txt"), PathBuf::from("b.txt")]);
+
+        let args = Cli::parse_from(["grss", "foo"]);
+        let error = args.patterns_and_paths().unwrap_err().to_string();
+        assert_eq!(error, "no path given");
+
+        let args = Cli::parse_from(["grss"]);
+        let error = args.patterns_and_paths().unwrap_err().to_string();
+        assert_eq!(error, "no pattern given");
+    }
+
+    const CONTEXT_FIXTURE: &str = "a\nb\nmatch 1\nc\nmatch 2\nd\ne\nf\ng\nmatch 3\nh\n";
+
+    #[test]
+    fn overlapping_context_is_printed_once() {
+        assert_eq!(
+            run_on(CONTEXT_FIXTURE, &["grss", "-n", "-C", "1", "match", "f"]),
+            "2-b\n3:match 1\n4-c\n5:match 2\n6-d\n--\n9-g\n10:match 3\n11-h\n"
+        );
+    }
+
+    #[test]
+    fn after_and_before_context() {
+        assert_eq!(
+            run_on(CONTEXT_FIXTURE, &["grss", "-A", "2", "match [13]", "f"]),
+            "match 1\nc\nmatch 2\n--\nmatch 3\nh\n"
+        );
+        assert_eq!(
+            run_on(CONTEXT_FIXTURE, &["grss", "-B", "3", "match 3", "f"]),
+            "e\nf\ng\nmatch 3\n"
+        );
+    }
+
+    #[test]
+    fn unescape_turns_escapes_into_bytes() {
+        assert_eq!(unescape("--"), b"--");
+        assert_eq!(unescape(r"\t|\n|\r|\0|\\"), b"\t|\n|\r|\0|\\");
+        assert_eq!(unescape(r"\x41\x7f\xFF"), b"A\x7f\xff");
+        assert_eq!(unescape("é\\tü"), "é\tü".as_bytes());
+        // What isn't an escape stays as it was
+        assert_eq!(unescape(r"\q \x4 \xZZ \"), br"\q \x4 \xZZ \");
+        assert_eq!(unescape(r"\\t"), br"\t");
+    }
+
+    #[test]
+    fn adjacent_groups_have_no_separator() {
+        assert_eq!(
+            run_on(CONTEXT_FIXTURE, &["grss", "-B", "1", "match [12]", "f"]),
+            "b\nmatch 1\nc\nmatch 2\n"
+        );
+    }
+
+    /// Runs a search over the file at `path` the way main() would
+    fn run_file(path: &Path, argv: &[&str]) -> String {
+        let path_arg = path.to_str().unwrap();
+        let args = Cli::parse_from(argv.iter().copied().chain([path_arg]));
+        let (patterns, _) = args.patterns_and_paths().unwrap();
+        let app = App::new(&args, &patterns, None).unwrap();
+        let mut out = Vec::new();
+        app.search_file(path, false, &Metrics::default(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn mmap_gives_the_same_output_as_buffered_reading() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fixture.txt");
+        // CRLF endings and no newline at the very end
+        std::fs::write(&path, "let x = 1;\r\nlet y = 2;\nx + y").unwrap();
+
+        for argv in [
+            &["grss", "-n", "x"][..],
+            &["grss", "-c", "y"],
+            &["grss", "-C", "1", "y ="],
+        ] {
+            let mmap_argv: Vec<&str> = argv.iter().copied().chain(["--mmap"]).collect();
+            assert_eq!(run_file(&path, argv), run_file(&path, &mmap_argv));
+        }
+    }
+
+    #[test]
+    fn mmap_falls_back_for_empty_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.txt");
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(run_file(&path, &["grss", "--mmap", "-c", "x"]), "0\n");
+    }
+
+    const TEN_MATCHES: &str = "m0\nm1\nm2\nm3\nm4\nm5\nm6\nm7\nm8\nm9\n";
+
+    #[test]
+    fn max_count_stops_after_n_lines() {
+        assert_eq!(
+            run_on(TEN_MATCHES, &["grss", "-m", "2", "m", "f"]),
+            "m0\nm1\n"
+        );
+        assert_eq!(
+            run_on(TEN_MATCHES, &["grss", "-m", "2", "-c", "m", "f"]),
+            "2\n"
+        );
+        assert_eq!(run_on(TEN_MATCHES, &["grss", "-m", "0", "m", "f"]), "");
+    }
+
+    #[test]
+    fn max_count_with_invert_counts_non_matching_lines() {
+        assert_eq!(
+            run_on(TEN_MATCHES, &["grss", "-v", "-m", "2", "m[0-4]", "f"]),
+            "m5\nm6\n"
+        );
+    }
+
+    #[test]
+    fn max_count_keeps_trailing_context() {
+        assert_eq!(
+            run_on(
+                CONTEXT_FIXTURE,
+                &["grss", "-m", "1", "-A", "2", "match", "f"]
+            ),
+            "match 1\nc\n"
+        );
+        assert_eq!(
+            run_on(
+                CONTEXT_FIXTURE,
+                &["grss", "-m", "2", "-A", "2", "match", "f"]
+            ),
+            "match 1\nc\nmatch 2\nd\ne\n"
+        );
+    }
+
+    #[test]
+    fn color_is_decided_by_choice_terminal_and_no_color() {
+        use ColorChoice::*;
+        for (choice, is_tty, no_color, expected) in [
+            (Always, false, false, true),
+            (Always, true, true, true),
+            (Never, true, false, false),
+            (Never, false, true, false),
+            (Auto, true, false, true),
+            (Auto, true, true, false),
+            (Auto, false, false, false),
+            (Auto, false, true, false),
+        ] {
+            assert_eq!(
+                resolve_color(choice, is_tty, no_color),
+                expected,
+                "{:?} tty={is_tty} NO_COLOR={no_color}",
+                choice.to_possible_value().unwrap().get_name()
+            );
+        }
+    }
+
+    #[test]
+    fn heading_by_default_only_on_a_terminal_with_several_files() {
+        let heading = |argv: &[&str], to_terminal: bool, several_files: bool| {
+            resolve_heading(&Cli::parse_from(argv), to_terminal, several_files)
+        };
+        assert!(heading(&["grss", "x", "."], true, true));
+        assert!(!heading(&["grss", "x", "."], false, true));
+        assert!(!heading(&["grss", "x", "a"], true, false));
+        assert!(!heading(&["grss", "--no-heading", "x", "."], true, true));
+        assert!(heading(&["grss", "--heading", "x", "a"], false, false));
+        // Only lines get headings
+        assert!(!heading(&["grss", "--heading", "-c", "x", "."], true, true));
+        assert!(!heading(&["grss", "-l", "x", "."], true, true));
+    }
+
+    #[test]
+    fn swapped_hint_only_when_the_pattern_is_the_file() {
+        let files = ["src/main.rs", "notes.txt"];
+        let is_file = |name: &str| files.contains(&name);
+        assert_eq!(
+            swapped_hint("src/main.rs", "TODO", is_file).as_deref(),
+            Some(
+                "note: 'src/main.rs' exists as a file — did you swap the pattern and path arguments?"
+            )
+        );
+        // Neither is a file, just a path that doesn't exist
+        assert_eq!(swapped_hint("TODO", "src/mian.rs", is_file), None);
+        // Both are, so they can't be told the wrong way round
+        assert_eq!(swapped_hint("notes.txt", "src/main.rs", is_file), None);
+    }
+
+    #[test]
+    fn positional_pattern_is_none_when_it_is_a_path() {
+        let pattern = |argv: &[&str]| Cli::parse_from(argv).positional_pattern().map(String::from);
+        assert_eq!(pattern(&["grss", "a", "b"]).as_deref(), Some("a"));
+        assert_eq!(pattern(&["grss", "-e", "x", "a", "b"]), None);
+        assert_eq!(pattern(&["grss", "--files", "a"]), None);
+    }
+
+    #[test]
+    fn no_ignore_flags_turn_off_their_part_of_the_ignore_files() {
+        let ignore = |argv: &[&str]| walk_options(&Cli::parse_from(argv)).ignore;
+        let honored = |vcs, grss, parents| IgnoreOptions {
+            vcs,
+            grss,
+            parents,
+            files: Vec::new(),
+        };
+        assert_eq!(ignore(&["grss", "x", "."]), honored(true, true, true));
+        assert_eq!(
+            ignore(&["grss", "--no-ignore", "x", "."]),
+            honored(false, false, false)
+        );
+        assert_eq!(
+            ignore(&["grss", "--no-ignore-vcs", "x", "."]),
+            honored(false, true, true)
+        );
+        assert_eq!(
+            ignore(&["grss", "--no-ignore-parent", "x", "."]),
+            honored(true, true, false)
+        );
+        // Whichever turns off more wins, but --ignore-file always counts
+        let argv = [
+            "grss",
+            "--no-ignore-parent",
+            "--no-ignore",
+            "--ignore-file",
+            "i",
+            "x",
+            ".",
+        ];
+        assert_eq!(
+            ignore(&argv),
+            IgnoreOptions {
+                files: vec![PathBuf::from("i")],
+                ..honored(false, false, false)
+            }
+        );
+    }
 }
@@ -0,0 +1,156 @@
+// FilePath: rust/rust-cli/grss/src/man.rs
+
+//! `grss --generate-man`: the man page, rendered from the clap definition
+//!
+//! clap_mangen turns the same doc comments that make up `--help` into the
+//! NAME, SYNOPSIS, DESCRIPTION and OPTIONS sections, so the two never drift
+//! apart. The sections a man page has on top of that (examples, exit status,
+//! environment, files) are written here. Packagers install the output as
+//! `grss.1`; `grss --generate-man > grss.1 && man -l grss.1` previews it.
+
+use clap_mangen::Man;
+use clap_mangen::roff::{Roff, roman};
+use std::io::{self, Write};
+
+/// Example command lines with what they do, shown both at the end of
+/// `--help` and in the man page's EXAMPLES section
+pub const EXAMPLES: &[(&str, &str)] = &[
+    (
+        "grss -rn 'fn main' src/",
+        "Print every line containing `fn main` under src/, with line numbers.",
+    ),
+    (
+        "grss -ri --include '*.rs' todo .",
+        "Find TODOs in any case, in Rust files only.",
+    ),
+    (
+        "grss -F -e 'vec![' -e 'Vec::new()' src/main.rs",
+        "Look for either of two literal strings, without regex escaping.",
+    ),
+    (
+        "grss -rl unsafe . | xargs wc -l",
+        "List the files that use `unsafe`, and count their lines.",
+    ),
+    (
+        "grss -z -C 2 ERROR /var/log/app.log.1.gz",
+        "Search a compressed log, with two lines of context around each match.",
+    ),
+    (
+        "grss -r --pre 'pdftotext {} -' --pre-glob '*.pdf' invoice docs/",
+        "Search the text inside the PDFs under docs/, and the other files as they are.",
+    ),
+    (
+        "grss count -r --type rust unwrap src/",
+        "Count the lines with `unwrap` in each Rust file under src/.",
+    ),
+    (
+        "grss replace -r --dry-run colour color docs/",
+        "Show, as a diff, what replacing `colour` with `color` would change.",
+    ),
+    (
+        "grss index . && grss -r 'Rectangle::new\\(' .",
+        "Index the current directory, then search it reading only the files that could match.",
+    ),
+];
+
+/// The EXAMPLES as plain text for the end of `--help`
+pub fn examples_help() -> String {
+    let mut help = String::from("Examples:\n");
+    for (command, description) in EXAMPLES {
+        help.push_str(&format!("  {}\n      {}\n", command, description));
+    }
+    help
+}
+
+/// Writes the whole man page, in roff, to `out`
+pub fn write_man<W: Write>(out: &mut W) -> io::Result<()> {
+    // Without the examples at the end of --help, which clap_mangen would
+    // otherwise put in a section of its own called EXTRA
+    let command = crate::command().after_long_help(None::<&str>);
+    Man::new(command).render(out)?;
+
+    let mut roff = Roff::new();
+    roff.control("SH", ["EXAMPLES"]);
+    for (command, description) in EXAMPLES {
+        // .TP: the next line is the tag (the command), the text after it is
+        // indented below
+        roff.control("TP", []);
+        roff.text([roman(*command)]);
+        roff.text([roman(*description)]);
+    }
+
+    roff.control("SH", ["EXIT STATUS"]);
+    roff.text([roman(
+        "0 if any line was selected (or with -L, any file listed), 1 if none was, \
+         and 2 if an error occurred, even when something else matched. With -q, \
+         a match still gives 0 whatever went wrong elsewhere. When whatever reads \
+         the output closes it early, like head(1), grss stops quietly and exits by \
+         what it found up to then, not with 141 like a process killed by SIGPIPE.",
+    )]);
+
+    roff.control("SH", ["ENVIRONMENT"]);
+    roff.control("TP", []);
+    roff.text([roman("GRSS_COLORS")]);
+    roff.text([roman(
+        "Colors for --color, in the same format as --colors, which goes on top of it.",
+    )]);
+    roff.control("TP", []);
+    roff.text([roman("GRSS_OPTS")]);
+    roff.text([roman(
+        "Default options, split like a shell would split them. They go after the config \
+         file's and before the ones on the command line, which win; --no-config skips them.",
+    )]);
+    roff.control("TP", []);
+    roff.text([roman("NO_COLOR")]);
+    roff.text([roman(
+        "When set to anything but an empty string, --color auto (the default) doesn't color.",
+    )]);
+    roff.control("TP", []);
+    roff.text([roman("GRSS_PAGER, PAGER")]);
+    roff.text([roman(
+        "The pager for --pager, GRSS_PAGER first. Without either, less -R is used.",
+    )]);
+    roff.control("TP", []);
+    roff.text([roman("XDG_CONFIG_HOME")]);
+    roff.text([roman("Where to look for the config file, see FILES.")]);
+
+    roff.control("SH", ["FILES"]);
+    roff.control("TP", []);
+    roff.text([roman("$XDG_CONFIG_HOME/grss/config, ~/.config/grss/config")]);
+    roff.text([roman(
+        "Default options, one or more per line as they would be typed on the command \
+         line. Lines starting with # are comments. Options given on the command line \
+         win; --no-config skips the file.",
+    )]);
+
+    roff.to_writer(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn man_page_has_the_usual_sections() {
+        let mut out = Vec::new();
+        write_man(&mut out).unwrap();
+        let page = String::from_utf8(out).unwrap();
+
+        assert!(page.starts_with(".ie \\n(.g .ds Aq \\(aq"));
+        assert!(page.contains(".TH grss 1"));
+        for section in [
+            "NAME",
+            "SYNOPSIS",
+            "DESCRIPTION",
+            "OPTIONS",
+            "EXAMPLES",
+            "\"EXIT STATUS\"",
+        ] {
+            assert!(page.contains(&format!(".SH {section}\n")), "{section}");
+        }
+        assert!(page.contains("\\-\\-ignore\\-case"));
+        assert!(!page.contains(".SH EXTRA"));
+        // Hidden flags stay out of the page
+        assert!(!page.contains("generate\\-man"));
+    }
+}
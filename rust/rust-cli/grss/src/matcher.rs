@@ -0,0 +1,1753 @@
+// FilePath: rust/rust-cli/grss/src/matcher.rs
+
+//! Deciding whether a line matches, and where
+//!
+//! A Matcher holds every pattern the user gave (one positional pattern, or
+//! several with repeated -e) compiled once up front. A line matches when ANY
+//! of the patterns matches it, and it's selected (printed or counted) when it
+//! matches, or with -v when it doesn't.
+//!
+//! --and and --not narrow that down: every --and pattern has to match the
+//! line as well, and no --not pattern may. Each --and pattern is a Matcher
+//! of its own, and all the --not patterns together are another one (any of
+//! them is enough to leave the line out). -v selects the lines that don't
+//! pass all of that, and what's highlighted (or printed by -o) is what the
+//! patterns and the --and patterns matched.
+//!
+//! Patterns are regexes by default. With -F each one is a plain string
+//! instead, searched for with memchr's memmem, which skips the regex engine
+//! entirely and is noticeably faster on long literals. A regex that's plain
+//! text anyway (`TODO`, `Vec::new\(\)`) is searched for the same way, unless
+//! -i or -w are on (the regex engine's idea of letter case and of a word
+//! goes beyond ASCII, which memmem's doesn't) or another pattern is a real
+//! regex, which then takes the regex engine for all of them.
+//!
+//! Trying hundreds of patterns (say from -f) one after the other on every
+//! line would be slow, so several patterns are also joined into one big
+//! alternation that answers "does anything match" in a single pass. Only the
+//! lines that are printed go through the patterns one by one, to find out
+//! which matched where.
+//!
+//! When every pattern is plain text, they go into one Aho-Corasick
+//! automaton instead, which is much quicker to build than the alternation
+//! and finds every pattern's matches in a single pass, printed lines
+//! included. With -i it folds ASCII letters only, like a single -F -i
+//! pattern does; -F -i patterns that aren't all ASCII go through the regex
+//! engine instead, one by one and as an alternation.
+//!
+//! That's what --engine auto picks, and --engine regex, literal or
+//! aho-corasick picks one of the three outright, to see what it makes of
+//! the patterns. Each is a Strategy the Matcher goes through, and whichever
+//! it is, the matches found are the same, with one exception: -w on a
+//! pattern that starts or ends with punctuation. The regex engine puts a
+//! `\b` there, which wants a word character on the other side of it, where
+//! the others only want no word character next to the match.
+//!
+//! Most regexes have a piece of plain text every match has to contain
+//! (`Rectangle::new(` in `Rectangle::new\(\d+`, `.unwrap()` in
+//! `\.unwrap\(\)`). The Searcher looks for that piece with memmem over
+//! whole buffers at once (see Candidates), and only the lines that have it
+//! are split out and handed to the regex engine at all. Patterns
+//! without such a piece (`\d+`, `foo|bar`, anything with -i) simply aren't
+//! prefiltered.
+//!
+//! Lines are bytes, not text: a file is whatever its bytes are, and a line
+//! with a Latin-1 `é` or a stray byte in it is matched as it is, invalid
+//! bytes and all. The regexes are the regex crate's `bytes` ones, which
+//! still match characters as UTF-8 (`.` is one character, never half of
+//! one, and never an invalid byte), so every match of a pattern that isn't
+//! `(?-u)` starts and ends between characters.
+//! --no-unicode makes every pattern `(?-u)`: then `\w`, `\b` and -i only
+//! know ASCII, `.` is any one byte, and so the memmem engines can take -i
+//! and -w patterns too.
+
+use crate::error::GrssError;
+use crate::pattern_error;
+use crate::template::Template;
+use aho_corasick::{AhoCorasick, MatchKind};
+use clap::ValueEnum;
+use memchr::memmem::Finder;
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::ParserBuilder;
+use regex_syntax::ast::{self, Ast};
+use regex_syntax::hir::{Hir, HirKind};
+use std::fmt;
+use std::ops::Range;
+
+/// One place in a line where a pattern matched
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match {
+    /// Index into the patterns the Matcher was built from, so callers can
+    /// tell which of several -e patterns this was
+    pub pattern: usize,
+    /// Byte range of the matched text within the line
+    pub range: Range<usize>,
+}
+
+/// Flags that change how every pattern matches
+#[derive(Clone, Copy, Default)]
+pub struct MatcherOptions {
+    /// -i: `foo` also matches `FOO`
+    pub ignore_case: bool,
+    /// -w: only match whole words, so `new` doesn't match inside `renew`
+    pub word: bool,
+    /// -F: the patterns are literal strings, not regexes
+    pub fixed_strings: bool,
+    /// -v: select the lines that DON'T match
+    pub invert: bool,
+    /// -U: patterns are matched against the whole input instead of one line
+    /// at a time, and `^`/`$` match at the start and end of every line in it
+    pub multiline: bool,
+    /// --crlf: with multiline, a `\r` before the `\n` isn't part of the
+    /// line either, so `$` matches before it and `.` doesn't match it
+    pub crlf: bool,
+    /// --engine: what matches the patterns, Engine::Auto to pick from them
+    pub engine: Engine,
+    /// --no-unicode: `\w`, `\d`, `\s`, `\b` and -i only know ASCII, and `.`
+    /// matches any one byte rather than a whole character
+    pub no_unicode: bool,
+}
+
+/// The engines that can match the patterns, for --engine
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Engine {
+    /// Pick one from the patterns: literal or aho-corasick for plain text,
+    /// regex otherwise
+    #[default]
+    Auto,
+    /// The regex engine, which takes any pattern
+    Regex,
+    /// A substring search for each pattern in turn, plain text only
+    Literal,
+    /// One Aho-Corasick automaton for all the patterns, plain text only
+    AhoCorasick,
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Engine::Auto => "auto",
+            Engine::Regex => "regex",
+            Engine::Literal => "literal",
+            Engine::AhoCorasick => "aho-corasick",
+        })
+    }
+}
+
+/// The three flags that decide whether letter case matters
+#[derive(Clone, Copy, Default)]
+pub struct CaseFlags {
+    /// -i: case never matters
+    pub ignore_case: bool,
+    /// -s: case always matters (the default, spelled out to undo a -i or
+    /// -S from the config file)
+    pub case_sensitive: bool,
+    /// -S: case only matters when a pattern has an upper case letter in it
+    pub smart_case: bool,
+}
+
+/// Works out whether `patterns` should match ignoring case
+///
+/// -i and -s say so outright, and win over -S whichever order they came in
+/// (clap already made the later of -i and -s win over the other). With -S
+/// alone, `foo` ignores case and `Foo` doesn't. Only the letters the pattern
+/// actually spells out count: `\W` or `\p{Lu}` are classes, not upper case
+/// letters, so `foo\W` still ignores case, and so do patterns that aren't
+/// valid regexes (compiling them will report the error anyway).
+///
+/// # Returns
+/// The value for MatcherOptions::ignore_case
+pub fn resolve_ignore_case(flags: CaseFlags, patterns: &[String], fixed_strings: bool) -> bool {
+    if flags.ignore_case || flags.case_sensitive {
+        return flags.ignore_case;
+    }
+    if !flags.smart_case {
+        return false;
+    }
+    !patterns.iter().any(|pattern| match fixed_strings {
+        true => pattern.chars().any(char::is_uppercase),
+        false => has_uppercase_literal(pattern),
+    })
+}
+
+/// Whether the regex `pattern` spells out an upper case letter, either
+/// as a plain character or inside a `[...]` class
+fn has_uppercase_literal(pattern: &str) -> bool {
+    /// Stops the walk over the syntax tree (by "failing") at the first upper
+    /// case literal
+    struct FindUppercase;
+
+    impl ast::Visitor for FindUppercase {
+        type Output = ();
+        type Err = ();
+
+        fn finish(self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn visit_pre(&mut self, ast: &Ast) -> Result<(), ()> {
+            match ast {
+                Ast::Literal(literal) if literal.c.is_uppercase() => Err(()),
+                _ => Ok(()),
+            }
+        }
+
+        fn visit_class_set_item_pre(&mut self, item: &ast::ClassSetItem) -> Result<(), ()> {
+            match item {
+                ast::ClassSetItem::Literal(literal) if literal.c.is_uppercase() => Err(()),
+                ast::ClassSetItem::Range(range)
+                    if range.start.c.is_uppercase() || range.end.c.is_uppercase() =>
+                {
+                    Err(())
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+
+    match ast::parse::Parser::new().parse(pattern) {
+        Ok(parsed) => ast::visit(&parsed, FindUppercase).is_err(),
+        Err(_) => false,
+    }
+}
+
+/// The compiled form of all the patterns given on the command line
+pub struct Matcher {
+    /// The patterns, compiled for whichever engine matches them
+    strategy: Box<dyn Strategy>,
+    /// How many patterns there are, which Match::pattern is an index into
+    patterns: usize,
+    /// Text a line needs to have for any pattern to match it, when every
+    /// pattern has some (see Prefilter::new())
+    prefilter: Option<Prefilter>,
+    /// The --and patterns, each of which has to match too
+    required: Vec<Matcher>,
+    /// The --not patterns, none of which may match
+    excluded: Option<Box<Matcher>>,
+    invert: bool,
+    multiline: bool,
+}
+
+/// At most this many patterns are prefiltered: each one is another memmem
+/// pass over the input, which soon costs more than the one pass of the
+/// combined regex it was meant to save
+const MAX_PREFILTERED: usize = 4;
+
+/// The literal text each pattern's matches have to contain
+struct Prefilter {
+    finders: Vec<Finder<'static>>,
+}
+
+/// Where in a buffer of lines the next one any pattern could match is, so
+/// the lines before it can be skipped without looking at them
+///
+/// Remembers where each pattern's text turned up next, so going through a
+/// whole buffer from start to end only searches it once per pattern.
+pub struct Candidates<'m, 'h> {
+    finders: &'m [Finder<'static>],
+    haystack: &'h [u8],
+    /// Where each pattern's text is next, None once it's nowhere further
+    next: Vec<Option<usize>>,
+}
+
+/// What every engine does with the patterns it was built for. The Matcher
+/// only ever goes through this, so which engine it has makes no difference
+/// to what it finds (see the conformance tests below), only to how fast.
+trait Strategy: Send + Sync {
+    /// Which engine this is
+    fn engine(&self) -> Engine;
+
+    /// Whether any of the patterns matches somewhere in `line`, which can
+    /// stop at the first match
+    fn is_match(&self, line: &[u8]) -> bool;
+
+    /// Every match of every pattern in `line`: each pattern's left to right
+    /// and not overlapping each other (like Regex::find_iter), but maybe
+    /// another pattern's
+    fn find_all(&self, line: &[u8]) -> Vec<Match>;
+
+    /// Appends what `template` makes of the match `found` in `line` to `out`
+    fn expand(&self, line: &[u8], found: &Match, template: &Template, out: &mut Vec<u8>);
+}
+
+/// Engine::Regex: every pattern a regex, -F ones escaped
+struct Regexes {
+    regexes: Vec<Regex>,
+    /// All of them in one, when there's more than one and they can be
+    /// joined (see combine())
+    combined: Option<Regex>,
+}
+
+/// Engine::Literal: every pattern a Literal, searched for one after the
+/// other
+struct Literals {
+    literals: Vec<Literal>,
+}
+
+/// Engine::AhoCorasick: every pattern in one automaton
+struct Automaton {
+    automaton: AhoCorasick,
+    word: bool,
+    /// --no-unicode: only ASCII letters and digits make up words for -w
+    ascii: bool,
+}
+
+/// A -F pattern, or a regex without anything special in it: a plain string
+/// looked for with a substring search
+struct Literal {
+    /// Searches for the pattern's bytes, lowercased with -i
+    finder: Finder<'static>,
+    /// With -i the line is lowercased too before searching. Only ASCII
+    /// letters are folded, which keeps every byte offset the same, so the
+    /// ranges found in the lowercased copy are valid in the original line.
+    ignore_case: bool,
+    word: bool,
+    /// --no-unicode: only ASCII letters and digits make up words for -w
+    ascii: bool,
+}
+
+impl Matcher {
+    /// Compiles every pattern for the engine `options` asks for (see
+    /// strategy()), stopping at the first one that's invalid
+    pub fn new(patterns: &[String], options: MatcherOptions) -> Result<Self, GrssError> {
+        let strategy = strategy(patterns, options)?;
+        log::debug!(
+            "{} patterns matched by the {} engine",
+            patterns.len(),
+            strategy.engine()
+        );
+        Ok(Matcher {
+            strategy,
+            patterns: patterns.len(),
+            prefilter: Prefilter::new(patterns, options),
+            required: Vec::new(),
+            excluded: None,
+            invert: options.invert,
+            multiline: options.multiline,
+        })
+    }
+
+    /// Narrows down the lines that match to the ones every pattern in `and`
+    /// matches too and none in `not` does (--and and --not)
+    ///
+    /// The patterns are compiled with the same `options`, except that -v
+    /// still applies to the whole and -U isn't supported. The prefilter
+    /// stays: the Matcher's own patterns still have to match.
+    pub fn with_conditions(
+        mut self,
+        and: &[String],
+        not: &[String],
+        options: MatcherOptions,
+    ) -> Result<Self, GrssError> {
+        let options = MatcherOptions {
+            invert: false,
+            multiline: false,
+            ..options
+        };
+        for pattern in and {
+            let required = Matcher::new(std::slice::from_ref(pattern), options)?;
+            self.required.push(required);
+        }
+        if !not.is_empty() {
+            self.excluded = Some(Box::new(Matcher::new(not, options)?));
+        }
+        Ok(self)
+    }
+
+    /// The same Matcher running every line through the regexes, without
+    /// looking for their literal text first; it matches exactly the same,
+    /// only slower, which is what it's for: comparing the two
+    pub fn without_prefilter(mut self) -> Self {
+        self.prefilter = None;
+        self
+    }
+
+    /// Which engine matches the Matcher's own patterns: never Engine::Auto,
+    /// which is the one it picked instead
+    pub fn engine(&self) -> Engine {
+        self.strategy.engine()
+    }
+
+    /// Whether lines without the patterns' literal text can be skipped
+    /// (see candidates()): never with -v, which selects exactly those
+    pub fn is_prefiltered(&self) -> bool {
+        self.prefilter.is_some() && !self.invert
+    }
+
+    /// Starts looking for the lines in `haystack` that could match
+    ///
+    /// # Returns
+    /// None when that can't be told without matching them (see
+    /// is_prefiltered())
+    pub fn candidates<'h>(&self, haystack: &'h [u8]) -> Option<Candidates<'_, 'h>> {
+        if !self.is_prefiltered() {
+            return None;
+        }
+        let finders = &self.prefilter.as_ref()?.finders;
+        Some(Candidates {
+            finders,
+            haystack,
+            next: finders.iter().map(|finder| finder.find(haystack)).collect(),
+        })
+    }
+
+    /// Whether lines are selected when they DON'T match (-v)
+    pub fn is_inverted(&self) -> bool {
+        self.invert
+    }
+
+    /// Whether the patterns should be run over the whole input at once
+    /// (-U), see Searcher::search_multiline()
+    pub fn is_multiline(&self) -> bool {
+        self.multiline
+    }
+
+    /// Whether `line` is selected: it matches, or with `invert` it doesn't
+    pub fn selects(&self, line: &[u8]) -> bool {
+        self.is_match(line) != self.invert
+    }
+
+    /// Where to highlight in a selected `line`
+    ///
+    /// # Returns
+    /// The byte ranges of the matches, left to right and never overlapping
+    /// (see find_matches()). A line selected by `invert` has by definition
+    /// nothing in it to highlight, so that gives an empty Vec.
+    pub fn find(&self, line: &[u8]) -> Vec<Range<usize>> {
+        if self.invert {
+            return Vec::new();
+        }
+        self.find_matches(line)
+            .into_iter()
+            .map(|found| found.range)
+            .collect()
+    }
+
+    /// Whether any pattern matches somewhere in `line`, and so do all the
+    /// --and patterns and none of the --not ones, whatever `invert` says
+    ///
+    /// Cheaper than find_matches() because it can stop at the first hit
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        self.strategy.is_match(line)
+            && self
+                .required
+                .iter()
+                .all(|required| required.strategy.is_match(line))
+            && !self
+                .excluded
+                .as_ref()
+                .is_some_and(|excluded| excluded.strategy.is_match(line))
+    }
+
+    /// How many times the patterns occur in `line`, for --count-matches
+    ///
+    /// Unlike find_matches(), every pattern's matches count, even where they
+    /// overlap another pattern's: `-e foo -e oo` finds two in "foo". So do
+    /// the --and patterns' matches.
+    pub fn count_matches(&self, line: &[u8]) -> usize {
+        let required: usize = self
+            .required
+            .iter()
+            .map(|required| required.count_matches(line))
+            .sum();
+        self.strategy.find_all(line).len() + required
+    }
+
+    /// `line` with every match replaced by `template`, for --replace
+    ///
+    /// Only the Matcher's own patterns' matches are replaced, not the --and
+    /// patterns'.
+    ///
+    /// # Returns
+    /// The new line, and where the replacements ended up in it for
+    /// highlighting (and -o)
+    pub fn replace(&self, line: &[u8], template: &Template) -> (Vec<u8>, Vec<Range<usize>>) {
+        let mut replaced = Vec::with_capacity(line.len());
+        let mut ranges = Vec::new();
+        let mut last = 0;
+        for found in leftmost_first(self.strategy.find_all(line)) {
+            replaced.extend_from_slice(&line[last..found.range.start]);
+            let start = replaced.len();
+            self.strategy.expand(line, &found, template, &mut replaced);
+            ranges.push(start..replaced.len());
+            last = found.range.end;
+        }
+        replaced.extend_from_slice(&line[last..]);
+        (replaced, ranges)
+    }
+
+    /// Finds every place any of the patterns matches in `line`
+    ///
+    /// # Returns
+    /// The matches sorted left to right. When two patterns match overlapping
+    /// text (say `foo` and `oo` in "foo"), only the one starting first (or,
+    /// on a tie, the longer one) is kept, so the highlighting never paints
+    /// the same character twice. An empty Vec means the line doesn't match
+    /// at all. A pattern like `^` gives a zero-length range, which still
+    /// counts as a match but has nothing to color. The --and patterns'
+    /// matches are in there too, numbered after the Matcher's own patterns.
+    pub fn find_matches(&self, line: &[u8]) -> Vec<Match> {
+        let mut matches = self.strategy.find_all(line);
+        let mut first = self.patterns;
+        for required in &self.required {
+            let found = required.strategy.find_all(line).into_iter();
+            matches.extend(found.map(|found| Match {
+                pattern: first + found.pattern,
+                range: found.range,
+            }));
+            first += required.patterns;
+        }
+        leftmost_first(matches)
+    }
+}
+
+/// `matches` sorted left to right, leaving out every one that overlaps one
+/// further left (see Matcher::find_matches())
+fn leftmost_first(mut matches: Vec<Match>) -> Vec<Match> {
+    // Earliest start first, and for the same start the longest match first
+    matches.sort_by(|a, b| {
+        a.range
+            .start
+            .cmp(&b.range.start)
+            .then(b.range.end.cmp(&a.range.end))
+    });
+
+    // Drop every match that begins inside the one kept before it
+    let mut kept: Vec<Match> = Vec::with_capacity(matches.len());
+    for found in matches {
+        match kept.last() {
+            Some(last) if found.range.start < last.range.end => continue,
+            Some(last) if found.range == last.range => continue,
+            _ => kept.push(found),
+        }
+    }
+    kept
+}
+
+/// Compiles `patterns` for the engine `options` asks for, or with
+/// Engine::Auto for the one that suits them (see the module docs)
+///
+/// Plain text, which is all the memmem and Aho-Corasick engines take, is
+/// the -F patterns and the regexes without anything special in them. Auto
+/// leaves out the regexes with -i or -w: the regex engine's idea of letter
+/// case and of a word goes beyond ASCII, which memmem's doesn't (unless
+/// --no-unicode keeps the regex engine's to ASCII too). Asked for
+/// outright, those engines still take them, with -i folding ASCII letters
+/// only, like -F -i does.
+///
+/// # Returns
+/// The first invalid pattern's error, or one saying why the engine asked
+/// for can't take a pattern
+fn strategy(patterns: &[String], options: MatcherOptions) -> Result<Box<dyn Strategy>, GrssError> {
+    // The regex crate would only say Unicode isn't allowed, or for `(?u)`
+    // go along with it
+    if options.no_unicode && !options.fixed_strings {
+        for pattern in patterns {
+            if let Some(construct) = unicode_construct(pattern) {
+                return Err(GrssError::InvalidPattern {
+                    pattern: pattern.clone(),
+                    message: format!(
+                        "`{}` needs Unicode, which --no-unicode turns off",
+                        construct
+                    ),
+                });
+            }
+        }
+    }
+    match options.engine {
+        Engine::Auto => {}
+        Engine::Regex => return Ok(Box::new(Regexes::new(patterns, options)?)),
+        Engine::Literal => {
+            let literals = literals(patterns, options)?;
+            return Ok(Box::new(Literals { literals }));
+        }
+        Engine::AhoCorasick => {
+            let literals = literals(patterns, options)?;
+            return match Automaton::new(&literals, options) {
+                Some(automaton) => Ok(Box::new(automaton)),
+                None => Err(GrssError::Usage(
+                    "--engine aho-corasick: the patterns make too big an automaton".to_string(),
+                )),
+            };
+        }
+    }
+    let plain =
+        options.fixed_strings || options.no_unicode || !(options.ignore_case || options.word);
+    let literals = match plain {
+        true => patterns
+            .iter()
+            .map(|pattern| literal(pattern, options).ok())
+            .collect::<Option<Vec<_>>>(),
+        false => None,
+    };
+    let Some(literals) = literals else {
+        return Ok(Box::new(Regexes::new(patterns, options)?));
+    };
+    // One pattern gains nothing from an automaton, and an empty one matches
+    // everywhere anyway. Nor do patterns too many for one, which are then
+    // searched for one by one.
+    if literals.len() > 1
+        && literals
+            .iter()
+            .all(|literal| !literal.finder.needle().is_empty())
+        && let Some(automaton) = Automaton::new(&literals, options)
+    {
+        return Ok(Box::new(automaton));
+    }
+    Ok(Box::new(Literals { literals }))
+}
+
+/// Every one of `patterns` as a Literal, for --engine literal or
+/// aho-corasick
+///
+/// # Returns
+/// An error for the first pattern that isn't plain text, saying what it
+/// would look for as it is
+fn literals(patterns: &[String], options: MatcherOptions) -> Result<Vec<Literal>, GrssError> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            literal(pattern, options).map_err(|why| {
+                // A regex that doesn't compile has better to say about it
+                if !options.fixed_strings
+                    && let Err(error) = compile_regex(pattern, pattern, options)
+                {
+                    return error;
+                }
+                GrssError::InvalidPattern {
+                    pattern: pattern.clone(),
+                    message: format!("--engine {} {}", options.engine, why),
+                }
+            })
+        })
+        .collect()
+}
+
+/// `pattern` as a Literal, when it's plain text
+///
+/// # Returns
+/// Why not otherwise, to follow `--engine literal` in an error
+fn literal(pattern: &str, options: MatcherOptions) -> Result<Literal, String> {
+    let text = match options.fixed_strings {
+        true => pattern.as_bytes().to_vec(),
+        false => plain_text(pattern, options).ok_or_else(|| {
+            format!(
+                "only takes plain text, and this is a regex; \
+                 with -F it would look for `{}` as it is",
+                pattern
+            )
+        })?,
+    };
+    // Folding anything beyond ASCII can change a string's length in bytes
+    if options.ignore_case && !options.no_unicode && !text.is_ascii() {
+        return Err(
+            "only ignores the case of ASCII letters, and -i would need more \
+                    for this; --engine regex can do it"
+                .to_string(),
+        );
+    }
+    Ok(Literal::new(&text, options))
+}
+
+impl Regexes {
+    /// Compiles every pattern, stopping at the first one that's invalid
+    fn new(patterns: &[String], options: MatcherOptions) -> Result<Self, GrssError> {
+        // Each pattern is compiled on its own first, even when they're
+        // combined below, so an error can say which one is invalid
+        let regexes = patterns
+            .iter()
+            .map(|pattern| match options.fixed_strings {
+                true => compile_regex(pattern, &regex::escape(pattern), options),
+                false => compile_regex(pattern, pattern, options),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Regexes {
+            regexes,
+            combined: combine(patterns, options),
+        })
+    }
+}
+
+impl Strategy for Regexes {
+    fn engine(&self) -> Engine {
+        Engine::Regex
+    }
+
+    fn is_match(&self, line: &[u8]) -> bool {
+        match &self.combined {
+            Some(combined) => combined.is_match(line),
+            None => self.regexes.iter().any(|regex| regex.is_match(line)),
+        }
+    }
+
+    fn find_all(&self, line: &[u8]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        for (index, regex) in self.regexes.iter().enumerate() {
+            matches.extend(regex.find_iter(line).map(|found| Match {
+                pattern: index,
+                range: found.range(),
+            }));
+        }
+        matches
+    }
+
+    fn expand(&self, line: &[u8], found: &Match, template: &Template, out: &mut Vec<u8>) {
+        // Starting from the match itself finds that same match again, now
+        // with its groups
+        let regex = &self.regexes[found.pattern];
+        if let Some(captures) = regex.captures_at(line, found.range.start) {
+            template.expand(&captures, out);
+        }
+    }
+}
+
+impl Strategy for Literals {
+    fn engine(&self) -> Engine {
+        Engine::Literal
+    }
+
+    fn is_match(&self, line: &[u8]) -> bool {
+        self.literals
+            .iter()
+            .any(|literal| !literal.find_all(line, true).is_empty())
+    }
+
+    fn find_all(&self, line: &[u8]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        for (index, literal) in self.literals.iter().enumerate() {
+            matches.extend(
+                literal
+                    .find_all(line, false)
+                    .into_iter()
+                    .map(|range| Match {
+                        pattern: index,
+                        range,
+                    }),
+            );
+        }
+        matches
+    }
+
+    fn expand(&self, line: &[u8], found: &Match, template: &Template, out: &mut Vec<u8>) {
+        template.expand_literal(&line[found.range.clone()], out)
+    }
+}
+
+impl Automaton {
+    /// Builds the automaton for `literals`, which -i and -w in `options`
+    /// apply to alike
+    ///
+    /// # Returns
+    /// None if it can't be built, when there are too many of them
+    fn new(literals: &[Literal], options: MatcherOptions) -> Option<Self> {
+        let automaton = AhoCorasick::builder()
+            .ascii_case_insensitive(options.ignore_case)
+            // Reports every occurrence of every pattern, find_all() picks
+            .match_kind(MatchKind::Standard)
+            .build(literals.iter().map(|literal| literal.finder.needle()))
+            .ok()?;
+        Some(Automaton {
+            automaton,
+            word: options.word,
+            ascii: options.no_unicode,
+        })
+    }
+}
+
+impl Strategy for Automaton {
+    fn engine(&self) -> Engine {
+        Engine::AhoCorasick
+    }
+
+    fn is_match(&self, line: &[u8]) -> bool {
+        match self.word {
+            true => self
+                .automaton
+                .find_overlapping_iter(line)
+                .any(|found| is_whole_word(line, &found.range(), self.ascii)),
+            false => self.automaton.is_match(line),
+        }
+    }
+
+    /// Finds what Literal::find_all() would for each pattern, in pattern
+    /// order
+    fn find_all(&self, line: &[u8]) -> Vec<Match> {
+        let mut found: Vec<Match> = self
+            .automaton
+            .find_overlapping_iter(line)
+            .map(|found| Match {
+                pattern: found.pattern().as_usize(),
+                range: found.range(),
+            })
+            .filter(|found| !self.word || is_whole_word(line, &found.range, self.ascii))
+            .collect();
+        found.sort_by_key(|found| (found.pattern, found.range.start));
+        let mut kept: Vec<Match> = Vec::with_capacity(found.len());
+        for found in found {
+            match kept.last() {
+                Some(last)
+                    if last.pattern == found.pattern && found.range.start < last.range.end =>
+                {
+                    continue;
+                }
+                _ => kept.push(found),
+            }
+        }
+        kept
+    }
+
+    fn expand(&self, line: &[u8], found: &Match, template: &Template, out: &mut Vec<u8>) {
+        template.expand_literal(&line[found.range.clone()], out)
+    }
+}
+
+impl Candidates<'_, '_> {
+    /// Where the first piece of the patterns' text at or after `from` in
+    /// the haystack starts; `from` can only ever move forward
+    ///
+    /// # Returns
+    /// None when there's none: no line from there on can match
+    pub fn next(&mut self, from: usize) -> Option<usize> {
+        for (finder, next) in self.finders.iter().zip(&mut self.next) {
+            if next.is_some_and(|at| at < from) {
+                *next = finder.find(&self.haystack[from..]).map(|at| from + at);
+            }
+        }
+        self.next.iter().flatten().min().copied()
+    }
+}
+
+impl Prefilter {
+    /// Finds the literal text in each of `patterns`
+    ///
+    /// # Returns
+    /// None when there's nothing to gain or it can't be done: -F patterns
+    /// are searched with memmem already, one pattern without any literal
+    /// text means every line has to go to the regexes anyway, and so does
+    /// -i (the text could be in any case), and too many patterns cost more
+    /// to prefilter than they save (see MAX_PREFILTERED).
+    fn new(patterns: &[String], options: MatcherOptions) -> Option<Self> {
+        if options.fixed_strings || patterns.len() > MAX_PREFILTERED {
+            return None;
+        }
+        let texts = required_texts(patterns, options)?;
+        let mut finders = Vec::with_capacity(patterns.len());
+        for (pattern, literal) in patterns.iter().zip(texts) {
+            log::debug!(
+                "pattern '{}': prefiltered by '{}'",
+                pattern,
+                String::from_utf8_lossy(&literal)
+            );
+            finders.push(Finder::new(&literal).into_owned());
+        }
+        Some(Prefilter { finders })
+    }
+}
+
+/// The text each of `patterns` has in every match: the pattern itself with
+/// -F, else the longest piece of plain text in it (see required_literal())
+///
+/// # Returns
+/// None when one of them has none, and with -i, where the text could be in
+/// any case
+pub fn required_texts(patterns: &[String], options: MatcherOptions) -> Option<Vec<Vec<u8>>> {
+    if options.ignore_case {
+        return None;
+    }
+    patterns
+        .iter()
+        .map(|pattern| {
+            if options.fixed_strings {
+                return Some(pattern.as_bytes().to_vec());
+            }
+            // -w only adds \b around it, which changes no literal
+            let hir = parser(options).parse(pattern).ok()?;
+            required_literal(&hir)
+        })
+        .collect()
+}
+
+/// The longest piece of plain text every match of `hir` contains
+///
+/// Only looks as far as it has to be sure: a concatenation contains each
+/// of its parts, a group its contents, and a repetition its contents when
+/// it has to happen at least once. An alternation could contain either
+/// side, and a class any character in it, so they contain nothing for sure.
+fn required_literal(hir: &Hir) -> Option<Vec<u8>> {
+    match hir.kind() {
+        // regex-syntax already joins adjacent characters into one literal
+        HirKind::Literal(literal) => Some(literal.0.to_vec()),
+        HirKind::Capture(capture) => required_literal(&capture.sub),
+        HirKind::Repetition(repetition) if repetition.min > 0 => required_literal(&repetition.sub),
+        HirKind::Concat(parts) => parts
+            .iter()
+            .filter_map(required_literal)
+            .max_by_key(Vec::len),
+        _ => None,
+    }
+}
+
+impl Literal {
+    /// Looks for `text`, ignoring the case of ASCII letters with -i
+    fn new(text: &[u8], options: MatcherOptions) -> Self {
+        let needle = match options.ignore_case {
+            true => text.to_ascii_lowercase(),
+            false => text.to_vec(),
+        };
+        Literal {
+            finder: Finder::new(&needle).into_owned(),
+            ignore_case: options.ignore_case,
+            word: options.word,
+            ascii: options.no_unicode,
+        }
+    }
+
+    /// Finds where the literal occurs in `line`, left to right and without
+    /// overlaps (like Regex::find_iter), stopping after the first one when
+    /// `first_only` is set
+    fn find_all(&self, line: &[u8], first_only: bool) -> Vec<Range<usize>> {
+        let needle_len = self.finder.needle().len();
+        // An empty pattern matches every line, but there's nothing to find
+        if needle_len == 0 {
+            return std::iter::once(0..0).collect();
+        }
+
+        let lowered;
+        let haystack = if self.ignore_case {
+            lowered = line.to_ascii_lowercase();
+            &lowered
+        } else {
+            line
+        };
+
+        let mut found = Vec::new();
+        let mut start = 0;
+        while let Some(offset) = self.finder.find(&haystack[start..]) {
+            let range = start + offset..start + offset + needle_len;
+            if self.word && !is_whole_word(line, &range, self.ascii) {
+                // `cat` in "concat cat": keep looking from just after where
+                // this occurrence began
+                start = range.start + 1;
+                continue;
+            }
+            start = range.end;
+            found.push(range);
+            if first_only {
+                break;
+            }
+        }
+        found
+    }
+}
+
+/// Whether `range` in `line` is a whole word for -F -w: neither the
+/// character just before it nor the one just after is a letter, digit or
+/// underscore (the start and end of the line count as boundaries, and so
+/// do bytes that aren't UTF-8)
+///
+/// With `ascii` (--no-unicode) that's an ASCII letter, digit or underscore,
+/// the bytes around the range are all it takes to tell.
+fn is_whole_word(line: &[u8], range: &Range<usize>, ascii: bool) -> bool {
+    if ascii {
+        let is_word_byte = |byte: &u8| byte.is_ascii_alphanumeric() || *byte == b'_';
+        let before = range.start.checked_sub(1).and_then(|at| line.get(at));
+        return !before.is_some_and(is_word_byte) && !line.get(range.end).is_some_and(is_word_byte);
+    }
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    // A character is at most 4 bytes, and an invalid byte is none at all
+    let before = &line[range.start.saturating_sub(4)..range.start];
+    let before = before
+        .utf8_chunks()
+        .last()
+        .and_then(|chunk| match chunk.invalid() {
+            [] => chunk.valid().chars().next_back(),
+            _ => None,
+        });
+    let after = &line[range.end..line.len().min(range.end + 4)];
+    let after = after
+        .utf8_chunks()
+        .next()
+        .and_then(|chunk| chunk.valid().chars().next());
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+/// The text the regex `pattern` matches when that's all it matches, like
+/// `Vec::new\(\)` does `Vec::new()`
+fn plain_text(pattern: &str, options: MatcherOptions) -> Option<Vec<u8>> {
+    let hir = parser(options).parse(pattern).ok()?;
+    match hir.into_kind() {
+        HirKind::Literal(literal) => Some(literal.0.into_vec()),
+        _ => None,
+    }
+}
+
+/// A parser that reads patterns the way compile_regex() has the regex crate
+/// read them, so `^`, `$` and `\xFF` mean the same
+fn parser(options: MatcherOptions) -> regex_syntax::Parser {
+    ParserBuilder::new()
+        .multi_line(options.multiline)
+        .crlf(options.crlf)
+        .unicode(!options.no_unicode)
+        .utf8(!options.no_unicode)
+        .build()
+}
+
+/// With --no-unicode, the first thing in `pattern` that needs Unicode
+/// anyway: a class like `\p{Greek}`, or the `u` flag turning it back on
+///
+/// # Returns
+/// That part of the pattern, None when there's nothing like it (or the
+/// pattern doesn't parse, which compiling it reports)
+fn unicode_construct(pattern: &str) -> Option<&str> {
+    /// Stops the walk over the syntax tree (by "failing") at the first
+    /// Unicode construct, with where it is in the pattern
+    struct FindUnicode;
+
+    impl ast::Visitor for FindUnicode {
+        type Output = ();
+        type Err = Range<usize>;
+
+        fn finish(self) -> Result<(), Range<usize>> {
+            Ok(())
+        }
+
+        fn visit_pre(&mut self, ast: &Ast) -> Result<(), Range<usize>> {
+            // For a group, only its start up to the `:`: `(?u:`
+            let (flags, span) = match ast {
+                Ast::ClassUnicode(class) => {
+                    return Err(class.span.start.offset..class.span.end.offset);
+                }
+                Ast::Flags(set) => (&set.flags, set.span.start.offset..set.span.end.offset),
+                Ast::Group(group) => match &group.kind {
+                    ast::GroupKind::NonCapturing(flags) => {
+                        (flags, group.span.start.offset..flags.span.end.offset + 1)
+                    }
+                    _ => return Ok(()),
+                },
+                _ => return Ok(()),
+            };
+            // `(?-u)` is fine, it's `u` after a `-` that turns it off
+            let mut negated = false;
+            for item in &flags.items {
+                match item.kind {
+                    ast::FlagsItemKind::Negation => negated = true,
+                    ast::FlagsItemKind::Flag(ast::Flag::Unicode) if !negated => return Err(span),
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+
+        fn visit_class_set_item_pre(
+            &mut self,
+            item: &ast::ClassSetItem,
+        ) -> Result<(), Range<usize>> {
+            match item {
+                ast::ClassSetItem::Unicode(class) => {
+                    Err(class.span.start.offset..class.span.end.offset)
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+
+    let parsed = ast::parse::Parser::new().parse(pattern).ok()?;
+    let span = ast::visit(&parsed, FindUnicode).err()?;
+    Some(&pattern[span])
+}
+
+/// Joins several patterns into a single regex that matches wherever any of
+/// them does
+///
+/// The regex engine runs an alternation like this over the line once,
+/// instead of once per pattern, for Engine::Regex (see strategy()).
+///
+/// # Returns
+/// None for a single pattern, which gains nothing from this, and when the
+/// joined regex doesn't compile even though every pattern does on its own:
+/// two patterns can use the same capture group name, and hundreds of big
+/// ones can go over the regex crate's size limit. The patterns are then
+/// tried one by one instead.
+fn combine(patterns: &[String], options: MatcherOptions) -> Option<Regex> {
+    if patterns.len() < 2 {
+        return None;
+    }
+    let alternation = patterns
+        .iter()
+        .map(|pattern| match options.fixed_strings {
+            true => format!("(?:{})", regex::escape(pattern)),
+            false => format!("(?:{})", pattern),
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+    let combined = compile_regex(&alternation, &alternation, options).ok();
+    if combined.is_some() {
+        log::debug!("{} patterns combined into one regex", patterns.len());
+    }
+    combined
+}
+
+/// Compiles `regex`, reporting errors against `pattern` (what the user typed)
+pub fn compile_regex(
+    pattern: &str,
+    regex: &str,
+    options: MatcherOptions,
+) -> Result<Regex, GrssError> {
+    // Only when what's compiled is the pattern as typed can an error be
+    // pointed out in it: -F patterns were escaped, and fail for other reasons
+    let typed = pattern == regex;
+    // For -w the pattern goes inside a non-capturing group before adding the
+    // word boundaries: `\bfoo|bar\b` would only put a boundary in front of
+    // `foo` and after `bar`, while `\b(?:foo|bar)\b` puts one around both
+    let regex = if options.word {
+        format!(r"\b(?:{})\b", regex)
+    } else {
+        regex.to_string()
+    };
+
+    log::debug!(
+        "pattern '{}': compiling as regex {} (ignore case: {}, multiline: {}, unicode: {})",
+        pattern,
+        regex,
+        options.ignore_case,
+        options.multiline,
+        !options.no_unicode
+    );
+    RegexBuilder::new(&regex)
+        .unicode(!options.no_unicode)
+        .case_insensitive(options.ignore_case)
+        .multi_line(options.multiline)
+        .crlf(options.crlf)
+        .build()
+        .map_err(|error| GrssError::InvalidPattern {
+            pattern: pattern.to_string(),
+            message: match typed {
+                true => pattern_error::render(pattern, &error.to_string()),
+                false => error.to_string(),
+            },
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn matcher(patterns: &[&str]) -> Matcher {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        Matcher::new(&patterns, MatcherOptions::default()).unwrap()
+    }
+
+    fn word_matcher(patterns: &[&str]) -> Matcher {
+        let options = MatcherOptions {
+            word: true,
+            ..MatcherOptions::default()
+        };
+        matcher_with(patterns, options)
+    }
+
+    fn matcher_with(patterns: &[&str], options: MatcherOptions) -> Matcher {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        Matcher::new(&patterns, options).unwrap()
+    }
+
+    fn fixed(ignore_case: bool, word: bool) -> MatcherOptions {
+        MatcherOptions {
+            ignore_case,
+            word,
+            fixed_strings: true,
+            ..MatcherOptions::default()
+        }
+    }
+
+    #[test]
+    fn invalid_pattern_is_a_friendly_error() {
+        let message = match Matcher::new(&["(".to_string()], MatcherOptions::default()) {
+            Err(error) => error.to_string(),
+            Ok(_) => panic!("`(` compiled"),
+        };
+        assert!(message.starts_with("invalid pattern '(': "));
+        assert!(message.contains("unclosed group"));
+    }
+
+    #[test]
+    fn empty_matches_still_select_the_line() {
+        assert_eq!(
+            matcher(&["^"]).find_matches(b"abc"),
+            vec![Match {
+                pattern: 0,
+                range: 0..0
+            }]
+        );
+    }
+
+    #[test]
+    fn any_pattern_can_match() {
+        let matcher = matcher(&["foo", "bar"]);
+        assert!(matcher.is_match(b"a bar"));
+        assert!(!matcher.is_match(b"baz"));
+    }
+
+    #[test]
+    fn and_and_not_narrow_down_the_lines() {
+        let strings =
+            |patterns: &[&str]| -> Vec<String> { patterns.iter().map(|p| p.to_string()).collect() };
+        let with = |options: MatcherOptions| {
+            matcher_with(&["foo", "fo+"], options)
+                .with_conditions(
+                    &strings(&["bar", r"\d"]),
+                    &strings(&["baz", "qux"]),
+                    options,
+                )
+                .unwrap()
+        };
+        let matcher = with(MatcherOptions::default());
+        assert!(matcher.is_match(b"foo bar 1"));
+        assert!(!matcher.is_match(b"foo bar"));
+        assert!(!matcher.is_match(b"bar 1"));
+        assert!(!matcher.is_match(b"foo bar 1 baz"));
+        assert!(!matcher.is_match(b"foo bar 1 qux"));
+
+        // The --and patterns are highlighted and counted too, numbered after
+        // the others, but only the pattern's matches are replaced
+        assert_eq!(matcher.find(b"1 foo bar"), [0..1, 2..5, 6..9]);
+        assert_eq!(matcher.find_matches(b"1 foo bar")[0].pattern, 3);
+        assert_eq!(matcher.count_matches(b"1 foo bar"), 4);
+        let template = Template::parse("<$0>");
+        assert_eq!(matcher.replace(b"1 foo bar", &template).0, b"1 <foo> bar");
+
+        // -v selects what doesn't pass all of it
+        let inverted = with(MatcherOptions {
+            invert: true,
+            ..MatcherOptions::default()
+        });
+        assert!(!inverted.selects(b"foo bar 1"));
+        assert!(inverted.selects(b"foo bar 1 baz"));
+        assert!(inverted.selects(b"foo bar"));
+        assert!(inverted.find(b"foo bar").is_empty());
+    }
+
+    #[test]
+    fn many_patterns_are_matched_in_one_pass() {
+        let patterns: Vec<String> = (0..300).map(|n| format!("call_{n}\\(")).collect();
+        let many = Matcher::new(&patterns, MatcherOptions::default()).unwrap();
+        assert_eq!(many.engine(), Engine::AhoCorasick);
+        assert!(many.is_match(b"x = call_299(y);"));
+        assert!(!many.is_match(b"x = call_300(y);"));
+        assert_eq!(many.find_matches(b"call_7(")[0].pattern, 7);
+        // As regexes they're one alternation
+        let regexes = Regexes::new(&patterns, MatcherOptions::default()).unwrap();
+        assert!(regexes.combined.is_some());
+
+        // The same group name twice can't be joined, but still matches
+        let same_names = ["(?P<n>a)".to_string(), "(?P<n>b)".to_string()];
+        let regexes = Regexes::new(&same_names, MatcherOptions::default()).unwrap();
+        assert!(regexes.combined.is_none());
+        assert!(regexes.is_match(b"b"));
+    }
+
+    #[test]
+    fn reports_which_pattern_matched() {
+        let found = matcher(&["foo", "bar"]).find_matches(b"bar foo");
+        assert_eq!(
+            found,
+            vec![
+                Match {
+                    pattern: 1,
+                    range: 0..3
+                },
+                Match {
+                    pattern: 0,
+                    range: 4..7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_matches_are_merged() {
+        let found = matcher(&["oo", "foo"]).find_matches(b"food");
+        assert_eq!(
+            found,
+            vec![Match {
+                pattern: 1,
+                range: 0..3
+            }]
+        );
+    }
+
+    #[test]
+    fn count_matches_counts_every_patterns_matches() {
+        assert_eq!(matcher(&["foo"]).count_matches(b"foo foo foo"), 3);
+        // Unlike find_matches(), overlaps between patterns aren't merged
+        assert_eq!(matcher(&["oo", "foo"]).count_matches(b"food"), 2);
+        assert_eq!(word_matcher(&["foo"]).count_matches(b"foo food foo"), 2);
+        assert_eq!(
+            matcher_with(&["a.b", "x"], fixed(true, false)).count_matches(b"A.B a.b x X axb"),
+            5
+        );
+    }
+
+    #[test]
+    fn lines_are_matched_as_the_bytes_they_are() {
+        let starts = |matcher: &Matcher, line: &[u8]| -> Vec<usize> {
+            matcher.find(line).iter().map(|range| range.start).collect()
+        };
+        // `.` is a whole character, never an invalid byte
+        let dot = matcher(&["caf."]);
+        assert!(dot.is_match("café".as_bytes()));
+        assert!(!dot.is_match(b"caf\xE9"));
+        assert_eq!(starts(&matcher(&[r"(?-u:\xE9)"]), b"caf\xE9 \xFF"), [3]);
+        // Literals go by the bytes too, and an invalid byte next to one is
+        // as good as a space for -w
+        assert_eq!(starts(&matcher(&["\u{FFFD}", "x"]), b"\xFFx"), [1]);
+        assert_eq!(starts(&word_matcher(&["x"]), b"\xFFx\xE9"), [1]);
+        let fixed_word = matcher_with(&["x"], fixed(false, true));
+        assert_eq!(starts(&fixed_word, b"\xFFx\xE9 x\xC3\xA9"), [1]);
+    }
+
+    #[test]
+    fn word_mode_skips_matches_inside_words() {
+        let matcher = word_matcher(&["new"]);
+        assert!(!matcher.is_match(b"renew the news"));
+        assert!(matcher.is_match(b"new line"));
+        assert!(matcher.is_match(b"a brand new"));
+        assert!(matcher.is_match(b"Vec::new()"));
+        assert!(matcher.is_match(b"(new)"));
+        assert!(!matcher.is_match(b"new_thing"));
+    }
+
+    #[test]
+    fn word_mode_bounds_every_alternative() {
+        let matcher = word_matcher(&["foo|bar"]);
+        assert!(!matcher.is_match(b"foobar"));
+        assert!(!matcher.is_match(b"xbar"));
+        assert!(!matcher.is_match(b"foox"));
+        assert_eq!(matcher.find_matches(b"a bar, foo.")[1].range, 7..10);
+    }
+
+    #[test]
+    fn fixed_strings_match_metacharacters_literally() {
+        let matcher = matcher_with(&["vec![1.0]"], fixed(false, false));
+        assert!(matcher.is_match(b"let v = vec![1.0];"));
+        assert!(!matcher.is_match(b"let v = vec![1x0];"));
+        assert_eq!(matcher.find_matches(b"vec![1.0], vec![1.0]").len(), 2);
+        // The same text isn't even a valid regex
+        assert!(Matcher::new(&["vec![1.0".to_string()], MatcherOptions::default()).is_err());
+    }
+
+    #[test]
+    fn fixed_strings_ignore_case() {
+        let matcher = matcher_with(&["Hello.World"], fixed(true, false));
+        assert_eq!(matcher.find_matches(b"say HELLO.world")[0].range, 4..15);
+        assert!(!matcher.is_match(b"hello world"));
+
+        // Non-ASCII patterns still fold case, through an escaped regex
+        let matcher = matcher_with(&["ÉTÉ."], fixed(true, false));
+        assert!(matcher.is_match("un été.".as_bytes()));
+        assert!(!matcher.is_match("un été!".as_bytes()));
+    }
+
+    #[test]
+    fn fixed_strings_whole_words() {
+        let matcher = matcher_with(&["cat"], fixed(false, true));
+        assert!(matcher.is_match(b"cat at the start"));
+        assert!(matcher.is_match(b"at the end: cat"));
+        assert!(matcher.is_match(b"(cat), cat."));
+        assert!(!matcher.is_match(b"concatenate cats"));
+        assert_eq!(matcher.find_matches(b"concat cat")[0].range, 7..10);
+    }
+
+    #[test]
+    fn invert_selects_the_other_lines_without_highlights() {
+        let options = MatcherOptions {
+            invert: true,
+            ..MatcherOptions::default()
+        };
+        let inverted = matcher_with(&["x"], options);
+        assert!(inverted.selects(b"abc"));
+        assert!(!inverted.selects(b"xyz"));
+        assert!(inverted.find(b"abc").is_empty());
+        assert_eq!(matcher(&["b"]).find(b"abcb"), [1..2, 3..4]);
+    }
+
+    #[test]
+    fn case_flags_resolve_like_ripgrep() {
+        let flags = |ignore_case, case_sensitive, smart_case| CaseFlags {
+            ignore_case,
+            case_sensitive,
+            smart_case,
+        };
+        let ignores =
+            |flags, pattern: &str| resolve_ignore_case(flags, &[pattern.to_string()], false);
+
+        // (-i, -s, -S), pattern, ignore case?
+        for ((i, s, smart), pattern, expected) in [
+            ((false, false, false), "foo", false),
+            ((false, false, false), "Foo", false),
+            ((true, false, false), "Foo", true),
+            ((false, true, false), "foo", false),
+            ((false, false, true), "foo", true),
+            ((false, false, true), "Foo", false),
+            ((false, false, true), r"foo\D", true),
+            ((false, false, true), r"foo\W\p{Lu}", true),
+            ((false, false, true), r"\x46oo", false),
+            ((false, false, true), "[A-Z]oo", false),
+            ((false, false, true), "f(o", true),
+            ((true, false, true), "Foo", true),
+            ((true, false, true), r"foo\D", true),
+            ((false, true, true), "foo", false),
+            ((false, true, true), r"foo\D", false),
+        ] {
+            let case = flags(i, s, smart);
+            assert_eq!(
+                ignores(case, pattern),
+                expected,
+                "{:?} {}",
+                (i, s, smart),
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn prefilter_looks_for_the_text_every_match_needs() {
+        let literal = |pattern: &str| {
+            let hir = regex_syntax::Parser::new().parse(pattern).unwrap();
+            required_literal(&hir).map(|bytes| String::from_utf8(bytes).unwrap())
+        };
+        assert_eq!(
+            literal(r"Rectangle::new\(\d+").as_deref(),
+            Some("Rectangle::new(")
+        );
+        assert_eq!(literal(r"\s*\.unwrap\(\)").as_deref(), Some(".unwrap()"));
+        assert_eq!(literal(r"\d+ (errors)+ x?").as_deref(), Some("errors"));
+        assert_eq!(literal(r"foo|bar"), None);
+        assert_eq!(literal(r"(?i)foo"), None);
+        assert_eq!(literal(r"(abc)* \d"), Some(" ".to_string()));
+
+        // Every pattern needs some for the lines to be prefiltered
+        assert!(matcher(&["foo", r"\d+"]).prefilter.is_none());
+        assert!(
+            matcher_with(&["foo"], fixed(false, false))
+                .prefilter
+                .is_none()
+        );
+        let both = matcher(&[r"foo\d", r"bar\s"]);
+        let haystack = b"foo\nbar\nfoo1 foo2\n";
+        let mut candidates = both.candidates(haystack).unwrap();
+        assert_eq!(candidates.next(0), Some(0));
+        assert_eq!(candidates.next(1), Some(4));
+        assert_eq!(candidates.next(5), Some(8));
+        assert_eq!(candidates.next(13), Some(13));
+        assert_eq!(candidates.next(14), None);
+        assert!(both.without_prefilter().candidates(haystack).is_none());
+    }
+
+    #[test]
+    fn plain_text_patterns_go_into_one_automaton() {
+        let fixed_many = matcher_with(&["foo", "oo", "bar"], fixed(false, false));
+        assert_eq!(fixed_many.engine(), Engine::AhoCorasick);
+        // The same as the regexes would, overlaps between patterns merged
+        assert_eq!(
+            fixed_many.find_matches(b"food bar"),
+            [
+                Match {
+                    pattern: 0,
+                    range: 0..3
+                },
+                Match {
+                    pattern: 2,
+                    range: 5..8
+                }
+            ]
+        );
+        assert_eq!(fixed_many.count_matches(b"food bar"), 3);
+
+        // Regexes without anything special in them count as plain text
+        let regexes = matcher(&[r"Vec::new\(\)", "TODO"]);
+        assert_eq!(regexes.engine(), Engine::AhoCorasick);
+        assert!(regexes.is_match(b"let v = Vec::new();"));
+        assert!(!regexes.is_match(b"let v = Vec::new;"));
+        assert_eq!(matcher(&["TODO", r"\d"]).engine(), Engine::Regex);
+
+        // Unless -i or -w want more than ASCII from them
+        let options = MatcherOptions {
+            ignore_case: true,
+            ..MatcherOptions::default()
+        };
+        assert_eq!(
+            matcher_with(&["foo", "bar"], options).engine(),
+            Engine::Regex
+        );
+        // -F -i is ASCII anyway, when the patterns are
+        let ascii = matcher_with(&["foo", "bar"], fixed(true, false));
+        assert_eq!(ascii.engine(), Engine::AhoCorasick);
+        assert_eq!(ascii.find(b"FOO Bar"), [0..3, 4..7]);
+        let accented = matcher_with(&["été", "bar"], fixed(true, false));
+        assert_eq!(accented.engine(), Engine::Regex);
+        assert!(accented.is_match("ÉTÉ".as_bytes()));
+
+        // -w picks whole words out of overlapping occurrences
+        let words = matcher_with(&["cat", "at"], fixed(false, true));
+        assert!(!words.is_match(b"concat"));
+        assert_eq!(words.find(b"concat at cat"), [7..9, 10..13]);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn automaton_matches_like_the_regexes(
+            patterns in proptest::collection::vec("[abAB]{1,3}", 2..6),
+            lines in proptest::collection::vec("[abAB .]{0,24}", 1..8),
+            ignore_case: bool,
+            word: bool,
+        ) {
+            let literals = matcher_with(
+                &patterns.iter().map(String::as_str).collect::<Vec<_>>(),
+                fixed(ignore_case, word),
+            );
+            proptest::prop_assert_eq!(literals.engine(), Engine::AhoCorasick);
+            // A group keeps each one a regex, without changing what it matches
+            let grouped: Vec<String> = patterns.iter().map(|pattern| format!("({})", pattern)).collect();
+            let options = MatcherOptions {
+                ignore_case,
+                word,
+                ..MatcherOptions::default()
+            };
+            let regexes = Matcher::new(&grouped, options).unwrap();
+            proptest::prop_assert_eq!(regexes.engine(), Engine::Regex);
+            for line in lines.iter().map(String::as_bytes) {
+                proptest::prop_assert_eq!(literals.is_match(line), regexes.is_match(line));
+                proptest::prop_assert_eq!(literals.find_matches(line), regexes.find_matches(line));
+                proptest::prop_assert_eq!(literals.count_matches(line), regexes.count_matches(line));
+            }
+        }
+    }
+
+    /// The pattern sets every engine is tried with in
+    /// engines_find_the_same_matches(), as -F patterns and as regexes
+    const CONFORMANCE_PATTERNS: &[&[&str]] = &[
+        &["TODO"],
+        &["todo", "TODO:"],
+        &["fn", "run", "()"],
+        &["o", "do", "one", "done"],
+        &["pub fn", "fn main", "!("],
+        &["é", "caf"],
+    ];
+
+    /// What they're matched against: every line of the files in
+    /// tests/data/tree, and a few more for -i and -w
+    fn conformance_lines() -> Vec<Vec<u8>> {
+        let tree = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/tree");
+        let mut lines: Vec<Vec<u8>> = walkdir::WalkDir::new(tree)
+            .sort_by_file_name()
+            .into_iter()
+            .map(Result::unwrap)
+            .filter(|entry| entry.file_type().is_file())
+            .flat_map(|entry| {
+                let text = std::fs::read(entry.path()).unwrap();
+                text.split(|&byte| byte == b'\n')
+                    .map(<[u8]>::to_vec)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        for extra in ["Todo: Café_fn RUN", "TODOTODO donedone", "déjà vu é"] {
+            lines.push(extra.as_bytes().to_vec());
+        }
+        lines.push(b"caf\xe9 fn\xffrun".to_vec());
+        lines
+    }
+
+    #[test]
+    fn engines_find_the_same_matches() {
+        let lines = conformance_lines();
+        let template = Template::parse("<$0>");
+        for (texts, fixed_strings) in CONFORMANCE_PATTERNS
+            .iter()
+            .flat_map(|patterns| [(patterns, true), (patterns, false)])
+        {
+            // As regexes they're escaped, so they're still plain text
+            let patterns: Vec<String> = texts
+                .iter()
+                .map(|pattern| match fixed_strings {
+                    true => pattern.to_string(),
+                    false => regex::escape(pattern),
+                })
+                .collect();
+            let flags = (0..8).map(|bits| (bits & 1 != 0, bits & 2 != 0, bits & 4 != 0));
+            for (ignore_case, word, no_unicode) in flags {
+                // -w's \b needs a word character on the other side where a
+                // pattern starts or ends with punctuation, the others' don't
+                let is_word = |c: char| match no_unicode {
+                    true => c.is_ascii_alphanumeric() || c == '_',
+                    false => c.is_alphanumeric() || c == '_',
+                };
+                let word_edged = texts
+                    .iter()
+                    .all(|text| text.starts_with(is_word) && text.ends_with(is_word));
+                if word && !word_edged {
+                    continue;
+                }
+                let options = MatcherOptions {
+                    ignore_case,
+                    word,
+                    fixed_strings,
+                    no_unicode,
+                    ..MatcherOptions::default()
+                };
+                let with = |engine| Matcher::new(&patterns, MatcherOptions { engine, ..options });
+                let expected = with(Engine::Regex).unwrap();
+                for engine in [Engine::Auto, Engine::Literal, Engine::AhoCorasick] {
+                    let Ok(matcher) = with(engine) else {
+                        // Only -i with more than ASCII is beyond them
+                        assert!(ignore_case && !no_unicode && !patterns.concat().is_ascii());
+                        assert_eq!(with(Engine::Auto).unwrap().engine(), Engine::Regex);
+                        continue;
+                    };
+                    for line in &lines {
+                        let case = format!(
+                            "{engine} {patterns:?} (-F {fixed_strings}, -i {ignore_case}, -w {word}, \
+                             --no-unicode {no_unicode}) on {:?}",
+                            String::from_utf8_lossy(line)
+                        );
+                        assert_eq!(matcher.is_match(line), expected.is_match(line), "{case}");
+                        assert_eq!(
+                            matcher.find_matches(line),
+                            expected.find_matches(line),
+                            "{case}"
+                        );
+                        assert_eq!(
+                            matcher.count_matches(line),
+                            expected.count_matches(line),
+                            "{case}"
+                        );
+                        assert_eq!(
+                            matcher.replace(line, &template),
+                            expected.replace(line, &template),
+                            "{case}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn literal_engines_only_take_plain_text() {
+        let with = |engine, patterns: &[&str], options: MatcherOptions| {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+            Matcher::new(&patterns, MatcherOptions { engine, ..options }).map(|m| m.engine())
+        };
+        let regex = MatcherOptions::default();
+        for engine in [Engine::Literal, Engine::AhoCorasick] {
+            assert_eq!(with(engine, &["TODO", r"\(\)"], regex).unwrap(), engine);
+            // A metacharacter says what -F would look for instead
+            let error = with(engine, &["TODO", "fo+"], regex).err().unwrap();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "invalid pattern 'fo+': --engine {engine} only takes plain text, \
+                     and this is a regex; with -F it would look for `fo+` as it is"
+                )
+            );
+            assert!(with(engine, &["fo+"], fixed(false, false)).is_ok());
+            // A broken regex is reported as broken
+            let error = with(engine, &["(fo"], regex).err().unwrap();
+            assert!(error.to_string().contains("unclosed group"), "{error}");
+            assert!(with(engine, &["été"], fixed(true, false)).is_err());
+            assert!(with(engine, &["ete"], fixed(true, false)).is_ok());
+        }
+        // While the regex engine takes anything
+        assert_eq!(
+            with(Engine::Regex, &["TODO"], fixed(true, true)).unwrap(),
+            Engine::Regex
+        );
+        // Auto picks the automaton for several plain patterns, memmem for one
+        assert_eq!(
+            with(Engine::Auto, &["TODO"], regex).unwrap(),
+            Engine::Literal
+        );
+        assert_eq!(
+            with(Engine::Auto, &["a", "b"], regex).unwrap(),
+            Engine::AhoCorasick
+        );
+        assert_eq!(
+            with(Engine::Auto, &["a", ""], fixed(false, false)).unwrap(),
+            Engine::Literal
+        );
+    }
+
+    #[test]
+    fn no_unicode_keeps_to_ascii() {
+        let ascii = MatcherOptions {
+            no_unicode: true,
+            ..MatcherOptions::default()
+        };
+        let word = matcher_with(&[r"\w+"], ascii);
+        assert_eq!(word.find_matches("café".as_bytes())[0].range, 0..3);
+        assert_eq!(
+            matcher(&[r"\w+"]).find_matches("café".as_bytes())[0].range,
+            0..5
+        );
+        // `.` is any byte, even one that isn't UTF-8
+        assert!(matcher_with(&["a.b"], ascii).is_match(b"a\xffb"));
+        assert!(!matcher(&["a.b"]).is_match(b"a\xffb"));
+
+        // -i and -w only know ASCII, whichever engine
+        for engine in [Engine::Regex, Engine::Literal] {
+            let options = MatcherOptions {
+                ignore_case: true,
+                engine,
+                ..ascii
+            };
+            let folded = matcher_with(&["été"], options);
+            assert!(folded.is_match("ÉtÉ été".as_bytes()));
+            assert!(!folded.is_match("ÉTÉ".as_bytes()));
+            assert!(matcher_with(&["ete"], options).is_match(b"ETE"));
+            let options = MatcherOptions {
+                word: true,
+                engine,
+                ..ascii
+            };
+            assert!(matcher_with(&["caf"], options).is_match("café".as_bytes()));
+        }
+
+        // Unicode classes, and turning it back on, are errors
+        let error = |pattern: &str| match Matcher::new(&[pattern.to_string()], ascii) {
+            Err(error) => error.to_string(),
+            Ok(_) => panic!("{pattern} compiled"),
+        };
+        assert_eq!(
+            error(r"\p{Greek}+"),
+            r"invalid pattern '\p{Greek}+': `\p{Greek}` needs Unicode, which --no-unicode turns off"
+        );
+        assert!(error(r"[\pL\d]").contains(r"`\pL` needs"));
+        assert!(error("(?u:é)").contains("`(?u:` needs"));
+        assert!(error("(?iu)x").contains("`(?iu)` needs"));
+        assert!(Matcher::new(&["(?i-u)x".to_string()], ascii).is_ok());
+        // As -F patterns they're only text
+        assert!(
+            Matcher::new(
+                &[r"\p{Greek}".to_string()],
+                MatcherOptions {
+                    fixed_strings: true,
+                    ..ascii
+                }
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn smart_case_looks_at_every_pattern() {
+        let smart = CaseFlags {
+            smart_case: true,
+            ..CaseFlags::default()
+        };
+        let patterns = ["foo".to_string(), "Bar".to_string()];
+        assert!(!resolve_ignore_case(smart, &patterns, false));
+        assert!(resolve_ignore_case(smart, &patterns[..1], false));
+        // -F: every character is literal, \D included
+        assert!(!resolve_ignore_case(smart, &[r"foo\D".to_string()], true));
+    }
+}
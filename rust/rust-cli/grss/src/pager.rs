@@ -0,0 +1,117 @@
+// FilePath: rust/rust-cli/grss/src/pager.rs
+
+//! --pager: showing the results in `less` (or any other pager) instead of
+//! letting them scroll off the terminal
+//!
+//! The pager is started before the search, and everything grss would write
+//! to stdout goes into its stdin instead. Nothing changes about the output
+//! itself: it still counts as going to a terminal, so --color auto still
+//! colors, which `less -R` shows as colors rather than escape codes.
+//!
+//! Quitting the pager before grss is done closes its stdin, and the next
+//! write fails with a broken pipe. That just means nobody wants the rest, so
+//! grss stops searching without saying anything, the same as when `head`
+//! closes a pipe (see run() in main.rs). Either way grss waits for
+//! the pager to exit before it does, so the shell prompt doesn't come back
+//! in the middle of the pager's screen.
+
+use std::io;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// What's run when neither GRSS_PAGER nor PAGER say otherwise
+const DEFAULT_PAGER: &str = "less -R";
+
+/// A running pager, waited for when it's dropped
+pub struct Pager {
+    child: Child,
+}
+
+impl Pager {
+    /// Starts the pager from GRSS_PAGER or PAGER (see command())
+    ///
+    /// # Returns
+    /// The pager and the stdin to write the output to, or an error when it
+    /// couldn't be started (e.g. it isn't installed)
+    pub fn spawn() -> io::Result<(Pager, ChildStdin)> {
+        let words = command(
+            std::env::var("GRSS_PAGER").ok(),
+            std::env::var("PAGER").ok(),
+        );
+        let Some((program, args)) = words.split_first() else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no pager given"));
+        };
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", program, error)))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        Ok((Pager { child }, stdin))
+    }
+}
+
+impl Drop for Pager {
+    /// Waits for the pager to exit, which it only does once its stdin is
+    /// closed (whatever was writing to it was dropped before this) and the
+    /// user quit it
+    fn drop(&mut self) {
+        let _ = self.child.wait();
+    }
+}
+
+/// The pager's command line: `grss_pager` (GRSS_PAGER) if it's set, else
+/// `pager` (PAGER), else `less -R`, split up the way a shell would
+///
+/// `less` without `-R` shows color codes as `ESC[31m` instead of colors, so
+/// it's added when missing.
+fn command(grss_pager: Option<String>, pager: Option<String>) -> Vec<String> {
+    let line = [grss_pager, pager]
+        .into_iter()
+        .flatten()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    let mut words = shell_words::split(&line).unwrap_or_else(|_| vec![line.clone()]);
+    let is_less = words
+        .first()
+        .is_some_and(|program| program == "less" || program.ends_with("/less"));
+    let has_raw_control_chars = words.iter().skip(1).any(|word| {
+        word == "--RAW-CONTROL-CHARS"
+            || (word.starts_with('-') && !word.starts_with("--") && word.contains('R'))
+    });
+    if is_less && !has_raw_control_chars {
+        words.push("-R".to_string());
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pager(grss_pager: Option<&str>, pager: Option<&str>) -> Vec<String> {
+        command(grss_pager.map(String::from), pager.map(String::from))
+    }
+
+    #[test]
+    fn grss_pager_then_pager_then_less() {
+        assert_eq!(pager(None, None), ["less", "-R"]);
+        assert_eq!(pager(None, Some("most")), ["most"]);
+        assert_eq!(pager(Some("bat --plain"), Some("most")), ["bat", "--plain"]);
+        // Set but empty is the same as not set
+        assert_eq!(pager(Some(""), Some("most")), ["most"]);
+    }
+
+    #[test]
+    fn less_always_gets_raw_control_chars() {
+        assert_eq!(pager(None, Some("less")), ["less", "-R"]);
+        assert_eq!(
+            pager(None, Some("/usr/bin/less -S")),
+            ["/usr/bin/less", "-S", "-R"]
+        );
+        assert_eq!(pager(None, Some("less -FRX")), ["less", "-FRX"]);
+        assert_eq!(
+            pager(Some("'less' --RAW-CONTROL-CHARS"), None),
+            ["less", "--RAW-CONTROL-CHARS"]
+        );
+    }
+}
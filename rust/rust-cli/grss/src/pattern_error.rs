@@ -0,0 +1,209 @@
+// FilePath: rust/rust-cli/grss/src/pattern_error.rs
+
+//! Explaining why a pattern doesn't compile
+//!
+//! The regex crate's errors are precise but written for people who know
+//! regex syntax well. Most bad patterns on a grep command line are text that
+//! was meant literally (`foo(bar`, `arr[0`) or a small slip, so the message
+//! here shows the pattern with a caret under the part that's wrong, and for
+//! the common mistakes a hint on how to fix it:
+//!
+//! ```text
+//! invalid pattern 'foo(bar': unclosed group
+//!     foo(bar
+//!        ^
+//! hint: this `(` is never closed; write `\(` to match a literal parenthesis
+//! hint: to search for the text as it is, use -F
+//! ```
+//!
+//! The position comes from parsing the pattern with regex-syntax, the parser
+//! the regex crate itself is built on, which says where each error is.
+
+use regex_syntax::ast::ErrorKind as AstErrorKind;
+use regex_syntax::hir::ErrorKind as HirErrorKind;
+use std::fmt::Write;
+
+/// Renders why `pattern`, as the user typed it, doesn't compile
+///
+/// `fallback` is the regex crate's own message, for the errors that parsing
+/// `pattern` alone doesn't find (a pattern that's fine by itself can still
+/// be too big once compiled, say).
+///
+/// # Returns
+/// The message to go after `invalid pattern '...': `, one or more lines
+pub fn render(pattern: &str, fallback: &str) -> String {
+    let (span, what, hint) = match regex_syntax::Parser::new().parse(pattern) {
+        Ok(_) => return fallback.to_string(),
+        Err(regex_syntax::Error::Parse(error)) => (
+            *error.span(),
+            error.kind().to_string(),
+            ast_hint(error.kind()),
+        ),
+        Err(regex_syntax::Error::Translate(error)) => (
+            *error.span(),
+            error.kind().to_string(),
+            hir_hint(error.kind()),
+        ),
+        // regex_syntax::Error is non_exhaustive
+        Err(_) => return fallback.to_string(),
+    };
+
+    let mut message = what;
+    // A caret under a pattern with a line break in it would point at the
+    // wrong line, so those only get the description
+    if !pattern.contains('\n') {
+        let before = pattern[..span.start.offset].chars().count();
+        let width = pattern[span.start.offset..span.end.offset].chars().count();
+        let _ = write!(
+            message,
+            "\n    {}\n    {}{}",
+            pattern,
+            " ".repeat(before),
+            "^".repeat(width.max(1))
+        );
+    }
+    if let Some(hint) = hint {
+        let _ = write!(message, "\nhint: {}", hint);
+    }
+    if looks_literal(pattern) {
+        message.push_str("\nhint: to search for the text as it is, use -F");
+    }
+    message
+}
+
+/// How to fix the syntax errors people run into most
+fn ast_hint(kind: &AstErrorKind) -> Option<&'static str> {
+    Some(match kind {
+        AstErrorKind::GroupUnclosed => {
+            "this `(` is never closed; write `\\(` to match a literal parenthesis"
+        }
+        AstErrorKind::GroupUnopened => {
+            "this `)` closes a group that was never opened; write `\\)` to match a literal \
+             parenthesis"
+        }
+        AstErrorKind::ClassUnclosed => {
+            "this `[` starts a character class that is never closed; write `\\[` to match a \
+             literal bracket"
+        }
+        AstErrorKind::RepetitionMissing => {
+            "`*`, `+` and `?` repeat what comes before them, and nothing does here; write \
+             `\\*`, `\\+` or `\\?` to match the character itself"
+        }
+        AstErrorKind::EscapeUnrecognized => {
+            "this isn't an escape regex syntax knows; write `\\\\` to match a literal backslash"
+        }
+        AstErrorKind::EscapeUnexpectedEof => {
+            "the pattern ends in a lone `\\`; write `\\\\` to match a literal backslash"
+        }
+        AstErrorKind::UnsupportedLookAround => {
+            "grss's regex engine has no look-ahead or look-behind; match the surrounding text \
+             too, or filter with a second grss (`-v` for a negative look-around)"
+        }
+        AstErrorKind::UnsupportedBackreference => {
+            "grss's regex engine has no backreferences; to match a literal digit after a \
+             backslash, write `\\\\`"
+        }
+        _ => return None,
+    })
+}
+
+/// The same as ast_hint(), for the errors found after parsing
+fn hir_hint(kind: &HirErrorKind) -> Option<&'static str> {
+    match kind {
+        HirErrorKind::UnicodePropertyNotFound | HirErrorKind::UnicodePropertyValueNotFound => {
+            Some("`\\p{...}` takes a Unicode property like `L`, `Greek` or `Emoji`")
+        }
+        _ => None,
+    }
+}
+
+/// Whether `pattern` looks like it was meant as plain text: it has a `(`
+/// or `[` in it, which is what made it fail, and none of the other
+/// characters that only mean something in a regex
+fn looks_literal(pattern: &str) -> bool {
+    pattern.contains(['(', '['])
+        && !pattern.contains(['.', '*', '+', '?', '{', '|', '^', '$', '\\'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered(pattern: &str) -> String {
+        render(pattern, "(the regex crate's message)")
+    }
+
+    #[test]
+    fn unbalanced_parenthesis() {
+        assert_eq!(
+            rendered("foo(bar"),
+            "unclosed group\n    \
+             foo(bar\n       \
+             ^\n\
+             hint: this `(` is never closed; write `\\(` to match a literal parenthesis\n\
+             hint: to search for the text as it is, use -F"
+        );
+        assert_eq!(
+            rendered("a)b"),
+            "unopened group\n    \
+             a)b\n     \
+             ^\n\
+             hint: this `)` closes a group that was never opened; write `\\)` to match a \
+             literal parenthesis"
+        );
+    }
+
+    #[test]
+    fn unclosed_character_class() {
+        assert_eq!(
+            rendered("arr[0"),
+            "unclosed character class\n    \
+             arr[0\n       \
+             ^\n\
+             hint: this `[` starts a character class that is never closed; write `\\[` to \
+             match a literal bracket\n\
+             hint: to search for the text as it is, use -F"
+        );
+    }
+
+    #[test]
+    fn dangling_repetition() {
+        assert_eq!(
+            rendered("a|*b"),
+            "repetition operator missing expression\n    \
+             a|*b\n      \
+             ^\n\
+             hint: `*`, `+` and `?` repeat what comes before them, and nothing does here; \
+             write `\\*`, `\\+` or `\\?` to match the character itself"
+        );
+    }
+
+    #[test]
+    fn invalid_escape() {
+        assert_eq!(
+            rendered(r"C:\Qué"),
+            "unrecognized escape sequence\n    \
+             C:\\Qué\n      \
+             ^^\n\
+             hint: this isn't an escape regex syntax knows; write `\\\\` to match a literal \
+             backslash"
+        );
+    }
+
+    #[test]
+    fn carets_count_characters_not_bytes() {
+        assert_eq!(
+            rendered("é(ü"),
+            "unclosed group\n    \
+             é(ü\n     \
+             ^\n\
+             hint: this `(` is never closed; write `\\(` to match a literal parenthesis\n\
+             hint: to search for the text as it is, use -F"
+        );
+    }
+
+    #[test]
+    fn what_parses_falls_back_to_the_regex_message() {
+        assert_eq!(rendered("a{1000}{1000}"), "(the regex crate's message)");
+    }
+}
@@ -0,0 +1,188 @@
+// FilePath: rust/rust-cli/grss/src/preprocess.rs
+
+//! --pre: searching what a command makes of a file instead of the file
+//!
+//! `grss -r --pre 'pdftotext {} -' --pre-glob '*.pdf' invoice docs/` runs
+//! pdftotext on every PDF under docs/ and searches its output, so line
+//! numbers (and -b offsets) count lines of that output, not of the PDF. The
+//! `{}` in the command is replaced by the file's path; a command without one
+//! gets the path as its last argument, so `--pre rev` runs `rev FILE`.
+//!
+//! The command is run once per file and its whole output is collected
+//! before searching, so a command that fails halfway leaves no half-printed
+//! results behind: the file is skipped with a warning instead. Starting a
+//! process per file is slow next to reading the file, which is what
+//! --pre-glob is for.
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use grss::GrssError;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The --pre command and the --pre-glob globs saying which files it's for
+pub struct Preprocessor {
+    /// The command line, split up the way a shell would
+    words: Vec<String>,
+    /// Globs without a `/`, matched against the file name
+    names: GlobSet,
+    /// Globs with a `/`, matched against the whole path
+    paths: GlobSet,
+}
+
+impl Preprocessor {
+    /// Parses `command` and compiles `globs`; no globs at all means every
+    /// file goes through the command
+    pub fn new(command: &str, globs: &[String]) -> Result<Self, GrssError> {
+        let words = shell_words::split(command)
+            .map_err(|error| GrssError::Usage(format!("--pre '{}': {}", command, error)))?;
+        if words.is_empty() {
+            return Err(GrssError::Usage("--pre needs a command".to_string()));
+        }
+        let glob_error = |error: globset::Error| GrssError::InvalidGlob(error.to_string());
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for glob in globs {
+            if glob.contains('/') {
+                let glob = GlobBuilder::new(glob).literal_separator(true).build();
+                paths.add(glob.map_err(glob_error)?);
+            } else {
+                names.add(Glob::new(glob).map_err(glob_error)?);
+            }
+        }
+        Ok(Preprocessor {
+            words,
+            names: names.build().map_err(glob_error)?,
+            paths: paths.build().map_err(glob_error)?,
+        })
+    }
+
+    /// Whether the file at `path` is searched through the command
+    pub fn applies_to(&self, path: &Path) -> bool {
+        if self.names.is_empty() && self.paths.is_empty() {
+            return true;
+        }
+        path.file_name()
+            .is_some_and(|name| self.names.is_match(Path::new(name)))
+            || self.paths.is_match(path)
+    }
+
+    /// Runs the command on the file at `path`
+    ///
+    /// # Returns
+    /// Everything the command wrote to its stdout, or an error when it
+    /// couldn't be started or exited with a failure, which then has the
+    /// first line it wrote to stderr in it
+    pub fn run(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let words = command_line(&self.words, path.as_os_str());
+        let program = &words[0];
+        let output = Command::new(program)
+            .args(&words[1..])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|error| {
+                io::Error::new(error.kind(), format!("{}: {}", program.display(), error))
+            })?;
+        if output.status.success() {
+            return Ok(output.stdout);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!("{} {}", program.display(), output.status);
+        if let Some(line) = stderr.lines().find(|line| !line.trim().is_empty()) {
+            message = format!("{} ({})", message, line.trim());
+        }
+        Err(io::Error::other(message))
+    }
+}
+
+/// `words` with every `{}` replaced by `path`, or with `path` added at the
+/// end when there's no `{}` anywhere
+///
+/// The path goes in as the OsStr it is, so a file name that isn't UTF-8
+/// still names the file.
+fn command_line(words: &[String], path: &OsStr) -> Vec<OsString> {
+    if !words.iter().any(|word| word.contains("{}")) {
+        let mut words: Vec<OsString> = words.iter().map(OsString::from).collect();
+        words.push(path.to_owned());
+        return words;
+    }
+    words
+        .iter()
+        .map(|word| {
+            let mut replaced = OsString::new();
+            for (index, piece) in word.split("{}").enumerate() {
+                if index > 0 {
+                    replaced.push(path);
+                }
+                replaced.push(piece);
+            }
+            replaced
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        shell_words::split(line).unwrap()
+    }
+
+    #[test]
+    fn path_replaces_braces_or_goes_last() {
+        assert_eq!(
+            command_line(&words("pdftotext {} -"), OsStr::new("docs/a b.pdf")),
+            ["pdftotext", "docs/a b.pdf", "-"]
+        );
+        assert_eq!(
+            command_line(&words("rev"), OsStr::new("a.txt")),
+            ["rev", "a.txt"]
+        );
+        assert_eq!(
+            command_line(&words("unzip -p '{}' --"), OsStr::new("x.zip")),
+            ["unzip", "-p", "x.zip", "--"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_that_arent_utf8_go_in_as_they_are() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = OsStr::from_bytes(b"caf\xE9.pdf");
+        let line = command_line(&words("pdftotext {} -"), path);
+        assert_eq!(line[1].as_bytes(), b"caf\xE9.pdf");
+        let line = command_line(&words("sh -c 'cat {}.{}'"), path);
+        assert_eq!(line[2].as_bytes(), b"cat caf\xE9.pdf.caf\xE9.pdf");
+    }
+
+    #[test]
+    fn pre_glob_picks_the_files() {
+        let every = Preprocessor::new("cat", &[]).unwrap();
+        assert!(every.applies_to(Path::new("src/main.rs")));
+
+        let globs = ["*.pdf".to_string(), "docs/**/*.odt".to_string()];
+        let some = Preprocessor::new("cat", &globs).unwrap();
+        assert!(some.applies_to(Path::new("deep/in/invoice.pdf")));
+        assert!(some.applies_to(Path::new("docs/2024/notes.odt")));
+        assert!(!some.applies_to(Path::new("notes.odt")));
+        assert!(!some.applies_to(Path::new("invoice.pdf.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_command_that_fails_is_an_error() {
+        let failing = Preprocessor::new("sh -c 'echo no such thing >&2; exit 3' sh", &[]).unwrap();
+        let error = failing.run(Path::new("a.txt")).unwrap_err();
+        assert_eq!(error.to_string(), "sh exit status: 3 (no such thing)");
+
+        let missing = Preprocessor::new("grss-no-such-preprocessor", &[]).unwrap();
+        assert_eq!(
+            missing.run(Path::new("a.txt")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(Preprocessor::new("", &[]).is_err());
+    }
+}
@@ -0,0 +1,1169 @@
+// FilePath: rust/rust-cli/grss/src/printer.rs
+
+//! Formatting what the search found
+//!
+//! A Printer writes to any `Write`: stdout when grss runs for real, a
+//! Vec<u8> per file when searching a directory in parallel, or a Vec<u8> in
+//! the tests below, which can then check the exact bytes.
+//!
+//! Lines come in as the bytes they are in the file, and this is the one
+//! place they're turned into text: whatever isn't valid UTF-8 in them is
+//! written as U+FFFD, the same way paths are written with Path::display()
+//! (except with --null, see path_bytes()).
+//! Byte offsets and the ranges of matches all still count the bytes in the
+//! file, columns count characters as they're written.
+
+use crate::colors::{Colors, RESET};
+use crate::format::{Field, Format, Part};
+use grss::LineKind;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Turns bold on, on top of the path's color, for --heading
+const BOLD: &str = "\x1b[1m";
+
+/// How many characters of a binary file's line get printed (see
+/// Printer::binary()) when --max-columns doesn't say
+const BINARY_MAX_COLUMNS: usize = 500;
+
+/// What a binary file's NUL bytes are written as: the Unicode symbol for
+/// NUL, one character like the byte, so columns still line up
+const NUL_MARKER: &str = "\u{2400}";
+
+/// How output lines should look, worked out once from the command line
+pub struct PrintOptions {
+    /// -n: put the line number in front of each line
+    pub line_number: bool,
+    /// --column: put the column of the first match in front of selected
+    /// lines, after the line number
+    pub column: bool,
+    /// -b: put the byte offset of the line in front of it, after the line
+    /// number (and column)
+    pub byte_offset: bool,
+    /// The escape codes to color the output with, None when color is off
+    pub colors: Option<Colors>,
+    /// --null: follow file names with a NUL byte
+    pub null: bool,
+    /// Whether context lines are on, which is when non-adjacent groups of
+    /// lines get a `--` between them
+    pub context: bool,
+    /// --line-buffered: flush after every line, so it's out right away
+    /// instead of when the buffer is full
+    pub line_buffered: bool,
+    /// --null-data: end lines (records, really) with a NUL byte instead of
+    /// a newline, like the input's
+    pub null_data: bool,
+    /// --heading: the path goes on a line of its own above a file's lines
+    /// instead of in front of every one of them
+    pub heading: bool,
+    /// -H / -h: whether to show the path at all, in front of the lines or
+    /// as their heading
+    pub with_filename: WithFilename,
+    /// What goes on the line between groups of lines with context on, `--`
+    /// unless --context-separator says otherwise, None for
+    /// --no-context-separator
+    pub context_separator: Option<Vec<u8>>,
+    /// --max-columns: lines longer than this many characters aren't printed
+    /// as they are
+    pub max_columns: Option<usize>,
+    /// --max-columns-preview: a line that's too long is cut off at
+    /// max_columns instead of left out altogether
+    pub max_columns_preview: bool,
+    /// --trim: leave out the spaces and tabs a line starts with
+    pub trim: bool,
+    /// --format: selected lines are laid out by this template instead
+    pub format: Option<Format>,
+    /// --csv: selected lines are written as CSV records instead
+    pub csv: bool,
+    /// --json: selected lines are written as JSON objects instead
+    pub json: bool,
+}
+
+impl PrintOptions {
+    /// Writes what sets one file's lines apart from the lines of the file
+    /// before it: a blank line between --heading groups, or with context on
+    /// the context separator, like grep puts between files
+    pub fn write_file_separator<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.heading && self.with_filename == WithFilename::Always {
+            self.write_terminator(out)
+        } else {
+            self.write_context_separator(out)
+        }
+    }
+
+    /// Writes the context separator on a line of its own, if context is on
+    /// and there is a separator
+    fn write_context_separator<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match &self.context_separator {
+            Some(separator) if self.context => {
+                out.write_all(separator)?;
+                self.write_terminator(out)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the header row of --csv, naming the fields of
+    /// Printer::csv_records()
+    pub fn write_csv_header<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut writer = csv::Writer::from_writer(out);
+        writer.write_record(["path", "line", "column", "text"])?;
+        writer.flush()
+    }
+
+    /// Ends a line (or, with --null-data, a record)
+    fn write_terminator<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(if self.null_data { b"\0" } else { b"\n" })
+    }
+}
+
+/// Whether output lines (and -c counts) start with the path of the file they
+/// came from, decided once for the whole search
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WithFilename {
+    /// `path:` in front: -H, or when more than one file is searched
+    Always,
+    /// Just the line: -h, or when there's only the one file
+    #[default]
+    Never,
+}
+
+/// A selected line as --json writes it
+#[derive(Serialize)]
+#[serde(tag = "type", rename = "match")]
+struct JsonLine<'t> {
+    /// None (null) for standard input
+    path: Option<Cow<'t, str>>,
+    line_number: usize,
+    offset: usize,
+    text: Cow<'t, str>,
+    matches: Vec<JsonMatch>,
+}
+
+/// Where one match in a --json line is: `start` and `end` in bytes, like
+/// the offset, and `column` in characters, like --column
+#[derive(Serialize)]
+struct JsonMatch {
+    start: usize,
+    end: usize,
+    column: usize,
+}
+
+/// Writes the output for one input (one file, or stdin) to `out`
+pub struct Printer<'a, W> {
+    out: W,
+    options: &'a PrintOptions,
+    /// The path to put in front of each line, None when there's none or
+    /// with_filename leaves it out
+    path: Option<&'a Path>,
+    /// The path whatever with_filename says, for --format's {path} and for
+    /// --csv and --json
+    source: Option<&'a Path>,
+    /// Number of the last line written out, so we know whether the next one
+    /// follows on directly or needs a `--` separator in between
+    last_printed: Option<usize>,
+    /// Whether nothing was written yet, so the next line is the first and
+    /// the heading and file separator still have to come before it
+    first_line_pending: bool,
+    /// Set once any file's lines were written, so the ones after it get a
+    /// separator (see with_file_separator())
+    files_written: Option<&'a AtomicBool>,
+    /// Whether the input is a binary file searched with --text (see
+    /// binary())
+    binary: bool,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    /// A Printer for the input at `path`, None for one without a path to
+    /// show
+    pub fn new(out: W, options: &'a PrintOptions, path: Option<&'a Path>) -> Self {
+        let source = path;
+        let path = path.filter(|_| options.with_filename == WithFilename::Always);
+        Printer {
+            out,
+            options,
+            path,
+            source,
+            last_printed: None,
+            first_line_pending: true,
+            files_written: None,
+            binary: false,
+        }
+    }
+
+    /// Puts the file separator (see PrintOptions::write_file_separator())
+    /// in front of the first line if `files_written` says another file's
+    /// lines came before it, and sets it
+    ///
+    /// Only for a Printer writing straight to the output. Files searched in
+    /// parallel each get their own buffer and are only put in order later,
+    /// so whoever does that adds the separators instead.
+    pub fn with_file_separator(mut self, files_written: &'a AtomicBool) -> Self {
+        self.files_written = Some(files_written);
+        self
+    }
+
+    /// Prints the lines of a binary file (one --text searches anyway) so
+    /// they can't mess up the terminal: NUL bytes are written as `␀`, and
+    /// lines are cut off after BINARY_MAX_COLUMNS characters unless
+    /// --max-columns says where
+    pub fn binary(mut self) -> Self {
+        self.binary = true;
+        self
+    }
+
+    /// Writes a selected or context line (numbered `number`, starting
+    /// `offset` bytes into the input), highlighting the `matches` ranges in
+    /// it when color is on
+    ///
+    /// Selected lines look like `file:13:text` and context lines like
+    /// `file-12-text`, so the two can be told apart
+    pub fn line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        kind: LineKind,
+        line: &[u8],
+        matches: &[Range<usize>],
+    ) -> io::Result<()> {
+        self.write_text_line(number, offset, kind, line, matches, 1)
+    }
+
+    /// Writes a selected line like line() does, that came `times` times in
+    /// the file (--count-duplicates), with ` (xN)` after it if that's more
+    /// than once
+    pub fn repeated_line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &[u8],
+        matches: &[Range<usize>],
+        times: usize,
+    ) -> io::Result<()> {
+        self.write_text_line(number, offset, LineKind::Selected, line, matches, times)
+    }
+
+    /// Does the writing for line() and repeated_line()
+    fn write_text_line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        kind: LineKind,
+        line: &[u8],
+        matches: &[Range<usize>],
+        times: usize,
+    ) -> io::Result<()> {
+        self.write_file_start()?;
+        // With context on, groups of lines that don't follow on from what
+        // was printed last get a `--` line between them, like grep
+        if let Some(last) = self.last_printed
+            && number > last + 1
+        {
+            self.options.write_context_separator(&mut self.out)?;
+        }
+        let separator = match kind {
+            LineKind::Selected => ':',
+            LineKind::Context => '-',
+        };
+        // Only selected lines have a match to give the column of. A line
+        // selected by -v has none in it, and gets column 1.
+        let column = (self.options.column && kind == LineKind::Selected)
+            .then(|| column(line, matches.first().map_or(0, |first| first.start)));
+        self.write_prefix(number, column, offset, separator)?;
+        // The column and offset are the whole line's, only the text shown
+        // loses its indentation
+        let (line, matches) = match self.options.trim {
+            true => {
+                let (line, matches) = trim_start(line, matches);
+                (line, Cow::Owned(matches))
+            }
+            false => (line, Cow::Borrowed(matches)),
+        };
+        // Where a line too long for --max-columns has to be cut, which is
+        // after max_columns characters. A binary file's lines are cut even
+        // without it, since one of them can be the whole file.
+        let (max_columns, preview) = match self.options.max_columns {
+            Some(max) => (Some(max), self.options.max_columns_preview),
+            None if self.binary => (Some(BINARY_MAX_COLUMNS), true),
+            None => (None, false),
+        };
+        let cut = max_columns.and_then(|max| char_start(line, max));
+        match cut {
+            None => self.write_line(line, &matches)?,
+            Some(at) if preview => {
+                // Cut before the color codes go in, so none of them is cut
+                // in half and every match that's left is still closed
+                let kept: Vec<Range<usize>> = matches
+                    .iter()
+                    .filter(|range| range.start < at)
+                    .map(|range| range.start..range.end.min(at))
+                    .collect();
+                self.write_line(&line[..at], &kept)?;
+                write!(self.out, " [... omitted]")?;
+            }
+            Some(_) => match kind {
+                LineKind::Selected => write!(self.out, "[Omitted long matching line]")?,
+                LineKind::Context => write!(self.out, "[Omitted long context line]")?,
+            },
+        }
+        if times > 1 {
+            write!(self.out, " (x{})", times)?;
+        }
+        self.write_terminator()?;
+        self.last_printed = Some(number);
+        self.end_line()
+    }
+
+    /// Writes each of the `matches` ranges in a selected line on a line of
+    /// its own for -o, with the prefix repeated for every one
+    ///
+    /// Empty ranges are skipped: a pattern like `a*` matches nothing at every
+    /// position, and printing all of those would only give blank lines.
+    /// With -b the offset is that of the match, not of the line.
+    pub fn matches(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &[u8],
+        matches: &[Range<usize>],
+    ) -> io::Result<()> {
+        for range in matches.iter().filter(|range| !range.is_empty()) {
+            self.write_file_start()?;
+            let column = self.options.column.then(|| column(line, range.start));
+            self.write_prefix(number, column, offset + range.start, ':')?;
+            self.write_colored(|colors| &colors.matched, &self.text(&line[range.clone()]))?;
+            self.write_terminator()?;
+            self.end_line()?;
+        }
+        Ok(())
+    }
+
+    /// Writes a selected line laid out by `format` (--format), once for
+    /// each of the `matches` ranges in it if the format has a {match} or
+    /// {column}, or else once
+    ///
+    /// Empty ranges are skipped like with -o, but a line with nothing else
+    /// (selected by -v, say) is still written once, with an empty {match}.
+    /// Nothing is cut or trimmed, and there are no headings: the template
+    /// says exactly what goes on the line.
+    pub fn formatted(
+        &mut self,
+        format: &Format,
+        number: usize,
+        offset: usize,
+        line: &[u8],
+        matches: &[Range<usize>],
+    ) -> io::Result<()> {
+        let mut each = matches.iter().filter(|range| !range.is_empty()).peekable();
+        if !format.per_match() || each.peek().is_none() {
+            return self.write_formatted(format, number, offset, line, matches, None);
+        }
+        for range in each {
+            self.write_formatted(format, number, offset, line, matches, Some(range))?;
+        }
+        Ok(())
+    }
+
+    /// Writes one output line for formatted(), about the match at `range`
+    /// in `line`, or about the whole line without one
+    fn write_formatted(
+        &mut self,
+        format: &Format,
+        number: usize,
+        offset: usize,
+        line: &[u8],
+        matches: &[Range<usize>],
+        range: Option<&Range<usize>>,
+    ) -> io::Result<()> {
+        let start = range.map_or(0, |range| range.start);
+        for part in format.parts() {
+            match part {
+                Part::Text(text) => self.out.write_all(text)?,
+                Part::Field(Field::Path) => {
+                    let path = self.source.map(|path| path.display().to_string());
+                    self.write_colored(|colors| &colors.path, path.as_deref().unwrap_or(""))?
+                }
+                Part::Field(Field::Line) => {
+                    self.write_colored(|colors| &colors.line_number, &number.to_string())?
+                }
+                Part::Field(Field::Column) => write!(self.out, "{}", column(line, start))?,
+                Part::Field(Field::Offset) => write!(self.out, "{}", offset + start)?,
+                Part::Field(Field::Text) => self.write_line(line, matches)?,
+                Part::Field(Field::Match) => {
+                    let matched = range.map_or(&[][..], |range| &line[range.clone()]);
+                    self.write_colored(|colors| &colors.matched, &self.text(matched))?
+                }
+            }
+        }
+        self.write_terminator()?;
+        self.end_line()
+    }
+
+    /// Writes a CSV record (--csv) for each of the `matches` ranges in a
+    /// selected line: the path, the line number, the column of the match
+    /// and the whole line
+    ///
+    /// Empty ranges are skipped like with -o, but a line with nothing else
+    /// (selected by -v, say) still gets a record, with the column of its
+    /// empty match, or 1.
+    pub fn csv_records(
+        &mut self,
+        number: usize,
+        line: &[u8],
+        matches: &[Range<usize>],
+    ) -> io::Result<()> {
+        let mut columns: Vec<usize> = matches
+            .iter()
+            .filter(|range| !range.is_empty())
+            .map(|range| column(line, range.start))
+            .collect();
+        if columns.is_empty() {
+            columns.push(column(line, matches.first().map_or(0, |first| first.start)));
+        }
+        let path = self.source.map(|path| path.display().to_string());
+        let number = number.to_string();
+        let line = self.text(line);
+        let mut writer = csv::Writer::from_writer(&mut self.out);
+        for column in columns {
+            let path = path.as_deref().unwrap_or("");
+            writer.write_record([path, &number, &column.to_string(), &line])?;
+        }
+        writer.flush()?;
+        drop(writer);
+        self.end_line()
+    }
+
+    /// Writes a selected line as a JSON object (--json) on a line of its
+    /// own, with where each of its `matches` is
+    pub fn json_record(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &[u8],
+        matches: &[Range<usize>],
+    ) -> io::Result<()> {
+        let record = JsonLine {
+            path: self.source.map(|path| path.to_string_lossy()),
+            line_number: number,
+            offset,
+            text: String::from_utf8_lossy(line),
+            matches: matches
+                .iter()
+                .map(|range| JsonMatch {
+                    start: range.start,
+                    end: range.end,
+                    column: column(line, range.start),
+                })
+                .collect(),
+        };
+        serde_json::to_writer(&mut self.out, &record)?;
+        self.out.write_all(b"\n")?;
+        self.end_line()
+    }
+
+    /// Writes the number of selected lines for -c (or of matches for
+    /// --count-matches), after the path if there is one
+    pub fn count(&mut self, count: usize) -> io::Result<()> {
+        if let Some(path) = self.path {
+            self.write_path(path, ':')?;
+        }
+        writeln!(self.out, "{}", count)?;
+        self.end_line()
+    }
+
+    /// Writes the number of a selected line on a line of its own for
+    /// --only-line-numbers, after the path if there is one, like count();
+    /// ended like the records are with --null-data
+    pub fn line_number(&mut self, number: usize) -> io::Result<()> {
+        if let Some(path) = self.path {
+            self.write_path(path, ':')?;
+        }
+        self.write_colored(|colors| &colors.line_number, &number.to_string())?;
+        self.write_terminator()?;
+        self.end_line()
+    }
+
+    /// Writes `name` on a line of its own for -l, -L and --files, ended
+    /// with a NUL byte instead of a newline with --null
+    pub fn file_name(&mut self, name: &Path) -> io::Result<()> {
+        self.out.write_all(&path_bytes(name, self.options.null))?;
+        self.out
+            .write_all(if self.options.null { b"\0" } else { b"\n" })?;
+        self.end_line()
+    }
+
+    /// Called after every complete line (or file name) written
+    fn end_line(&mut self) -> io::Result<()> {
+        match self.options.line_buffered {
+            true => self.out.flush(),
+            false => Ok(()),
+        }
+    }
+
+    /// Writes the `path:`, `line:`, `column:` and `offset:` parts in front
+    /// of an output line, when they're wanted
+    fn write_prefix(
+        &mut self,
+        number: usize,
+        column: Option<usize>,
+        offset: usize,
+        separator: char,
+    ) -> io::Result<()> {
+        if let Some(path) = self.path
+            && !self.options.heading
+        {
+            self.write_path(path, separator)?;
+        }
+        if self.options.line_number {
+            self.write_colored(|colors| &colors.line_number, &number.to_string())?;
+            write!(self.out, "{}", separator)?;
+        }
+        if let Some(column) = column {
+            write!(self.out, "{}{}", column, separator)?;
+        }
+        if self.options.byte_offset {
+            write!(self.out, "{}{}", offset, separator)?;
+        }
+        Ok(())
+    }
+
+    /// Writes what comes before the first line: the file separator if
+    /// there were lines from another file before, and the --heading line
+    fn write_file_start(&mut self) -> io::Result<()> {
+        if !mem::take(&mut self.first_line_pending) {
+            return Ok(());
+        }
+        if let Some(written) = self.files_written
+            && written.swap(true, Ordering::Relaxed)
+        {
+            self.options.write_file_separator(&mut self.out)?;
+        }
+        let Some(path) = self.path.filter(|_| self.options.heading) else {
+            return Ok(());
+        };
+        let path = path.display();
+        match &self.options.colors {
+            Some(colors) => write!(self.out, "{}{}{}{}", BOLD, colors.path, path, RESET)?,
+            None => write!(self.out, "{}", path)?,
+        }
+        self.write_terminator()
+    }
+
+    /// Writes `path` and the separator after it, which is a NUL byte with
+    /// --null whatever `separator` is
+    fn write_path(&mut self, path: &Path, separator: char) -> io::Result<()> {
+        let name = path_bytes(path, self.options.null);
+        match &self.options.colors {
+            Some(colors) => {
+                write!(self.out, "{}", colors.path)?;
+                self.out.write_all(&name)?;
+                write!(self.out, "{}", RESET)?;
+            }
+            None => self.out.write_all(&name)?,
+        }
+        if self.options.null {
+            self.out.write_all(b"\0")
+        } else {
+            write!(self.out, "{}", separator)
+        }
+    }
+
+    /// Writes `text`, wrapped in the escape codes `color` picks out of the
+    /// colors when color is on
+    fn write_colored(&mut self, color: fn(&Colors) -> &str, text: &str) -> io::Result<()> {
+        match &self.options.colors {
+            Some(colors) => write!(self.out, "{}{}{}", color(colors), text, RESET),
+            None => write!(self.out, "{}", text),
+        }
+    }
+
+    /// Writes a line, without its newline, highlighting each of the
+    /// `matches` ranges when color is on
+    fn write_line(&mut self, line: &[u8], matches: &[Range<usize>]) -> io::Result<()> {
+        let Some(colors) = &self.options.colors else {
+            // Exactly what grss printed before colors existed
+            return write!(self.out, "{}", self.text(line));
+        };
+
+        // Copy the plain text between matches as-is and wrap each match in
+        // color codes. `last` is where the previous match ended.
+        let mut last = 0;
+        for range in matches.iter().filter(|range| !range.is_empty()) {
+            write!(self.out, "{}", self.text(&line[last..range.start]))?;
+            write!(
+                self.out,
+                "{}{}{}",
+                colors.matched,
+                self.text(&line[range.clone()]),
+                RESET
+            )?;
+            last = range.end;
+        }
+        write!(self.out, "{}", self.text(&line[last..]))
+    }
+
+    /// `bytes` as text, with U+FFFD in place of every piece that isn't
+    /// UTF-8, and in a binary file `␀` in place of every NUL byte; borrowed
+    /// when there's nothing to replace, which there usually isn't
+    fn text<'t>(&self, bytes: &'t [u8]) -> Cow<'t, str> {
+        let text = String::from_utf8_lossy(bytes);
+        if self.binary && text.contains('\0') {
+            return Cow::Owned(text.replace('\0', NUL_MARKER));
+        }
+        text
+    }
+
+    /// Ends a line: with a newline, or with --null-data a NUL
+    fn write_terminator(&mut self) -> io::Result<()> {
+        self.options.write_terminator(&mut self.out)
+    }
+}
+
+/// `line` without the spaces and tabs it starts with, for --trim, and
+/// `matches` moved along with the text
+///
+/// A match (or the part of one) inside the indentation that's left out goes
+/// with it.
+fn trim_start<'l>(line: &'l [u8], matches: &[Range<usize>]) -> (&'l [u8], Vec<Range<usize>>) {
+    let shift = line
+        .iter()
+        .position(|byte| !matches!(byte, b' ' | b'\t'))
+        .unwrap_or(line.len());
+    let trimmed = &line[shift..];
+    let matches = matches
+        .iter()
+        .filter(|range| range.end > shift)
+        .map(|range| range.start.max(shift) - shift..range.end - shift)
+        .collect();
+    (trimmed, matches)
+}
+
+/// How `path` is written: with --null (`null`) as the bytes it is, since a
+/// program reads it back then and needs the exact path, and otherwise the
+/// way Path::display() shows it
+///
+/// Only Unix paths are bytes. Elsewhere --null paths are shown too.
+#[cfg(unix)]
+fn path_bytes(path: &Path, null: bool) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    match null {
+        true => Cow::Borrowed(path.as_os_str().as_bytes()),
+        false => shown(path),
+    }
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path, _null: bool) -> Cow<'_, [u8]> {
+    shown(path)
+}
+
+/// `path` the way Path::display() shows it, U+FFFD for what isn't UTF-8
+fn shown(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+        Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+    }
+}
+
+/// The 1-based column at byte `at` in `line`, for --column and the regions
+/// of --sarif
+///
+/// Columns count characters, so an emoji or an `é` before the match is one
+/// column rather than the 4 or 2 bytes it takes. A tab is one column too,
+/// however wide the terminal shows it, and so is the U+FFFD written for
+/// bytes that aren't UTF-8.
+pub fn column(line: &[u8], at: usize) -> usize {
+    characters(&line[..at]).count() + 1
+}
+
+/// Where the character after the first `n` of `line` starts, for cutting a
+/// line at --max-columns
+///
+/// # Returns
+/// None when the line isn't any longer than that
+fn char_start(line: &[u8], n: usize) -> Option<usize> {
+    characters(line).nth(n)
+}
+
+/// Where each character of `line` starts, counting what's written as one
+/// U+FFFD (see text()) as one character
+fn characters(line: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    line.utf8_chunks()
+        .scan(0, |start, chunk| {
+            let valid = *start;
+            let invalid = valid + chunk.valid().len();
+            *start = invalid + chunk.invalid().len();
+            let replaced = (!chunk.invalid().is_empty()).then_some(invalid);
+            Some(
+                chunk
+                    .valid()
+                    .char_indices()
+                    .map(move |(at, _)| valid + at)
+                    .chain(replaced),
+            )
+        })
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> PrintOptions {
+        PrintOptions {
+            line_number: false,
+            column: false,
+            byte_offset: false,
+            colors: None,
+            null: false,
+            context: false,
+            line_buffered: false,
+            null_data: false,
+            heading: false,
+            with_filename: WithFilename::Always,
+            context_separator: Some(b"--".to_vec()),
+            max_columns: None,
+            max_columns_preview: false,
+            trim: false,
+            format: None,
+            csv: false,
+            json: false,
+        }
+    }
+
+    /// Runs `print` against a Printer writing into a Vec, and gives back
+    /// what it wrote
+    fn printed(
+        options: &PrintOptions,
+        path: Option<&Path>,
+        print: impl FnOnce(&mut Printer<&mut Vec<u8>>) -> io::Result<()>,
+    ) -> String {
+        let mut out = Vec::new();
+        print(&mut Printer::new(&mut out, options, path)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn plain_lines_are_written_as_they_are() {
+        let output = printed(&options(), None, |printer| {
+            printer.line(3, 0, LineKind::Selected, b"foo bar foo", &[0..3, 8..11])
+        });
+        assert_eq!(output, "foo bar foo\n");
+    }
+
+    #[test]
+    fn path_and_line_number_prefixes() {
+        let options = PrintOptions {
+            line_number: true,
+            ..options()
+        };
+        let output = printed(&options, Some(Path::new("src/a.rs")), |printer| {
+            printer.line(12, 0, LineKind::Context, b"before", &[])?;
+            printer.line(13, 0, LineKind::Selected, b"match", &[])
+        });
+        assert_eq!(output, "src/a.rs-12-before\nsrc/a.rs:13:match\n");
+    }
+
+    #[test]
+    fn byte_offsets_come_after_line_numbers() {
+        let options = PrintOptions {
+            line_number: true,
+            byte_offset: true,
+            ..options()
+        };
+        let output = printed(&options, None, |printer| {
+            printer.line(2, 6, LineKind::Context, b"before", &[])?;
+            printer.line(3, 13, LineKind::Selected, b"match", &[])
+        });
+        assert_eq!(output, "2-6-before\n3:13:match\n");
+    }
+
+    #[test]
+    fn columns_count_characters_not_bytes() {
+        let options = PrintOptions {
+            line_number: true,
+            column: true,
+            ..options()
+        };
+        // 🦀 is 4 bytes, é 2 and the tab 1: `bug` starts at byte 9, but
+        // it's the 6th character
+        let line = "🦀é\tx bug bug";
+        let output = printed(&options, Some(Path::new("a.rs")), |printer| {
+            printer.line(6, 0, LineKind::Context, b"before", &[])?;
+            printer.line(7, 0, LineKind::Selected, line.as_bytes(), &[9..12, 13..16])?;
+            printer.line(8, 0, LineKind::Selected, b"inverted", &[])?;
+            printer.matches(7, 0, line.as_bytes(), &[9..12, 13..16])
+        });
+        assert_eq!(
+            output,
+            "a.rs-6-before\na.rs:7:6:🦀é\tx bug bug\na.rs:8:1:inverted\n\
+             a.rs:7:6:bug\na.rs:7:10:bug\n"
+        );
+    }
+
+    #[test]
+    fn separator_between_groups_only_with_context() {
+        let print = |printer: &mut Printer<&mut Vec<u8>>| {
+            printer.line(1, 0, LineKind::Selected, b"a", &[])?;
+            printer.line(2, 0, LineKind::Context, b"b", &[])?;
+            printer.line(5, 0, LineKind::Selected, b"c", &[])
+        };
+        assert_eq!(printed(&options(), None, print), "a\nb\nc\n");
+
+        let options = PrintOptions {
+            context: true,
+            ..options()
+        };
+        assert_eq!(printed(&options, None, print), "a\nb\n--\nc\n");
+
+        let null_data = PrintOptions {
+            null_data: true,
+            ..options
+        };
+        assert_eq!(printed(&null_data, None, print), "a\0b\0--\0c\0");
+
+        let custom = PrintOptions {
+            context_separator: Some(b"\t~".to_vec()),
+            ..null_data
+        };
+        assert_eq!(printed(&custom, None, print), "a\0b\0\t~\0c\0");
+        let none = PrintOptions {
+            context_separator: None,
+            ..custom
+        };
+        assert_eq!(printed(&none, None, print), "a\0b\0c\0");
+    }
+
+    #[test]
+    fn files_are_separated_only_between_them() {
+        let context = PrintOptions {
+            context: true,
+            context_separator: Some(b"==".to_vec()),
+            ..options()
+        };
+        let print = |options: &PrintOptions| {
+            let written = AtomicBool::new(false);
+            let mut out = Vec::new();
+            for (path, lines) in [("a", &["x"][..]), ("empty", &[]), ("b", &["y"])] {
+                let mut printer = Printer::new(&mut out, options, Some(Path::new(path)))
+                    .with_file_separator(&written);
+                for line in lines {
+                    printer
+                        .line(1, 0, LineKind::Selected, line.as_bytes(), &[])
+                        .unwrap();
+                }
+            }
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(print(&context), "a:x\n==\nb:y\n");
+        // Without context lines, or a separator, files follow on directly
+        assert_eq!(print(&options()), "a:x\nb:y\n");
+        let none = PrintOptions {
+            context_separator: None,
+            ..context
+        };
+        assert_eq!(print(&none), "a:x\nb:y\n");
+    }
+
+    #[test]
+    fn trim_shifts_highlights_but_not_columns() {
+        let options = PrintOptions {
+            column: true,
+            colors: Some(Colors::default()),
+            trim: true,
+            ..options()
+        };
+        let output = printed(&options, None, |printer| {
+            // A match partly inside the indentation keeps the rest of it
+            printer.line(1, 0, LineKind::Selected, b" \t  x = foo", &[2..5, 8..11])?;
+            printer.line(2, 0, LineKind::Context, b"\t\tbar", &[])
+        });
+        assert_eq!(output, "3:\x1b[1;31mx\x1b[0m = \x1b[1;31mfoo\x1b[0m\nbar\n");
+    }
+
+    #[test]
+    fn max_columns_leaves_out_or_cuts_long_lines() {
+        let limited = PrintOptions {
+            line_number: true,
+            max_columns: Some(8),
+            ..options()
+        };
+        let long = "0123456789 needle needle";
+        let print = |options: &PrintOptions| {
+            printed(options, None, |printer| {
+                printer.line(1, 0, LineKind::Selected, long.as_bytes(), &[11..17, 18..24])?;
+                printer.line(2, 0, LineKind::Context, long.as_bytes(), &[])?;
+                printer.line(3, 0, LineKind::Selected, b"12345678", &[])
+            })
+        };
+        // Exactly max_columns characters is still short enough
+        assert_eq!(
+            print(&limited),
+            "1:[Omitted long matching line]\n2-[Omitted long context line]\n3:12345678\n"
+        );
+
+        // The matches are past the cut, so nothing of them is left
+        let preview = PrintOptions {
+            max_columns_preview: true,
+            ..limited
+        };
+        assert_eq!(
+            print(&preview),
+            "1:01234567 [... omitted]\n2-01234567 [... omitted]\n3:12345678\n"
+        );
+
+        // Characters, not bytes: 🦀 is four bytes and é two
+        let output = printed(&preview, None, |printer| {
+            printer.line(1, 0, LineKind::Selected, "🦀é🦀é🦀é🦀é🦀é".as_bytes(), &[])
+        });
+        assert_eq!(output, "1:🦀é🦀é🦀é🦀é [... omitted]\n");
+    }
+
+    #[test]
+    fn max_columns_cuts_before_the_color_codes_go_in() {
+        let options = PrintOptions {
+            colors: Some(Colors::default()),
+            max_columns: Some(5),
+            max_columns_preview: true,
+            ..options()
+        };
+        // The second match straddles the cut and the third is past it
+        let output = printed(&options, None, |printer| {
+            printer.line(
+                1,
+                0,
+                LineKind::Selected,
+                "ab ééé ab".as_bytes(),
+                &[0..2, 3..9, 11..13],
+            )
+        });
+        assert_eq!(
+            output,
+            "\x1b[1;31mab\x1b[0m \x1b[1;31méé\x1b[0m [... omitted]\n"
+        );
+    }
+
+    #[test]
+    fn binary_lines_show_nuls_and_get_cut() {
+        let binary = |options: &PrintOptions, line: &[u8], matches: &[Range<usize>]| {
+            let mut out = Vec::new();
+            let mut printer = Printer::new(&mut out, options, None).binary();
+            printer
+                .line(1, 0, LineKind::Selected, line, matches)
+                .unwrap();
+            printer.matches(1, 0, line, matches).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            binary(&options(), b"\0\0a\0b\0", &[0..1, 2..5]),
+            "\u{2400}\u{2400}a\u{2400}b\u{2400}\n\u{2400}\na\u{2400}b\n"
+        );
+
+        // Cut after BINARY_MAX_COLUMNS characters, a NUL being one
+        let long = [b"\0".repeat(BINARY_MAX_COLUMNS), b"needle".to_vec()].concat();
+        let cut = format!("{} [... omitted]\n", "\u{2400}".repeat(BINARY_MAX_COLUMNS));
+        assert_eq!(binary(&options(), &long, &[]), cut);
+
+        // --max-columns still has the last word
+        let limited = PrintOptions {
+            max_columns: Some(4),
+            ..options()
+        };
+        assert_eq!(
+            binary(&limited, b"ab\0needle", &[0..2, 3..9]),
+            "[Omitted long matching line]\nab\nneedle\n"
+        );
+
+        // Only a binary file's NULs are replaced
+        let output = printed(&options(), None, |printer| {
+            printer.line(1, 0, LineKind::Selected, &long, &[])
+        });
+        assert_eq!(output.len(), long.len() + 1);
+    }
+
+    #[test]
+    fn colors_wrap_matches_numbers_and_paths() {
+        let options = PrintOptions {
+            line_number: true,
+            colors: Some(Colors::default()),
+            ..options()
+        };
+        let output = printed(&options, Some(Path::new("f")), |printer| {
+            printer.line(7, 0, LineKind::Selected, b"a-b-", &[1..2, 3..4])
+        });
+        assert_eq!(
+            output,
+            "\x1b[35mf\x1b[0m:\x1b[32m7\x1b[0m:a\x1b[1;31m-\x1b[0mb\x1b[1;31m-\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn only_matching_prints_each_match_on_its_own_line() {
+        let prefixed = PrintOptions {
+            line_number: true,
+            byte_offset: true,
+            ..options()
+        };
+        let output = printed(&prefixed, Some(Path::new("f")), |printer| {
+            printer.matches(3, 10, b"a1 b22", &[0..0, 1..2, 4..6])
+        });
+        assert_eq!(output, "f:3:11:1\nf:3:14:22\n");
+
+        let colored = PrintOptions {
+            colors: Some(Colors::default()),
+            ..options()
+        };
+        let output = printed(&colored, None, |printer| {
+            printer.matches(1, 0, b"xyz", &[1..2, 3..3])
+        });
+        assert_eq!(output, "\x1b[1;31my\x1b[0m\n");
+    }
+
+    #[test]
+    fn format_lays_out_each_match_or_each_line() {
+        // -h doesn't take {path} away
+        let options = PrintOptions {
+            with_filename: WithFilename::Never,
+            ..options()
+        };
+        let each_match = Format::parse(r"{path}\t{line}:{column}:{offset}\t{match}").unwrap();
+        let output = printed(&options, Some(Path::new("f")), |printer| {
+            printer.formatted(&each_match, 3, 10, "é1 b22".as_bytes(), &[0..0, 2..3, 5..7])?;
+            printer.formatted(&each_match, 4, 20, b"inverted", &[])
+        });
+        assert_eq!(output, "f\t3:2:12\t1\nf\t3:5:15\t22\nf\t4:1:20\t\n");
+
+        let each_line = Format::parse("{line}@{offset} {text}").unwrap();
+        let colored = PrintOptions {
+            colors: Some(Colors::default()),
+            ..options
+        };
+        let output = printed(&colored, None, |printer| {
+            printer.formatted(&each_line, 3, 10, b"a1 b22", &[1..2, 4..6])
+        });
+        assert_eq!(
+            output,
+            "\x1b[32m3\x1b[0m@10 a\x1b[1;31m1\x1b[0m b\x1b[1;31m22\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn csv_records_are_quoted_where_they_need_to_be() {
+        let mut header = Vec::new();
+        options().write_csv_header(&mut header).unwrap();
+        assert_eq!(header, b"path,line,column,text\n");
+
+        let output = printed(&options(), Some(Path::new("a,b.txt")), |printer| {
+            printer.csv_records(2, br#"say "hi", x"#, &[0..3, 9..9, 11..12])?;
+            printer.csv_records(3, b"plain", &[])
+        });
+        assert_eq!(
+            output,
+            "\"a,b.txt\",2,1,\"say \"\"hi\"\", x\"\n\
+             \"a,b.txt\",2,12,\"say \"\"hi\"\", x\"\n\
+             \"a,b.txt\",3,1,plain\n"
+        );
+    }
+
+    #[test]
+    fn counts_and_file_names() {
+        let output = printed(&options(), Some(Path::new("f")), |printer| printer.count(4));
+        assert_eq!(output, "f:4\n");
+
+        let options = PrintOptions {
+            null: true,
+            ..options()
+        };
+        let output = printed(&options, None, |printer| {
+            printer.file_name(Path::new("a b"))
+        });
+        assert_eq!(output, "a b\0");
+    }
+
+    #[test]
+    fn line_buffered_flushes_after_every_line() {
+        /// Counts the flushes, and what was written when the last one came
+        #[derive(Default)]
+        struct Flushes {
+            written: Vec<u8>,
+            flushed: Vec<usize>,
+        }
+        impl Write for Flushes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.written.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushed.push(self.written.len());
+                Ok(())
+            }
+        }
+
+        let print = |options: &PrintOptions| {
+            let mut out = Flushes::default();
+            let mut printer = Printer::new(&mut out, options, None);
+            printer
+                .line(1, 0, LineKind::Selected, b"foo foo", &[0..3, 4..7])
+                .unwrap();
+            printer.matches(2, 4, b"foo foo", &[0..3, 4..7]).unwrap();
+            out.flushed
+        };
+        assert!(print(&options()).is_empty());
+        let line_buffered = PrintOptions {
+            line_buffered: true,
+            ..options()
+        };
+        assert_eq!(print(&line_buffered), [8, 12, 16]);
+    }
+
+    #[test]
+    fn heading_goes_above_each_files_lines() {
+        let options = PrintOptions {
+            line_number: true,
+            heading: true,
+            ..options()
+        };
+        let written = AtomicBool::new(false);
+        let mut out = Vec::new();
+        for (path, lines) in [
+            ("a.rs", &["x"][..]),
+            ("empty.rs", &[]),
+            ("b.rs", &["y", "z"]),
+        ] {
+            let mut printer = Printer::new(&mut out, &options, Some(Path::new(path)))
+                .with_file_separator(&written);
+            for (number, line) in lines.iter().enumerate() {
+                printer
+                    .line(number + 1, 0, LineKind::Selected, line.as_bytes(), &[])
+                    .unwrap();
+            }
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a.rs\n1:x\n\nb.rs\n1:y\n2:z\n"
+        );
+
+        // Standard input without a path to show has no heading
+        let output = printed(&options, None, |printer| {
+            printer.line(1, 0, LineKind::Selected, b"x", &[])
+        });
+        assert_eq!(output, "1:x\n");
+
+        let colored = PrintOptions {
+            colors: Some(Colors::default()),
+            ..options
+        };
+        let output = printed(&colored, Some(Path::new("a.rs")), |printer| {
+            printer.matches(1, 0, b"x", &[0..0, 0..1])
+        });
+        assert!(
+            output.starts_with("\x1b[1m\x1b[35ma.rs\x1b[0m\n"),
+            "{output:?}"
+        );
+    }
+}
@@ -0,0 +1,362 @@
+// FilePath: rust/rust-cli/grss/src/replace.rs
+
+//! `grss replace <PATTERN> <REPLACEMENT> <PATH>`: rewriting files in place
+//!
+//! Every match of the pattern is replaced, line by line like the search
+//! matches, and `$1` or `${name}` in the replacement stand for the pattern's
+//! capture groups. A file is never half-written: the new contents go into a
+//! temporary file in the same directory, which is then renamed over the
+//! original in one step. Renaming within one filesystem is atomic, so anyone
+//! reading the file sees either all of the old contents or all of the new.
+//!
+//! Like a search, the replacing goes by bytes: a file that isn't all UTF-8
+//! (a Latin-1 `é` in a comment, say) still has its matches replaced, and
+//! every byte around them is written back the way it was.
+
+use clap::Parser;
+use grss::GrssError;
+use grss::filter::PathFilter;
+use grss::input::{BINARY_CHECK_LEN, is_binary};
+use grss::matcher::{self, MatcherOptions};
+use grss::walk::{WalkOptions, walk_files};
+use regex::bytes::Regex;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+/// Replace every match of a pattern in a file, or with -r in every file
+/// under a directory
+#[derive(Parser)]
+#[command(name = "grss replace", bin_name = "grss replace")]
+pub struct ReplaceCli {
+    /// The regular expression to look for
+    pattern: String,
+    /// What to put in place of each match. `$1` or `${name}` insert what a
+    /// capture group matched, `$$` is a literal `$`.
+    replacement: String,
+    /// The file (or, with -r, directory) to rewrite
+    path: PathBuf,
+    /// Rewrite every file under the directory PATH
+    #[arg(short = 'r', long)]
+    recursive: bool,
+    /// Match the pattern case-insensitively
+    #[arg(short = 'i', long)]
+    ignore_case: bool,
+    /// With -r, only rewrite files matching GLOB (can be repeated)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// With -r, leave files and directories matching GLOB alone (can be
+    /// repeated)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Keep a copy of each changed file as its name plus SUFFIX (e.g.
+    /// `--backup .bak` keeps `main.rs.bak`)
+    #[arg(long, value_name = "SUFFIX")]
+    backup: Option<String>,
+    /// Print the changes as a unified diff instead of making them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Parses the arguments after `grss` (starting with `replace` itself) and
+/// does the replacing
+///
+/// # Returns
+/// Whether anything was (or with --dry-run, would be) replaced
+pub fn run(argv: impl IntoIterator<Item = OsString>) -> Result<bool, GrssError> {
+    let args = ReplaceCli::parse_from(argv);
+    crate::init_logging(0, false);
+    let options = MatcherOptions {
+        ignore_case: args.ignore_case,
+        ..MatcherOptions::default()
+    };
+    let regex = matcher::compile_regex(&args.pattern, &args.pattern, options)?;
+    let replacer = Replacer { args: &args, regex };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let metadata = fs::metadata(&args.path).map_err(|error| GrssError::io(&args.path, error))?;
+    if !metadata.is_dir() {
+        return replacer
+            .replace_file(&args.path, false, &mut out)
+            .map(|count| count > 0);
+    }
+    if !args.recursive {
+        return Err(GrssError::IsADirectory(args.path.clone()));
+    }
+
+    let filter = PathFilter::new(&args.include, &args.exclude)?;
+    let mut replaced_any = false;
+    walk_files(
+        &args.path,
+        &filter,
+        WalkOptions::default(),
+        |error| log::warn!("{}", error),
+        // A file that can't be rewritten is reported and the rest still are
+        |path| {
+            match replacer.replace_file(&path, true, &mut out) {
+                Ok(count) => replaced_any |= count > 0,
+                Err(error) => log::warn!("{}", error),
+            }
+            ControlFlow::Continue(())
+        },
+    );
+    Ok(replaced_any)
+}
+
+/// The compiled pattern together with the options it's used with
+struct Replacer<'a> {
+    args: &'a ReplaceCli,
+    regex: Regex,
+}
+
+/// A line that a replacement changed, for the --dry-run diff
+#[derive(Debug, PartialEq, Eq)]
+struct Change {
+    /// 1-based line number in the original file
+    number: usize,
+    old: Vec<u8>,
+    new: Vec<u8>,
+}
+
+impl Replacer<'_> {
+    /// Replaces every match in the file at `path`, or with --dry-run prints
+    /// what would change
+    ///
+    /// Binary files are refused: an error for a file named on the command
+    /// line, a warning for one found by -r.
+    ///
+    /// # Returns
+    /// How many matches were replaced
+    fn replace_file<W: Write>(
+        &self,
+        path: &Path,
+        in_walk: bool,
+        out: &mut W,
+    ) -> Result<usize, GrssError> {
+        let bytes = fs::read(path).map_err(|error| GrssError::io(path, error))?;
+        let refuse = |message: &str| {
+            GrssError::io(path, io::Error::new(io::ErrorKind::InvalidData, message))
+        };
+
+        if is_binary(&bytes[..bytes.len().min(BINARY_CHECK_LEN)]) {
+            if in_walk {
+                log::warn!("{}: binary file, skipping", path.display());
+                return Ok(0);
+            }
+            return Err(refuse("binary file, not replacing in it"));
+        }
+        let (new_text, count, changes) = replace_lines(&self.regex, &bytes, &self.args.replacement);
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let stdout_error = |error| GrssError::io(Path::new("(standard output)"), error);
+        if self.args.dry_run {
+            write_diff(out, path, &changes).map_err(stdout_error)?;
+            return Ok(count);
+        }
+
+        if let Some(suffix) = &self.args.backup {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(suffix);
+            // fs::copy copies the permission bits along with the contents
+            fs::copy(path, &backup).map_err(|error| GrssError::io(Path::new(&backup), error))?;
+        }
+        write_atomically(path, &new_text).map_err(|error| GrssError::io(path, error))?;
+
+        let plural = if count == 1 { "" } else { "s" };
+        writeln!(out, "{}: {} replacement{}", path.display(), count, plural)
+            .map_err(stdout_error)?;
+        Ok(count)
+    }
+}
+
+/// Replaces every match of `regex` in `text`, one line at a time
+///
+/// # Returns
+/// The new text, the number of matches replaced, and the lines that changed
+fn replace_lines(regex: &Regex, text: &[u8], replacement: &str) -> (Vec<u8>, usize, Vec<Change>) {
+    let mut new_text = Vec::with_capacity(text.len());
+    let mut count = 0;
+    let mut changes = Vec::new();
+
+    for (index, line) in text.split_inclusive(|&byte| byte == b'\n').enumerate() {
+        // The line ending stays out of the matching, as in a search
+        let ending = line
+            .iter()
+            .rev()
+            .take_while(|&&byte| byte == b'\n' || byte == b'\r')
+            .count();
+        let (content, ending) = line.split_at(line.len() - ending);
+
+        let mut new_content = Vec::with_capacity(content.len());
+        let mut last = 0;
+        for captures in regex.captures_iter(content) {
+            let found = captures.get(0).expect("group 0 is the whole match");
+            new_content.extend_from_slice(&content[last..found.start()]);
+            captures.expand(replacement.as_bytes(), &mut new_content);
+            last = found.end();
+            count += 1;
+        }
+        new_content.extend_from_slice(&content[last..]);
+
+        if new_content != content {
+            changes.push(Change {
+                number: index + 1,
+                old: content.to_vec(),
+                new: new_content.clone(),
+            });
+        }
+        new_text.extend_from_slice(&new_content);
+        new_text.extend_from_slice(ending);
+    }
+    (new_text, count, changes)
+}
+
+/// Writes `changes` as a unified diff without context lines, which `patch`
+/// can apply: a run of consecutive changed lines becomes one hunk
+fn write_diff<W: Write>(out: &mut W, path: &Path, changes: &[Change]) -> io::Result<()> {
+    writeln!(out, "--- {}", path.display())?;
+    writeln!(out, "+++ {}", path.display())?;
+
+    let mut rest = changes;
+    // The number of lines added or removed so far (a replacement containing
+    // `\n` adds lines), to turn old line numbers into new ones
+    let mut shift: isize = 0;
+    while !rest.is_empty() {
+        let run = 1 + rest
+            .windows(2)
+            .take_while(|pair| pair[1].number == pair[0].number + 1)
+            .count();
+        let (hunk, remaining) = rest.split_at(run);
+        rest = remaining;
+
+        let new_lines: Vec<&[u8]> = hunk.iter().flat_map(|change| lines(&change.new)).collect();
+        let start = hunk[0].number;
+        writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            start,
+            hunk.len(),
+            start.saturating_add_signed(shift),
+            new_lines.len()
+        )?;
+        // The lines as they are in the file, so `patch` finds them
+        for change in hunk {
+            write_diff_line(out, b'-', &change.old)?;
+        }
+        for line in &new_lines {
+            write_diff_line(out, b'+', line)?;
+        }
+        shift += new_lines.len() as isize - hunk.len() as isize;
+    }
+    Ok(())
+}
+
+/// The lines of `text` the way str::lines() splits them: at `\n` or
+/// `\r\n`, with nothing after a last line ending
+fn lines(text: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let text = text.strip_suffix(b"\n").unwrap_or(text);
+    text.split(|&byte| byte == b'\n')
+        .filter(move |_| !text.is_empty())
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+/// Writes one line of a diff hunk, `line` after the `-` or `+` `marker`
+fn write_diff_line<W: Write>(out: &mut W, marker: u8, line: &[u8]) -> io::Result<()> {
+    out.write_all(&[marker])?;
+    out.write_all(line)?;
+    out.write_all(b"\n")
+}
+
+/// Replaces the file at `path` with `contents` so that it's never seen half
+/// written, keeping its permissions
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    // The temporary file has to be in the same directory: a rename is only
+    // atomic (or even possible) within one filesystem
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let permissions = fs::metadata(path)?.permissions();
+
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    temp.write_all(contents)?;
+    temp.as_file().set_permissions(permissions)?;
+    temp.as_file().sync_all()?;
+    // If anything above failed, dropping `temp` deletes it again
+    temp.persist(path).map_err(|error| error.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(pattern: &str, text: &str, replacement: &str) -> (String, usize, Vec<Change>) {
+        let regex = Regex::new(pattern).unwrap();
+        let (text, count, changes) = replace_lines(&regex, text.as_bytes(), replacement);
+        (String::from_utf8(text).unwrap(), count, changes)
+    }
+
+    #[test]
+    fn replaces_every_match_and_counts_them() {
+        let (text, count, changes) = replace("foo", "foo foo\nbar\nfoo\n", "baz");
+        assert_eq!(text, "baz baz\nbar\nbaz\n");
+        assert_eq!(count, 3);
+        assert_eq!(
+            changes
+                .iter()
+                .map(|change| change.number)
+                .collect::<Vec<_>>(),
+            [1, 3]
+        );
+    }
+
+    #[test]
+    fn capture_groups_are_expanded() {
+        let (text, _, _) = replace(r"(\w+)\.unwrap\(\)", "let x = y.unwrap();\n", "$1?");
+        assert_eq!(text, "let x = y?;\n");
+
+        let (text, _, _) = replace(r"(?P<key>\w+)=(?P<value>\w+)", "a=1 b=2", "${value}=${key}");
+        assert_eq!(text, "1=a 2=b");
+    }
+
+    #[test]
+    fn line_endings_are_kept() {
+        let (text, _, _) = replace("x$", "ax\r\nbx\nc", "y");
+        assert_eq!(text, "ay\r\nby\nc");
+    }
+
+    #[test]
+    fn bytes_that_arent_utf8_are_kept() {
+        let regex = Regex::new("caf.").unwrap();
+        let text = b"caf\xE9 caf\xC3\xA9\n\xFF\n";
+        let (text, count, changes) = replace_lines(&regex, text, "tea");
+        assert_eq!(text, b"caf\xE9 tea\n\xFF\n");
+        assert_eq!(count, 1);
+        let mut out = Vec::new();
+        write_diff(&mut out, Path::new("f.txt"), &changes).unwrap();
+        assert_eq!(
+            out,
+            b"--- f.txt\n+++ f.txt\n@@ -1,1 +1,1 @@\n-caf\xE9 caf\xC3\xA9\n+caf\xE9 tea\n"
+        );
+    }
+
+    #[test]
+    fn dry_run_diff_groups_consecutive_lines() {
+        let (_, _, changes) = replace("old", "old 1\nold 2\nkeep\nold 3\n", "new");
+        let mut out = Vec::new();
+        write_diff(&mut out, Path::new("f.txt"), &changes).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "--- f.txt\n+++ f.txt\n\
+             @@ -1,2 +1,2 @@\n-old 1\n-old 2\n+new 1\n+new 2\n\
+             @@ -4,1 +4,1 @@\n-old 3\n+new 3\n"
+        );
+    }
+}
@@ -0,0 +1,315 @@
+// FilePath: rust/rust-cli/grss/src/sarif.rs
+
+//! --sarif: the matches as a SARIF 2.1.0 log, for code scanning in CI
+//!
+//! SARIF is the JSON that GitHub code scanning (and most other CI tools that
+//! show findings on the code) reads. grss writes one run, with a rule for
+//! each pattern and a result for each match, pointing at its file, line and
+//! column. Only the part of the format that takes is modeled here, see
+//! https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html for the
+//! rest.
+//!
+//! Unlike every other output, nothing can be written before the search is
+//! over, since it's all one JSON document: the results of every file are
+//! collected as they're found, and sorted by where they are at the end, so
+//! the same search gives the same bytes however the files' searches raced.
+
+use crate::printer::column;
+use grss::matcher::Match;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::path::{Component, Path};
+use std::sync::Mutex;
+
+/// Where the JSON schema of the version written is, for `$schema`
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// A whole SARIF file
+#[derive(Serialize, Deserialize)]
+pub struct Log {
+    #[serde(rename = "$schema")]
+    pub schema: String,
+    pub version: String,
+    pub runs: Vec<Run>,
+}
+
+/// One run of one tool, which is all grss ever writes
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Run {
+    pub tool: Tool,
+    /// What columns count: characters, like --column, rather than the
+    /// UTF-16 code units SARIF counts by default
+    pub column_kind: String,
+    pub results: Vec<Finding>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Tool {
+    pub driver: Driver,
+}
+
+/// grss itself, and the rules it checked
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Driver {
+    pub name: String,
+    pub version: String,
+    pub rules: Vec<Rule>,
+}
+
+/// One pattern, its text as the ID, so a finding stays the same finding
+/// from run to run however the patterns are ordered
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    pub id: String,
+    pub short_description: Message,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Message {
+    pub text: String,
+}
+
+/// One match: a `result` in SARIF's terms
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Finding {
+    pub rule_id: String,
+    pub rule_index: usize,
+    pub level: String,
+    pub message: Message,
+    pub locations: Vec<Location>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    pub artifact_location: ArtifactLocation,
+    pub region: Region,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ArtifactLocation {
+    pub uri: String,
+}
+
+/// Where a match starts, both counted from 1
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: usize,
+    pub start_column: usize,
+}
+
+/// The findings of a whole search, added to by every file's search
+pub struct Collector {
+    /// The patterns, in the order the Matcher numbers them
+    patterns: Vec<String>,
+    findings: Mutex<Vec<Finding>>,
+}
+
+impl Collector {
+    /// A Collector for a search for `patterns`, each of which gets a rule
+    pub fn new(patterns: Vec<String>) -> Self {
+        Collector {
+            patterns,
+            findings: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Adds a finding for each of the `matches` in `line`, line `number`
+    /// of the input at `path` (None for standard input)
+    ///
+    /// Empty matches are left out, like with -o, unless the line has
+    /// nothing else: `^$` still finds every empty line, at column 1.
+    pub fn add(&self, path: Option<&Path>, number: usize, line: &[u8], matches: &[Match]) {
+        let uri = path.map_or_else(|| "-".to_string(), uri);
+        let mut found: Vec<&Match> = matches.iter().filter(|m| !m.range.is_empty()).collect();
+        if found.is_empty() {
+            found.extend(matches.first());
+        }
+        let findings = found.into_iter().map(|found| {
+            let pattern = &self.patterns[found.pattern];
+            let matched = String::from_utf8_lossy(&line[found.range.clone()]);
+            Finding {
+                rule_id: pattern.clone(),
+                rule_index: found.pattern,
+                level: "warning".to_string(),
+                message: Message {
+                    text: format!("`{}` matches `{}`", matched, pattern),
+                },
+                locations: vec![Location {
+                    physical_location: PhysicalLocation {
+                        artifact_location: ArtifactLocation { uri: uri.clone() },
+                        region: Region {
+                            start_line: number,
+                            start_column: column(line, found.range.start),
+                        },
+                    },
+                }],
+            }
+        });
+        self.findings.lock().unwrap().extend(findings);
+    }
+
+    /// Writes the SARIF file with everything added to `out`, as one line
+    /// of JSON
+    pub fn write<W: Write>(self, out: &mut W) -> io::Result<()> {
+        let mut results = self.findings.into_inner().unwrap();
+        results.sort_by(|a, b| {
+            let place = |finding: &Finding| {
+                let location = &finding.locations[0].physical_location;
+                let region = &location.region;
+                (
+                    location.artifact_location.uri.clone(),
+                    region.start_line,
+                    region.start_column,
+                    finding.rule_index,
+                )
+            };
+            place(a).cmp(&place(b))
+        });
+        let rules = self
+            .patterns
+            .into_iter()
+            .map(|pattern| Rule {
+                short_description: Message {
+                    text: format!("matches `{}`", pattern),
+                },
+                id: pattern,
+            })
+            .collect();
+        let log = Log {
+            schema: SCHEMA.to_string(),
+            version: "2.1.0".to_string(),
+            runs: vec![Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: "grss".to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                        rules,
+                    },
+                },
+                column_kind: "unicodeCodePoints".to_string(),
+                results,
+            }],
+        };
+        serde_json::to_writer(&mut *out, &log)?;
+        writeln!(out)
+    }
+}
+
+/// `path` as a URI reference: relative paths stay relative (without a
+/// leading `./`), so code scanning finds them in the repository, and
+/// absolute ones become `file://` URIs. Whatever isn't allowed in a URI
+/// as it is, like a space, is percent-encoded.
+fn uri(path: &Path) -> String {
+    let parts: Vec<Cow<str>> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Prefix(prefix) => Some(Cow::Owned(encode(prefix.as_os_str()))),
+            Component::RootDir | Component::CurDir => None,
+            Component::ParentDir => Some(Cow::Borrowed("..")),
+            Component::Normal(name) => Some(Cow::Owned(encode(name))),
+        })
+        .collect();
+    match path.has_root() {
+        true => format!("file:///{}", parts.join("/")),
+        false => parts.join("/"),
+    }
+}
+
+/// `name` percent-encoded for a URI path, byte by byte
+fn encode(name: &std::ffi::OsStr) -> String {
+    let mut encoded = String::new();
+    for &byte in name.as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => encoded.push(byte as char),
+            b'-' | b'.' | b'_' | b'~' | b'!' | b'$' | b'&' | b'\'' | b'(' | b')' => {
+                encoded.push(byte as char)
+            }
+            b'*' | b'+' | b',' | b';' | b'=' | b':' | b'@' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_become_uri_references() {
+        assert_eq!(uri(Path::new("./src/main.rs")), "src/main.rs");
+        assert_eq!(uri(Path::new("../my notes.txt")), "../my%20notes.txt");
+        assert_eq!(uri(Path::new("a#b/100%.rs")), "a%23b/100%25.rs");
+        #[cfg(unix)]
+        assert_eq!(
+            uri(Path::new("/srv/caf\u{e9}.rs")),
+            "file:///srv/caf%C3%A9.rs"
+        );
+    }
+
+    #[test]
+    fn findings_are_sorted_and_point_at_characters() {
+        let collector = Collector::new(vec!["unwrap\\(\\)".to_string(), "todo!".to_string()]);
+        let found = |pattern, range| Match { pattern, range };
+        let line = "é.unwrap() todo!()".as_bytes();
+        collector.add(
+            Some(Path::new("b.rs")),
+            3,
+            line,
+            &[found(0, 3..11), found(1, 12..17)],
+        );
+        collector.add(Some(Path::new("a.rs")), 9, b"todo!", &[found(1, 0..5)]);
+
+        let mut out = Vec::new();
+        collector.write(&mut out).unwrap();
+        let log: Log = serde_json::from_slice(&out).unwrap();
+        assert_eq!(log.version, "2.1.0");
+        let run = &log.runs[0];
+        let rules: Vec<&str> = run
+            .tool
+            .driver
+            .rules
+            .iter()
+            .map(|rule| rule.id.as_str())
+            .collect();
+        assert_eq!(rules, ["unwrap\\(\\)", "todo!"]);
+        let places: Vec<(&str, usize, usize, &str)> = run
+            .results
+            .iter()
+            .map(|finding| {
+                let location = &finding.locations[0].physical_location;
+                (
+                    location.artifact_location.uri.as_str(),
+                    location.region.start_line,
+                    location.region.start_column,
+                    finding.rule_id.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            places,
+            [
+                ("a.rs", 9, 1, "todo!"),
+                ("b.rs", 3, 3, "unwrap\\(\\)"),
+                ("b.rs", 3, 12, "todo!")
+            ]
+        );
+        assert_eq!(
+            run.results[1].message.text,
+            "`unwrap()` matches `unwrap\\(\\)`"
+        );
+    }
+}
@@ -0,0 +1,985 @@
+// FilePath: rust/rust-cli/grss/src/searcher.rs
+
+//! Going through input line by line and picking out the lines worth showing
+//!
+//! A Searcher doesn't print anything itself. It hands every line to show to
+//! a sink, in order: the selected lines, and with before/after context the
+//! lines around them too. Formatting them (or counting them, or collecting
+//! them into a Vec) is up to whoever passes the sink in. The sink also
+//! decides when enough is enough: it can ask to stop at any line, either
+//! just this input (what -l needs once it has a match) or every input (-q).
+//!
+//! With -U the patterns can match across lines, so they're run over the
+//! whole input at once. Every line a match touches counts as selected, and
+//! from there on it's the same as the usual line-by-line search.
+//!
+//! When the patterns all need some plain text to match (see the Matcher's
+//! prefilter), the search doesn't go through every line: it looks for that
+//! text in the whole buffer and jumps to the next line that has it, only
+//! counting the newlines on the way for the line numbers. The lines just
+//! before it are still looked at when they're wanted as before-context, and
+//! nothing is skipped while after-context is still being shown.
+
+use crate::matcher::Matcher;
+use memchr::{memchr, memchr_iter, memrchr};
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+/// Why a line was handed to the sink
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineKind {
+    /// The line is selected: it matches (or with invert, it doesn't)
+    Selected,
+    /// The line is only shown as context around a selected one
+    Context,
+}
+
+/// A line handed to the sink
+pub struct Match<'a> {
+    /// 1-based line number, so editors can jump straight there
+    pub number: usize,
+    /// Byte offset of the start of the line from the start of the input,
+    /// counting every byte before it (line endings included)
+    pub offset: usize,
+    /// The line itself, without its line ending, as the bytes it is: a
+    /// file needn't be UTF-8, only printing it replaces what isn't
+    pub line: &'a [u8],
+    pub kind: LineKind,
+    matcher: &'a Matcher,
+    /// Where the matches of a multiline search fall within this line, which
+    /// can't be worked out from the line alone
+    multiline_ranges: Option<&'a [Range<usize>]>,
+}
+
+impl Match<'_> {
+    /// Where the patterns matched in the line, for highlighting
+    ///
+    /// Only worked out when asked for, a sink that just counts lines never
+    /// pays for it. Context lines (and lines selected by invert) have
+    /// nothing to highlight and give an empty Vec.
+    pub fn ranges(&self) -> Vec<Range<usize>> {
+        match (self.kind, self.multiline_ranges) {
+            (LineKind::Selected, Some(ranges)) => ranges.to_vec(),
+            (LineKind::Selected, None) => self.matcher.find(self.line),
+            (LineKind::Context, _) => Vec::new(),
+        }
+    }
+
+    /// How many times the patterns occur in the line, for --count-matches
+    ///
+    /// A line selected by invert has no matches in it and counts as one,
+    /// so `-v --count-matches` is the same as `-v -c`. Context lines count
+    /// as none.
+    pub fn match_count(&self) -> usize {
+        match (self.kind, self.multiline_ranges) {
+            (LineKind::Context, _) => 0,
+            _ if self.matcher.is_inverted() => 1,
+            (LineKind::Selected, Some(ranges)) => ranges.len(),
+            (LineKind::Selected, None) => self.matcher.count_matches(self.line),
+        }
+    }
+}
+
+/// How much to search and what to hand to the sink besides selected lines
+#[derive(Clone, Copy, Default)]
+pub struct SearchOptions {
+    /// Lines of context to show before each selected line (-B)
+    pub before_context: usize,
+    /// Lines of context to show after each selected line (-A)
+    pub after_context: usize,
+    /// Stop after this many selected lines (-m). Trailing context after the
+    /// last one is still handed over.
+    pub max_count: Option<usize>,
+    /// --null-data: the input is made of records ending in a NUL byte
+    /// instead of lines ending in `\n`, as `find -print0` writes them.
+    /// Everything said about lines below then goes for records, and line
+    /// numbers count records.
+    pub null_data: bool,
+}
+
+impl SearchOptions {
+    /// The byte every line (or record) ends with
+    pub fn terminator(&self) -> u8 {
+        match self.null_data {
+            true => b'\0',
+            false => b'\n',
+        }
+    }
+}
+
+/// What a sink can ask for instead of the next line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// Nothing more is needed from this input, e.g. -l knows the file
+    /// matches after one line
+    File,
+    /// Nothing more is needed from this input or any other, e.g. -q knows
+    /// there's a match somewhere. The Searcher only stops this input;
+    /// stopping the others is up to whoever started them.
+    All,
+}
+
+/// The sink lines are handed to. It returns None to go on to the next line
+/// or a Stop to end the search there, and an error (e.g. because stdout was
+/// closed) stops the search with that error.
+pub type Sink<'s> = dyn FnMut(Match) -> io::Result<Option<Stop>> + 's;
+
+/// How the search of one input went
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    /// How many lines were selected
+    pub count: usize,
+    /// What the sink asked for when it ended the search early, None when
+    /// the search went as far as it would have anyway
+    pub stopped: Option<Stop>,
+}
+
+/// Searches input with a Matcher
+pub struct Searcher {
+    matcher: Matcher,
+    options: SearchOptions,
+}
+
+impl Searcher {
+    pub fn new(matcher: Matcher, options: SearchOptions) -> Self {
+        Searcher { matcher, options }
+    }
+
+    pub fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+
+    pub fn options(&self) -> &SearchOptions {
+        &self.options
+    }
+
+    /// Reads `reader` line by line and hands the lines to show to `sink`
+    ///
+    /// Only the current line (plus any before-context) is ever held in
+    /// memory, so a 10 GB log is searched in a few MB. Lines end at `\n` or
+    /// `\r\n`, which aren't part of the line, and a last line without a
+    /// newline still counts. Bytes that aren't valid UTF-8 are searched as
+    /// they are instead of failing the whole input.
+    ///
+    /// A multiline Matcher (-U) is the exception: it needs all of the input
+    /// at once, so it's read into memory first.
+    ///
+    /// # Returns
+    /// How many lines were selected, and whether the sink stopped the search
+    pub fn search_reader<R: BufRead>(&self, mut reader: R, sink: &mut Sink) -> io::Result<Outcome> {
+        if self.matcher.is_multiline() {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            return self.search_multiline(&data, sink);
+        }
+        let mut state = SearchState::new(self);
+        // One buffer for every line: clearing it keeps its capacity, so it
+        // grows to fit the longest line once instead of being allocated
+        // again for each one
+        let mut buffer = Vec::new();
+        let mut number = 0;
+        let mut offset = 0;
+        let terminator = self.options.terminator();
+        // Lines left to read one by one before skipping again
+        let mut unchecked = 0;
+
+        loop {
+            if unchecked == 0 && state.can_skip() {
+                // Only the whole lines in what's buffered can be skipped,
+                // a line that doesn't fit is read as usual
+                let buffered = reader.fill_buf()?;
+                if let Some(last) = memrchr(terminator, buffered) {
+                    let window = &buffered[..=last];
+                    let at = self
+                        .matcher
+                        .candidates(window)
+                        .and_then(|mut candidates| candidates.next(0))
+                        .unwrap_or(window.len());
+                    let skip = self.skip(window, at);
+                    number += skip.lines;
+                    offset += skip.to;
+                    unchecked = skip.unchecked;
+                    reader.consume(skip.to);
+                    if unchecked == 0 {
+                        continue;
+                    }
+                }
+            }
+            unchecked = unchecked.saturating_sub(1);
+            buffer.clear();
+            let read = reader.read_until(terminator, &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            number += 1;
+            let line = trim_line_ending(&buffer, terminator);
+            if !state.line(number, offset, line, None, sink)? {
+                break;
+            }
+            offset += read;
+        }
+        Ok(state.outcome())
+    }
+
+    /// Like search_reader(), over input that's in memory already, e.g. a
+    /// memory-mapped file
+    ///
+    /// Lines are borrowed straight from `data` instead of being copied into
+    /// a buffer first.
+    ///
+    /// # Returns
+    /// How many lines were selected, and whether the sink stopped the search
+    pub fn search_slice(&self, data: &[u8], sink: &mut Sink) -> io::Result<Outcome> {
+        if self.matcher.is_multiline() {
+            return self.search_multiline(data, sink);
+        }
+        let mut state = SearchState::new(self);
+        let mut candidates = self.matcher.candidates(data);
+        let mut number = 0;
+        let mut offset = 0;
+        let terminator = self.options.terminator();
+        let mut unchecked = 0;
+        while offset < data.len() {
+            if unchecked == 0
+                && state.can_skip()
+                && let Some(candidates) = &mut candidates
+            {
+                // No more candidates, no more lines to select
+                let Some(at) = candidates.next(offset) else {
+                    break;
+                };
+                let skip = self.skip(&data[offset..], at - offset);
+                number += skip.lines;
+                offset += skip.to;
+                unchecked = skip.unchecked;
+            }
+            unchecked = unchecked.saturating_sub(1);
+            let end =
+                memchr(terminator, &data[offset..]).map_or(data.len(), |end| offset + end + 1);
+            let raw = &data[offset..end];
+            number += 1;
+            let line = trim_line_ending(raw, terminator);
+            if !state.line(number, offset, line, None, sink)? {
+                break;
+            }
+            offset = end;
+        }
+        Ok(state.outcome())
+    }
+
+    /// Works out how far to skip ahead in `window`, a run of whole lines, to
+    /// the line with a candidate for a match at `at` (see Candidates), or
+    /// with `at` at the end of the window to past every line in it
+    ///
+    /// Stops short by as many lines as before-context wants, which have to
+    /// be looked at to be remembered. There's no candidate in any of them.
+    fn skip(&self, window: &[u8], at: usize) -> Skip {
+        let terminator = self.options.terminator();
+        let mut to = memrchr(terminator, &window[..at]).map_or(0, |end| end + 1);
+        for _ in 0..self.options.before_context {
+            if to == 0 {
+                break;
+            }
+            to = memrchr(terminator, &window[..to - 1]).map_or(0, |end| end + 1);
+        }
+        Skip {
+            to,
+            lines: memchr_iter(terminator, &window[..to]).count(),
+            // The lines up to and including the one with the candidate
+            unchecked: memchr_iter(terminator, &window[to..at]).count()
+                + usize::from(at < window.len()),
+        }
+    }
+
+    /// Runs the patterns over all of `data` at once, so a match can span
+    /// several lines, then goes through the lines handing over every one a
+    /// match touches (with -v, every one none does)
+    ///
+    /// Memory use grows with the size of the input rather than the longest
+    /// line: the matches are all found up front.
+    ///
+    /// # Returns
+    /// How many lines were selected, and whether the sink stopped the search
+    fn search_multiline(&self, data: &[u8], sink: &mut Sink) -> io::Result<Outcome> {
+        let found = self.matcher.find_matches(data);
+        let mut state = SearchState::new(self);
+        // The first match that can still touch the current line. Matches
+        // never overlap and are sorted, so their ends are sorted too and
+        // this only ever moves forward.
+        let mut next = 0;
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        let terminator = self.options.terminator();
+        let lines = data.split_inclusive(|&byte| byte == terminator);
+        for (index, with_ending) in lines.enumerate() {
+            let end = start + with_ending.len();
+            let line = trim_line_ending(with_ending, terminator);
+            while found
+                .get(next)
+                .is_some_and(|earlier| ends_before(&earlier.range, start))
+            {
+                next += 1;
+            }
+
+            // Clip every match touching the line to the line itself. A
+            // match can also start at the very end of a last line without
+            // a newline, like `$` does.
+            let is_last = with_ending.last() != Some(&terminator);
+            ranges.clear();
+            let mut matched = false;
+            for touching in found[next..]
+                .iter()
+                .take_while(|m| m.range.start < end || (is_last && m.range.start == end))
+            {
+                matched = true;
+                let from = touching.range.start.max(start) - start;
+                let to = touching.range.end.min(start + line.len()).max(start) - start;
+                if from < to {
+                    ranges.push(from..to);
+                }
+            }
+            if self.matcher.is_inverted() {
+                ranges.clear();
+            }
+
+            let multiline = Multiline {
+                matched,
+                ranges: &ranges,
+            };
+            if !state.line(index + 1, start, line, Some(multiline), sink)? {
+                break;
+            }
+            start = end;
+        }
+        Ok(state.outcome())
+    }
+}
+
+/// How far Searcher::skip() says to go
+struct Skip {
+    /// The offset to go on from, at the start of a line
+    to: usize,
+    /// How many lines that skips
+    lines: usize,
+    /// How many lines from there to read one by one before skipping again
+    unchecked: usize,
+}
+
+/// Whether a match over `range` ends before a line starting at `start`, so
+/// it can't touch that line or any after it
+fn ends_before(range: &Range<usize>, start: usize) -> bool {
+    match range.is_empty() {
+        true => range.start < start,
+        false => range.end <= start,
+    }
+}
+
+/// What a multiline search found out about one line, which a line-by-line
+/// search works out from the line itself
+#[derive(Clone, Copy)]
+struct Multiline<'r> {
+    /// Whether any match touches the line
+    matched: bool,
+    /// The parts of those matches that fall inside the line, as byte ranges
+    /// into it
+    ranges: &'r [Range<usize>],
+}
+
+/// `line` without the `\n` or `\r\n` at its end (or with --null-data, the
+/// NUL)
+fn trim_line_ending(line: &[u8], terminator: u8) -> &[u8] {
+    match terminator {
+        b'\n' => {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            line.strip_suffix(b"\r").unwrap_or(line)
+        }
+        _ => line.strip_suffix(&[terminator]).unwrap_or(line),
+    }
+}
+
+/// What a search has to remember from one line to the next
+struct SearchState<'s> {
+    searcher: &'s Searcher,
+    /// How many lines were selected so far
+    count: usize,
+    /// A ring buffer of the last `before_context` lines that weren't shown,
+    /// as (line number, offset, text). Once it's full the oldest line falls
+    /// out the front whenever a new one is pushed on the back.
+    before_lines: VecDeque<(usize, usize, Vec<u8>)>,
+    /// How many more lines still need showing after the last selected one
+    after_left: usize,
+    /// What the sink asked for when it stopped the search
+    stopped: Option<Stop>,
+}
+
+impl<'s> SearchState<'s> {
+    fn new(searcher: &'s Searcher) -> Self {
+        SearchState {
+            searcher,
+            count: 0,
+            before_lines: VecDeque::with_capacity(searcher.options.before_context),
+            after_left: 0,
+            stopped: None,
+        }
+    }
+
+    /// Whether lines without a candidate for a match can be skipped right
+    /// now: not while after-context still wants the lines that come next,
+    /// and not once -m was reached, when the next line ends the search
+    fn can_skip(&self) -> bool {
+        let options = &self.searcher.options;
+        self.after_left == 0
+            && options.max_count.is_none_or(|max| self.count < max)
+            && self.searcher.matcher.is_prefiltered()
+    }
+
+    fn outcome(&self) -> Outcome {
+        Outcome {
+            count: self.count,
+            stopped: self.stopped,
+        }
+    }
+
+    /// Hands `found` to `sink`, remembering it if the sink asks to stop
+    ///
+    /// # Returns
+    /// Whether the search should go on
+    fn hand_over(&mut self, sink: &mut Sink, found: Match) -> io::Result<bool> {
+        self.stopped = sink(found)?;
+        Ok(self.stopped.is_none())
+    }
+
+    /// Looks at the next line, numbered `number` and starting `offset` bytes
+    /// into the input, handing it and any context that goes with it to
+    /// `sink`
+    ///
+    /// `multiline` is what a multiline search already found out about the
+    /// line; without it the line is matched on its own.
+    ///
+    /// # Returns
+    /// Whether the search should go on to the next line
+    fn line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &[u8],
+        multiline: Option<Multiline>,
+        sink: &mut Sink,
+    ) -> io::Result<bool> {
+        let searcher = self.searcher;
+        let options = &searcher.options;
+        let matcher = &searcher.matcher;
+
+        // Once the max count is reached the only thing left to do is finish
+        // the trailing context of the last selected line
+        let limit_reached = options.max_count.is_some_and(|max| self.count >= max);
+        if limit_reached && self.after_left == 0 {
+            return Ok(false);
+        }
+
+        let selected = match multiline {
+            Some(multiline) => multiline.matched != matcher.is_inverted(),
+            None => matcher.selects(line),
+        };
+        if selected && limit_reached {
+            // Like grep, the context stops at the next line that would have
+            // been selected
+            return Ok(false);
+        }
+        if !selected {
+            if self.after_left > 0 {
+                self.after_left -= 1;
+                return self.hand_over(sink, context(matcher, number, offset, line));
+            } else if options.before_context > 0 {
+                // Reuse the Vec of the line falling out of the buffer
+                // instead of allocating a new one every time
+                let mut text = match self.before_lines.len() == options.before_context {
+                    true => self
+                        .before_lines
+                        .pop_front()
+                        .map(|(_, _, text)| text)
+                        .unwrap_or_default(),
+                    false => Vec::new(),
+                };
+                text.clear();
+                text.extend_from_slice(line);
+                self.before_lines.push_back((number, offset, text));
+            }
+            return Ok(true);
+        }
+        self.count += 1;
+
+        while let Some((context_number, context_offset, context_line)) =
+            self.before_lines.pop_front()
+        {
+            let found = context(matcher, context_number, context_offset, &context_line);
+            if !self.hand_over(sink, found)? {
+                return Ok(false);
+            }
+        }
+        self.after_left = options.after_context;
+        self.hand_over(
+            sink,
+            Match {
+                number,
+                offset,
+                line,
+                kind: LineKind::Selected,
+                matcher,
+                multiline_ranges: multiline.map(|multiline| multiline.ranges),
+            },
+        )
+    }
+}
+
+fn context<'l>(matcher: &'l Matcher, number: usize, offset: usize, line: &'l [u8]) -> Match<'l> {
+    Match {
+        number,
+        offset,
+        line,
+        kind: LineKind::Context,
+        matcher,
+        multiline_ranges: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::MatcherOptions;
+
+    const INPUT: &str = "a\nmatch 1\nb\nc\nd\nmatch 2\ne\n";
+
+    /// Searches INPUT for `pattern`, giving back (kind, number, line) for
+    /// every line the sink saw, and the count
+    fn search(
+        pattern: &str,
+        matcher_options: MatcherOptions,
+        options: SearchOptions,
+    ) -> (Vec<(LineKind, usize, String)>, usize) {
+        let matcher = Matcher::new(&[pattern.to_string()], matcher_options).unwrap();
+        let searcher = Searcher::new(matcher, options);
+        let mut seen = Vec::new();
+        let outcome = searcher
+            .search_reader(INPUT.as_bytes(), &mut |found| {
+                seen.push((
+                    found.kind,
+                    found.number,
+                    String::from_utf8_lossy(found.line).into_owned(),
+                ));
+                Ok(None)
+            })
+            .unwrap();
+        (seen, outcome.count)
+    }
+
+    fn selected(number: usize, line: &str) -> (LineKind, usize, String) {
+        (LineKind::Selected, number, line.to_string())
+    }
+
+    fn context(number: usize, line: &str) -> (LineKind, usize, String) {
+        (LineKind::Context, number, line.to_string())
+    }
+
+    #[test]
+    fn hands_over_selected_lines() {
+        let (seen, count) = search("match", MatcherOptions::default(), SearchOptions::default());
+        assert_eq!(seen, [selected(2, "match 1"), selected(6, "match 2")]);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn context_lines_come_in_order() {
+        let options = SearchOptions {
+            before_context: 1,
+            after_context: 1,
+            ..SearchOptions::default()
+        };
+        let (seen, _) = search("match", MatcherOptions::default(), options);
+        assert_eq!(
+            seen,
+            [
+                context(1, "a"),
+                selected(2, "match 1"),
+                context(3, "b"),
+                context(5, "d"),
+                selected(6, "match 2"),
+                context(7, "e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn invert_and_max_count() {
+        let matcher_options = MatcherOptions {
+            invert: true,
+            ..MatcherOptions::default()
+        };
+        let options = SearchOptions {
+            max_count: Some(2),
+            ..SearchOptions::default()
+        };
+        let (seen, count) = search("match", matcher_options, options);
+        assert_eq!(seen, [selected(1, "a"), selected(3, "b")]);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn the_sink_can_stop_the_search_at_any_line() {
+        let matcher = Matcher::new(&["match".to_string()], MatcherOptions::default()).unwrap();
+        let options = SearchOptions {
+            after_context: 1,
+            ..SearchOptions::default()
+        };
+        let searcher = Searcher::new(matcher, options);
+        for stop in [Stop::File, Stop::All] {
+            // Stopping at the first selected line, like -l and -q do
+            let mut seen = 0;
+            let outcome = searcher
+                .search_slice(INPUT.as_bytes(), &mut |_| {
+                    seen += 1;
+                    Ok(Some(stop))
+                })
+                .unwrap();
+            assert_eq!(seen, 1);
+            assert_eq!(
+                outcome,
+                Outcome {
+                    count: 1,
+                    stopped: Some(stop)
+                }
+            );
+
+            // A context line can stop it too, and a reader isn't read on
+            let mut reader = INPUT.as_bytes();
+            let outcome = searcher
+                .search_reader(&mut reader, &mut |found| match found.kind {
+                    LineKind::Context => Ok(Some(stop)),
+                    LineKind::Selected => Ok(None),
+                })
+                .unwrap();
+            assert_eq!(outcome.stopped, Some(stop));
+            assert_eq!(reader, b"c\nd\nmatch 2\ne\n");
+        }
+
+        // Going on to the end stops nothing
+        let outcome = searcher
+            .search_slice(INPUT.as_bytes(), &mut |_| Ok(None))
+            .unwrap();
+        assert_eq!(
+            outcome,
+            Outcome {
+                count: 2,
+                stopped: None
+            }
+        );
+    }
+
+    #[test]
+    fn ranges_are_only_worked_out_for_selected_lines() {
+        let matcher = Matcher::new(&["[0-9]".to_string()], MatcherOptions::default()).unwrap();
+        let options = SearchOptions {
+            after_context: 1,
+            ..SearchOptions::default()
+        };
+        let mut ranges = Vec::new();
+        Searcher::new(matcher, options)
+            .search_reader("x1y2\nzz\n".as_bytes(), &mut |found| {
+                ranges.push(found.ranges());
+                Ok(None)
+            })
+            .unwrap();
+        assert_eq!(ranges, [vec![1..2, 3..4], vec![]]);
+    }
+
+    #[test]
+    fn sink_errors_stop_the_search() {
+        let matcher = Matcher::new(&["match".to_string()], MatcherOptions::default()).unwrap();
+        let mut calls = 0;
+        let result = Searcher::new(matcher, SearchOptions::default()).search_reader(
+            INPUT.as_bytes(),
+            &mut |_| {
+                calls += 1;
+                Err(io::Error::other("stdout closed"))
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn offsets_count_every_byte_before_the_line() {
+        let matcher = Matcher::new(&["x".to_string()], MatcherOptions::default()).unwrap();
+        let options = SearchOptions {
+            before_context: 1,
+            ..SearchOptions::default()
+        };
+        let searcher = Searcher::new(matcher, options);
+        // "é" is two bytes, the CRLF two more
+        let input = "é\r\nab\nx\n".as_bytes();
+
+        let mut from_reader = Vec::new();
+        searcher
+            .search_reader(input, &mut |found| {
+                from_reader.push((found.number, found.offset));
+                Ok(None)
+            })
+            .unwrap();
+        assert_eq!(from_reader, [(2, 4), (3, 7)]);
+
+        let mut from_slice = Vec::new();
+        searcher
+            .search_slice(input, &mut |found| {
+                from_slice.push((found.number, found.offset));
+                Ok(None)
+            })
+            .unwrap();
+        assert_eq!(from_slice, from_reader);
+    }
+
+    #[test]
+    fn invalid_utf8_and_line_endings() {
+        let matcher = Matcher::new(&["b".to_string()], MatcherOptions::default()).unwrap();
+        let mut seen = Vec::new();
+        Searcher::new(matcher, SearchOptions::default())
+            .search_reader(&b"ab\xFF\r\nc\nb at the end"[..], &mut |found| {
+                seen.push((found.number, found.line.to_vec()));
+                Ok(None)
+            })
+            .unwrap();
+        // The bytes as they are, nothing replaced
+        assert_eq!(
+            seen,
+            [(1, b"ab\xFF".to_vec()), (3, b"b at the end".to_vec())]
+        );
+    }
+
+    #[test]
+    fn null_data_splits_records_on_nul() {
+        let matcher = Matcher::new(&["^match".to_string()], MatcherOptions::default()).unwrap();
+        let options = SearchOptions {
+            null_data: true,
+            ..SearchOptions::default()
+        };
+        let searcher = Searcher::new(matcher, options);
+        // Newlines are just part of a record, and a \r before a NUL stays
+        let input = b"one\nmatch\0match two\r\0three\0match\nfour";
+        let mut from_reader = Vec::new();
+        searcher
+            .search_reader(&input[..], &mut |found| {
+                from_reader.push((
+                    found.number,
+                    found.offset,
+                    String::from_utf8_lossy(found.line).into_owned(),
+                ));
+                Ok(None)
+            })
+            .unwrap();
+        assert_eq!(
+            from_reader,
+            [
+                (2, 10, "match two\r".to_string()),
+                (4, 27, "match\nfour".to_string())
+            ]
+        );
+
+        let mut from_slice = Vec::new();
+        searcher
+            .search_slice(input, &mut |found| {
+                from_slice.push((
+                    found.number,
+                    found.offset,
+                    String::from_utf8_lossy(found.line).into_owned(),
+                ));
+                Ok(None)
+            })
+            .unwrap();
+        assert_eq!(from_slice, from_reader);
+    }
+
+    #[test]
+    fn multiline_matches_are_clipped_to_each_line() {
+        let options = MatcherOptions {
+            multiline: true,
+            ..MatcherOptions::default()
+        };
+        let matcher = Matcher::new(&[r"b\r?\nc".to_string()], options).unwrap();
+        let searcher = Searcher::new(matcher, SearchOptions::default());
+        // \xff is searched as the 1 byte it is
+        let data = b"a\xff\nab\r\ncd\ne";
+        let mut seen = Vec::new();
+        let outcome = searcher
+            .search_slice(data, &mut |found| {
+                seen.push((
+                    found.number,
+                    found.offset,
+                    String::from_utf8_lossy(found.line).into_owned(),
+                    found.ranges().first().cloned(),
+                ));
+                Ok(None)
+            })
+            .unwrap();
+        assert_eq!(outcome.count, 2);
+        assert_eq!(
+            seen,
+            [
+                (2, 3, "ab".to_string(), Some(1..2)),
+                (3, 7, "cd".to_string(), Some(0..1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn crlf_lets_multiline_anchors_stop_at_the_carriage_return() {
+        let data = b"an error\r\nok\nerror\nerror here\r\n";
+        let found = |crlf| {
+            let options = MatcherOptions {
+                multiline: true,
+                crlf,
+                ..MatcherOptions::default()
+            };
+            let matcher = Matcher::new(&["error$".to_string()], options).unwrap();
+            let mut seen = Vec::new();
+            Searcher::new(matcher, SearchOptions::default())
+                .search_slice(data, &mut |found| {
+                    seen.push((found.number, found.offset, found.ranges().first().cloned()));
+                    Ok(None)
+                })
+                .unwrap();
+            seen
+        };
+        assert_eq!(found(false), [(3, 13, Some(0..5))]);
+        // Offsets still count the \r before them
+        assert_eq!(found(true), [(1, 0, Some(3..8)), (3, 13, Some(0..5))]);
+    }
+
+    #[test]
+    fn multiline_empty_match_at_the_very_end() {
+        let options = MatcherOptions {
+            multiline: true,
+            ..MatcherOptions::default()
+        };
+        let matcher = Matcher::new(&["z$".to_string(), "^$".to_string()], options).unwrap();
+        let searcher = Searcher::new(matcher, SearchOptions::default());
+        let mut numbers = Vec::new();
+        searcher
+            .search_reader(&b"a\n\nxyz"[..], &mut |found| {
+                numbers.push(found.number);
+                Ok(None)
+            })
+            .unwrap();
+        assert_eq!(numbers, [2, 3]);
+    }
+
+    /// (kind, number, offset, line, ranges) of a line the sink saw
+    type Seen = (LineKind, usize, usize, String, Vec<Range<usize>>);
+
+    /// Every line the sink saw, with everything about it, searching
+    /// `input` in memory, or with `buffer` set through a reader with a
+    /// buffer that small
+    fn everything(searcher: &Searcher, input: &[u8], buffer: Option<usize>) -> Vec<Seen> {
+        let mut seen = Vec::new();
+        let mut sink = |found: Match| {
+            let ranges = found.ranges();
+            seen.push((
+                found.kind,
+                found.number,
+                found.offset,
+                String::from_utf8_lossy(found.line).into_owned(),
+                ranges,
+            ));
+            Ok(None)
+        };
+        match buffer {
+            Some(capacity) => {
+                searcher.search_reader(io::BufReader::with_capacity(capacity, input), &mut sink)
+            }
+            None => searcher.search_slice(input, &mut sink),
+        }
+        .unwrap();
+        seen
+    }
+
+    #[test]
+    fn skipping_starts_each_line_count_and_context_in_the_right_place() {
+        let matcher = Matcher::new(&[r"match \d".to_string()], MatcherOptions::default()).unwrap();
+        let options = SearchOptions {
+            before_context: 1,
+            ..SearchOptions::default()
+        };
+        let searcher = Searcher::new(matcher, options);
+        assert!(searcher.matcher().is_prefiltered());
+        // Both matches take up their whole line
+        let whole: Vec<Range<usize>> = std::iter::once(0..7).collect();
+        for buffer in [None, Some(4), Some(64)] {
+            assert_eq!(
+                everything(&searcher, INPUT.as_bytes(), buffer),
+                [
+                    (LineKind::Context, 1, 0, "a".to_string(), vec![]),
+                    (
+                        LineKind::Selected,
+                        2,
+                        2,
+                        "match 1".to_string(),
+                        whole.clone()
+                    ),
+                    (LineKind::Context, 5, 14, "d".to_string(), vec![]),
+                    (
+                        LineKind::Selected,
+                        6,
+                        16,
+                        "match 2".to_string(),
+                        whole.clone()
+                    ),
+                ]
+            );
+        }
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn skipped_lines_are_never_missed(
+            patterns in proptest::collection::vec(
+                r"[ab.]{1,3}[*+?|]?(\(?[ab]{1,2}\)?[*+?]?)?[ab]{0,2}",
+                1..3,
+            ),
+            lines in proptest::collection::vec("[ab. \r]{0,12}", 0..24),
+            ends_with_newline: bool,
+            word: bool,
+            invert: bool,
+            before_context in 0..3usize,
+            after_context in 0..3usize,
+            max_count in proptest::option::of(0..4usize),
+            buffer in 1..40usize,
+        ) {
+            let matcher_options = MatcherOptions {
+                word,
+                invert,
+                ..MatcherOptions::default()
+            };
+            // Generated patterns can have unbalanced parentheses
+            let Ok(prefiltered) = Matcher::new(&patterns, matcher_options) else {
+                return Ok(());
+            };
+            let plain = Matcher::new(&patterns, matcher_options).unwrap().without_prefilter();
+            let options = SearchOptions {
+                before_context,
+                after_context,
+                max_count,
+                ..SearchOptions::default()
+            };
+            let prefiltered = Searcher::new(prefiltered, options);
+            let plain = Searcher::new(plain, options);
+            let mut input = lines.join("\n");
+            if ends_with_newline {
+                input.push('\n');
+            }
+            let input = input.as_bytes();
+
+            let expected = everything(&plain, input, None);
+            proptest::prop_assert_eq!(&everything(&prefiltered, input, None), &expected);
+            proptest::prop_assert_eq!(&everything(&prefiltered, input, Some(buffer)), &expected);
+        }
+    }
+}
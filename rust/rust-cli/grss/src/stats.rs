@@ -0,0 +1,552 @@
+// FilePath: rust/rust-cli/grss/src/stats.rs
+
+//! The numbers behind --stats and --summary, and the timings behind
+//! --debug-timing
+//!
+//! --stats writes a Summary, as text or with --json as a JSON object; both
+//! come from the same struct, so they always say the same.
+//!
+//! Files are searched on several threads at once, so every counter is an
+//! atomic that any thread can add to without taking a lock. Relaxed ordering
+//! is enough: no other data is handed between threads through the counters,
+//! and they're only read once every thread has been joined.
+//!
+//! The counts of each file for --summary are the one thing behind a lock,
+//! taken once per file that matched, and sorted once the search is done.
+//!
+//! Timings are added to far more often, so each thread keeps its own
+//! Metrics instead and merges it into the shared one when it's done.
+
+use grss::matcher::Engine;
+use serde::{Serialize, Serializer};
+use std::cell::Cell;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Why a file was left out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Skip {
+    /// It looked binary
+    Binary,
+    /// --include, --exclude, --type and the like left it out
+    Filtered,
+    /// The index said it can't match (see `grss index`)
+    Indexed,
+    /// Bigger than --max-filesize, or an archive entry bigger than
+    /// input::MAX_ENTRY_SIZE
+    TooBig,
+    /// It couldn't be read, or --pre failed on it
+    Failed,
+    /// The --output file, or an archive inside an archive
+    Other,
+}
+
+impl Skip {
+    const ALL: [Skip; 6] = [
+        Skip::Binary,
+        Skip::Filtered,
+        Skip::Indexed,
+        Skip::TooBig,
+        Skip::Failed,
+        Skip::Other,
+    ];
+}
+
+/// Counts what a search did, across every thread searching
+#[derive(Default)]
+pub struct Stats {
+    /// Files (and standard input) that were actually searched
+    files_searched: AtomicUsize,
+    /// Files left out, by Skip
+    files_skipped: [AtomicUsize; 6],
+    /// Searched files with at least one selected line
+    files_matched: AtomicUsize,
+    /// Selected lines over all files
+    lines_matched: AtomicUsize,
+    /// Matches in those lines, only counted with --stats
+    matches: AtomicUsize,
+    /// Bytes of text the search went through, after any decoding
+    bytes_searched: AtomicU64,
+    /// Every file with a selected line, only kept with --summary
+    files: Mutex<Vec<FileCount>>,
+}
+
+/// A file's line in the --summary table
+#[derive(Debug, PartialEq)]
+pub struct FileCount {
+    pub path: PathBuf,
+    /// Selected lines
+    pub lines: usize,
+    /// Matches in those lines
+    pub matches: usize,
+}
+
+/// What --stats prints at the end: a search's Stats, added up
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename = "summary")]
+pub struct Summary {
+    pub files_searched: usize,
+    pub files_matched: usize,
+    pub files_skipped: Skipped,
+    pub matched_lines: usize,
+    pub total_matches: usize,
+    pub bytes_searched: u64,
+    /// Wall time
+    #[serde(rename = "elapsed_ms", serialize_with = "milliseconds")]
+    pub elapsed: Duration,
+}
+
+/// How many files were left out, in all and for each Skip
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Skipped {
+    pub total: usize,
+    pub binary: usize,
+    pub filtered: usize,
+    pub indexed: usize,
+    pub too_big: usize,
+    pub failed: usize,
+    pub other: usize,
+}
+
+fn milliseconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+impl Stats {
+    /// Counts a file that was searched and had `lines` selected lines with
+    /// `matches` matches in them
+    pub fn searched(&self, lines: usize, matches: usize) {
+        self.files_searched.fetch_add(1, Ordering::Relaxed);
+        self.lines_matched.fetch_add(lines, Ordering::Relaxed);
+        self.matches.fetch_add(matches, Ordering::Relaxed);
+        if lines > 0 {
+            self.files_matched.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Keeps the counts of `path` for the --summary table, when it had any
+    /// selected lines
+    pub fn file(&self, path: &Path, lines: usize, matches: usize) {
+        if lines == 0 {
+            return;
+        }
+        self.files.lock().unwrap().push(FileCount {
+            path: path.to_path_buf(),
+            lines,
+            matches,
+        });
+    }
+
+    /// The `limit` files with the most matches, most first; a tie goes to
+    /// the one with more lines, then by path
+    pub fn top_files(&self, limit: usize) -> Vec<FileCount> {
+        let mut files = std::mem::take(&mut *self.files.lock().unwrap());
+        files.sort_by(|a, b| {
+            (b.matches, b.lines)
+                .cmp(&(a.matches, a.lines))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        files.truncate(limit);
+        files
+    }
+
+    /// Counts `files` files that were left out, for `why`
+    pub fn skipped(&self, why: Skip, files: usize) {
+        self.files_skipped[why as usize].fetch_add(files, Ordering::Relaxed);
+    }
+
+    /// Counts `bytes` bytes of text searched
+    pub fn read(&self, bytes: usize) {
+        self.bytes_searched
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Adds up what was counted, for a search that took `elapsed` of wall
+    /// time
+    pub fn summary(&self, elapsed: Duration) -> Summary {
+        let get = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        let skipped = |why: Skip| get(&self.files_skipped[why as usize]);
+        Summary {
+            files_searched: get(&self.files_searched),
+            files_matched: get(&self.files_matched),
+            files_skipped: Skipped {
+                total: Skip::ALL.into_iter().map(skipped).sum(),
+                binary: skipped(Skip::Binary),
+                filtered: skipped(Skip::Filtered),
+                indexed: skipped(Skip::Indexed),
+                too_big: skipped(Skip::TooBig),
+                failed: skipped(Skip::Failed),
+                other: skipped(Skip::Other),
+            },
+            matched_lines: get(&self.lines_matched),
+            total_matches: get(&self.matches),
+            bytes_searched: self.bytes_searched.load(Ordering::Relaxed),
+            elapsed,
+        }
+    }
+}
+
+impl Summary {
+    /// Writes the summary as text, after a blank line to set it apart from
+    /// the results
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out)?;
+        writeln!(out, "{} matched lines", self.matched_lines)?;
+        writeln!(out, "{} files contained matches", self.files_matched)?;
+        writeln!(out, "{} files searched", self.files_searched)?;
+        writeln!(out, "{} files skipped", self.files_skipped.total)?;
+        writeln!(out, "{} bytes searched", self.bytes_searched)?;
+        writeln!(out, "{:.6} seconds", self.elapsed.as_secs_f64())
+    }
+
+    /// Writes the summary as one line of JSON (--json), its `type` telling
+    /// it apart from the records of the matches before it
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        serde_json::to_writer(&mut *out, self)?;
+        writeln!(out)
+    }
+}
+
+/// Writes `files` as the --summary table: a `path  lines  matches` header,
+/// then a row for each file, with the numbers lined up on the right
+///
+/// It's set apart from the results by a blank line when `after_results`
+/// says some were written before it; with -q it's the first thing out.
+pub fn write_table<W: Write>(
+    files: &[FileCount],
+    after_results: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let paths: Vec<String> = files
+        .iter()
+        .map(|file| file.path.display().to_string())
+        .collect();
+    // Padding counts characters, and so do these
+    let width = |header: &str, cells: &mut dyn Iterator<Item = String>| {
+        cells
+            .map(|cell| cell.chars().count())
+            .fold(header.len(), usize::max)
+    };
+    let path_width = width("path", &mut paths.iter().cloned());
+    let lines_width = width(
+        "lines",
+        &mut files.iter().map(|file| file.lines.to_string()),
+    );
+    let matches_width = width(
+        "matches",
+        &mut files.iter().map(|file| file.matches.to_string()),
+    );
+    if after_results {
+        writeln!(out)?;
+    }
+    let mut row = |path: &str, lines: &str, matches: &str| {
+        writeln!(
+            out,
+            "{:<path_width$}  {:>lines_width$}  {:>matches_width$}",
+            path, lines, matches
+        )
+    };
+    row("path", "lines", "matches")?;
+    for (file, path) in files.iter().zip(&paths) {
+        row(path, &file.lines.to_string(), &file.matches.to_string())?;
+    }
+    Ok(())
+}
+
+/// What --debug-timing breaks the time of a search down into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Going through directories to find the files to search
+    Walk,
+    /// Opening files and reading (or decompressing, or running --pre on)
+    /// them
+    Read,
+    /// Running the patterns over what was read, which is whatever time a
+    /// file took that isn't counted as Read or Print
+    Match,
+    /// Formatting and writing the output
+    Print,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::Walk, Phase::Read, Phase::Match, Phase::Print];
+
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Walk => "walk",
+            Phase::Read => "read",
+            Phase::Match => "match",
+            Phase::Print => "print",
+        }
+    }
+}
+
+/// How long each Phase took, for one thread
+///
+/// Cheap enough to always be on: time is only taken around whole files,
+/// buffer fills (see Timed) and printed lines, never around each line
+/// searched, and adding it up is a plain add without any atomics.
+#[derive(Default)]
+pub struct Metrics {
+    spent: [Cell<Duration>; 4],
+}
+
+impl Metrics {
+    /// Counts `duration` more to `phase`
+    pub fn add(&self, phase: Phase, duration: Duration) {
+        let spent = &self.spent[phase as usize];
+        spent.set(spent.get() + duration);
+    }
+
+    /// How long went to `phase` so far
+    pub fn spent(&self, phase: Phase) -> Duration {
+        self.spent[phase as usize].get()
+    }
+
+    /// Runs `run` and counts the time it took to `phase`
+    pub fn time<T>(&self, phase: Phase, run: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = run();
+        self.add(phase, started.elapsed());
+        result
+    }
+
+    /// Runs `run` and counts to `phase` whatever of its time wasn't counted
+    /// to another phase while it ran
+    pub fn time_rest<T>(&self, phase: Phase, run: impl FnOnce() -> T) -> T {
+        let total = || self.spent.iter().map(Cell::get).sum::<Duration>();
+        let before = total();
+        let started = Instant::now();
+        let result = run();
+        let elapsed = started.elapsed();
+        self.add(phase, elapsed.saturating_sub(total() - before));
+        result
+    }
+
+    /// Adds everything `other` counted to this
+    pub fn merge(&self, other: &Metrics) {
+        for phase in Phase::ALL {
+            self.add(phase, other.spent(phase));
+        }
+    }
+
+    /// Writes the breakdown of a search that took `elapsed` of wall time,
+    /// with the file and byte counts from `stats` and the `engine` that
+    /// matched
+    ///
+    /// Read, match and print are added up over every thread that searched,
+    /// so with several threads they can come to more than the wall time.
+    pub fn write<W: Write>(
+        &self,
+        out: &mut W,
+        stats: &Stats,
+        engine: Engine,
+        elapsed: Duration,
+    ) -> io::Result<()> {
+        writeln!(out, "timing:")?;
+        for phase in Phase::ALL {
+            let seconds = self.spent(phase).as_secs_f64();
+            writeln!(out, "  {:<6}{:>12.6} seconds", phase.name(), seconds)?;
+        }
+        writeln!(
+            out,
+            "  {:<6}{:>12.6} seconds",
+            "wall",
+            elapsed.as_secs_f64()
+        )?;
+        writeln!(
+            out,
+            "  {} files searched, {} bytes",
+            stats.files_searched.load(Ordering::Relaxed),
+            stats.bytes_searched.load(Ordering::Relaxed)
+        )?;
+        writeln!(out, "  {} engine", engine)
+    }
+}
+
+/// A reader that counts the time spent in every read() to Phase::Read
+///
+/// Goes underneath the BufReader, so its read()s fill whole buffers and
+/// there are two Instant::now() calls per buffer, not per line.
+pub struct Timed<'m, R> {
+    inner: R,
+    metrics: &'m Metrics,
+}
+
+impl<'m, R> Timed<'m, R> {
+    pub fn new(inner: R, metrics: &'m Metrics) -> Self {
+        Timed { inner, metrics }
+    }
+}
+
+impl<R: Read> Read for Timed<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.metrics.time(Phase::Read, || self.inner.read(buf))
+    }
+}
+
+/// A reader that counts every byte taken out of it into `stats`
+///
+/// The Searcher reads lines with read_until(), which consume()s exactly up
+/// to the end of each line, so a search that stops early only counts what it
+/// really went through, not the rest of the buffer.
+pub struct Counted<'s, R> {
+    inner: R,
+    stats: &'s Stats,
+}
+
+impl<'s, R> Counted<'s, R> {
+    pub fn new(inner: R, stats: &'s Stats) -> Self {
+        Counted { inner, stats }
+    }
+}
+
+impl<R: Read> Read for Counted<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.stats.read(read);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for Counted<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.stats.read(amount);
+        self.inner.consume(amount);
+    }
+}
+
+/// A writer that notes whether anything was written through it, for the
+/// --summary table to know if there are results before it
+pub struct Noted<W> {
+    inner: W,
+    written: bool,
+}
+
+impl<W> Noted<W> {
+    pub fn new(inner: W) -> Self {
+        Noted {
+            inner,
+            written: false,
+        }
+    }
+
+    /// Whether any bytes were written so far
+    pub fn written(&self) -> bool {
+        self.written
+    }
+}
+
+impl<W: Write> Write for Noted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written |= written > 0;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_add_up_and_are_written_out() {
+        let stats = Stats::default();
+        stats.searched(3, 4);
+        stats.searched(0, 0);
+        stats.skipped(Skip::Binary, 1);
+        stats.skipped(Skip::Filtered, 1);
+        let mut reader = Counted::new(&b"hello\nworld\n"[..], &stats);
+        reader.read_until(b'\n', &mut Vec::new()).unwrap();
+
+        let summary = stats.summary(Duration::from_millis(1500));
+        let mut out = Vec::new();
+        summary.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\n3 matched lines\n1 files contained matches\n2 files searched\n\
+             2 files skipped\n6 bytes searched\n1.500000 seconds\n"
+        );
+
+        let mut out = Vec::new();
+        summary.write_json(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"type\":\"summary\",\"files_searched\":2,\"files_matched\":1,\
+             \"files_skipped\":{\"total\":2,\"binary\":1,\"filtered\":1,\"indexed\":0,\
+             \"too_big\":0,\"failed\":0,\"other\":0},\"matched_lines\":3,\
+             \"total_matches\":4,\"bytes_searched\":6,\"elapsed_ms\":1500}\n"
+        );
+    }
+
+    #[test]
+    fn top_files_go_by_matches_then_lines_then_path() {
+        let stats = Stats::default();
+        stats.file(Path::new("b.rs"), 2, 5);
+        stats.file(Path::new("none.rs"), 0, 0);
+        stats.file(Path::new("c.rs"), 3, 5);
+        stats.file(Path::new("a.rs"), 2, 5);
+        stats.file(Path::new("most.rs"), 1, 12);
+        let top: Vec<_> = stats
+            .top_files(3)
+            .into_iter()
+            .map(|file| (file.path.display().to_string(), file.lines, file.matches))
+            .collect();
+        let row = |path: &str, lines, matches| (path.to_string(), lines, matches);
+        assert_eq!(
+            top,
+            [row("most.rs", 1, 12), row("c.rs", 3, 5), row("a.rs", 2, 5)]
+        );
+    }
+
+    #[test]
+    fn table_lines_up_the_columns() {
+        let file = |path: &str, lines, matches| FileCount {
+            path: PathBuf::from(path),
+            lines,
+            matches,
+        };
+        let files = [file("src/main.rs", 120, 1234), file("é.rs", 3, 4)];
+        let mut out = Vec::new();
+        write_table(&files, true, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\npath         lines  matches\n\
+             src/main.rs    120     1234\n\
+             é.rs             3        4\n"
+        );
+    }
+
+    #[test]
+    fn time_rest_leaves_out_what_other_phases_counted() {
+        let metrics = Metrics::default();
+        metrics.time_rest(Phase::Match, || {
+            metrics.add(Phase::Read, Duration::from_secs(3600));
+        });
+        // The hour of reading was more than the whole call took
+        assert_eq!(metrics.spent(Phase::Match), Duration::ZERO);
+        assert_eq!(metrics.spent(Phase::Read), Duration::from_secs(3600));
+
+        let mut data = Vec::new();
+        Timed::new(&b"hello"[..], &metrics)
+            .read_to_end(&mut data)
+            .unwrap();
+        assert!(metrics.spent(Phase::Read) > Duration::from_secs(3600));
+
+        let merged = Metrics::default();
+        merged.merge(&metrics);
+        merged.merge(&metrics);
+        assert_eq!(merged.spent(Phase::Read), metrics.spent(Phase::Read) * 2);
+    }
+}
@@ -0,0 +1,164 @@
+// FilePath: rust/rust-cli/grss/src/subcommands.rs
+
+//! `grss search`, `grss count` and `grss files`: the search by name
+//!
+//! `grss search ARGS` is `grss ARGS`, `grss count ARGS` is `grss --count
+//! ARGS` (or --count-matches) and `grss files ARGS` is `grss --files ARGS`.
+//! What they add is a command line of their own that only takes the flags
+//! that mean something for them, so `grss count --help` is short and
+//! `grss count -n` is an error rather than ignored. The flags they share
+//! with the search are the same PatternArgs and WalkArgs it flattens in.
+//!
+//! Once checked, their arguments are turned back into the plain search's,
+//! which then go through GRSS_OPTS, the config file and parse() like any
+//! other: whichever way a search is written, it ends up as the same Cli.
+//! A pattern that happens to be `search`, `count` or `files` still works
+//! after `--` or with -e, like one that's `replace`.
+
+use crate::{PatternArgs, WalkArgs};
+use clap::{ArgAction, Parser};
+use std::ffi::OsString;
+
+/// Only print how many lines matched in each file
+#[derive(Parser)]
+#[command(
+    name = "grss count",
+    bin_name = "grss count",
+    args_override_self = true,
+    disable_help_flag = true
+)]
+pub struct CountCli {
+    #[command(flatten)]
+    patterns: PatternArgs,
+    #[command(flatten)]
+    walk: WalkArgs,
+    /// Count how many times the pattern occurs instead of the lines it's
+    /// on, so `foo foo foo` counts three times
+    #[arg(long)]
+    count_matches: bool,
+    /// Start every count with the path of the file, even when only one file
+    /// is searched
+    #[arg(short = 'H', long, overrides_with = "no_filename")]
+    with_filename: bool,
+    /// Never show the path in front of the counts
+    #[arg(short = 'h', long, overrides_with = "with_filename")]
+    no_filename: bool,
+    /// Follow every file name with a NUL byte instead of `:`
+    #[arg(short = '0', short_alias = 'Z', long)]
+    null: bool,
+    /// Print help
+    #[arg(long, action = ArgAction::HelpLong)]
+    help: Option<bool>,
+}
+
+/// Print the path of every file a search would look at, without searching
+/// any of them
+#[derive(Parser)]
+#[command(
+    name = "grss files",
+    bin_name = "grss files",
+    args_override_self = true,
+    disable_help_flag = true
+)]
+pub struct FilesCli {
+    #[command(flatten)]
+    walk: WalkArgs,
+    /// Follow every path with a NUL byte instead of a newline, for `xargs
+    /// -0`
+    #[arg(short = '0', short_alias = 'Z', long)]
+    null: bool,
+    /// Print help
+    #[arg(long, action = ArgAction::HelpLong)]
+    help: Option<bool>,
+}
+
+/// Checks the arguments of `grss search`, `count` or `files` (`argv`
+/// starting with the program name, then the subcommand) against that
+/// subcommand's flags
+///
+/// # Returns
+/// The same search as the plain command line main() goes on with, or
+/// clap's error (or help) to exit with
+pub fn classic(argv: Vec<OsString>) -> Result<Vec<OsString>, clap::Error> {
+    let mut argv = argv.into_iter();
+    let program = argv.next().unwrap_or_else(|| "grss".into());
+    let subcommand = argv.next().unwrap_or_default();
+    let rest: Vec<OsString> = argv.collect();
+    let flag = match subcommand.to_str() {
+        Some("count") => {
+            let args = CountCli::try_parse_from(with_name(&subcommand, &rest))?;
+            // --count-matches is a count already, and conflicts with -c
+            (!args.count_matches).then_some("--count")
+        }
+        Some("files") => {
+            FilesCli::try_parse_from(with_name(&subcommand, &rest))?;
+            Some("--files")
+        }
+        _ => None,
+    };
+    Ok(std::iter::once(program)
+        .chain(flag.map(OsString::from))
+        .chain(rest)
+        .collect())
+}
+
+/// `rest` after `subcommand`, for the subcommand's parser
+fn with_name<'a>(subcommand: &'a OsString, rest: &'a [OsString]) -> Vec<&'a OsString> {
+    std::iter::once(subcommand).chain(rest).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cli;
+
+    /// What `argv` parses to, through classic() when it starts with a
+    /// subcommand
+    fn parsed(argv: &[&str]) -> Cli {
+        let argv: Vec<OsString> = argv.iter().map(OsString::from).collect();
+        let argv = match argv[1].to_str() {
+            Some("search" | "count" | "files") => classic(argv).unwrap(),
+            _ => argv,
+        };
+        Cli::parse_from(argv)
+    }
+
+    #[test]
+    fn subcommands_parse_like_the_plain_search() {
+        let same = [
+            (
+                &["grss", "search", "-i", "foo", "src", "-n"][..],
+                &["grss", "-i", "foo", "src", "-n"][..],
+            ),
+            (
+                &["grss", "count", "-rw", "foo", "src", "--include", "*.rs"],
+                &["grss", "-c", "-rw", "foo", "src", "--include", "*.rs"],
+            ),
+            (
+                &["grss", "count", "--count-matches", "-e", "a", "x"],
+                &["grss", "--count-matches", "-e", "a", "x"],
+            ),
+            (
+                &["grss", "files", "src", "--hidden", "-t", "rust", "-0"],
+                &["grss", "--files", "src", "--hidden", "-t", "rust", "-0"],
+            ),
+        ];
+        for (subcommand, plain) in same {
+            assert_eq!(parsed(subcommand), parsed(plain), "{subcommand:?}");
+        }
+        // The path of `grss files` goes where a pattern would, as with --files
+        let files = parsed(&["grss", "files", "src"]);
+        assert_eq!(files.patterns.pattern.as_deref(), Some("src"));
+    }
+
+    #[test]
+    fn subcommands_only_take_their_own_flags() {
+        let fails = |argv: &[&str]| classic(argv.iter().map(OsString::from).collect()).is_err();
+        assert!(fails(&["grss", "count", "-n", "foo"]));
+        assert!(fails(&["grss", "count", "-o", "foo"]));
+        assert!(fails(&["grss", "files", "-i", "src"]));
+        assert!(fails(&["grss", "files", "-e", "foo"]));
+        assert!(!fails(&["grss", "count", "-i", "-j2", "foo", "src"]));
+        assert!(!fails(&["grss", "files", "--no-ignore", "-0"]));
+    }
+}
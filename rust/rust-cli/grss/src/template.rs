@@ -0,0 +1,251 @@
+// FilePath: rust/rust-cli/grss/src/template.rs
+
+//! --replace templates: what each match is replaced with in the output
+//!
+//! The syntax is the regex crate's: `$0` is the whole match, `$1` or `$name`
+//! what a capture group matched, `${1}` or `${name}` the same when letters
+//! follow straight after, and `$$` a literal `$`. A `$` that isn't followed
+//! by any of those is kept as it is.
+//!
+//! Unlike the regex crate, which quietly puts nothing in place of a group
+//! that doesn't exist, a template is checked against the patterns before the
+//! search starts, so a typo like `$nmae` is an error instead of empty output.
+
+use crate::error::GrssError;
+use regex::bytes::Captures;
+use regex_syntax::ast::{self, Ast, GroupKind};
+use std::fmt;
+
+/// A parsed --replace template
+#[derive(Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Group(Group),
+}
+
+/// A reference to a capture group in a template
+#[derive(Debug, PartialEq, Eq)]
+pub enum Group {
+    Index(usize),
+    Name(String),
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Group::Index(index) => write!(f, "${}", index),
+            Group::Name(name) => write!(f, "${{{}}}", name),
+        }
+    }
+}
+
+impl Template {
+    /// Parses `text`, which can't fail: anything that isn't a group
+    /// reference is literal text
+    pub fn parse(text: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(dollar) = rest.find('$') {
+            literal.push_str(&rest[..dollar]);
+            let after = &rest[dollar + 1..];
+            let (group, consumed) = match after.as_bytes().first() {
+                Some(b'$') => (None, 1),
+                Some(b'{') => match after.find('}') {
+                    Some(close) if close > 1 => (Some(group(&after[1..close])), close + 1),
+                    _ => (None, 0),
+                },
+                _ => {
+                    let name_len = after
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(after.len());
+                    match name_len {
+                        0 => (None, 0),
+                        _ => (Some(group(&after[..name_len])), name_len),
+                    }
+                }
+            };
+            match group {
+                Some(group) => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Group(group));
+                }
+                // `$$`, or a `$` that doesn't start a reference
+                None => literal.push('$'),
+            }
+            rest = &after[consumed..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Template { parts }
+    }
+
+    /// Makes sure every group the template uses exists in every one of
+    /// `patterns` (with `fixed_strings`, literal strings which only have
+    /// `$0`)
+    ///
+    /// A pattern that isn't a valid regex passes, compiling it reports that
+    /// better.
+    pub fn check(&self, patterns: &[String], fixed_strings: bool) -> Result<(), GrssError> {
+        for pattern in patterns {
+            let groups = match fixed_strings {
+                true => Groups::default(),
+                false => match ast::parse::Parser::new().parse(pattern) {
+                    Ok(parsed) => ast::visit(&parsed, Groups::default()).unwrap_or_default(),
+                    Err(_) => continue,
+                },
+            };
+            for group in self.groups() {
+                let exists = match group {
+                    Group::Index(index) => *index <= groups.count,
+                    Group::Name(name) => groups.names.contains(name),
+                };
+                if !exists {
+                    return Err(GrssError::Usage(format!(
+                        "--replace uses {}, but pattern '{}' has no such group",
+                        group, pattern
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the template to `out` for one match of a regex, filling in
+    /// the groups from `captures` (a group that didn't take part in the
+    /// match adds nothing)
+    pub fn expand(&self, captures: &Captures, out: &mut Vec<u8>) {
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.extend_from_slice(text.as_bytes()),
+                Part::Group(Group::Index(index)) => {
+                    out.extend_from_slice(captures.get(*index).map_or(&[], |m| m.as_bytes()))
+                }
+                Part::Group(Group::Name(name)) => {
+                    out.extend_from_slice(captures.name(name).map_or(&[], |m| m.as_bytes()))
+                }
+            }
+        }
+    }
+
+    /// Appends the template to `out` for one match of a -F pattern, which
+    /// has nothing but `$0`
+    pub fn expand_literal(&self, matched: &[u8], out: &mut Vec<u8>) {
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.extend_from_slice(text.as_bytes()),
+                Part::Group(Group::Index(0)) => out.extend_from_slice(matched),
+                Part::Group(_) => {}
+            }
+        }
+    }
+
+    fn groups(&self) -> impl Iterator<Item = &Group> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Group(group) => Some(group),
+            Part::Literal(_) => None,
+        })
+    }
+}
+
+/// `$12` is group 12, `$name` (or even `$1a`) a named group
+fn group(name: &str) -> Group {
+    match name.parse() {
+        Ok(index) if name.bytes().all(|byte| byte.is_ascii_digit()) => Group::Index(index),
+        _ => Group::Name(name.to_string()),
+    }
+}
+
+/// The capture groups of a pattern, collected from its syntax tree
+#[derive(Default)]
+struct Groups {
+    /// How many there are, not counting group 0 (the whole match)
+    count: usize,
+    names: Vec<String>,
+}
+
+impl ast::Visitor for Groups {
+    type Output = Groups;
+    type Err = ();
+
+    fn finish(self) -> Result<Groups, ()> {
+        Ok(self)
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), ()> {
+        if let Ast::Group(group) = ast {
+            match &group.kind {
+                GroupKind::CaptureIndex(_) => self.count += 1,
+                GroupKind::CaptureName { name, .. } => {
+                    self.count += 1;
+                    self.names.push(name.name.clone());
+                }
+                GroupKind::NonCapturing(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::bytes::Regex;
+
+    fn expand(pattern: &str, template: &str, haystack: &str) -> String {
+        let captures = Regex::new(pattern)
+            .unwrap()
+            .captures(haystack.as_bytes())
+            .unwrap();
+        let mut out = Vec::new();
+        Template::parse(template).expand(&captures, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn numbered_and_named_groups_and_escapes() {
+        let pattern = r#"(?P<key>\w+) = "(.*)""#;
+        let line = r#"name = "grss""#;
+        assert_eq!(expand(pattern, "$2", line), "grss");
+        assert_eq!(expand(pattern, "$key: $2", line), "name: grss");
+        assert_eq!(expand(pattern, "${key}s", line), "names");
+        assert_eq!(expand(pattern, "<$0>", line), r#"<name = "grss">"#);
+        assert_eq!(expand(pattern, "$$1 costs $$$2", line), "$1 costs $grss");
+        assert_eq!(expand(pattern, "100$ and $-", line), "100$ and $-");
+    }
+
+    #[test]
+    fn missing_groups_are_caught_up_front() {
+        let patterns = [r"(?P<key>\w+)=(\d+)".to_string()];
+        assert!(
+            Template::parse("$0 $1 $2 $key")
+                .check(&patterns, false)
+                .is_ok()
+        );
+        let error = Template::parse("$3").check(&patterns, false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r"--replace uses $3, but pattern '(?P<key>\w+)=(\d+)' has no such group"
+        );
+        assert!(Template::parse("$nmae").check(&patterns, false).is_err());
+        // Non-capturing groups don't count
+        assert!(
+            Template::parse("$1")
+                .check(&["(?:a)".to_string()], false)
+                .is_err()
+        );
+        // -F patterns only have the whole match
+        let literal = ["(a)".to_string()];
+        assert!(Template::parse("[$0]").check(&literal, true).is_ok());
+        assert!(Template::parse("$1").check(&literal, true).is_err());
+    }
+}
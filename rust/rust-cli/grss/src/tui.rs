@@ -0,0 +1,602 @@
+// FilePath: rust/rust-cli/grss/src/tui.rs
+
+//! --tui: browsing the matches interactively instead of printing them
+//!
+//! The search runs on a thread of its own and sends every match over to the
+//! interface as it's found, so the list fills up while a big tree is still
+//! being searched and the keys keep working the whole time. Changing the
+//! pattern with `/` stops that search and starts a new one on every key
+//! typed; each search has a number, and whatever an old one still manages to
+//! send is dropped.
+//!
+//! The interface is drawn on the terminal itself (/dev/tty) rather than on
+//! stdout, which is left for the one line written when a match is picked:
+//! its file's path, so `$EDITOR $(grss --tui foo -r .)` opens it. grss exits
+//! with 0 then, and with 1 when nothing was picked. Whatever happens,
+//! panics included, the terminal is put back the way it was.
+//!
+//! Warnings would scribble over the interface, so they're not printed while
+//! it's up. A path that can't be searched at all shows in the status line.
+//! Files are searched as they are: --pre, -z and --search-archives don't
+//! apply here.
+//!
+//! Only built with the `tui` feature, being the only part of grss that needs
+//! ratatui.
+
+use crate::App;
+use crate::Cli;
+use grss::input;
+use grss::{GrssError, LineKind, Stop};
+use ratatui::Frame;
+use ratatui::Terminal;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::crossterm::{cursor, execute};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{List, ListItem, ListState, Paragraph};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Once};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for a key before drawing what the search found since
+const FRAME: Duration = Duration::from_millis(50);
+
+/// At most this many matches are taken from the search per frame, so a
+/// flood of them can't hold up the keys
+const MAX_PER_FRAME: usize = 5_000;
+
+/// A search stops after this many matches; more than that isn't browsed
+/// through anyway, and they'd all be kept in memory
+const MAX_HITS: usize = 100_000;
+
+/// Whether the terminal is in raw mode on the alternate screen, and so has
+/// to be put back (see restore_terminal())
+static TERMINAL_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// One match in the list
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hit {
+    pub path: PathBuf,
+    pub number: usize,
+    pub line: String,
+}
+
+/// What a search sends over to the interface
+#[derive(Debug, PartialEq, Eq)]
+pub enum Found {
+    Hit(Hit),
+    /// A path that couldn't be searched, and why
+    Problem(String),
+    /// MAX_HITS was reached, the search stopped there
+    Full,
+    Done,
+}
+
+/// What the interface should do after a key
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    Nothing,
+    /// Search again for this pattern
+    Search(String),
+    /// Exit, printing this match's file
+    Pick(Hit),
+    /// Exit without picking anything
+    Quit,
+}
+
+/// Everything the interface shows, and what the keys do to it
+///
+/// Knows nothing about terminals, so it can be driven key by key in tests.
+pub struct Browser {
+    hits: Vec<Hit>,
+    selected: usize,
+    /// How many matches fit on the screen, for PageUp and PageDown
+    page: usize,
+    /// What's being searched for
+    pattern: String,
+    /// While `/` is changing the pattern, what it was before, for Esc
+    refining: Option<String>,
+    /// The number of the search the matches are from
+    search: usize,
+    searching: bool,
+    full: bool,
+    /// The last problem a search ran into, or why the pattern is invalid
+    message: Option<String>,
+}
+
+impl Browser {
+    pub fn new(pattern: &str) -> Self {
+        Browser {
+            hits: Vec::new(),
+            selected: 0,
+            page: 1,
+            pattern: pattern.to_string(),
+            refining: None,
+            search: 0,
+            searching: true,
+            full: false,
+            message: None,
+        }
+    }
+
+    /// Starts over for search number `search`, which looks for the pattern
+    /// as it is now
+    pub fn restart(&mut self, search: usize) {
+        self.hits.clear();
+        self.selected = 0;
+        self.search = search;
+        self.searching = true;
+        self.full = false;
+        self.message = None;
+    }
+
+    /// Shows why the pattern as it is now couldn't be searched for; the
+    /// matches of the last one that could stay in the list
+    pub fn invalid(&mut self, error: &GrssError) {
+        self.message = Some(error.to_string());
+    }
+
+    /// Takes in what search number `search` found, unless that's an old one
+    pub fn found(&mut self, search: usize, found: Found) {
+        if search != self.search {
+            return;
+        }
+        match found {
+            Found::Hit(hit) => self.hits.push(hit),
+            Found::Problem(problem) => self.message = Some(problem),
+            Found::Full => self.full = true,
+            Found::Done => self.searching = false,
+        }
+    }
+
+    pub fn key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+        match self.refining.is_some() {
+            true => self.refine_key(key),
+            false => self.browse_key(key),
+        }
+    }
+
+    fn browse_key(&mut self, key: KeyEvent) -> Action {
+        let last = self.hits.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Enter => {
+                if let Some(hit) = self.hits.get(self.selected) {
+                    return Action::Pick(hit.clone());
+                }
+            }
+            KeyCode::Char('/') => self.refining = Some(self.pattern.clone()),
+            KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(last),
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::PageDown => self.selected = (self.selected + self.page).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(self.page),
+            KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => self.selected = last,
+            _ => {}
+        }
+        Action::Nothing
+    }
+
+    fn refine_key(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Enter => {
+                self.refining = None;
+                Action::Nothing
+            }
+            KeyCode::Esc => {
+                let before = self.refining.take().unwrap_or_default();
+                if before == self.pattern {
+                    return Action::Nothing;
+                }
+                self.pattern = before;
+                Action::Search(self.pattern.clone())
+            }
+            KeyCode::Backspace => match self.pattern.pop() {
+                Some(_) => Action::Search(self.pattern.clone()),
+                None => Action::Nothing,
+            },
+            KeyCode::Char(c) => {
+                self.pattern.push(c);
+                Action::Search(self.pattern.clone())
+            }
+            _ => Action::Nothing,
+        }
+    }
+
+    /// Draws the list of matches with the status line under it
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list_area, status_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        self.page = usize::from(list_area.height).max(1);
+
+        // Only what's on screen is turned into widgets, however many
+        // matches there are: the list starts a page before the selection at
+        // the most, and the selection is kept in view within that
+        let first = self.selected.saturating_sub(self.page - 1);
+        let items = self.hits[first..].iter().take(self.page).map(|hit| {
+            ListItem::new(Line::from(vec![
+                Span::from(hit.path.display().to_string()).magenta(),
+                Span::from(":"),
+                Span::from(hit.number.to_string()).green(),
+                Span::from(": "),
+                Span::from(hit.line.trim().to_string()),
+            ]))
+        });
+        let list = List::new(items).highlight_style(Style::new().reversed());
+        let mut state = ListState::default().with_selected(Some(self.selected - first));
+        frame.render_stateful_widget(list, list_area, &mut state);
+
+        frame.render_widget(Paragraph::new(self.status()), status_area);
+    }
+
+    /// The status line: the pattern (being typed, with `/`), how many
+    /// matches so far, and any problem
+    fn status(&self) -> Line<'static> {
+        let pattern = match self.refining {
+            Some(_) => Span::from(format!("/{}", self.pattern)).bold(),
+            None => Span::from(self.pattern.clone()).bold(),
+        };
+        let mut count = format!("  {} matches", self.hits.len());
+        if self.full {
+            count.push_str(" (stopped there)");
+        } else if self.searching {
+            count.push_str(", searching...");
+        }
+        let mut spans = vec![pattern, Span::from(count)];
+        if let Some(message) = &self.message {
+            spans.push(Span::from(format!("  {}", message)).red());
+        }
+        Line::from(spans)
+    }
+}
+
+/// Runs the interface, searching `paths` for `patterns` to start with
+///
+/// # Returns
+/// Whether a match was picked
+pub fn run(args: &Cli, patterns: &[String], paths: &[PathBuf]) -> Result<bool, GrssError> {
+    if paths.iter().any(|path| path.as_os_str() == "-") {
+        return Err(GrssError::Usage(
+            "--tui can't search standard input, it reads the keys from the terminal".to_string(),
+        ));
+    }
+    let app = Arc::new(App::new(args, patterns, None)?);
+    // Several -e patterns can only be changed into one
+    let shown = match patterns {
+        [pattern] => pattern.as_str(),
+        _ => "",
+    };
+    let tty = open_terminal().map_err(|error| GrssError::io(Path::new("/dev/tty"), error))?;
+    let log_level = log::max_level();
+    log::set_max_level(log::LevelFilter::Off);
+    let picked = take_terminal(tty).and_then(|terminal| {
+        let _restore = RestoreOnDrop;
+        browse(terminal, args, app, shown, paths)
+    });
+    log::set_max_level(log_level);
+
+    match picked.map_err(|error| GrssError::io(Path::new("/dev/tty"), error))? {
+        Some(hit) => {
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{}", hit.path.display())
+                .and_then(|()| stdout.flush())
+                .map_err(|error| GrssError::io(Path::new("(standard output)"), error))?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// The event loop: draws, takes in what the search found and handles keys,
+/// until a match is picked (which it returns) or the user quits
+fn browse(
+    mut terminal: Terminal<CrosstermBackend<File>>,
+    args: &Cli,
+    app: Arc<App>,
+    pattern: &str,
+    paths: &[PathBuf],
+) -> io::Result<Option<Hit>> {
+    let (sender, receiver) = mpsc::channel();
+    let mut browser = Browser::new(pattern);
+    thread::scope(|scope| {
+        let mut current = app;
+        let mut search = 0;
+        start(scope, &current, search, paths, &sender);
+        let picked = loop {
+            take_found(&mut browser, &receiver);
+            terminal.draw(|frame| browser.draw(frame))?;
+            if !event::poll(FRAME)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match browser.key(key) {
+                Action::Nothing => {}
+                Action::Pick(hit) => break Some(hit),
+                Action::Quit => break None,
+                Action::Search(pattern) => match App::new(args, &[pattern], None) {
+                    Ok(next) => {
+                        current.stopped.store(true, Ordering::Relaxed);
+                        current = Arc::new(next);
+                        search += 1;
+                        browser.restart(search);
+                        start(scope, &current, search, paths, &sender);
+                    }
+                    Err(error) => browser.invalid(&error),
+                },
+            }
+        };
+        // The scope waits for the search thread, which sees this soon
+        current.stopped.store(true, Ordering::Relaxed);
+        Ok(picked)
+    })
+}
+
+/// Takes what the searches found since the last frame into `browser`
+fn take_found(browser: &mut Browser, receiver: &Receiver<(usize, Found)>) {
+    for (search, found) in receiver.try_iter().take(MAX_PER_FRAME) {
+        browser.found(search, found);
+    }
+}
+
+/// Starts search number `search` on a thread of its own
+fn start<'scope, 'env>(
+    scope: &'scope thread::Scope<'scope, 'env>,
+    app: &Arc<App<'env>>,
+    search: usize,
+    paths: &'env [PathBuf],
+    sender: &Sender<(usize, Found)>,
+) {
+    let app = Arc::clone(app);
+    let sender = sender.clone();
+    scope.spawn(move || {
+        let send = |found| {
+            // The interface is gone, nothing more is wanted
+            if sender.send((search, found)).is_err() {
+                app.stopped.store(true, Ordering::Relaxed);
+            }
+        };
+        search_paths(&app, paths, &send);
+        send(Found::Done);
+    });
+}
+
+/// Searches every one of `paths`, sending what's found with `send`
+fn search_paths(app: &App, paths: &[PathBuf], send: &dyn Fn(Found)) {
+    let mut hits = 0;
+    for path in paths {
+        if app.is_stopped() {
+            break;
+        }
+        let searched = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_dir() && app.args.walk.recursive => {
+                app.walk(path, |file, _| {
+                    if let Err(error) = search_file(app, &file, &mut hits, send)
+                        && !app.is_stopped()
+                    {
+                        send(Found::Problem(GrssError::io(&file, error).to_string()));
+                    }
+                });
+                Ok(())
+            }
+            Ok(metadata) if metadata.is_dir() => Err(GrssError::IsADirectory(path.clone())),
+            Ok(_) => search_file(app, path, &mut hits, send).map_err(|e| GrssError::io(path, e)),
+            Err(error) => Err(GrssError::io(path, error)),
+        };
+        if let Err(error) = searched
+            && !app.is_stopped()
+        {
+            send(Found::Problem(error.to_string()));
+        }
+    }
+}
+
+/// Searches the file at `path`, sending each selected line as a Hit, and
+/// stopping everything once `hits` reaches MAX_HITS; binary files are
+/// skipped, unless --text
+fn search_file(app: &App, path: &Path, hits: &mut usize, send: &dyn Fn(Found)) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let head = reader.fill_buf()?;
+    if !app.args.text && !input::has_utf16_bom(head) && input::is_binary(head) {
+        return Ok(());
+    }
+    let reader = crate::Interruptible::new(app.decode(reader), &app.stopped);
+    app.searcher.search_reader(reader, &mut |found| {
+        if found.kind != LineKind::Selected {
+            return Ok(None);
+        }
+        send(Found::Hit(Hit {
+            path: path.to_path_buf(),
+            number: found.number,
+            line: String::from_utf8_lossy(found.line).into_owned(),
+        }));
+        *hits += 1;
+        if *hits < MAX_HITS {
+            return Ok(None);
+        }
+        send(Found::Full);
+        app.stopped.store(true, Ordering::Relaxed);
+        Ok(Some(Stop::All))
+    })?;
+    Ok(())
+}
+
+/// The terminal to draw on, even when stdout and stdin are redirected
+fn open_terminal() -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).open("/dev/tty")
+}
+
+/// Puts the terminal in raw mode on the alternate screen, making sure it's
+/// put back if grss panics while it's like that
+fn take_terminal(tty: File) -> io::Result<Terminal<CrosstermBackend<File>>> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            previous(info);
+        }));
+    });
+    terminal::enable_raw_mode()?;
+    TERMINAL_TAKEN.store(true, Ordering::SeqCst);
+    let mut backend = CrosstermBackend::new(tty);
+    execute!(backend, EnterAlternateScreen)?;
+    Terminal::new(backend)
+}
+
+/// Puts the terminal back the way take_terminal() found it, if it hasn't
+/// been already
+fn restore_terminal() {
+    if !TERMINAL_TAKEN.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let _ = terminal::disable_raw_mode();
+    if let Ok(mut tty) = open_terminal() {
+        let _ = execute!(tty, LeaveAlternateScreen, cursor::Show);
+    }
+}
+
+/// Calls restore_terminal() when dropped, i.e. however browse() ends
+struct RestoreOnDrop;
+
+impl Drop for RestoreOnDrop {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::from(code)
+    }
+
+    fn hit(number: usize) -> Hit {
+        Hit {
+            path: PathBuf::from("src/main.rs"),
+            number,
+            line: format!("line {}", number),
+        }
+    }
+
+    #[test]
+    fn moving_through_the_list_and_picking() {
+        let mut browser = Browser::new("line");
+        assert_eq!(browser.key(press(KeyCode::Enter)), Action::Nothing);
+        for number in 1..=30 {
+            browser.found(0, Found::Hit(hit(number)));
+        }
+        browser.page = 10;
+
+        browser.key(press(KeyCode::Down));
+        browser.key(press(KeyCode::Char('j')));
+        assert_eq!(browser.key(press(KeyCode::Enter)), Action::Pick(hit(3)));
+        browser.key(press(KeyCode::PageDown));
+        assert_eq!(browser.selected, 12);
+        browser.key(press(KeyCode::End));
+        browser.key(press(KeyCode::Down));
+        assert_eq!(browser.selected, 29);
+        browser.key(press(KeyCode::Home));
+        browser.key(press(KeyCode::Up));
+        assert_eq!(browser.selected, 0);
+
+        assert_eq!(browser.key(press(KeyCode::Char('q'))), Action::Quit);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(browser.key(ctrl_c), Action::Quit);
+    }
+
+    #[test]
+    fn refining_searches_again_on_every_key() {
+        let mut browser = Browser::new("fo");
+        browser.found(0, Found::Hit(hit(1)));
+        browser.found(0, Found::Done);
+        assert!(!browser.searching);
+
+        assert_eq!(browser.key(press(KeyCode::Char('/'))), Action::Nothing);
+        // q is typed into the pattern now, it doesn't quit
+        assert_eq!(
+            browser.key(press(KeyCode::Char('q'))),
+            Action::Search("foq".to_string())
+        );
+        browser.restart(1);
+        assert!(browser.hits.is_empty());
+        assert_eq!(
+            browser.key(press(KeyCode::Backspace)),
+            Action::Search("fo".to_string())
+        );
+        browser.restart(2);
+        assert_eq!(
+            browser.key(press(KeyCode::Char('o'))),
+            Action::Search("foo".to_string())
+        );
+        browser.restart(3);
+
+        // What an old search still sends is dropped
+        browser.found(2, Found::Hit(hit(7)));
+        browser.found(3, Found::Hit(hit(8)));
+        assert_eq!(browser.hits, [hit(8)]);
+
+        // Esc goes back to the pattern from before `/`
+        assert_eq!(
+            browser.key(press(KeyCode::Esc)),
+            Action::Search("fo".to_string())
+        );
+        assert_eq!(browser.key(press(KeyCode::Char('/'))), Action::Nothing);
+        assert_eq!(browser.key(press(KeyCode::Enter)), Action::Nothing);
+        assert_eq!(browser.key(press(KeyCode::Esc)), Action::Quit);
+    }
+
+    #[test]
+    fn the_status_line_tells_how_the_search_is_going() {
+        let text = |browser: &Browser| browser.status().to_string();
+        let mut browser = Browser::new("foo(");
+        browser.invalid(&GrssError::Usage("bad pattern".to_string()));
+        assert_eq!(text(&browser), "foo(  0 matches, searching...  bad pattern");
+
+        browser.restart(1);
+        browser.found(1, Found::Hit(hit(1)));
+        browser.found(1, Found::Full);
+        assert_eq!(text(&browser), "foo(  1 matches (stopped there)");
+    }
+
+    #[test]
+    fn searching_sends_every_selected_line() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "one\ntwo\nthree\n").unwrap();
+        fs::write(dir.path().join("b.bin"), b"two\0\n").unwrap();
+        let args = Cli::parse_from(["grss", "-r", "t", dir.path().to_str().unwrap()]);
+        let (patterns, paths) = args.patterns_and_paths().unwrap();
+        let app = App::new(&args, &patterns, None).unwrap();
+
+        let found = std::cell::RefCell::new(Vec::new());
+        search_paths(&app, &paths, &|hit| found.borrow_mut().push(hit));
+        let numbers: Vec<_> = found
+            .into_inner()
+            .into_iter()
+            .map(|found| match found {
+                Found::Hit(hit) => (hit.path.file_name().unwrap().to_owned(), hit.number),
+                other => panic!("{:?}", other),
+            })
+            .collect();
+        assert_eq!(numbers, [("a.txt".into(), 2), ("a.txt".into(), 3)]);
+    }
+}
@@ -0,0 +1,146 @@
+// FilePath: rust/rust-cli/grss/src/types.rs
+
+//! File types for --type and --type-not: short names for sets of globs
+//!
+//! `--type rust` is easier to remember (and type) than `--include '*.rs'`,
+//! and `--type cpp` saves spelling out the half a dozen extensions C++ files
+//! come with. A few common types are built in; --type-add defines more, or
+//! adds globs to a built-in one.
+
+use crate::error::GrssError;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// The built-in types, each with the file name globs that make it up
+pub const DEFAULT_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    (
+        "cpp",
+        &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"],
+    ),
+    ("css", &["*.css", "*.scss"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"]),
+    ("json", &["*.json"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// Every known file type, by name
+///
+/// A BTreeMap keeps the names sorted, which is the order --type-list prints
+/// them in
+pub struct FileTypes {
+    types: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for FileTypes {
+    /// Just the built-in types
+    fn default() -> Self {
+        let types = DEFAULT_TYPES
+            .iter()
+            .map(|(name, globs)| {
+                (
+                    name.to_string(),
+                    globs.iter().map(|g| g.to_string()).collect(),
+                )
+            })
+            .collect();
+        FileTypes { types }
+    }
+}
+
+impl FileTypes {
+    /// Adds a `NAME:GLOB` definition from --type-add
+    ///
+    /// A new name becomes a new type, an existing one (built in or not) gets
+    /// the glob on top of the ones it already has
+    pub fn add(&mut self, definition: &str) -> Result<(), GrssError> {
+        match definition.split_once(':') {
+            Some((name, glob)) if !name.is_empty() && !glob.is_empty() => {
+                self.types
+                    .entry(name.to_string())
+                    .or_default()
+                    .push(glob.to_string());
+                Ok(())
+            }
+            _ => Err(GrssError::Usage(format!(
+                "--type-add wants NAME:GLOB, like 'web:*.vue', not '{}'",
+                definition
+            ))),
+        }
+    }
+
+    /// Looks up the globs of every type in `names`
+    ///
+    /// # Returns
+    /// All their globs in one list, or a usage error naming the first type
+    /// that doesn't exist
+    pub fn globs(&self, names: &[String]) -> Result<Vec<String>, GrssError> {
+        let mut globs = Vec::new();
+        for name in names {
+            let type_globs = self.types.get(name).ok_or_else(|| {
+                GrssError::Usage(format!(
+                    "unknown file type '{}' (--type-list shows them all)",
+                    name
+                ))
+            })?;
+            globs.extend(type_globs.iter().cloned());
+        }
+        Ok(globs)
+    }
+
+    /// Writes the --type-list table: one `name: glob, glob` line per type,
+    /// sorted by name
+    pub fn write_list<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (name, globs) in &self.types {
+            writeln!(out, "{}: {}", name, globs.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_add_extends_and_defines_types() {
+        let mut types = FileTypes::default();
+        types.add("rust:*.rs.in").unwrap();
+        types.add("web:*.vue").unwrap();
+        assert_eq!(
+            types
+                .globs(&["rust".to_string(), "web".to_string()])
+                .unwrap(),
+            ["*.rs", "*.rs.in", "*.vue"]
+        );
+        assert!(types.add("no-colon").is_err());
+        assert!(types.add(":*.x").is_err());
+        assert!(types.globs(&["cobol".to_string()]).is_err());
+    }
+
+    #[test]
+    fn list_is_sorted_by_name() {
+        let mut types = FileTypes::default();
+        types.add("aaa:*.a").unwrap();
+        let mut out = Vec::new();
+        types.write_list(&mut out).unwrap();
+        let list = String::from_utf8(out).unwrap();
+        let names: Vec<&str> = list
+            .lines()
+            .map(|line| line.split(':').next().unwrap())
+            .collect();
+        assert!(names.is_sorted(), "{names:?}");
+        assert_eq!(names[0], "aaa");
+        assert!(list.contains("\nrust: *.rs\n"));
+    }
+}
@@ -0,0 +1,423 @@
+// FilePath: rust/rust-cli/grss/src/walk.rs
+
+//! Finding the files to search under a directory
+
+use crate::filter::PathFilter;
+use crate::ignore_files::{IgnoreOptions, IgnoreStack};
+use std::collections::HashSet;
+use std::fs;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// How walk_files() goes through a directory tree
+#[derive(Clone, Default)]
+pub struct WalkOptions {
+    /// Descend into symlinked directories and search symlinked files
+    /// (--follow) instead of skipping them
+    pub follow_links: bool,
+    /// Don't go deeper than this many levels below the root (--max-depth):
+    /// the root is depth 0, what's directly inside it depth 1, and so on
+    pub max_depth: Option<usize>,
+    /// Also search hidden files and go into hidden directories (--hidden)
+    pub hidden: bool,
+    /// Which ignore files to honor
+    pub ignore: IgnoreOptions,
+    /// Don't go into directories on another file system than the root
+    /// (--one-file-system), see on_same_file_system()
+    pub one_file_system: bool,
+}
+
+/// Walks `root` and calls `found` with every regular file underneath it
+/// that passes `filter`
+///
+/// Entries that can't be read are handed to `on_error` and skipped, one bad
+/// file shouldn't abort a search over a whole tree. Symlinks are only
+/// followed with `follow_links`; a broken link is then one of those errors,
+/// and a directory reached a second time (through a link to it, or a link
+/// pointing back up the tree) is skipped with a warning.
+/// Directories excluded with --exclude or --exclude-dir, ignored by an ignore
+/// file (see ignore_files.rs), and hidden ones unless `options.hidden`, are
+/// skipped before the walk ever reads them. `found` can end the walk early
+/// by returning Break.
+///
+/// # Returns
+/// How many files `filter` left out (files inside an excluded directory
+/// aren't even seen, so they don't count)
+pub fn walk_files(
+    root: &Path,
+    filter: &PathFilter,
+    options: WalkOptions,
+    mut on_error: impl FnMut(walkdir::Error),
+    mut found: impl FnMut(PathBuf) -> ControlFlow<()>,
+) -> usize {
+    // Ignore files root their patterns at their own directory, so they're
+    // matched against absolute paths
+    let absolute_root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let mut ignores = IgnoreStack::new(&absolute_root, &options.ignore);
+    let root_device = match options.one_file_system {
+        true => fs::metadata(root)
+            .ok()
+            .and_then(|metadata| device(&metadata)),
+        false => None,
+    };
+    // Every directory entered so far, only kept when following links:
+    // without them there's no way to come to the same one twice
+    let mut visited = options.follow_links.then(HashSet::new);
+
+    // filter_entry() is asked about every entry before walkdir goes into
+    // it, so returning false for a directory skips everything inside. The
+    // root itself (depth 0) is always searched.
+    let walker = WalkDir::new(root)
+        // When following links, walkdir compares each directory's device
+        // and inode number with those of the directories above it, so a
+        // link pointing back up the tree (`a -> b -> a`) gives a "file
+        // system loop" error instead of sending us around in circles.
+        // `visited` catches the rest, like two links to the same directory.
+        .follow_links(options.follow_links)
+        // walkdir doesn't even read a directory at the deepest level, so
+        // nothing below it is visited just to be thrown away
+        .max_depth(options.max_depth.unwrap_or(usize::MAX))
+        // Sorting by name keeps the walk itself in the same order from run
+        // to run
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let relative = relative_path(root, entry.path());
+            let is_dir = entry.file_type().is_dir();
+            if entry.depth() == 0 {
+                first_visit(&mut visited, entry);
+                ignores.enter(0, &absolute_root);
+                return true;
+            }
+            if !options.hidden && is_hidden(entry) {
+                log::debug!("{}: skipped, hidden", entry.path().display());
+                return false;
+            }
+            let absolute = absolute_root.join(relative);
+            if ignores.is_ignored(entry.depth(), &absolute, is_dir) {
+                log::debug!("{}: skipped, ignored", entry.path().display());
+                return false;
+            }
+            if is_dir && !filter.should_descend(relative) {
+                log::debug!("{}: not entered, excluded", entry.path().display());
+                return false;
+            }
+            let entry_device = || entry.metadata().ok().and_then(|metadata| device(&metadata));
+            if is_dir && root_device.is_some() && !on_same_file_system(root_device, entry_device())
+            {
+                log::debug!(
+                    "{}: not entered, on another file system",
+                    entry.path().display()
+                );
+                return false;
+            }
+            // Checked last, so a directory only counts as visited once it's
+            // really entered
+            if is_dir && !first_visit(&mut visited, entry) {
+                warn_visited(entry.path());
+                return false;
+            }
+            if is_dir {
+                ignores.enter(entry.depth(), &absolute);
+            }
+            true
+        });
+
+    let mut filtered_out = 0;
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                match error.loop_ancestor() {
+                    Some(_) => warn_visited(error.path().unwrap_or(root)),
+                    None => on_error(error),
+                }
+                continue;
+            }
+        };
+
+        // Skip directories, sockets and other special files. Followed
+        // symlinks have the type of what they point to, the rest are skipped
+        // too.
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        if !filter.is_included(relative_path(root, entry.path())) {
+            log::debug!(
+                "{}: skipped, left out by --include/--exclude/--iglob/--type",
+                entry.path().display()
+            );
+            filtered_out += 1;
+            continue;
+        }
+
+        if found(entry.into_path()).is_break() {
+            break;
+        }
+    }
+    filtered_out
+}
+
+/// Whether `entry` is hidden: its name starts with a `.` (so `.git/` and
+/// `.env`), or on Windows it has the hidden attribute set
+fn is_hidden(entry: &walkdir::DirEntry) -> bool {
+    if entry.file_name().as_encoded_bytes().starts_with(b".") {
+        return true;
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        if entry
+            .metadata()
+            .is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether a walk that started on the device `root` should go into a
+/// directory on the device `dir`, for --one-file-system
+///
+/// Not knowing either device means going in: on platforms without device
+/// IDs (see device()) --one-file-system doesn't leave anything out.
+pub fn on_same_file_system(root: Option<u64>, dir: Option<u64>) -> bool {
+    match (root, dir) {
+        (Some(root), Some(dir)) => root == dir,
+        _ => true,
+    }
+}
+
+/// The ID of the device (file system) a file is on, going by its
+/// `metadata`
+///
+/// Only Unix has one in std. Windows has a volume serial number, but
+/// getting at it isn't stable yet, so there this is always None.
+#[cfg(unix)]
+fn device(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// What tells a directory apart from every other one, for noticing when
+/// following links comes to the same directory again: its device and inode
+/// numbers on Unix, and elsewhere its canonical path
+#[cfg(unix)]
+type DirId = (u64, u64);
+
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+/// The DirId of the directory `entry`, None if it can't be looked up
+#[cfg(unix)]
+fn dir_id(entry: &walkdir::DirEntry) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = entry.metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(entry: &walkdir::DirEntry) -> Option<DirId> {
+    fs::canonicalize(entry.path()).ok()
+}
+
+/// Adds the directory `entry` to `visited` (when following links)
+///
+/// # Returns
+/// Whether it's the first time the walk comes to it. A directory that
+/// can't be looked up counts as new, as does every one without `visited`.
+fn first_visit(visited: &mut Option<HashSet<DirId>>, entry: &walkdir::DirEntry) -> bool {
+    match (visited, dir_id(entry)) {
+        (Some(visited), Some(id)) => visited.insert(id),
+        _ => true,
+    }
+}
+
+/// Warns that the directory at `path` isn't entered, having been already
+fn warn_visited(path: &Path) {
+    log::warn!(
+        "{}: skipping already visited directory (symlink loop?)",
+        path.display()
+    );
+}
+
+/// The part of `path` below the directory being searched, which is what
+/// --include and --exclude globs are matched against
+pub fn relative_path<'p>(root: &Path, path: &'p Path) -> &'p Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Walks `root` with `max_depth`, returning what was found relative to
+    /// it, with `/` separators
+    fn walk(root: &Path, max_depth: Option<usize>) -> Vec<String> {
+        let options = WalkOptions {
+            max_depth,
+            ..WalkOptions::default()
+        };
+        walk_with(root, options)
+    }
+
+    fn walk_with(root: &Path, options: WalkOptions) -> Vec<String> {
+        let filter = PathFilter::new(&[], &[]).unwrap();
+        let mut found = Vec::new();
+        walk_files(
+            root,
+            &filter,
+            options,
+            |error| panic!("{error}"),
+            |path| {
+                let relative = relative_path(root, &path)
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                found.push(relative);
+                ControlFlow::Continue(())
+            },
+        );
+        found
+    }
+
+    #[test]
+    fn max_depth_stops_the_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        for file in ["top.txt", "a/one.txt", "a/b/two.txt", "a/b/three.txt"] {
+            fs::write(root.join(file), "x\n").unwrap();
+        }
+        // Files at depth 3 are inside a directory at depth 2
+        fs::create_dir(root.join("a/b/c")).unwrap();
+        fs::write(root.join("a/b/c/deep.txt"), "x\n").unwrap();
+
+        assert!(walk(root, Some(0)).is_empty());
+        assert_eq!(walk(root, Some(1)), ["top.txt"]);
+        assert_eq!(walk(root, Some(2)), ["a/one.txt", "top.txt"]);
+        assert_eq!(
+            walk(root, Some(3)),
+            ["a/b/three.txt", "a/b/two.txt", "a/one.txt", "top.txt"]
+        );
+        assert_eq!(walk(root, None).len(), 5);
+    }
+
+    #[test]
+    fn hidden_entries_are_skipped_unless_asked_for() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join(".git/objects")).unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        for file in [
+            ".git/HEAD",
+            ".git/objects/ab",
+            ".env",
+            "src/.hidden.rs",
+            "src/main.rs",
+        ] {
+            fs::write(root.join(file), "x\n").unwrap();
+        }
+
+        assert_eq!(walk_with(root, WalkOptions::default()), ["src/main.rs"]);
+        let hidden = WalkOptions {
+            hidden: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(
+            walk_with(root, hidden),
+            [
+                ".env",
+                ".git/HEAD",
+                ".git/objects/ab",
+                "src/.hidden.rs",
+                "src/main.rs"
+            ]
+        );
+        // A hidden directory given as the root is still searched, only
+        // hidden things inside it are skipped
+        assert_eq!(
+            walk_with(&root.join(".git"), WalkOptions::default()),
+            ["HEAD", "objects/ab"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn following_links_enters_each_directory_once() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        fs::write(root.join("a/one.txt"), "x\n").unwrap();
+        fs::write(root.join("b/two.txt"), "x\n").unwrap();
+        // a/to_b and b/to_a go around in a circle without either being above
+        // the other, and twin is a second way into a
+        symlink("../b", root.join("a/to_b")).unwrap();
+        symlink("../a", root.join("b/to_a")).unwrap();
+        symlink("a", root.join("twin")).unwrap();
+
+        let follow = WalkOptions {
+            follow_links: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(walk_with(root, follow), ["a/one.txt", "a/to_b/two.txt"]);
+        assert_eq!(
+            walk_with(root, WalkOptions::default()),
+            ["a/one.txt", "b/two.txt"]
+        );
+    }
+
+    #[test]
+    fn one_file_system_only_stays_out_of_other_devices() {
+        assert!(on_same_file_system(Some(2049), Some(2049)));
+        assert!(!on_same_file_system(Some(2049), Some(42)));
+        // Unknown devices never keep the walk out
+        assert!(on_same_file_system(None, Some(42)));
+        assert!(on_same_file_system(Some(2049), None));
+    }
+
+    #[test]
+    fn excluded_directories_are_never_entered() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let deep = root.join("web/node_modules/left-pad/lib/util/deep");
+        fs::create_dir_all(&deep).unwrap();
+        for i in 0..50 {
+            fs::write(deep.join(format!("{i}.js")), "x\n").unwrap();
+        }
+        fs::write(root.join("web/node_modules/index.js"), "x\n").unwrap();
+        fs::write(root.join("web/app.js"), "x\n").unwrap();
+        fs::write(root.join("web/app.min.js"), "x\n").unwrap();
+
+        let filter = PathFilter::new(&[], &["*.min.js".to_string()])
+            .unwrap()
+            .with_excluded_dirs(&["node_modules".to_string()])
+            .unwrap();
+        let mut visited = Vec::new();
+        let filtered_out = walk_files(
+            root,
+            &filter,
+            WalkOptions::default(),
+            |error| panic!("{error}"),
+            |path| {
+                visited.push(path);
+                ControlFlow::Continue(())
+            },
+        );
+        // Only app.min.js was looked at and left out: nothing under
+        // node_modules was even seen
+        assert_eq!(visited, [root.join("web/app.js")]);
+        assert_eq!(filtered_out, 1);
+    }
+}
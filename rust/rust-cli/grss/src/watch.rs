@@ -0,0 +1,229 @@
+// FilePath: rust/rust-cli/grss/src/watch.rs
+
+//! --watch: keep searching files as they grow, like `tail -f | grep`
+//!
+//! Each file is searched once from the start, then grss waits for changes
+//! and only searches what was appended since. Only complete lines are
+//! searched: a line still being written (no newline yet) waits for the next
+//! change, so it's never matched half-way. A file that got shorter was
+//! truncated or replaced by log rotation, and is searched again from the
+//! start.
+//!
+//! Changes are noticed through the OS's file notifications (the `notify`
+//! crate). Every file is also checked every so often regardless, which
+//! keeps things working where notifications don't, like on network
+//! filesystems. Ctrl-C stops watching, and grss exits with 0 if anything
+//! matched and 1 if not, as it would without --watch.
+
+use crate::App;
+use crate::interrupt;
+use crate::printer::Printer;
+use grss::GrssError;
+use notify::{RecursiveMode, Watcher};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long to wait for a notification before checking every file anyway
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How far into one file the search has got
+struct Watched {
+    path: PathBuf,
+    /// Byte offset just past the last complete line searched
+    offset: u64,
+    /// How many lines come before `offset`, to keep numbering them for -n
+    lines: usize,
+}
+
+/// Searches `paths` and then keeps searching whatever is appended to them,
+/// until Ctrl-C
+///
+/// # Returns
+/// Whether anything matched
+pub fn run<W: Write>(app: &App, paths: &[PathBuf], out: &mut W) -> Result<bool, GrssError> {
+    for path in paths {
+        if path.as_os_str() == "-" || fs::metadata(path).is_ok_and(|m| m.is_dir()) {
+            return Err(GrssError::Usage(
+                "--watch only works on files, not directories or standard input".to_string(),
+            ));
+        }
+    }
+    // Notifications only wake us up early, the files are checked after
+    // every POLL_INTERVAL anyway, so a watcher that can't be set up isn't
+    // an error. Watching the directory rather than the file also catches a
+    // rotated log being replaced by a new file.
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).ok();
+    for path in paths {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if let Some(watcher) = &mut watcher {
+            let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+    }
+
+    let mut watched: Vec<Watched> = paths
+        .iter()
+        .map(|path| Watched {
+            path: path.clone(),
+            offset: 0,
+            lines: 0,
+        })
+        .collect();
+    let mut found_match = false;
+    // Ctrl-C only ends the watch here, it isn't treated as an interrupted
+    // search (see interrupt.rs): watching is only ever stopped that way
+    while !interrupt::is_interrupted() {
+        for file in &mut watched {
+            match search_appended(app, file, out) {
+                Ok(matched) => found_match |= matched,
+                // The file may be gone for a moment while it's rotated
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                // Nobody reads the results anymore (see run() in main.rs)
+                Err(error) if error.kind() == io::ErrorKind::BrokenPipe => return Ok(found_match),
+                Err(error) => log::warn!("{}", GrssError::io(&file.path, error)),
+            }
+        }
+        match out.flush() {
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => return Ok(found_match),
+            flushed => {
+                flushed.map_err(|error| GrssError::io(Path::new("(standard output)"), error))?
+            }
+        }
+        // Any event (or none before the timeout) means "check again"
+        let _ = receiver.recv_timeout(POLL_INTERVAL);
+    }
+    Ok(found_match)
+}
+
+/// Searches the complete lines added to `file` since it was last searched
+///
+/// # Returns
+/// Whether any of them matched
+fn search_appended<W: Write>(app: &App, file: &mut Watched, out: &mut W) -> io::Result<bool> {
+    let mut handle = File::open(&file.path)?;
+    let len = handle.metadata()?.len();
+    if len < file.offset {
+        // Truncated or rotated: start over
+        file.offset = 0;
+        file.lines = 0;
+    }
+    let Some(end) = end_of_last_line(&mut handle, file.offset, len)? else {
+        return Ok(false);
+    };
+
+    handle.seek(SeekFrom::Start(file.offset))?;
+    let mut reader = LineCounter {
+        inner: BufReader::new(handle.take(end - file.offset)),
+        lines: 0,
+    };
+    let mut printer = Printer::new(&mut *out, &app.print, Some(&file.path));
+    let (lines_before, offset_before) = (file.lines, file.offset as usize);
+
+    // The Searcher numbers lines (and offsets) from the start of what it's
+    // given, which here is wherever the last search stopped
+    let outcome = app.searcher.search_reader(&mut reader, &mut |found| {
+        let number = lines_before + found.number;
+        let offset = offset_before + found.offset;
+        app.print_found(&mut printer, number, offset, &found)?;
+        Ok(None)
+    })?;
+
+    file.offset = end;
+    file.lines += reader.lines;
+    Ok(outcome.count > 0)
+}
+
+/// Where the last complete line between `start` and `len` in `file` ends,
+/// i.e. the offset just past its `\n`
+///
+/// Reads backwards from the end a block at a time, so a big file that grew
+/// by a lot doesn't have to be read twice.
+///
+/// # Returns
+/// None when there's no complete line after `start` yet
+fn end_of_last_line(file: &mut File, start: u64, len: u64) -> io::Result<Option<u64>> {
+    let mut block = [0; 8 * 1024];
+    let mut block_end = len;
+    while block_end > start {
+        let block_start = block_end.saturating_sub(block.len() as u64).max(start);
+        let block = &mut block[..(block_end - block_start) as usize];
+        file.seek(SeekFrom::Start(block_start))?;
+        file.read_exact(block)?;
+        if let Some(newline) = memchr::memrchr(b'\n', block) {
+            return Ok(Some(block_start + newline as u64 + 1));
+        }
+        block_end = block_start;
+    }
+    Ok(None)
+}
+
+/// A reader that counts the lines taken out of it, so numbering can carry
+/// on from there the next time the file is searched
+struct LineCounter<R> {
+    inner: R,
+    lines: usize,
+}
+
+impl<R: Read> Read for LineCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.lines += memchr::memchr_iter(b'\n', &buf[..read]).count();
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for LineCounter<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if let Ok(buffer) = self.inner.fill_buf() {
+            let taken = &buffer[..amount.min(buffer.len())];
+            self.lines += memchr::memchr_iter(b'\n', taken).count();
+        }
+        self.inner.consume(amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_complete_lines_are_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "one\ntwo\nthr").unwrap();
+        let mut file = File::open(&path).unwrap();
+
+        assert_eq!(end_of_last_line(&mut file, 0, 11).unwrap(), Some(8));
+        assert_eq!(end_of_last_line(&mut file, 8, 11).unwrap(), None);
+
+        // Lines longer than one block
+        let long = format!("{}\n{}", "x".repeat(20_000), "y".repeat(20_000));
+        fs::write(&path, &long).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let len = long.len() as u64;
+        assert_eq!(end_of_last_line(&mut file, 0, len).unwrap(), Some(20_001));
+    }
+
+    #[test]
+    fn line_counter_counts_consumed_lines() {
+        let mut reader = LineCounter {
+            inner: &b"a\nb\nc"[..],
+            lines: 0,
+        };
+        let mut line = Vec::new();
+        reader.read_until(b'\n', &mut line).unwrap();
+        assert_eq!(reader.lines, 1);
+        reader.read_to_end(&mut line).unwrap();
+        assert_eq!(reader.lines, 2);
+    }
+}
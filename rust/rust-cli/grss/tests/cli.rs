@@ -0,0 +1,2703 @@
+// FilePath: rust/rust-cli/grss/tests/cli.rs
+
+//! End-to-end tests that run the real grss binary
+//!
+//! Cargo builds the binary before running integration tests and tells us
+//! where it is through the CARGO_BIN_EXE_<name> environment variable
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Runs grss with `args`, feeding `stdin` to it, and waits for it to finish
+///
+/// A config file or GRSS_COLORS on the machine running the tests mustn't
+/// change the results, so grss is pointed at a config directory that doesn't
+/// exist and GRSS_COLORS is removed
+fn grss(args: &[&str], stdin: &str) -> Output {
+    grss_with_config_home(Path::new("/nonexistent"), args, stdin)
+}
+
+/// Like grss(), with XDG_CONFIG_HOME set to `config_home`
+fn grss_with_config_home(config_home: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_grss"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", config_home)
+        .env_remove("GRSS_COLORS")
+        .env_remove("GRSS_OPTS")
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // grss may exit (say, on a usage error) before reading any of stdin,
+    // and then the write fails with a broken pipe, which is fine
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn count_single_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    fs::write(&path, "foo\nbar\nfoo bar\n").unwrap();
+
+    let output = grss(&["-c", "foo", path.to_str().unwrap()], "");
+    assert_eq!(stdout(&output), "2\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn count_matches_counts_occurrences_not_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "foo foo foo\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "bar\n").unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    assert_eq!(stdout(&grss(&["-c", "foo", a], "")), "1\n");
+    assert_eq!(stdout(&grss(&["--count-matches", "foo", a], "")), "3\n");
+    let output = grss(&["--count-matches", "foo", a, b], "");
+    assert_eq!(stdout(&output), format!("{}:3\n{}:0\n", a, b));
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = grss(&["-c", "--count-matches", "foo", a], "");
+    assert!(stderr(&output).contains("cannot be used with"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn with_and_without_filename_for_one_and_several_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "foo\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "foo bar\n").unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+    let output = |args: &[&str]| stdout(&grss(args, ""));
+
+    // Without either flag it depends on how many files there are
+    assert_eq!(output(&["foo", a]), "foo\n");
+    assert_eq!(output(&["foo", a, b]), format!("{a}:foo\n{b}:foo bar\n"));
+
+    assert_eq!(output(&["-H", "foo", a]), format!("{a}:foo\n"));
+    assert_eq!(
+        output(&["-H", "foo", a, b]),
+        format!("{a}:foo\n{b}:foo bar\n")
+    );
+    assert_eq!(output(&["-h", "foo", a]), "foo\n");
+    assert_eq!(output(&["-h", "foo", a, b]), "foo\nfoo bar\n");
+
+    // The same goes for -c, and for standard input
+    assert_eq!(output(&["-Hc", "foo", a]), format!("{a}:1\n"));
+    assert_eq!(output(&["-hc", "foo", a, b]), "1\n1\n");
+    assert_eq!(
+        stdout(&grss(&["-H", "foo", "-"], "foo\n")),
+        "(standard input):foo\n"
+    );
+    // The last of the two wins, so a config file's -H can be undone
+    assert_eq!(output(&["-H", "-h", "foo", a, b]), "foo\nfoo bar\n");
+
+    // -h is taken, help is --help only
+    let help = grss(&["--help"], "");
+    assert!(stdout(&help).contains("--no-filename"));
+    assert_eq!(help.status.code(), Some(0));
+}
+
+#[test]
+fn context_separator_can_be_changed_or_left_out() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "hit 1\nx\ny\nz\nhit 2\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "before\nhit 3\n").unwrap();
+    let run = |separator: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_grss"))
+            .args(["-h", "-B1"])
+            .args(separator)
+            .args(["hit", "a.txt", "b.txt"])
+            .current_dir(dir.path())
+            .env("XDG_CONFIG_HOME", "/nonexistent")
+            .output()
+            .unwrap();
+        stdout(&output)
+    };
+
+    // Between groups in a file and between files, never before the first
+    // group or after the last
+    assert_eq!(run(&[]), "hit 1\n--\nz\nhit 2\n--\nbefore\nhit 3\n");
+    assert_eq!(
+        run(&["--context-separator", r"\t@@\x41"]),
+        "hit 1\n\t@@A\nz\nhit 2\n\t@@A\nbefore\nhit 3\n"
+    );
+    assert_eq!(
+        run(&["--no-context-separator"]),
+        "hit 1\nz\nhit 2\nbefore\nhit 3\n"
+    );
+    // An empty one still takes up a line
+    assert_eq!(
+        run(&["--context-separator", ""]),
+        "hit 1\n\nz\nhit 2\n\nbefore\nhit 3\n"
+    );
+}
+
+#[test]
+fn trim_drops_indentation_from_the_text_only() {
+    let input = "fn main() {\n        if ready {\n\t\t    go();\n        }\n}\n";
+    let output = grss(&["--trim", "-n", "--column", "-A1", "go", "-"], input);
+    // `go` is the 7th character of its line in the input
+    assert_eq!(stdout(&output), "3:7:go();\n4-}\n");
+    let output = grss(&["--trim", "-o", "-b", "ready", "-"], input);
+    assert_eq!(stdout(&output), "23:ready\n");
+}
+
+#[test]
+fn max_columns_tames_minified_lines() {
+    let minified = format!("{}needle{}\n", "x".repeat(5000), "y".repeat(5000));
+    let input = format!("short needle\n{}", minified);
+
+    let output = grss(&["-n", "--max-columns", "100", "needle", "-"], &input);
+    assert_eq!(
+        stdout(&output),
+        "1:short needle\n2:[Omitted long matching line]\n"
+    );
+    // Still a match, for the exit code and -c
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = grss(
+        &["-M", "12", "--max-columns-preview", "needle", "-"],
+        &input,
+    );
+    assert_eq!(
+        stdout(&output),
+        "short needle\nxxxxxxxxxxxx [... omitted]\n"
+    );
+
+    let output = grss(&["--max-columns-preview", "needle", "-"], &input);
+    assert!(stderr(&output).contains("--max-columns"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn count_multiple_files_prints_path_and_count() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "foo\nfoo\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "bar\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(&["-r", "-c", "foo", root], "");
+    assert_eq!(stdout(&output), format!("{root}/a.txt:2\n{root}/b.txt:0\n"));
+}
+
+#[test]
+fn count_stdin() {
+    let output = grss(&["-c", "foo", "-"], "foo\nbar\nfood\n");
+    assert_eq!(stdout(&output), "2\n");
+
+    let output = grss(&["-c", "baz", "-"], "foo\nbar\n");
+    assert_eq!(stdout(&output), "0\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn parallel_search_never_mixes_up_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    for file in 0..200 {
+        let contents: String = (0..50)
+            .map(|line| format!("file {file} line {line} match\n"))
+            .collect();
+        fs::write(dir.path().join(format!("{file}.txt")), contents).unwrap();
+    }
+
+    let output = grss(&["-r", "match", dir.path().to_str().unwrap()], "");
+    let stdout = stdout(&output);
+    assert_eq!(stdout.lines().count(), 200 * 50);
+
+    // Every line must be whole, and each file's lines must stay in order
+    let mut next_line = std::collections::HashMap::new();
+    for line in stdout.lines() {
+        let (path, text) = line.split_once(':').unwrap();
+        let words: Vec<&str> = text.split(' ').collect();
+        assert_eq!(words.len(), 5, "corrupted line: {line}");
+        assert!(path.ends_with(&format!("/{}.txt", words[1])));
+
+        let expected = next_line.entry(words[1].to_string()).or_insert(0);
+        assert_eq!(words[3], expected.to_string());
+        *expected += 1;
+    }
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn missing_file_is_one_tidy_error_line() {
+    let output = grss(&["foo", "/nonexistent"], "");
+    assert_eq!(
+        stderr(&output),
+        "grss: /nonexistent: No such file or directory (os error 2)\n"
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn invalid_pattern_is_reported_not_panicked() {
+    let output = grss(&["(", "-"], "");
+    assert!(stderr(&output).starts_with("grss: invalid pattern '(': "));
+    assert!(!stderr(&output).contains("panicked"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn missing_path_is_a_usage_error() {
+    let output = grss(&["foo"], "");
+    assert!(stderr(&output).starts_with("grss: no path given\nusage: "));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn directory_without_r_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_str().unwrap();
+    let output = grss(&["foo", root], "");
+    assert_eq!(
+        stderr(&output),
+        format!("grss: {root}: is a directory (use -r to search it)\n")
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn every_path_is_searched_even_after_one_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "foo 1\nbar\n").unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub/b.txt"), "foo 2\n").unwrap();
+    fs::write(dir.path().join("c.txt"), "foo 3\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+    let (a, missing, sub, c) = (
+        format!("{root}/a.txt"),
+        format!("{root}/missing.txt"),
+        format!("{root}/sub"),
+        format!("{root}/c.txt"),
+    );
+
+    let output = grss(&["foo", &a, &missing, &sub, &c, "-"], "foo 4\n");
+    assert_eq!(
+        stdout(&output),
+        format!("{a}:foo 1\n{c}:foo 3\n(standard input):foo 4\n")
+    );
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "grss: {missing}: No such file or directory (os error 2)\n\
+             grss: {sub}: is a directory (use -r to search it)\n"
+        )
+    );
+    // Matches were found, but something went wrong too
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = grss(&["-r", "foo", &a, &sub], "");
+    assert_eq!(stdout(&output), format!("{a}:foo 1\n{sub}/b.txt:foo 2\n"));
+    assert_eq!(output.status.code(), Some(0));
+
+    // -q only wants to know whether anything matched
+    let output = grss(&["-q", "foo", &missing, &a], "");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn quiet_prints_nothing_and_uses_the_exit_code() {
+    let output = grss(&["-q", "foo", "-"], "a\nfoo\n");
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = grss(&["-q", "-c", "nothing", "-"], "a\nfoo\n");
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn quiet_silences_warnings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    fs::write(&path, b"foo\0bar").unwrap();
+
+    let output = grss(&["foo", path.to_str().unwrap()], "");
+    assert!(stderr(&output).contains("binary file, skipping"));
+
+    let output = grss(&["-q", "foo", path.to_str().unwrap()], "");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn text_searches_binary_files_with_their_nuls_shown() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut contents = b"ELF\0\0head\0 the match \0tail\nno\0thing\n".to_vec();
+    // A line with no newline for a long way, like most of a binary file
+    contents.extend(b"match".repeat(200));
+    let path = dir.path().join("blob.bin");
+    fs::write(&path, &contents).unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = grss(&["match", path], "");
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = grss(&["-a", "-n", "match", path], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty(), "{}", stderr(&output));
+    let out = stdout(&output);
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], "1:ELF␀␀head␀ the match ␀tail");
+    // Cut after 500 characters, unless --max-columns says otherwise
+    assert_eq!(lines[1], format!("3:{} [... omitted]", "match".repeat(100)));
+
+    let output = grss(&["--text", "-M", "10", "-o", "the match", path], "");
+    assert_eq!(stdout(&output), "the match\n");
+
+    // Found by -r too, instead of skipped quietly
+    let output = grss(
+        &["-r", "-a", "-c", "match", dir.path().to_str().unwrap()],
+        "",
+    );
+    assert_eq!(stdout(&output), format!("{}:2\n", path));
+}
+
+#[test]
+fn quiet_stops_at_the_first_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("big.log");
+    let mut contents = String::from("needle\n");
+    contents.push_str(&"nothing to see on this line\n".repeat(2_000_000));
+    fs::write(&path, contents).unwrap();
+
+    let start = Instant::now();
+    let output = grss(&["-q", "needle", path.to_str().unwrap()], "");
+    assert!(start.elapsed() < Duration::from_millis(500));
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn quiet_cancels_the_rest_of_a_recursive_search() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::write(root.join("a.txt"), "needle\n").unwrap();
+    let filler = "nothing to see on this line\n".repeat(100_000);
+    for i in 0..16 {
+        fs::write(root.join(format!("big{:02}.log", i)), &filler).unwrap();
+    }
+    let root = root.to_str().unwrap();
+
+    // -c has to read every file to the end, which is what -q gets to skip
+    let start = Instant::now();
+    let counted = grss(&["-rc", "needle", root], "");
+    let everything = start.elapsed();
+    assert_eq!(counted.status.code(), Some(0));
+
+    let start = Instant::now();
+    let quiet = grss(&["-rq", "needle", root], "");
+    let early = start.elapsed();
+    assert!(quiet.stdout.is_empty());
+    assert_eq!(quiet.status.code(), Some(0));
+    assert!(
+        early * 4 < everything,
+        "-q took {:?}, searching everything {:?}",
+        early,
+        everything
+    );
+
+    // -l only stops each file early: every matching file is still listed
+    fs::write(dir.path().join("b.txt"), "needle\nneedle\n").unwrap();
+    let listed = grss(&["-rl", "--sort", "path", "needle", root], "");
+    let names: Vec<_> = stdout(&listed)
+        .lines()
+        .map(|line| Path::new(line).file_name().unwrap().to_owned())
+        .collect();
+    assert_eq!(names, ["a.txt", "b.txt"]);
+}
+
+#[test]
+fn max_total_prints_exactly_that_many_lines_overall() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let matches = "match on this line\n".repeat(50_000);
+    for i in 0..16 {
+        fs::write(root.join(format!("big{:02}.log", i)), &matches).unwrap();
+    }
+    let root = root.to_str().unwrap();
+
+    let start = Instant::now();
+    let counted = grss(&["-rc", "match", root], "");
+    let everything = start.elapsed();
+    assert_eq!(counted.status.code(), Some(0));
+
+    // However many threads race for the last few
+    for threads in ["-j0", "-j8", "-j1"] {
+        let start = Instant::now();
+        let output = grss(&["-r", threads, "--max-total", "100", "match", root], "");
+        let early = start.elapsed();
+        assert_eq!(stdout(&output).lines().count(), 100);
+        assert_eq!(output.status.code(), Some(0));
+        assert!(
+            early * 4 < everything,
+            "--max-total took {:?}, searching everything {:?}",
+            early,
+            everything
+        );
+    }
+
+    // One after the other, the first 100 in path order, with --sort path
+    // still in order when it stops
+    let output = grss(
+        &[
+            "-rn",
+            "-j1",
+            "--sort",
+            "path",
+            "--max-total",
+            "100",
+            "match",
+            root,
+        ],
+        "",
+    );
+    let last = stdout(&output).lines().last().unwrap().to_string();
+    assert_eq!(last, format!("{root}/big00.log:100:match on this line"));
+    let output = grss(
+        &["-r", "--sort", "path", "--max-total", "30", "match", root],
+        "",
+    );
+    assert_eq!(stdout(&output).lines().count(), 30);
+
+    // Fewer than that is fine too
+    let output = grss(&["--max-total", "5", "match", "-"], "match\nno\nmatch\n");
+    assert_eq!(stdout(&output), "match\nmatch\n");
+}
+
+#[test]
+fn output_closing_early_ends_the_search_quietly() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    // Far more than a pipe holds, so grss is still writing when the
+    // reader goes away
+    let matches = "match on this line\n".repeat(50_000);
+    for i in 0..4 {
+        fs::write(root.join(format!("big{}.log", i)), &matches).unwrap();
+    }
+    let big = root.join("big0.log");
+    let (root, big) = (root.to_str().unwrap(), big.to_str().unwrap());
+
+    let cases: [&[&str]; 4] = [
+        &["match", big],
+        &["-n", "--stats", "match", big],
+        &["-r", "match", root],
+        &["-r", "-j1", "--sort", "path", "match", root],
+    ];
+    for args in cases {
+        // Like `grss ... | head -n 5`
+        let mut child = Command::new(env!("CARGO_BIN_EXE_grss"))
+            .args(args)
+            .env("XDG_CONFIG_HOME", "/nonexistent")
+            .env_remove("GRSS_COLORS")
+            .env_remove("GRSS_OPTS")
+            .env_remove("RUST_LOG")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let reader = BufReader::new(child.stdout.take().unwrap());
+        let head: Vec<String> = reader.lines().take(5).map(Result::unwrap).collect();
+        assert_eq!(head.len(), 5);
+        // `reader` is gone with take(), which closed the pipe
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(stderr(&output), "", "{:?}", args);
+        assert_eq!(output.status.code(), Some(0), "{:?}", args);
+    }
+}
+
+#[test]
+fn invalid_utf8_lines_are_still_searched() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("latin1.log");
+    // A Latin-1 "é" on the first line and no newline after the last one
+    fs::write(&path, b"caf\xE9 error\nok\nlast error").unwrap();
+    let path = path.to_str().unwrap();
+
+    for mmap in [false, true] {
+        let mut args = vec!["-n", "-A1", "error", path];
+        if mmap {
+            args.push("--mmap");
+        }
+        let output = grss(&args, "");
+        assert_eq!(stdout(&output), "1:caf\u{FFFD} error\n2-ok\n3:last error\n");
+        assert_eq!(output.status.code(), Some(0));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn paths_that_arent_utf8_are_searched_and_shown() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    // Latin-1 in the name and in the contents
+    let latin1 = dir.path().join(OsStr::from_bytes(b"caf\xE9.txt"));
+    fs::write(&latin1, b"caf\xE9 matched\n").unwrap();
+    fs::write(dir.path().join("other.log"), "matched\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+    let shown = format!("{root}/caf\u{FFFD}.txt");
+
+    // Globs go by the name as it is
+    let output = grss(&["-r", "--include", "*.txt", "matched", root], "");
+    assert_eq!(stdout(&output), format!("{shown}:caf\u{FFFD} matched\n"));
+    assert_eq!(stderr(&output), "");
+    assert_eq!(output.status.code(), Some(0));
+
+    let every: [&[&str]; 4] = [
+        &["-rl", "--exclude", "*.log", "matched", root],
+        &["-r", "--heading", "--color", "always", "matched", root],
+        &["-r", "--csv", "matched", root],
+        &["-r", "--format", "{path}:{column}:{match}", "matched", root],
+    ];
+    for args in every {
+        let output = grss(args, "");
+        assert!(stdout(&output).contains(&shown), "{:?}", args);
+        assert_eq!(stderr(&output), "", "{:?}", args);
+        assert_eq!(output.status.code(), Some(0), "{:?}", args);
+    }
+    // The column counts the é as the one character it's shown as
+    let output = grss(
+        &["-r", "--column", "--include", "*.txt", "matched", root],
+        "",
+    );
+    assert_eq!(
+        stdout(&output),
+        format!("{shown}:1:6:caf\u{FFFD} matched\n")
+    );
+
+    // A Latin-1 é isn't a UTF-8 character, so `.` doesn't match it
+    let output = grss(&["-r", "--include", "*.txt", "caf. matched", root], "");
+    assert_eq!(output.status.code(), Some(1));
+
+    // Named on the command line, and through --pre
+    for pre in [&[][..], &["--pre", "cat"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_grss"))
+            .args(pre)
+            .args(["-H", "matched"])
+            .arg(&latin1)
+            .env("XDG_CONFIG_HOME", "/nonexistent")
+            .env_remove("GRSS_COLORS")
+            .env_remove("GRSS_OPTS")
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap();
+        assert_eq!(stdout(&output), format!("{shown}:caf\u{FFFD} matched\n"));
+        assert_eq!(output.status.code(), Some(0));
+    }
+}
+
+#[test]
+fn byte_offsets_count_from_the_start_of_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("offsets.txt");
+    // "naïve" is 6 bytes, plus the CRLF: "key" starts at byte 8, then after
+    // "key = 1\r\n" (9 bytes) and "other\n" (6) the last line at byte 23
+    fs::write(&path, "naïve\r\nkey = 1\r\nother\nkey = 2").unwrap();
+    let path = path.to_str().unwrap();
+
+    for mmap in [false, true] {
+        let mut args = vec!["-nb", "key", path];
+        if mmap {
+            args.push("--mmap");
+        }
+        let output = grss(&args, "");
+        assert_eq!(stdout(&output), "2:8:key = 1\n4:23:key = 2\n");
+    }
+
+    let output = grss(&["-b", "-B1", "key = 2", path], "");
+    assert_eq!(stdout(&output), "17-other\n23:key = 2\n");
+}
+
+#[test]
+fn max_count_zero_prints_nothing_and_exits_1() {
+    let output = grss(&["-m", "0", "foo", "-"], "foo\nfoo\n");
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(1));
+}
+
+/// Sorted lines of `output`'s stdout, for searches over a directory where the
+/// order of files isn't fixed
+fn sorted_lines(output: &Output) -> Vec<String> {
+    let mut lines: Vec<String> = stdout(output).lines().map(String::from).collect();
+    lines.sort();
+    lines
+}
+
+/// A tree where `a.rs` only has matching lines, `b.rs` has both and `c.rs`
+/// has none, so -l, -L and -v all give different lists
+fn three_file_tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.rs"), "unsafe {}\nunsafe fn f() {}\n").unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub/b.rs"), "fn g() {}\nunsafe {}\n").unwrap();
+    fs::write(dir.path().join("sub/c.rs"), "fn h() {}\n").unwrap();
+    dir
+}
+
+#[test]
+fn files_with_matches_lists_each_matching_file_once() {
+    let dir = three_file_tree();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(&["-rln", "--color=always", "unsafe", root], "");
+    assert_eq!(
+        sorted_lines(&output),
+        [format!("{root}/a.rs"), format!("{root}/sub/b.rs")]
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    // With -v: files with at least one line that doesn't match
+    let output = grss(&["-rlv", "unsafe", root], "");
+    assert_eq!(
+        sorted_lines(&output),
+        [format!("{root}/sub/b.rs"), format!("{root}/sub/c.rs")]
+    );
+}
+
+#[test]
+fn files_without_match_lists_the_rest() {
+    let dir = three_file_tree();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(&["-rL", "unsafe", root], "");
+    assert_eq!(sorted_lines(&output), [format!("{root}/sub/c.rs")]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = grss(&["-rL", "fn", root], "");
+    assert_eq!(stdout(&output), "");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn files_with_matches_names_a_single_file_and_stdin() {
+    let dir = three_file_tree();
+    let path = dir.path().join("a.rs");
+    let path = path.to_str().unwrap();
+
+    let output = grss(&["-l", "unsafe", path], "");
+    assert_eq!(stdout(&output), format!("{path}\n"));
+
+    let output = grss(&["-l", "foo", "-"], "foo\n");
+    assert_eq!(stdout(&output), "(standard input)\n");
+}
+
+#[test]
+fn null_ends_file_names_with_a_nul_byte() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("my notes.txt"), "todo: a\nb\ntodo: c\n").unwrap();
+    fs::write(dir.path().join("other.txt"), "nothing\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(&["-rl0", "todo", root], "");
+    assert_eq!(output.stdout, format!("{root}/my notes.txt\0").into_bytes());
+
+    let output = grss(&["-rn", "--null", "todo", root], "");
+    assert_eq!(
+        output.stdout,
+        format!("{root}/my notes.txt\x001:todo: a\n{root}/my notes.txt\x003:todo: c\n")
+            .into_bytes()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn null_file_lists_keep_every_name_whole() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let newline = dir.path().join("two\nlines.txt");
+    let latin1 = dir.path().join(OsStr::from_bytes(b"caf\xE9.txt"));
+    fs::write(&newline, "todo\n").unwrap();
+    fs::write(&latin1, "todo\n").unwrap();
+    fs::write(dir.path().join("done.txt"), "done\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+    let names = |output: &Output| -> Vec<Vec<u8>> {
+        let mut names: Vec<Vec<u8>> = output
+            .stdout
+            .split(|&byte| byte == 0)
+            .map(<[u8]>::to_vec)
+            .collect();
+        assert_eq!(names.pop(), Some(Vec::new()), "not NUL-ended");
+        names.sort();
+        names
+    };
+    let bytes = |path: &Path| path.as_os_str().as_bytes().to_vec();
+
+    let mut both = vec![bytes(&latin1), bytes(&newline)];
+    both.sort();
+    assert_eq!(names(&grss(&["-rlZ", "todo", root], "")), both);
+    assert_eq!(names(&grss(&["-rL", "--null", "done", root], "")), both);
+    let files = grss(&["--files", "-0", "--include", "two*", root], "");
+    assert_eq!(names(&files), [bytes(&newline)]);
+
+    // xargs -0 gets the name with a newline in it as the one argument
+    let listed = Command::new(env!("CARGO_BIN_EXE_grss"))
+        .args(["-l0", "todo"])
+        .arg(&newline)
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .env_remove("GRSS_COLORS")
+        .env_remove("GRSS_OPTS")
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap()
+        .stdout;
+    let mut xargs = Command::new("xargs")
+        .args(["-0", "-n1", "printf", "[%s]"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    xargs.stdin.take().unwrap().write_all(&listed).unwrap();
+    let output = xargs.wait_with_output().unwrap();
+    assert_eq!(stdout(&output), format!("[{root}/two\nlines.txt]"));
+}
+
+/// `text` as UTF-16 with a byte order mark, little or big endian
+fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+    let mut bytes = if little_endian {
+        vec![0xFF, 0xFE]
+    } else {
+        vec![0xFE, 0xFF]
+    };
+    for unit in text.encode_utf16() {
+        let pair = if little_endian {
+            unit.to_le_bytes()
+        } else {
+            unit.to_be_bytes()
+        };
+        bytes.extend_from_slice(&pair);
+    }
+    bytes
+}
+
+#[test]
+fn utf16_files_are_decoded_by_their_bom() {
+    let dir = tempfile::tempdir().unwrap();
+    let text = "first line\r\ncafé au lait\r\nlast line\r\n";
+    for (name, little_endian) in [("le.txt", true), ("be.txt", false)] {
+        let path = dir.path().join(name);
+        fs::write(&path, utf16(text, little_endian)).unwrap();
+
+        for mmap in [false, true] {
+            let mut args = vec!["-n", "café", path.to_str().unwrap()];
+            if mmap {
+                args.push("--mmap");
+            }
+            let output = grss(&args, "");
+            assert_eq!(stdout(&output), "2:café au lait\n", "{name}");
+        }
+    }
+}
+
+#[test]
+fn encoding_reads_files_without_a_bom() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("le.txt");
+    fs::write(&path, &utf16("café\n", true)[2..]).unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = grss(&["--encoding", "utf-16le", "café", path], "");
+    assert_eq!(stdout(&output), "café\n");
+
+    let output = grss(&["--encoding", "klingon", "café", path], "");
+    assert!(stderr(&output).starts_with(
+        "grss: unknown encoding 'klingon', it takes WHATWG labels like utf-8, utf-16le, \
+         windows-1252, latin1, shift_jis or euc-kr\n"
+    ));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn encoding_makes_legacy_text_match() {
+    let latin1 = data_dir().join("latin1.txt");
+    let shift_jis = data_dir().join("shift_jis.txt");
+    let (latin1, shift_jis) = (latin1.to_str().unwrap(), shift_jis.to_str().unwrap());
+
+    // The bytes aren't UTF-8, so without the encoding nothing matches
+    let output = grss(&["-n", "café", latin1], "");
+    assert_eq!(stdout(&output), "");
+    assert_eq!(output.status.code(), Some(1));
+    let output = grss(&["--encoding", "latin1", "-n", "café", latin1], "");
+    assert_eq!(stdout(&output), "2:café au lait\n");
+    let output = grss(&["--encoding", "windows-1252", "-c", "é", latin1], "");
+    assert_eq!(stdout(&output), "2\n");
+
+    let output = grss(&["エラー", shift_jis], "");
+    assert_eq!(output.status.code(), Some(1));
+    let output = grss(&["--encoding", "shift_jis", "-n", "エラー", shift_jis], "");
+    assert_eq!(stdout(&output), "2:エラー: 接続できません\n");
+    // Nor is the wrong one any help
+    let output = grss(&["--encoding", "euc-jp", "エラー", shift_jis], "");
+    assert_eq!(output.status.code(), Some(1));
+
+    // Standard input is decoded too
+    let mut child = Command::new(env!("CARGO_BIN_EXE_grss"))
+        .args(["--encoding", "shift_jis", "再試行", "-"])
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .env_remove("GRSS_OPTS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = fs::read(shift_jis).unwrap();
+    child.stdin.take().unwrap().write_all(&input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(stdout(&output), "再試行します\n");
+}
+
+#[test]
+fn pattern_file_supplies_the_patterns() {
+    let dir = tempfile::tempdir().unwrap();
+    let patterns = dir.path().join("badcalls.txt");
+    // CRLF endings and a blank line, as saved by an editor on Windows
+    fs::write(&patterns, "unwrap\\(\\)\r\n\r\nexpect\r\n").unwrap();
+    let patterns = patterns.to_str().unwrap();
+
+    let input = "a.unwrap()\nb?\nc.expect(\"x\")\n";
+    let output = grss(&["-n", "-f", patterns, "-"], input);
+    assert_eq!(stdout(&output), "1:a.unwrap()\n3:c.expect(\"x\")\n");
+
+    // Patterns can come from standard input too, as long as the text to
+    // search doesn't
+    let file = dir.path().join("code.rs");
+    fs::write(&file, input).unwrap();
+    let output = grss(&["-f", "-", file.to_str().unwrap()], "b\\?\n");
+    assert_eq!(stdout(&output), "b?\n");
+
+    let output = grss(&["-f", "-", "-"], "b\n");
+    assert!(stderr(&output).starts_with("grss: standard input can't hold both"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn empty_pattern_file_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let patterns = dir.path().join("empty.txt");
+    fs::write(&patterns, "\n\r\n").unwrap();
+    let patterns = patterns.to_str().unwrap();
+
+    let output = grss(&["-f", patterns, "-"], "anything\n");
+    assert_eq!(
+        stderr(&output),
+        format!("grss: {patterns}: no patterns in pattern file\n")
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn config_file_supplies_default_options() {
+    let config_home = tempfile::tempdir().unwrap();
+    fs::create_dir(config_home.path().join("grss")).unwrap();
+    fs::write(
+        config_home.path().join("grss/config"),
+        "# defaults\n-i\n-n\n",
+    )
+    .unwrap();
+
+    let output = grss_with_config_home(config_home.path(), &["foo", "-"], "x\nFOO\n");
+    assert_eq!(stdout(&output), "2:FOO\n");
+
+    let output = grss_with_config_home(config_home.path(), &["--no-config", "foo", "-"], "FOO\n");
+    assert_eq!(output.status.code(), Some(1));
+
+    fs::write(config_home.path().join("grss/config"), "-i\n--exclude 'x\n").unwrap();
+    let output = grss_with_config_home(config_home.path(), &["foo", "-"], "foo\n");
+    assert!(stderr(&output).contains("grss/config:2: invalid config line"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn grss_opts_go_between_the_config_file_and_the_command_line() {
+    let config_home = tempfile::tempdir().unwrap();
+    fs::create_dir(config_home.path().join("grss")).unwrap();
+    fs::write(
+        config_home.path().join("grss/config"),
+        "-i\n--color never\n",
+    )
+    .unwrap();
+    let run = |opts: &str, args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_grss"))
+            .args(args)
+            .args(["foo", "-"])
+            .env("XDG_CONFIG_HOME", config_home.path())
+            .env("GRSS_OPTS", opts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"x\nFOO\n").unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = run("-n --color always", &[]);
+    assert_eq!(stdout(&output), "\x1b[32m2\x1b[0m:\x1b[1;31mFOO\x1b[0m\n");
+    let output = run("-n --color always", &["--color", "never"]);
+    assert_eq!(stdout(&output), "2:FOO\n");
+    // Quoting works as in a shell
+    let output = run("-n --colors 'line:fg:cyan' --color=always", &[]);
+    assert_eq!(stdout(&output), "\x1b[36m2\x1b[0m:\x1b[1;31mFOO\x1b[0m\n");
+    // --no-config skips both; in GRSS_OPTS only the file
+    let output = run("-n", &["--no-config"]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = run("--no-config -n", &["-i"]);
+    assert_eq!(stdout(&output), "2:FOO\n");
+
+    let output = run("-n --exclude 'oops", &[]);
+    assert_eq!(
+        stderr(&output),
+        "grss: invalid GRSS_OPTS: missing closing quote\n"
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn and_and_not_combine_patterns() {
+    let input = "foo bar\nfoo baz\nfoo bar baz\nbar\nbar foo\n";
+    let output = grss(&["foo", "--and", "bar", "--not", "baz", "-"], input);
+    assert_eq!(stdout(&output), "foo bar\nbar foo\n");
+    let output = grss(&["-c", "foo", "--and", "bar", "--not", "baz", "-"], input);
+    assert_eq!(stdout(&output), "2\n");
+    // -v goes for the whole of it
+    let output = grss(&["-vc", "foo", "--and", "bar", "--not", "baz", "-"], input);
+    assert_eq!(stdout(&output), "3\n");
+    // Any --not pattern leaves a line out, every --and one has to match
+    let output = grss(&["-c", "foo", "--not", "baz", "--not", "^bar", "-"], input);
+    assert_eq!(stdout(&output), "1\n");
+    let output = grss(&["-c", "foo", "--and", "bar", "--and", "baz", "-"], input);
+    assert_eq!(stdout(&output), "1\n");
+
+    // -o and the colors go for the --and matches too, not the --not ones
+    let output = grss(&["-o", "foo", "--and", "bar", "--not", "baz", "-"], input);
+    assert_eq!(stdout(&output), "foo\nbar\nbar\nfoo\n");
+    let output = grss(
+        &["--color=always", "foo", "--and", "ba.", "-"],
+        "foo bar baz\n",
+    );
+    assert_eq!(
+        stdout(&output),
+        "\x1b[1;31mfoo\x1b[0m \x1b[1;31mbar\x1b[0m \x1b[1;31mbaz\x1b[0m\n"
+    );
+
+    let output = grss(&["foo", "--and", "(", "-"], input);
+    assert!(stderr(&output).contains("invalid pattern '('"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn format_lays_out_the_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.rs");
+    fs::write(&path, "x\nfoo(1) + foo(22)\nlet foo = {};\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    // One line per match, the same line twice
+    let output = grss(
+        &[
+            "--format",
+            r"{line}\t{column}\t{match}",
+            r"foo\(\d+\)",
+            path,
+        ],
+        "",
+    );
+    assert_eq!(stdout(&output), "2\t1\tfoo(1)\n2\t10\tfoo(22)\n");
+
+    // Only one per line without {match} or {column}; braces escaped
+    let output = grss(
+        &[
+            "--format",
+            "{{{path}}} {{line}} {line}: {text}",
+            "-C1",
+            "foo",
+            path,
+        ],
+        "",
+    );
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "{{{path}}} {{line}} 2: foo(1) + foo(22)\n{{{path}}} {{line}} 3: let foo = {{}};\n"
+        )
+    );
+
+    let output = grss(&["--format", "{path}:{lnie}", "foo", path], "");
+    assert!(stderr(&output).starts_with(
+        "grss: invalid --format: unknown placeholder {lnie}, the ones there are: \
+         {path}, {line}, {column}, {text}, {match}, {offset}\n"
+    ));
+    assert_eq!(output.status.code(), Some(2));
+    let output = grss(&["--format", "{line}", "-o", "foo", path], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn only_line_numbers_prints_where_the_lines_are() {
+    let dir = tempfile::tempdir().unwrap();
+    let main = dir.path().join("main.rs");
+    fs::write(&main, "use std::io;\nfn a() {}\n\nfn b() {}\nfn c() {}\n").unwrap();
+    let main = main.to_str().unwrap();
+
+    let output = grss(&["--only-line-numbers", "fn ", main], "");
+    assert_eq!(stdout(&output), "2\n4\n5\n");
+    assert_eq!(output.status.code(), Some(0));
+    // Context asked for doesn't come into it
+    let output = grss(
+        &["--only-line-numbers", "-C", "1", "-m", "2", "fn ", main],
+        "",
+    );
+    assert_eq!(stdout(&output), "2\n4\n");
+    let output = grss(&["--only-line-numbers", "-v", "fn ", main], "");
+    assert_eq!(stdout(&output), "1\n3\n");
+    let output = grss(&["--only-line-numbers", "nothing", main], "");
+    assert_eq!(stdout(&output), "");
+    assert_eq!(output.status.code(), Some(1));
+    // With --null-data the numbers end in a NUL byte, like the records do
+    let output = grss(
+        &["--only-line-numbers", "--null-data", "b", "-"],
+        "a\0b\0ab\0",
+    );
+    assert_eq!(stdout(&output), "2\x003\0");
+}
+
+#[test]
+fn only_line_numbers_puts_the_path_first_for_several_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "x\nfoo\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "foo\n").unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    let output = grss(&["--only-line-numbers", "--heading", "foo", a, b], "");
+    assert_eq!(stdout(&output), format!("{a}:2\n{b}:1\n"));
+    let output = grss(&["--only-line-numbers", "-h", "foo", a, b], "");
+    assert_eq!(stdout(&output), "2\n1\n");
+    let output = grss(&["--only-line-numbers", "-H", "foo", b], "");
+    assert_eq!(stdout(&output), format!("{b}:1\n"));
+}
+
+#[test]
+fn only_line_numbers_conflicts_with_the_other_output_modes() {
+    for flag in ["-o", "-c", "--json", "--count-matches", "-l"] {
+        let output = grss(&["--only-line-numbers", flag, "foo", "-"], "foo\n");
+        assert!(stderr(&output).contains("cannot be used with"), "{flag}");
+        assert_eq!(output.status.code(), Some(2), "{flag}");
+    }
+}
+
+#[test]
+fn csv_reads_back_as_the_same_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.txt");
+    fs::write(&path, "ok\nTODO: fix \"a, b\" then TODO c\n").unwrap();
+    let path = path.to_str().unwrap();
+    let records = |output: &Output| -> Vec<Vec<String>> {
+        let mut reader = csv::Reader::from_reader(output.stdout.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["path", "line", "column", "text"]
+        );
+        reader
+            .records()
+            .map(|record| record.unwrap().iter().map(String::from).collect())
+            .collect()
+    };
+
+    // Never colored, so asking for color is an error
+    let output = grss(&["--csv", "--color=always", "TODO", path], "");
+    assert!(stderr(&output).contains("cannot be used with"));
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = grss(&["--csv", "-H", "TODO", path], "");
+    let line = r#"TODO: fix "a, b" then TODO c"#;
+    assert_eq!(
+        records(&output),
+        [[path, "2", "1", line], [path, "2", "23", line]]
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    // Lines with line breaks in them, which --null-data gives
+    let output = grss(&["--csv", "--null-data", "b", "-"], "a\nb\0c\0");
+    assert_eq!(records(&output), [["(standard input)", "1", "3", "a\nb"]]);
+
+    // Nothing found still gives the header
+    let output = grss(&["--csv", "nothing", path], "");
+    assert_eq!(stdout(&output), "path,line,column,text\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn sarif_is_one_document_with_a_rule_per_pattern() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(
+        dir.path().join("src/main.rs"),
+        "fn main() {\n    let x = y.unwrap();\n    todo!(); z.unwrap()\n}\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("src/lib.rs"), "// unwrap() is fine here\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+    let args = [
+        "-r",
+        "--sarif",
+        "-e",
+        r"\.unwrap\(\)",
+        "-e",
+        r"todo!\(\)",
+        root,
+    ];
+
+    let output = grss(&args, "");
+    assert_eq!(output.status.code(), Some(0));
+    let log: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(log["version"], "2.1.0");
+    assert!(log["$schema"].as_str().unwrap().contains("sarif-2.1.0"));
+    let run = &log["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "grss");
+    let rules: Vec<&str> = run["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rule| rule["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(rules, [r"\.unwrap\(\)", r"todo!\(\)"]);
+
+    let uri = format!("file://{root}/src/main.rs");
+    let results: Vec<(&str, &str, u64, u64)> = run["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| {
+            assert_eq!(result["level"], "warning");
+            assert!(result["message"]["text"].is_string());
+            let location = &result["locations"][0]["physicalLocation"];
+            (
+                result["ruleId"].as_str().unwrap(),
+                location["artifactLocation"]["uri"].as_str().unwrap(),
+                location["region"]["startLine"].as_u64().unwrap(),
+                location["region"]["startColumn"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        results,
+        [
+            (r"\.unwrap\(\)", uri.as_str(), 2, 14),
+            (r"todo!\(\)", uri.as_str(), 3, 5),
+            (r"\.unwrap\(\)", uri.as_str(), 3, 15),
+        ]
+    );
+
+    // Nothing found is still a document, just without results
+    let output = grss(&["-r", "--sarif", "nothing", root], "");
+    assert_eq!(output.status.code(), Some(1));
+    let log: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(log["runs"][0]["results"], serde_json::json!([]));
+
+    let output = grss(&["--sarif", "--csv", "x", root], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn dedupe_prints_each_distinct_line_once() {
+    let log = "ERROR disk full\nok\nERROR disk full\nERROR timeout\n\
+               ERROR disk full\nERROR disk full\nERROR disk full\n";
+    let output = grss(&["-n", "--dedupe", "ERROR", "-"], log);
+    assert_eq!(stdout(&output), "1:ERROR disk full\n4:ERROR timeout\n");
+
+    let output = grss(&["-n", "--dedupe", "--count-duplicates", "ERROR", "-"], log);
+    assert_eq!(stdout(&output), "1:ERROR disk full (x5)\n4:ERROR timeout\n");
+
+    // What's compared is the text as printed
+    let output = grss(
+        &["--dedupe", "--replace", "$1", "-o", r"ERROR (\w+)", "-"],
+        log,
+    );
+    assert_eq!(stdout(&output), "disk\ntimeout\n");
+
+    // Per file, or across all of them
+    let dir = three_file_tree();
+    let root = dir.path().to_str().unwrap();
+    let output = grss(
+        &["-rF", "--sort", "path", "--dedupe", "unsafe {}", root],
+        "",
+    );
+    assert_eq!(
+        stdout(&output),
+        format!("{root}/a.rs:unsafe {{}}\n{root}/sub/b.rs:unsafe {{}}\n")
+    );
+    let output = grss(
+        &[
+            "-r",
+            "-j1",
+            "--sort",
+            "path",
+            "--dedupe-global",
+            "unsafe",
+            root,
+        ],
+        "",
+    );
+    assert_eq!(
+        stdout(&output),
+        format!("{root}/a.rs:unsafe {{}}\n{root}/a.rs:unsafe fn f() {{}}\n")
+    );
+
+    let output = grss(&["--count-duplicates", "ERROR", "-"], log);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn colors_can_be_changed() {
+    let output = grss(
+        &[
+            "--color=always",
+            "-n",
+            "--colors",
+            "match:fg:yellow,line:fg:cyan",
+            "b",
+            "-",
+        ],
+        "abc\n",
+    );
+    assert_eq!(stdout(&output), "\x1b[36m1\x1b[0m:a\x1b[1;33mb\x1b[0mc\n");
+
+    // A bad spec only warns, the defaults are used
+    let output = grss(
+        &["--color=always", "--colors", "match:fg:purple", "b", "-"],
+        "abc\n",
+    );
+    assert_eq!(stdout(&output), "a\x1b[1;31mb\x1b[0mc\n");
+    assert!(stderr(&output).contains("invalid colors"));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn subcommands_search_like_the_flags_they_stand_for() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "count\ncount count\nnone\n").unwrap();
+    fs::write(dir.path().join("b.md"), "count\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+    let same = |subcommand: &[&str], plain: &[&str]| {
+        let (subcommand, plain) = (grss(subcommand, ""), grss(plain, ""));
+        assert_eq!(stdout(&subcommand), stdout(&plain));
+        assert_eq!(subcommand.status.code(), plain.status.code());
+        stdout(&subcommand)
+    };
+
+    let counted = same(
+        &["count", "-r", "--include", "*.txt", "count", root],
+        &["-c", "-r", "--include", "*.txt", "count", root],
+    );
+    assert!(counted.ends_with("a.txt:2\n"), "{counted}");
+    same(
+        &["count", "--count-matches", "-h", "count", root, "-r"],
+        &["--count-matches", "-h", "count", root, "-r"],
+    );
+    same(
+        &["files", "--sort", "path", root],
+        &["--files", "--sort", "path", root],
+    );
+    same(
+        &["search", "-n", "none", root, "-r"],
+        &["-n", "none", root, "-r"],
+    );
+    // After --, or with -e, `count` is a pattern again
+    let file = dir.path().join("b.md");
+    let searched = grss(&["--", "count", file.to_str().unwrap()], "");
+    assert_eq!(stdout(&searched), "count\n");
+
+    // Each only takes its own flags
+    let output = grss(&["count", "-n", "count", root], "");
+    assert!(stderr(&output).contains("unexpected argument '-n'"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn a_missing_path_notes_when_the_pattern_is_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    fs::write(&file, "// TODO\n").unwrap();
+    let file = file.to_str().unwrap();
+
+    let output = grss(&[file, "TODO"], "");
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "grss: TODO: No such file or directory (os error 2)\n\
+             grss: note: '{file}' exists as a file — did you swap the pattern and path arguments?\n"
+        )
+    );
+    assert_eq!(output.status.code(), Some(2));
+    let output = grss(&["TODO", "nowhere.rs"], "");
+    assert!(!stderr(&output).contains("note:"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn replace_rewrites_the_file_with_capture_groups() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("main.rs");
+    fs::write(&path, "let a = x.unwrap();\nlet b = y.unwrap();\nok\n").unwrap();
+    let path_str = path.to_str().unwrap();
+
+    let output = grss(
+        &[
+            "replace",
+            "--backup",
+            ".bak",
+            r"(\w+)\.unwrap\(\)",
+            "$1?",
+            path_str,
+        ],
+        "",
+    );
+    assert_eq!(stdout(&output), format!("{path_str}: 2 replacements\n"));
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "let a = x?;\nlet b = y?;\nok\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("main.rs.bak")).unwrap(),
+        "let a = x.unwrap();\nlet b = y.unwrap();\nok\n"
+    );
+}
+
+#[test]
+fn replace_dry_run_prints_a_diff_and_changes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "color\nshade\ncolor and color\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "nothing here\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(&["replace", "-r", "--dry-run", "color", "colour", root], "");
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "--- {root}/a.txt\n+++ {root}/a.txt\n\
+             @@ -1,1 +1,1 @@\n-color\n+colour\n\
+             @@ -3,1 +3,1 @@\n-color and color\n+colour and colour\n"
+        )
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "color\nshade\ncolor and color\n"
+    );
+}
+
+#[test]
+fn replace_refuses_binary_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    fs::write(&path, b"foo\0foo").unwrap();
+
+    let output = grss(&["replace", "foo", "bar", path.to_str().unwrap()], "");
+    assert!(stderr(&output).contains("binary file, not replacing in it"));
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(fs::read(&path).unwrap(), b"foo\0foo");
+}
+
+#[cfg(unix)]
+#[test]
+fn replace_keeps_the_file_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("run.sh");
+    fs::write(&path, "echo old\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+
+    let output = grss(&["replace", "old", "new", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "echo new\n");
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o751);
+}
+
+#[cfg(unix)]
+#[test]
+fn pre_searches_the_commands_output() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    // Stands in for pdftotext: a header line, then the file upside down,
+    // and it fails on anything called broken.*
+    let script = dir.path().join("convert.sh");
+    fs::write(
+        &script,
+        "#!/bin/sh\ncase \"$1\" in *broken*) echo \"can't read $1\" >&2; exit 1;; esac\n\
+         echo converted\ntac \"$1\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let docs = dir.path().join("docs");
+    fs::create_dir(&docs).unwrap();
+    fs::write(docs.join("a.pdf"), "invoice 7\nsomething else\n").unwrap();
+    fs::write(docs.join("broken.pdf"), "invoice 8\n").unwrap();
+    fs::write(docs.join("notes.txt"), "invoice 9\n").unwrap();
+
+    let pre = format!("{} {{}}", script.display());
+    let output = Command::new(env!("CARGO_BIN_EXE_grss"))
+        .args([
+            "-rn",
+            "--sort",
+            "path",
+            "--pre",
+            &pre,
+            "--pre-glob",
+            "*.pdf",
+        ])
+        .args(["invoice", "docs"])
+        .current_dir(dir.path())
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .output()
+        .unwrap();
+    // Line 3 of what the script printed, not line 1 of a.pdf, and
+    // notes.txt isn't a PDF so it's searched as it is
+    assert_eq!(
+        stdout(&output),
+        "docs/a.pdf:3:invoice 7\ndocs/notes.txt:1:invoice 9\n"
+    );
+    assert!(stderr(&output).contains("docs/broken.pdf: skipped, --pre failed"));
+    assert!(stderr(&output).contains("can't read docs/broken.pdf"));
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = grss(&["--pre-glob", "*.pdf", "x", "-"], "");
+    assert!(stderr(&output).contains("--pre"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[cfg(unix)]
+#[test]
+fn follow_searches_symlinks_and_survives_cycles() {
+    use std::os::unix::fs::symlink;
+
+    let dir = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    fs::write(outside.path().join("lib.rs"), "todo: outside\n").unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/main.rs"), "todo: inside\n").unwrap();
+    symlink(outside.path(), dir.path().join("linked")).unwrap();
+    // src/again -> src: following it forever would never terminate
+    symlink(dir.path().join("src"), dir.path().join("src/again")).unwrap();
+    symlink(dir.path().join("missing"), dir.path().join("broken")).unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(&["-r", "todo", root], "");
+    assert_eq!(
+        stdout(&output),
+        format!("{root}/src/main.rs:todo: inside\n")
+    );
+    assert!(output.stderr.is_empty());
+
+    let output = grss(&["-r", "--follow", "todo", root], "");
+    assert_eq!(
+        stdout(&output),
+        format!("{root}/linked/lib.rs:todo: outside\n{root}/src/main.rs:todo: inside\n")
+    );
+    let stderr = stderr(&output);
+    assert!(stderr.contains("broken"), "{stderr}");
+    assert!(
+        stderr.contains("src/again: skipping already visited directory (symlink loop?)"),
+        "{stderr}"
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn stats_counts_files_lines_and_bytes() {
+    let dir = three_file_tree();
+    fs::write(dir.path().join("data.bin"), b"unsafe\0").unwrap();
+    fs::write(dir.path().join("notes.txt"), "unsafe\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(
+        &["-rc", "--stats", "--exclude", "*.txt", "unsafe", root],
+        "",
+    );
+    let printed = stdout(&output);
+    // Everything but the time is exact: a.rs (27 bytes) has 2 matches,
+    // sub/b.rs (20) 1 and sub/c.rs (10) none, data.bin is binary and
+    // notes.txt excluded
+    let (counts, summary) = printed.split_once("\n\n").unwrap();
+    assert_eq!(counts.lines().count(), 3);
+    let summary: Vec<&str> = summary.lines().collect();
+    assert_eq!(
+        summary[..5],
+        [
+            "3 matched lines",
+            "2 files contained matches",
+            "3 files searched",
+            "2 files skipped",
+            "57 bytes searched",
+        ]
+    );
+    assert!(summary[5].ends_with(" seconds"));
+
+    // -q prints the summary alone
+    let output = grss(&["-q", "--stats", "fn", "-"], "fn a\n");
+    assert!(stdout(&output).starts_with("\n1 matched lines\n"));
+}
+
+#[test]
+fn summary_lists_the_files_with_the_most_matches() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir(root.join("src")).unwrap();
+    fs::write(root.join("src/few.rs"), "TODO\nok\n").unwrap();
+    fs::write(root.join("src/many.rs"), "TODO TODO TODO\nTODO\n").unwrap();
+    fs::write(root.join("lines.rs"), "TODO\nTODO\nTODO\n").unwrap();
+    fs::write(root.join("clean.rs"), "nothing\n").unwrap();
+    let table = |flags: &[&str]| {
+        let mut args = vec!["-r", "--summary"];
+        args.extend(flags);
+        args.push("TODO");
+        let output = Command::new(env!("CARGO_BIN_EXE_grss"))
+            .args(&args)
+            .arg(".")
+            .current_dir(root)
+            .env("XDG_CONFIG_HOME", "/nonexistent")
+            .env_remove("GRSS_OPTS")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        stdout(&output)
+    };
+
+    // -q leaves only the table, with nothing before it to set it apart
+    // from, and every file read to the end
+    let quiet = table(&["-q"]);
+    assert!(quiet.starts_with("path "), "{quiet:?}");
+    assert_eq!(
+        quiet,
+        "path           lines  matches\n\
+         ./src/many.rs      2        4\n\
+         ./lines.rs         3        3\n\
+         ./src/few.rs       1        1\n"
+    );
+    assert_eq!(
+        table(&["-q", "--summary-limit", "1"]),
+        "path           lines  matches\n\
+         ./src/many.rs      2        4\n"
+    );
+    // After the results, whatever they are
+    let counted = table(&["-c", "--sort", "path", "--summary-limit", "2"]);
+    assert_eq!(
+        counted,
+        "./clean.rs:0\n./lines.rs:3\n./src/few.rs:1\n./src/many.rs:2\n\
+         \npath           lines  matches\n\
+         ./src/many.rs      2        4\n\
+         ./lines.rs         3        3\n"
+    );
+}
+
+#[test]
+fn stats_with_json_end_with_a_summary_record() {
+    let dir = three_file_tree();
+    fs::write(dir.path().join("data.bin"), b"unsafe\0").unwrap();
+    fs::write(dir.path().join("notes.txt"), "unsafe\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(
+        &[
+            "-r",
+            "--stats",
+            "--json",
+            "--exclude",
+            "*.txt",
+            "unsafe|fn",
+            root,
+        ],
+        "",
+    );
+    let records: Vec<serde_json::Value> = stdout(&output)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let (summary, matches) = records.split_last().unwrap();
+    assert_eq!(matches.len(), 5);
+    assert!(matches.iter().all(|record| record["type"] == "match"));
+    let second = matches
+        .iter()
+        .find(|record| record["text"] == "unsafe fn f() {}")
+        .unwrap();
+    assert_eq!(second["path"], format!("{root}/a.rs"));
+    assert_eq!(second["line_number"], 2);
+    assert_eq!(second["offset"], 10);
+    assert_eq!(
+        second["matches"],
+        serde_json::json!([
+            {"start": 0, "end": 6, "column": 1},
+            {"start": 7, "end": 9, "column": 8}
+        ])
+    );
+
+    // Everything but the time is exact: a.rs (27 bytes) has 2 lines with 3
+    // matches, sub/b.rs (20) 2 with 2, sub/c.rs (10) 1 with 1, data.bin is
+    // binary and notes.txt excluded
+    let mut summary = summary.clone();
+    assert!(summary["elapsed_ms"].is_u64());
+    summary["elapsed_ms"] = 0.into();
+    assert_eq!(
+        summary,
+        serde_json::json!({
+            "type": "summary",
+            "files_searched": 3,
+            "files_matched": 3,
+            "files_skipped": {
+                "total": 2,
+                "binary": 1,
+                "filtered": 1,
+                "indexed": 0,
+                "too_big": 0,
+                "failed": 0,
+                "other": 0
+            },
+            "matched_lines": 5,
+            "total_matches": 6,
+            "bytes_searched": 57,
+            "elapsed_ms": 0
+        })
+    );
+}
+
+#[test]
+fn debug_timing_breaks_the_time_down_on_stderr() {
+    let dir = three_file_tree();
+    let root = dir.path().to_str().unwrap();
+    let output = grss(&["-rc", "--debug-timing", "unsafe", root], "");
+    // The results aren't touched, the report goes to stderr
+    assert_eq!(stdout(&output).lines().count(), 3);
+    let report = stderr(&output);
+    let mut lines = report.lines();
+    assert_eq!(lines.next(), Some("timing:"));
+    for phase in ["walk", "read", "match", "print", "wall"] {
+        let line = lines.next().unwrap();
+        let seconds = line
+            .strip_prefix(&format!("  {}", phase))
+            .and_then(|rest| rest.strip_suffix(" seconds"))
+            .unwrap_or_else(|| panic!("no {} in {:?}", phase, line));
+        assert!(seconds.trim().parse::<f64>().unwrap() >= 0.0);
+    }
+    assert_eq!(lines.next(), Some("  3 files searched, 57 bytes"));
+    assert_eq!(lines.next(), Some("  literal engine"));
+    assert_eq!(output.status.code(), Some(0));
+
+    // Standard input counts too
+    let output = grss(&["--debug-timing", "a", "-"], "a\n");
+    assert!(stderr(&output).contains("  1 files searched, 2 bytes"));
+}
+
+#[test]
+fn engine_picks_how_the_patterns_match() {
+    let dir = three_file_tree();
+    let root = dir.path().to_str().unwrap();
+    let search = |engine: &str| {
+        let output = grss(
+            &["-rn", "--engine", engine, "-e", "unsafe", "-e", "fn", root],
+            "",
+        );
+        assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+        sorted_lines(&output)
+    };
+    let expected = search("auto");
+    assert_eq!(expected.len(), 5);
+    for engine in ["regex", "literal", "aho-corasick"] {
+        assert_eq!(search(engine), expected, "{engine}");
+    }
+
+    // Only the regex engine takes a regex
+    let output = grss(&["--engine", "literal", r"fn \w+", "-"], "fn f\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stderr(&output).contains("with -F it would look for `fn \\w+` as it is"),
+        "{}",
+        stderr(&output)
+    );
+    let output = grss(
+        &["--engine", "regex", "--debug-timing", r"fn \w+", "-"],
+        "fn f\n",
+    );
+    assert_eq!(stdout(&output), "fn f\n");
+    assert!(stderr(&output).ends_with("  regex engine\n"));
+}
+
+#[test]
+fn no_unicode_stops_word_classes_at_ascii() {
+    let input = "café au lait\n";
+    let output = grss(&["-o", r"\w+", "-"], input);
+    assert_eq!(stdout(&output), "café\nau\nlait\n");
+    let output = grss(&["--no-unicode", "-o", r"\w+", "-"], input);
+    assert_eq!(stdout(&output), "caf\nau\nlait\n");
+    let output = grss(&["--no-unicode", "-w", "caf", "-"], input);
+    assert_eq!(stdout(&output), input);
+
+    let output = grss(&["--no-unicode", r"\p{Latin}", "-"], input);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("`\\p{Latin}` needs Unicode, which --no-unicode turns off"));
+}
+
+/// tests/data, where fixtures that can't be written out as text live
+fn data_dir() -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data")
+}
+
+#[test]
+fn iglob_matches_names_ignoring_case() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("Foo.RS"), "fn foo() {}\n").unwrap();
+    fs::write(dir.path().join("bar.rs"), "fn bar() {}\n").unwrap();
+    fs::write(dir.path().join("notes.md"), "fn in prose\n").unwrap();
+    fs::write(dir.path().join(".hidden.rs"), "fn hidden() {}\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+    let files = |globs: &[&str]| {
+        let mut args = vec!["-rl"];
+        args.extend(globs);
+        args.extend(["fn", root]);
+        let output = grss(&args, "");
+        let mut names: Vec<String> = stdout(&output)
+            .lines()
+            .map(|line| line.trim_start_matches(root).to_string())
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(files(&["--include", "*.rs"]), ["/bar.rs"]);
+    assert_eq!(files(&["--iglob", "*.rs"]), ["/Foo.RS", "/bar.rs"]);
+    // Either one including a file is enough
+    assert_eq!(
+        files(&["--iglob", "*.rs", "--include", "*.md"]),
+        ["/Foo.RS", "/bar.rs", "/notes.md"]
+    );
+    // Exclusions win, whichever flag they come from
+    assert_eq!(
+        files(&["--iglob", "*.rs", "--exclude", "bar.rs"]),
+        ["/Foo.RS"]
+    );
+    assert_eq!(
+        files(&["--include", "*.rs", "--iglob", "!BAR.*"]),
+        Vec::<String>::new()
+    );
+    // Globs don't bring back what the walk leaves out
+    assert_eq!(files(&["--iglob", ".HIDDEN.rs"]), Vec::<String>::new());
+    assert_eq!(
+        files(&["--hidden", "--iglob", ".HIDDEN.rs"]),
+        ["/.hidden.rs"]
+    );
+}
+
+#[test]
+fn gitignore_above_the_root_counts_until_turned_off() {
+    let dir = tempfile::tempdir().unwrap();
+    let top = dir.path();
+    fs::create_dir_all(top.join(".git")).unwrap();
+    fs::create_dir_all(top.join("src/gen")).unwrap();
+    fs::write(top.join(".gitignore"), "*.log\n").unwrap();
+    fs::write(top.join("src/.gitignore"), "gen/\n").unwrap();
+    for file in ["src/main.rs", "src/debug.log", "src/gen/out.rs"] {
+        fs::write(top.join(file), "x\n").unwrap();
+    }
+    let src = top.join("src");
+    let src = src.to_str().unwrap();
+    let files = |flags: &[&str]| {
+        let mut args = vec!["--files"];
+        args.extend(flags);
+        args.push(src);
+        let output = grss(&args, "");
+        assert_eq!(stderr(&output), "");
+        let mut names: Vec<String> = stdout(&output)
+            .lines()
+            .map(|line| line.trim_start_matches(src).to_string())
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(files(&[]), ["/main.rs"]);
+    assert_eq!(files(&["--no-ignore-parent"]), ["/debug.log", "/main.rs"]);
+    assert_eq!(
+        files(&["--no-ignore-vcs"]),
+        ["/debug.log", "/gen/out.rs", "/main.rs"]
+    );
+    assert_eq!(
+        files(&["--no-ignore"]),
+        ["/debug.log", "/gen/out.rs", "/main.rs"]
+    );
+
+    // A search goes by the same files
+    let output = grss(&["-rc", "x", src], "");
+    assert_eq!(stdout(&output), format!("{src}/main.rs:1\n"));
+}
+
+#[test]
+fn grssignore_and_ignore_file_leave_files_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("tree");
+    fs::create_dir_all(root.join("docs/drafts")).unwrap();
+    fs::write(root.join(".grssignore"), "*.bak\n/docs/drafts/\n").unwrap();
+    // Deeper wins: this brings one .bak back, and the bad line is warned
+    // about without losing the good one
+    fs::write(root.join("docs/.grssignore"), "!keep.bak\nx{a\n").unwrap();
+    for file in [
+        "main.rs",
+        "old.bak",
+        "notes.txt",
+        "docs/keep.bak",
+        "docs/guide.md",
+        "docs/drafts/wip.md",
+    ] {
+        fs::write(root.join(file), "x\n").unwrap();
+    }
+    // Rooted at the searched directory, wherever the file itself is
+    let extra = dir.path().join("extra-ignore");
+    fs::write(&extra, "/notes.txt\nguide.md\n").unwrap();
+    let root = root.to_str().unwrap();
+    let extra = extra.to_str().unwrap();
+    let files = |flags: &[&str]| {
+        let mut args = vec!["--files"];
+        args.extend(flags);
+        args.push(root);
+        let output = grss(&args, "");
+        let mut names: Vec<String> = stdout(&output)
+            .lines()
+            .map(|line| line.trim_start_matches(root).to_string())
+            .collect();
+        names.sort();
+        (names, stderr(&output))
+    };
+
+    let (names, warnings) = files(&[]);
+    assert_eq!(
+        names,
+        ["/docs/guide.md", "/docs/keep.bak", "/main.rs", "/notes.txt"]
+    );
+    assert!(warnings.starts_with(&format!("grss: {root}/docs/.grssignore: line 2: ")));
+    assert_eq!(
+        files(&["--ignore-file", extra]).0,
+        ["/docs/keep.bak", "/main.rs"]
+    );
+    // --no-ignore-vcs is only about .gitignore
+    assert_eq!(files(&["--no-ignore-vcs"]).0.len(), 4);
+    // --no-ignore leaves --ignore-file alone
+    assert_eq!(
+        files(&["--no-ignore", "--ignore-file", extra]).0,
+        [
+            "/docs/drafts/wip.md",
+            "/docs/keep.bak",
+            "/main.rs",
+            "/old.bak"
+        ]
+    );
+}
+
+#[test]
+fn one_file_system_changes_nothing_within_one() {
+    let dir = three_file_tree();
+    let root = dir.path().to_str().unwrap();
+    let sorted = |output: &std::process::Output| {
+        let mut lines: Vec<String> = stdout(output).lines().map(String::from).collect();
+        lines.sort();
+        lines
+    };
+
+    let plain = grss(&["-rn", "unsafe", root], "");
+    let one = grss(&["-rn", "--one-file-system", "unsafe", root], "");
+    assert_eq!(one.status.code(), Some(0));
+    assert!(!sorted(&one).is_empty());
+    assert_eq!(sorted(&one), sorted(&plain));
+}
+
+#[test]
+fn search_zip_looks_inside_gzip_files() {
+    let path = data_dir().join("app.log.gz");
+    let path = path.to_str().unwrap();
+
+    let output = grss(&["-zn", "ERROR", path], "");
+    assert_eq!(
+        stdout(&output),
+        "2:2024-01-01 ERROR disk full\n4:2024-01-02 ERROR disk still full\n"
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    // Without -z it's just a binary file
+    let output = grss(&["ERROR", path], "");
+    assert!(stderr(&output).contains("binary file, skipping"));
+}
+
+#[test]
+fn corrupt_gzip_files_are_reported_and_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::copy(data_dir().join("app.log.gz"), dir.path().join("a.log.gz")).unwrap();
+    fs::write(dir.path().join("b.log.gz"), "not gzip at all\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(&["-rzc", "ERROR", root], "");
+    assert_eq!(stdout(&output), format!("{root}/a.log.gz:2\n"));
+    assert!(stderr(&output).starts_with(&format!("grss: {root}/b.log.gz: ")));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn search_archives_looks_inside_zip_files() {
+    let path = data_dir().join("bundle.zip");
+    let path = path.to_str().unwrap();
+
+    // logo.png is binary and nested.zip is an archive in the archive, so
+    // neither is searched even though both have a TODO in them
+    let output = grss(&["--search-archives", "-n", "TODO", path], "");
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "{path}!docs/readme.txt:2:TODO write the docs\n\
+             {path}!src/main.rs:2:    // TODO handle errors\n"
+        )
+    );
+    assert_eq!(stderr(&output), "");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = grss(&["--search-archives", "-c", "TODO", path], "");
+    assert_eq!(
+        stdout(&output),
+        format!("{path}!docs/readme.txt:1\n{path}!src/main.rs:1\n")
+    );
+}
+
+#[test]
+fn corrupt_zip_archives_are_reported_and_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::copy(data_dir().join("bundle.zip"), dir.path().join("a.zip")).unwrap();
+    fs::write(dir.path().join("b.zip"), "not a zip at all\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(&["-rl", "--search-archives", "TODO", root], "");
+    assert_eq!(
+        stdout(&output),
+        format!("{root}/a.zip!docs/readme.txt\n{root}/a.zip!src/main.rs\n")
+    );
+    assert!(stderr(&output).starts_with(&format!("grss: {root}/b.zip: ")));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[cfg(unix)]
+#[test]
+fn watch_searches_appended_lines_until_interrupted() {
+    use std::io::Read;
+    use std::thread::sleep;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    fs::write(&path, "ERROR one\nok\n").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_grss"))
+        .args(["--watch", "-n", "ERROR", path.to_str().unwrap()])
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let pause = || sleep(Duration::from_millis(600));
+    let append = |text: &str| {
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    };
+
+    pause();
+    // The half-written line is only searched once it's complete
+    append("ERROR two\nERR");
+    pause();
+    append("OR three\n");
+    pause();
+    // Rotated: shorter than before, so searched from the start again
+    fs::write(&path, "ERROR new\n").unwrap();
+    pause();
+
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let exit = child.wait().unwrap();
+    let mut printed = String::new();
+    child.stdout.unwrap().read_to_string(&mut printed).unwrap();
+
+    assert_eq!(
+        printed,
+        "1:ERROR one\n3:ERROR two\n4:ERROR three\n1:ERROR new\n"
+    );
+    assert_eq!(exit.code(), Some(0));
+}
+
+#[test]
+fn tui_refuses_what_it_cant_browse() {
+    let output = grss(&["--tui", "-c", "foo", "."], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = grss(&["--tui", "foo", "-"], "foo\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout(&output).is_empty());
+    #[cfg(feature = "tui")]
+    assert!(stderr(&output).contains("can't search standard input"));
+    #[cfg(not(feature = "tui"))]
+    assert!(stderr(&output).contains("built with the tui feature"));
+}
+
+#[test]
+fn max_depth_limits_how_deep_r_goes() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("a/b")).unwrap();
+    fs::write(dir.path().join("top.txt"), "version\n").unwrap();
+    fs::write(dir.path().join("a/mid.txt"), "version\n").unwrap();
+    fs::write(dir.path().join("a/b/deep.txt"), "version\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    // Files come out in whatever order the threads finish them
+    let output = grss(&["-rl", "--max-depth", "2", "version", root], "");
+    let mut listed: Vec<String> = stdout(&output).lines().map(String::from).collect();
+    listed.sort();
+    assert_eq!(
+        listed,
+        [format!("{root}/a/mid.txt"), format!("{root}/top.txt")]
+    );
+
+    let output = grss(&["-r", "--max-depth", "0", "version", root], "");
+    assert!(output.stdout.is_empty());
+    assert!(
+        stderr(&output).contains("--max-depth 0"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn hidden_files_need_hidden_unless_given_explicitly() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join(".config")).unwrap();
+    fs::write(dir.path().join(".config/app.toml"), "secret\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+    let config = format!("{root}/.config");
+
+    assert!(grss(&["-r", "secret", root], "").stdout.is_empty());
+    let expected = format!("{config}/app.toml:secret\n");
+    assert_eq!(
+        stdout(&grss(&["-r", "--hidden", "secret", root], "")),
+        expected
+    );
+    assert_eq!(stdout(&grss(&["-r", "secret", &config], "")), expected);
+}
+
+#[test]
+fn type_rust_only_searches_rust_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    for file in [
+        "src/main.rs",
+        "src/notes.md",
+        "Cargo.toml",
+        "build.rs",
+        "x.vue",
+    ] {
+        fs::write(dir.path().join(file), "unsafe\n").unwrap();
+    }
+    let root = dir.path().to_str().unwrap();
+    let listed = |args: &[&str]| {
+        let output = grss(&[&["-rl"], args, &["unsafe", root]].concat(), "");
+        let mut files: Vec<String> = stdout(&output)
+            .lines()
+            .map(|line| line.strip_prefix(root).unwrap().to_string())
+            .collect();
+        files.sort();
+        files
+    };
+
+    assert_eq!(listed(&["--type", "rust"]), ["/build.rs", "/src/main.rs"]);
+    assert_eq!(
+        listed(&["-t", "rust", "-T", "md", "--exclude", "build.rs"]),
+        ["/src/main.rs"]
+    );
+    assert_eq!(
+        listed(&[
+            "--type-not",
+            "rust",
+            "--type-not",
+            "toml",
+            "--type-not",
+            "md"
+        ]),
+        ["/x.vue"]
+    );
+    assert_eq!(
+        listed(&["--type-add", "web:*.vue", "-t", "web"]),
+        ["/x.vue"]
+    );
+
+    let output = grss(&["-r", "--type", "cobol", "unsafe", root], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("unknown file type 'cobol'"));
+}
+
+#[test]
+fn type_list_prints_every_type_sorted() {
+    let output = grss(&["--type-list", "--type-add", "web:*.vue"], "");
+    assert_eq!(output.status.code(), Some(0));
+    let list = stdout(&output);
+    assert!(list.contains("rust: *.rs\n") && list.contains("web: *.vue\n"));
+    let names: Vec<&str> = list
+        .lines()
+        .map(|line| line.split(':').next().unwrap())
+        .collect();
+    assert!(names.is_sorted(), "{names:?}");
+}
+
+#[test]
+fn output_writes_results_to_a_file_and_never_searches_it() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "needle one\nhay\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "needle two\n").unwrap();
+    let results = dir.path().join("results.txt");
+    // Left over from an earlier run, with a match in it
+    fs::write(&results, "old needle\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(
+        &[
+            "-r",
+            "--color",
+            "auto",
+            "--output",
+            results.to_str().unwrap(),
+            "needle",
+            root,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    let mut lines: Vec<String> = fs::read_to_string(&results)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        [
+            format!("{root}/a.txt:needle one"),
+            format!("{root}/b.txt:needle two")
+        ]
+    );
+
+    // Only --color always colors the file
+    let file = results.to_str().unwrap();
+    grss(
+        &[
+            "--color",
+            "always",
+            "--output",
+            file,
+            "needle",
+            &format!("{root}/b.txt"),
+        ],
+        "",
+    );
+    assert!(fs::read_to_string(&results).unwrap().contains("\x1b["));
+}
+
+#[test]
+fn sort_path_gives_the_same_bytes_every_run() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("a")).unwrap();
+    // Enough files that the workers finish them out of order
+    for i in 0..200 {
+        let line = format!("match {i}\n").repeat(i % 7 + 1);
+        fs::write(dir.path().join(format!("f{i:03}.txt")), &line).unwrap();
+    }
+    fs::write(dir.path().join("a/inner.txt"), "match inner\n").unwrap();
+    fs::write(dir.path().join("a.txt"), "match a\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let first = grss(&["-r", "--sort", "path", "match", root], "").stdout;
+    let second = grss(&["-r", "--sort", "path", "match", root], "").stdout;
+    assert_eq!(first, second);
+    let files: Vec<String> = String::from_utf8(first)
+        .unwrap()
+        .lines()
+        .map(|line| line.split(':').next().unwrap().to_string())
+        .collect();
+    assert_eq!(files[0], format!("{root}/a/inner.txt"));
+    assert_eq!(files[1], format!("{root}/a.txt"));
+    assert!(files[2..].is_sorted());
+
+    let reversed = grss(&["-rl", "--sortr", "path", "match", root], "");
+    let last_first: Vec<String> = stdout(&reversed).lines().map(String::from).collect();
+    assert_eq!(last_first[0], format!("{root}/f199.txt"));
+    assert_eq!(last_first.last().unwrap(), &format!("{root}/a/inner.txt"));
+
+    // f006.txt has 7 lines, the most, as do f013, f020...: ties go by path
+    let by_size = grss(&["-rl", "--sort", "size", "match", root], "");
+    let smallest_first: Vec<String> = stdout(&by_size).lines().map(String::from).collect();
+    assert_eq!(smallest_first[0], format!("{root}/a.txt"));
+    assert_eq!(smallest_first.last().unwrap(), &format!("{root}/f195.txt"));
+}
+
+#[test]
+fn sort_by_times_and_sortr_for_the_newest_first() {
+    let dir = tempfile::tempdir().unwrap();
+    // (name, modified, accessed), in seconds since 1970
+    let files = [
+        ("a.log", 3000, 1000),
+        ("b.log", 1000, 3000),
+        ("c.log", 2000, 2000),
+    ];
+    for (name, _, _) in files {
+        fs::write(dir.path().join(name), "error\n").unwrap();
+    }
+    let root = dir.path().to_str().unwrap();
+    let order = |sort: &str, by: &str| {
+        // Set every time, since each search reads the files
+        for (name, modified, accessed) in files {
+            let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+            let times = fs::FileTimes::new()
+                .set_modified(at(modified))
+                .set_accessed(at(accessed));
+            let file = fs::File::options()
+                .write(true)
+                .open(dir.path().join(name))
+                .unwrap();
+            file.set_times(times).unwrap();
+        }
+        let output = grss(&["-rl", sort, by, "error", root], "");
+        assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+        stdout(&output)
+            .lines()
+            .map(|line| line.rsplit('/').next().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(order("--sort", "modified"), ["b.log", "c.log", "a.log"]);
+    assert_eq!(order("--sortr", "modified"), ["a.log", "c.log", "b.log"]);
+    // Searching a file reads it, but its time is taken before that
+    assert_eq!(order("--sort", "accessed"), ["a.log", "c.log", "b.log"]);
+    assert_eq!(order("--sortr", "accessed"), ["b.log", "c.log", "a.log"]);
+    // Creation times can't be set, and not every file system has them
+    assert_eq!(order("--sort", "created").len(), 3);
+}
+
+#[test]
+fn crlf_files_match_dollar_anchors_and_print_no_carriage_returns() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log.txt");
+    // Windows line endings, except for the third line
+    fs::write(&path, "an error\r\nok\r\nerror\nerror here\r\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = grss(&["-nb", "error$", path], "");
+    assert_eq!(stdout(&output), "1:0:an error\n3:14:error\n");
+
+    // -U matches across the whole file, where only --crlf stops `$` at a \r
+    let output = grss(&["-U", "error$", path], "");
+    assert_eq!(stdout(&output), "error\n");
+    let output = grss(&["-U", "--crlf", "-n", "error$|^ok$", path], "");
+    assert_eq!(stdout(&output), "1:an error\n2:ok\n3:error\n");
+    let output = grss(&["-U", "--crlf", "-o", "error.*", path], "");
+    assert_eq!(stdout(&output), "error\nerror\nerror here\n");
+}
+
+#[test]
+fn max_filesize_skips_big_files_found_by_r() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("small.txt"), "x".repeat(1023) + "\n").unwrap();
+    fs::write(
+        dir.path().join("exact.txt"),
+        "x".repeat(1024 * 2 - 1) + "\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("big.txt"), "x".repeat(1024 * 2) + "\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = grss(&["-rl", "--max-filesize", "2K", "--stats", "x", root], "");
+    let out = stdout(&output);
+    assert!(
+        out.contains("exact.txt") && out.contains("small.txt"),
+        "{out}"
+    );
+    assert!(!out.contains("big.txt"), "{out}");
+    assert!(out.contains("1 files skipped"), "{out}");
+    assert!(stderr(&output).contains("big.txt: skipped, bigger than --max-filesize"));
+
+    // Named on the command line, it's searched anyway
+    let big = format!("{root}/big.txt");
+    let output = grss(&["-c", "--max-filesize", "2K", "x", &big], "");
+    assert_eq!(stdout(&output), "1\n");
+
+    let output = grss(&["-r", "--max-filesize", "2X", "x", root], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn one_thread_matches_the_golden_output_byte_for_byte() {
+    let golden = fs::read(data_dir().join("tree.golden")).unwrap();
+    // Run from inside the tree, so the paths printed don't depend on where
+    // the repository is checked out
+    let output = Command::new(env!("CARGO_BIN_EXE_grss"))
+        .args(["-r", "-n", "-j", "1", "TODO", "."])
+        .current_dir(data_dir().join("tree"))
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .env_remove("GRSS_COLORS")
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&golden)
+    );
+
+    let output = grss(&["-j", "1025", "TODO", "."], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("1025 is not in 0..=1024"));
+}
+
+#[test]
+fn verbose_explains_what_happens_to_each_path() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/lib.rs"), "fn foo() {}\n").unwrap();
+    fs::write(dir.path().join("notes.md"), "foo\n").unwrap();
+    fs::write(dir.path().join("blob.bin"), b"foo\0\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    // Nothing extra without --verbose
+    let output = grss(&["-r", "--exclude", "*.md", "foo", root], "");
+    assert_eq!(stderr(&output), "");
+
+    let output = grss(&["--verbose", "-r", "--exclude", "*.md", "foo", root], "");
+    let err = stderr(&output);
+    assert!(err.contains("lib.rs: searching"), "{err}");
+    assert!(err.contains("blob.bin: skipped, binary file"), "{err}");
+    assert!(!err.contains("debug"), "{err}");
+
+    let output = grss(
+        &[
+            "--verbose",
+            "--verbose",
+            "-r",
+            "--exclude",
+            "*.md",
+            "foo",
+            root,
+        ],
+        "",
+    );
+    let err = stderr(&output);
+    assert!(err.contains("grss: debug: pattern 'foo'"), "{err}");
+    assert!(err.contains("notes.md: skipped, left out by"), "{err}");
+    // Results still go to stdout alone
+    assert!(stdout(&output).ends_with("lib.rs:fn foo() {}\n"));
+
+    // RUST_LOG overrides --verbose
+    let output = Command::new(env!("CARGO_BIN_EXE_grss"))
+        .args(["--verbose", "-r", "foo", root])
+        .env("RUST_LOG", "off")
+        .output()
+        .unwrap();
+    assert_eq!(stderr(&output), "");
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_stops_between_lines_and_exits_with_130() {
+    use std::io::Read;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_grss"))
+        .args(["--stats", "match", "-"])
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Standard input that never ends, until grss stops reading it
+    let mut stdin = child.stdin.take().unwrap();
+    let feeder = std::thread::spawn(move || {
+        let chunk = "a line that should match\n".repeat(1000);
+        while stdin.write_all(chunk.as_bytes()).is_ok() {}
+    });
+
+    // Wait until results are coming out, then press Ctrl-C
+    let mut stdout = child.stdout.take().unwrap();
+    let mut out = vec![0; 4096];
+    stdout.read_exact(&mut out).unwrap();
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    stdout.read_to_end(&mut out).unwrap();
+    let output = child.wait_with_output().unwrap();
+    feeder.join().unwrap();
+
+    assert_eq!(output.status.code(), Some(130));
+    assert_eq!(stderr(&output), "");
+    let out = String::from_utf8(out).unwrap();
+    let (results, stats) = out.split_once("\n\n").unwrap();
+    assert!(
+        results
+            .lines()
+            .all(|line| line == "a line that should match")
+    );
+    assert!(stats.contains("files searched\n"), "{stats}");
+}
+
+#[test]
+fn line_buffered_prints_a_match_before_the_input_ends() {
+    use std::io::{BufRead, BufReader};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_grss"))
+        .args(["--line-buffered", "match", "-"])
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"skip\nfirst match\n").unwrap();
+    stdin.flush().unwrap();
+
+    // Standard input is still open, so this only returns if the line was
+    // written out as soon as it was found
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "first match\n");
+
+    drop(stdin);
+    assert_eq!(child.wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn files_with_and_without_match_split_the_fixture_tree() {
+    let tree = data_dir().join("tree");
+    let list = |flag: &str| {
+        Command::new(env!("CARGO_BIN_EXE_grss"))
+            .args(["-r", flag, "--sort", "path", "--exclude", "*.md"])
+            .args(["^// TODO", "."])
+            .current_dir(&tree)
+            .env("XDG_CONFIG_HOME", "/nonexistent")
+            .output()
+            .unwrap()
+    };
+
+    let output = list("-l");
+    assert_eq!(stdout(&output), "./src/lib.rs\n./src/parse/mod.rs\n");
+    assert_eq!(output.status.code(), Some(0));
+    // docs/README.md is excluded, so it isn't listed either way
+    let output = list("-L");
+    assert_eq!(stdout(&output), "./TODO.txt\n./empty.txt\n./src/main.rs\n");
+    assert_eq!(output.status.code(), Some(0));
+
+    // Binary files are skipped, not listed as having no match
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("blob.bin"), b"\0\0\0").unwrap();
+    let output = grss(&["-rL", "x", dir.path().to_str().unwrap()], "");
+    assert_eq!(stdout(&output), "");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = grss(&["-l", "-L", "x", "-"], "");
+    assert!(stderr(&output).contains("cannot be used with"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn null_data_matches_whole_records() {
+    let input = "src/main.rs\0docs/read me.md\0src/parse\nmod.rs\0";
+    let output = grss(&["--null-data", "-n", "^src/", "-"], input);
+    assert_eq!(stdout(&output), "1:src/main.rs\x003:src/parse\nmod.rs\0");
+    assert_eq!(output.status.code(), Some(0));
+
+    // Without it, the NULs make a file binary
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("files.list");
+    fs::write(&path, input).unwrap();
+    let path = path.to_str().unwrap();
+    let output = grss(&["^src/", path], "");
+    assert_eq!(stdout(&output), "");
+    let output = grss(&["--null-data", "-c", "^src/", path], "");
+    assert_eq!(stdout(&output), "2\n");
+}
+
+#[test]
+fn heading_groups_lines_under_each_path() {
+    // The terminal-style layout can't be had from a pipe by default, so
+    // --heading asks for it; piped, the path goes in front of every line
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_grss"))
+            .args(["-r", "-n", "--sort", "path"])
+            .args(args)
+            .args(["TODO", "."])
+            .current_dir(data_dir().join("tree"))
+            .env("XDG_CONFIG_HOME", "/nonexistent")
+            .output()
+            .unwrap()
+    };
+    let heading = fs::read(data_dir().join("tree.heading.golden")).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&run(&["--heading"]).stdout),
+        String::from_utf8_lossy(&heading)
+    );
+    let prefixed = fs::read(data_dir().join("tree.golden")).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&run(&[]).stdout),
+        String::from_utf8_lossy(&prefixed)
+    );
+    assert_eq!(run(&["--heading", "--no-heading"]).stdout, prefixed);
+
+    // -c keeps its path:count lines
+    let output = run(&["--heading", "-c", "--include", "*.txt"]);
+    assert_eq!(stdout(&output), "./TODO.txt:2\n./empty.txt:0\n");
+}
+
+#[test]
+fn indexed_searches_find_what_full_scans_do() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_str().unwrap();
+    fs::write(
+        dir.path().join("a.rs"),
+        "let shape = Rectangle::new(1, 2);\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("b.rs"), "let shape = Circle::new(3);\n").unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub/c.rs"), "fn h() {}\n").unwrap();
+
+    let searches: [&[&str]; 6] = [
+        &[r"Rectangle::new\(\d"],
+        &["-F", "Circle::"],
+        &["-e", "shape", "-e", "fn h"],
+        &["-w", "new"],
+        &["-i", "rectangle"],
+        &["-lv", "Rect"],
+    ];
+    let same_as_full_scan = || {
+        for args in searches {
+            let search = |extra: &[&str]| {
+                let args = [&["-rn", "--sort", "path"], extra, args, &[root]].concat();
+                grss(&args, "")
+            };
+            let (indexed, full) = (search(&[]), search(&["--no-index"]));
+            assert_eq!(stdout(&indexed), stdout(&full), "{:?}", args);
+            assert_eq!(indexed.status.code(), full.status.code(), "{:?}", args);
+        }
+    };
+
+    let output = grss(&["index", "--status", root], "");
+    assert_eq!(stdout(&output), format!("{root}: not indexed\n"));
+    assert_eq!(output.status.code(), Some(1));
+    let output = grss(&["index", root], "");
+    assert!(
+        stdout(&output).contains("indexed 3 files"),
+        "{}",
+        stdout(&output)
+    );
+    assert_eq!(output.status.code(), Some(0));
+    same_as_full_scan();
+    // b.rs and sub/c.rs were never read
+    let output = grss(&["-r", "--verbose", "Rectangle::new", root], "");
+    let logged = stderr(&output);
+    let skipped = logged
+        .lines()
+        .filter(|line| line.ends_with("skipped, the index rules it out"));
+    assert_eq!(skipped.count(), 2, "{}", logged);
+
+    // Changed and new files are searched, the rest still skipped
+    fs::write(
+        dir.path().join("b.rs"),
+        "let shape = Rectangle::new(3, 4);\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("sub/d.rs"), "Rectangle::new(5, 6)\n").unwrap();
+    fs::remove_file(dir.path().join("a.rs")).unwrap();
+    same_as_full_scan();
+    let output = grss(&["-rl", "--sort", "path", "Rectangle::new", root], "");
+    assert_eq!(stdout(&output), format!("{root}/b.rs\n{root}/sub/d.rs\n"));
+    let output = grss(&["index", "--status", root], "");
+    assert!(
+        stdout(&output)
+            .ends_with("3 files indexed, 1 unchanged, 1 changed, 1 gone, 1 not indexed\n"),
+        "{}",
+        stdout(&output)
+    );
+
+    // A search of a directory inside the indexed one uses it too
+    let sub = dir.path().join("sub");
+    let output = grss(&["-r", "--verbose", "fn h", sub.to_str().unwrap()], "");
+    assert!(stderr(&output).contains("using the index, 1 of its 3 files could match"));
+    assert_eq!(
+        stdout(&output),
+        format!("{}/c.rs:fn h() {{}}\n", sub.display())
+    );
+}
+
+#[test]
+fn byte_range_searches_whole_lines_numbered_as_in_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("big.log");
+    let log: String = (1..=1000)
+        .map(|n| match n % 100 {
+            0 => format!("{n:04} ERROR disk full\n"),
+            _ => format!("{n:04} INFO all good\n"),
+        })
+        .collect();
+    fs::write(&path, &log).unwrap();
+    let path = path.to_str().unwrap();
+
+    // Lines are 19 bytes, 21 with an ERROR, so byte 5000 is in line 263
+    // and byte 10000 in line 526
+    let output = grss(&["-nb", "--byte-range", "5000..10000", "ERROR", path], "");
+    assert_eq!(
+        stdout(&output),
+        "300:5685:0300 ERROR disk full\n400:7587:0400 ERROR disk full\n\
+         500:9489:0500 ERROR disk full\n"
+    );
+    let output = grss(&["-n", "--byte-range", "5000..", "-c", "ERROR", path], "");
+    assert_eq!(stdout(&output), "8\n");
+    let output = grss(&["-n", "--byte-range", "..5700", "ERROR", path], "");
+    assert_eq!(
+        stdout(&output).lines().last(),
+        Some("300:0300 ERROR disk full")
+    );
+
+    // The same lines as searching the whole file, just fewer of them
+    let whole = stdout(&grss(&["-n", "INFO", path], ""));
+    let ranged = stdout(&grss(&["-n", "--byte-range", "1K..2K", "INFO", path], ""));
+    assert!(whole.contains(&ranged));
+    assert_eq!(ranged.lines().next(), Some("55:0055 INFO all good"));
+
+    let output = grss(&["--byte-range", "20..10", "ERROR", path], "");
+    assert!(stderr(&output).contains("END has to come after START"));
+    assert_eq!(output.status.code(), Some(2));
+}
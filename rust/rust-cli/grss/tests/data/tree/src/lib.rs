@@ -0,0 +1,2 @@
+// TODO: errors
+pub fn run() {}
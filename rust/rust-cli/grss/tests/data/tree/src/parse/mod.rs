@@ -0,0 +1,4 @@
+pub fn parse() {
+    todo!()
+}
+// TODO: tests
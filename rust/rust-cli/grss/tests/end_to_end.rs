@@ -0,0 +1,155 @@
+// FilePath: rust/rust-cli/grss/tests/end_to_end.rs
+
+//! End-to-end tests of the everyday flags, written with assert_cmd
+//!
+//! Each test runs the real binary on a small file and checks stdout, stderr
+//! and the exit code together
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+const POEM: &str = "\
+The quick brown fox
+jumps over the lazy dog.
+THE END
+";
+
+/// A directory holding poem.txt, and grss ready to run in it
+///
+/// A config file or GRSS_COLORS on the machine running the tests mustn't
+/// change the results, so both are kept out of the way
+fn setup() -> (TempDir, Command) {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("poem.txt"), POEM).unwrap();
+
+    let mut grss = Command::cargo_bin("grss").unwrap();
+    grss.current_dir(dir.path())
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .env_remove("GRSS_COLORS");
+    (dir, grss)
+}
+
+#[test]
+fn plain_search_prints_matching_lines() {
+    let (_dir, mut grss) = setup();
+    grss.args(["o", "poem.txt"])
+        .assert()
+        .success()
+        .stdout("The quick brown fox\njumps over the lazy dog.\n")
+        .stderr("");
+}
+
+#[test]
+fn plain_search_is_case_sensitive() {
+    let (_dir, mut grss) = setup();
+    grss.args(["the", "poem.txt"])
+        .assert()
+        .success()
+        .stdout("jumps over the lazy dog.\n");
+}
+
+#[test]
+fn line_numbers() {
+    let (_dir, mut grss) = setup();
+    grss.args(["-n", "fox|END", "poem.txt"])
+        .assert()
+        .success()
+        .stdout("1:The quick brown fox\n3:THE END\n");
+}
+
+#[test]
+fn ignore_case() {
+    let (_dir, mut grss) = setup();
+    grss.args(["-i", "the", "poem.txt"])
+        .assert()
+        .success()
+        .stdout(POEM);
+}
+
+#[test]
+fn ignore_case_with_line_numbers_and_count() {
+    let (_dir, mut grss) = setup();
+    grss.args(["-i", "-c", "END", "poem.txt"])
+        .assert()
+        .success()
+        .stdout("1\n");
+}
+
+#[test]
+fn no_match_exits_with_1() {
+    let (_dir, mut grss) = setup();
+    grss.args(["cat", "poem.txt"])
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr("");
+}
+
+#[test]
+fn missing_file_exits_with_2() {
+    let (_dir, mut grss) = setup();
+    grss.args(["fox", "missing.txt"])
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::starts_with("grss: missing.txt: "));
+}
+
+#[test]
+fn invalid_regex_exits_with_2() {
+    let (_dir, mut grss) = setup();
+    grss.args(["fox(", "poem.txt"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("invalid pattern 'fox('"));
+}
+
+#[test]
+fn unknown_flag_is_a_usage_error() {
+    let (_dir, mut grss) = setup();
+    grss.args(["--no-such-flag", "fox", "poem.txt"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("--no-such-flag"));
+}
+
+#[test]
+fn stdin_is_read_for_dash() {
+    let (_dir, mut grss) = setup();
+    grss.args(["-n", "b", "-"])
+        .write_stdin("a\nb\nc\n")
+        .assert()
+        .success()
+        .stdout("2:b\n");
+}
+
+#[test]
+fn invert_match() {
+    let (_dir, mut grss) = setup();
+    grss.args(["-v", "o", "poem.txt"])
+        .assert()
+        .success()
+        .stdout("THE END\n");
+}
+
+#[test]
+fn color_always_highlights_matches() {
+    let (_dir, mut grss) = setup();
+    grss.args(["--color=always", "fox", "poem.txt"])
+        .assert()
+        .success()
+        .stdout("The quick brown \x1b[1;31mfox\x1b[0m\n");
+}
+
+#[test]
+fn recursive_search_prefixes_paths() {
+    let (dir, mut grss) = setup();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub/notes.txt"), "a lazy afternoon\n").unwrap();
+    grss.args(["-r", "-n", "lazy", "sub"])
+        .assert()
+        .success()
+        .stdout("sub/notes.txt:1:a lazy afternoon\n");
+}
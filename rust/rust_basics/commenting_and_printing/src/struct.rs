@@ -10,9 +10,24 @@
 //! This file demonstrates different types of structs, their usage,
 //! and best practices in Rust programming
 
+use std::fmt;
+
 /// A basic struct representing a person
 /// Structs are custom data types that group related data together
 /// They're similar to classes in other languages but without inheritance
+///
+/// DERIVES EXPLAINED:
+/// - `#[derive(...)]` asks the compiler to write a trait implementation for us
+/// - Each derive works field by field, so every field's type must implement
+///   the trait too (String, u32 and bool all do)
+/// - `Debug` prints every field with its name: `Person { name: "John", ... }`,
+///   which is what `{:?}` uses
+/// - `Clone` clones every field: the Strings get their own new heap copies,
+///   so the clone and the original never share memory
+/// - `PartialEq` makes `==` compare every field in order, and two persons are
+///   equal only when ALL of them are equal (including the private `is_active`)
+/// - Without these, `{:?}`, `.clone()` and `==` on a Person don't compile
+#[derive(Debug, Clone, PartialEq)]
 struct Person {
     name: String, // Owned string data - String type has full ownership of its memory,
     // controls its data exclusively, and is responsible for cleanup when dropped
@@ -48,6 +63,8 @@ pub struct Rectangle {
 /// This demonstrates an unusual visibility pattern
 /// The struct can only be created within this module, but if exposed through
 /// a function or method, its public fields can be accessed from outside
+// Not used by main() yet (see the TODO below), so the compiler would warn
+#[allow(dead_code)]
 struct PrivateStructPublicFields {
     pub id: u32,           // Public field
     pub name: String,      // Public field
@@ -55,6 +72,7 @@ struct PrivateStructPublicFields {
 }
 
 // TODO: Use this also in the program
+#[allow(dead_code)]
 impl PrivateStructPublicFields {
     /// Constructor - can only be called within this module
     fn new(id: u32, name: String, internal_data: String) -> Self {
@@ -178,6 +196,27 @@ impl Person {
     }
 }
 
+/// Display for Person - what `{}` prints, for people rather than programmers
+///
+/// DISPLAY vs DEBUG EXPLAINED:
+/// - `Debug` (derived above) shows the raw structure, for developers
+/// - `Display` can't be derived: there's no one obvious way to show a type
+///   to a user, so we write `fmt` ourselves and decide
+/// - Implementing Display also gives us `.to_string()` for free
+impl fmt::Display for Person {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // `is_active` is a private field, but this impl is in the same module
+        // as the struct, so it can read the field directly instead of going
+        // through the `is_active()` getter that outside code needs
+        let status = if self.is_active { "active" } else { "inactive" };
+        write!(
+            f,
+            "{} <{}> ({}, {})",
+            self.name, self.email, self.age, status
+        )
+    }
+}
+
 /// Implementation for Rectangle
 impl Rectangle {
     /// Constructor for Rectangle
@@ -311,7 +350,7 @@ fn demonstrate_scope_resolution() {
                                     // Person::greet(&person);         // Type::method(&instance) - equivalent
 
     // 3. Standard library examples
-    let vector = Vec::new(); // Vec::new() - associated function
+    let vector: Vec<i32> = Vec::new(); // Vec::new() - associated function
     let string = String::from("Hi"); // String::from() - associated function
     let number = i32::MAX; // i32::MAX - associated constant
 
@@ -362,6 +401,64 @@ fn demonstrate_advanced_features() {
     println!("Point coordinates: x={}, y={}, z={}", x, y, z);
 }
 
+/// Demonstrate the traits derived for Person: Debug, Clone and PartialEq
+fn demonstrate_derives() {
+    println!("\n=== Derived Traits Examples ===");
+
+    let person = Person::new(String::from("Dana"), 41, String::from("dana@example.com"));
+
+    // 1. Debug - `{:?}` prints the raw structure, field names included
+    println!("Debug: {:?}", person);
+
+    // 2. Clone - a deep copy, made BEFORE a call that consumes the original
+    // delete() takes `self` by value, so after it `person` is gone. Cloning
+    // first keeps an independent copy we can still use afterwards.
+    let backup = person.clone();
+
+    // 3. PartialEq - `==` compares field by field
+    println!("Clone equals original? {}", backup == person); // true
+
+    let deletion_msg = person.delete(); // person is consumed here
+    println!("{}", deletion_msg);
+    println!("Backup still usable: {}", backup.greet());
+
+    // ❌ This would cause compile error - person was moved into delete():
+    // println!("{:?}", person);
+
+    // Changing one field of a clone makes it unequal to the original
+    let mut changed = backup.clone();
+    changed.deactivate();
+    println!("Still equal after deactivate? {}", changed == backup); // false
+}
+
+/// Demonstrate `{}` vs `{:?}` vs `{:#?}` on the same Person
+fn demonstrate_display() {
+    println!("\n=== Display vs Debug Examples ===");
+
+    let mut person = Person::new(
+        String::from("John Doe"),
+        25,
+        String::from("john@example.com"),
+    );
+
+    // 1. `{}` - Display, our own human-friendly format
+    println!("Display:      {}", person);
+
+    // 2. `{:?}` - Debug, the raw structure on one line
+    println!("Debug:        {:?}", person);
+
+    // 3. `{:#?}` - "pretty" Debug, one field per line, for bigger structs
+    println!("Pretty Debug: {:#?}", person);
+
+    // Display follows the data: deactivating changes what's printed
+    person.deactivate();
+    println!("Display:      {}", person);
+
+    // `.to_string()` comes with Display
+    let text: String = person.to_string();
+    println!("As a String:  {}", text);
+}
+
 /// Main function demonstrating struct usage
 fn main() {
     println!("=== Rust Structs Tutorial ===\n");
@@ -439,6 +536,12 @@ fn main() {
     // 12. Scope resolution examples
     demonstrate_scope_resolution();
 
+    // 13. Derived traits examples
+    demonstrate_derives();
+
+    // 14. Display vs Debug examples
+    demonstrate_display();
+
     println!("\n=== Key Takeaways ===");
     println!("STRUCTS:");
     println!("• Group related data together into custom types");
@@ -460,4 +563,69 @@ fn main() {
     println!("• `Type::function()` = associated function (like constructor)");
     println!("• `instance.method()` = method call on instance");
     println!("• `module::Type::item` = access items in namespaces");
+
+    println!("\nDERIVES:");
+    println!("• `#[derive(Debug)]` = print with `{{:?}}`");
+    println!("• `#[derive(Clone)]` = `.clone()` makes an independent deep copy");
+    println!("• `#[derive(PartialEq)]` = `==` compares every field");
+
+    println!("\nPRINTING:");
+    println!("• `{{}}` = Display, written by hand for users");
+    println!("• `{{:?}}` = Debug, derived, the raw structure");
+    println!("• `{{:#?}}` = Debug pretty-printed, one field per line");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn john() -> Person {
+        Person::new(
+            String::from("John Doe"),
+            25,
+            String::from("john@example.com"),
+        )
+    }
+
+    #[test]
+    fn clone_equals_the_original() {
+        let person = john();
+        let copy = person.clone();
+        assert_eq!(copy, person);
+        // Dropping the original doesn't affect the clone
+        drop(person);
+        assert_eq!(copy.name, "John Doe");
+    }
+
+    #[test]
+    fn clone_differs_after_mutation() {
+        let person = john();
+        let mut copy = person.clone();
+        copy.deactivate();
+        assert_ne!(copy, person);
+        // Only the clone changed
+        assert!(person.is_active());
+
+        let mut older = person.clone();
+        older.age += 1;
+        assert_ne!(older, person);
+    }
+
+    #[test]
+    fn display_shows_an_active_person() {
+        assert_eq!(
+            john().to_string(),
+            "John Doe <john@example.com> (25, active)"
+        );
+    }
+
+    #[test]
+    fn display_shows_a_deactivated_person() {
+        let mut person = john();
+        person.deactivate();
+        assert_eq!(
+            format!("{}", person),
+            "John Doe <john@example.com> (25, inactive)"
+        );
+    }
 }